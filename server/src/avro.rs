@@ -0,0 +1,164 @@
+//! Apache Avro encoding of sensor readings.
+//!
+//! The schema is inferred from a reading's JSON shape: objects become nested
+//! records, numbers `long` or `double`, and arrays are carried as JSON text
+//! (their element types aren't known up front, e.g. an empty geofence list).
+//! Every field is a `["null", T]` union defaulting to null, so optional
+//! measurements and fields missing from a later reading still encode. A
+//! reading that brings a new field, or a new type for a known one, widens the
+//! schema: the field or type is added to the union, and since every field
+//! defaults to null, data written with the older schema still resolves.
+
+use apache_avro::{types::Value as AvroValue, Schema, Writer};
+use serde_json::{json, Value};
+
+/// Avro names must match `[A-Za-z_][A-Za-z0-9_]*`
+fn avro_name(s: &str) -> String {
+    let mut name: String = s
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        name.insert(0, '_');
+    }
+    name
+}
+
+fn infer_type(value: &Value, record_name: &str) -> Value {
+    match value {
+        Value::Bool(_) => json!("boolean"),
+        Value::Number(n) if n.is_f64() => json!("double"),
+        Value::Number(_) => json!("long"),
+        Value::Object(obj) => json!({
+            "type": "record",
+            "name": record_name,
+            "fields": obj
+                .iter()
+                .map(|(k, v)| {
+                    let name = avro_name(k);
+                    let ty = if v.is_null() {
+                        // Type unknown from this reading; accept any scalar later
+                        json!(["null", "long", "double", "boolean", "string"])
+                    } else {
+                        json!(["null", infer_type(v, &format!("{}_{}", record_name, name))])
+                    };
+                    json!({ "name": name, "type": ty, "default": null })
+                })
+                .collect::<Vec<_>>()
+        }),
+        // Strings and arrays (as JSON text)
+        _ => json!("string"),
+    }
+}
+
+/// Avro schema (`.avsc` JSON) for a sensor, inferred from one of its readings
+pub fn schema_for(sensor: &str, data: &Value) -> Value {
+    let mut schema = infer_type(data, "SensorReading");
+    schema["namespace"] = json!(format!("io.simmurator.{}", avro_name(sensor)));
+    schema["doc"] = json!(format!("Simmurator {} reading", sensor));
+    schema
+}
+
+/// Whether a union branch accepts the value (a `long` also fits a `double`)
+fn branch_accepts(branch: &Value, value: &Value) -> bool {
+    match value {
+        Value::Null => branch == "null",
+        Value::Bool(_) => branch == "boolean",
+        Value::Number(n) if n.is_f64() => branch == "double",
+        Value::Number(_) => branch == "long" || branch == "double",
+        Value::Object(_) => branch["type"] == "record",
+        _ => branch == "string",
+    }
+}
+
+/// Add any fields of `obj` the record lacks, and widen the ones it has
+fn widen_record(record: &mut Value, obj: &serde_json::Map<String, Value>) -> bool {
+    let record_name = record["name"].as_str().unwrap_or_default().to_string();
+    let Some(fields) = record["fields"].as_array_mut() else {
+        return false;
+    };
+    let mut changed = false;
+    for (k, v) in obj {
+        let name = avro_name(k);
+        match fields.iter_mut().find(|f| f["name"] == name.as_str()) {
+            Some(field) => changed |= widen_union(&mut field["type"], v, &format!("{}_{}", record_name, name)),
+            None => {
+                let ty = if v.is_null() {
+                    json!(["null", "long", "double", "boolean", "string"])
+                } else {
+                    json!(["null", infer_type(v, &format!("{}_{}", record_name, name))])
+                };
+                fields.push(json!({ "name": name, "type": ty, "default": null }));
+                changed = true;
+            }
+        }
+    }
+    changed
+}
+
+fn widen_union(union: &mut Value, value: &Value, record_name: &str) -> bool {
+    let Some(branches) = union.as_array_mut() else {
+        return false;
+    };
+    match branches.iter_mut().find(|b| branch_accepts(b, value)) {
+        Some(record) if record["type"] == "record" => widen_record(record, value.as_object().unwrap()),
+        Some(_) => false,
+        None => {
+            branches.push(infer_type(value, record_name));
+            true
+        }
+    }
+}
+
+/// Widen `schema` so it also describes `data`; returns whether it changed
+pub fn widen(schema: &mut Value, data: &Value) -> bool {
+    data.as_object().is_some_and(|obj| widen_record(schema, obj))
+}
+
+/// Rename keys to their Avro names and turn arrays into JSON text, matching
+/// what `schema_for` declares
+fn normalize(value: &Value) -> Value {
+    match value {
+        Value::Object(obj) => Value::Object(obj.iter().map(|(k, v)| (avro_name(k), normalize(v))).collect()),
+        Value::Array(_) => json!(value.to_string()),
+        _ => value.clone(),
+    }
+}
+
+/// Encode a reading as an Avro object container file with the schema embedded
+pub fn encode(schema: &Value, data: &Value) -> Result<Vec<u8>, String> {
+    let schema = Schema::parse(schema).map_err(|e| e.to_string())?;
+    let value = AvroValue::try_from(normalize(data))
+        .and_then(|v| v.resolve(&schema))
+        .map_err(|e| e.to_string())?;
+
+    let mut writer = Writer::new(&schema, Vec::new()).map_err(|e| e.to_string())?;
+    writer.append_value(value).map_err(|e| e.to_string())?;
+    writer.into_inner().map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn later_readings_widen_the_schema() {
+        let first = json!({ "dataQuality": "good", "value": { "level": 2, "label": "ok" } });
+        let mut schema = schema_for("level-sensor", &first);
+        let later = json!({
+            "dataQuality": "good",
+            "value": { "level": 2.5, "label": null, "alarms": { "high": true } },
+            "drivenBy": { "source": "control-valve" }
+        });
+        assert!(encode(&schema, &later).is_err());
+
+        assert!(widen(&mut schema, &later));
+        assert!(!widen(&mut schema, &later), "widening is idempotent");
+        encode(&schema, &later).unwrap();
+        encode(&schema, &first).unwrap();
+
+        let value = schema["fields"].as_array().unwrap().iter().find(|f| f["name"] == "value").unwrap();
+        let level = value["type"][1]["fields"].as_array().unwrap().iter().find(|f| f["name"] == "level").unwrap();
+        assert_eq!(level["type"], json!(["null", "long", "double"]));
+    }
+}
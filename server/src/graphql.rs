@@ -0,0 +1,208 @@
+//! GraphQL API over the same readings the REST routes serve.
+//!
+//! `sensor(key)` / `sensors(keys)` and the `sensorStream(key, interval)`
+//! subscription all go through `generate_sensor_data`, so faults, injections,
+//! re-ranging and the rest apply exactly as they do for REST. A reading's
+//! `value` object differs per sensor and is exposed as JSON; pass
+//! `fields: [...]` to select just the members a dashboard needs.
+
+use crate::{generate_sensor_data, is_sensor_key, sensor_keys, SharedState};
+use async_graphql::{Context, EmptyMutation, Error, Json, Object, Result, Schema, SimpleObject, Subscription};
+use futures_util::{Stream, StreamExt};
+use serde_json::Value;
+use std::time::Duration;
+use tokio_stream::wrappers::IntervalStream;
+
+pub type SensorSchema = Schema<QueryRoot, EmptyMutation, SubscriptionRoot>;
+
+pub fn schema(state: SharedState) -> SensorSchema {
+    Schema::build(QueryRoot, EmptyMutation, SubscriptionRoot).data(state).finish()
+}
+
+fn str_field(v: &Value) -> String {
+    v.as_str().unwrap_or_default().to_string()
+}
+
+#[derive(SimpleObject)]
+struct OpcUaNode {
+    node_id: String,
+    browse_name: String,
+    display_name: String,
+    namespace_index: u16,
+}
+
+#[derive(SimpleObject)]
+struct Isa95Equipment {
+    site: String,
+    area: String,
+    line: String,
+    unit: String,
+    equipment: String,
+}
+
+#[derive(SimpleObject)]
+struct SparkplugTopic {
+    version: String,
+    group_id: String,
+    message_type: String,
+    edge_node_id: String,
+    device_id: String,
+}
+
+#[derive(SimpleObject)]
+struct UcumUnit {
+    code: String,
+    display: String,
+}
+
+/// One reading (the JSON form of `UnifiedSensorData`)
+pub struct SensorReading {
+    sensor: String,
+    data: Value,
+}
+
+#[Object]
+impl SensorReading {
+    async fn sensor(&self) -> &str {
+        &self.sensor
+    }
+
+    async fn opc_ua(&self) -> OpcUaNode {
+        let node = &self.data["opcUa"];
+        OpcUaNode {
+            node_id: str_field(&node["nodeId"]),
+            browse_name: str_field(&node["browseName"]),
+            display_name: str_field(&node["displayName"]),
+            namespace_index: node["namespaceIndex"].as_u64().unwrap_or_default() as u16,
+        }
+    }
+
+    async fn equipment_hierarchy(&self) -> Isa95Equipment {
+        let eq = &self.data["equipmentHierarchy"];
+        Isa95Equipment {
+            site: str_field(&eq["site"]),
+            area: str_field(&eq["area"]),
+            line: str_field(&eq["line"]),
+            unit: str_field(&eq["unit"]),
+            equipment: str_field(&eq["equipment"]),
+        }
+    }
+
+    async fn sparkplug_topic(&self) -> SparkplugTopic {
+        let topic = &self.data["sparkplugTopic"];
+        SparkplugTopic {
+            version: str_field(&topic["version"]),
+            group_id: str_field(&topic["groupId"]),
+            message_type: str_field(&topic["messageType"]),
+            edge_node_id: str_field(&topic["edgeNodeId"]),
+            device_id: str_field(&topic["deviceId"]),
+        }
+    }
+
+    async fn source_timestamp(&self) -> String {
+        str_field(&self.data["sourceTimestamp"])
+    }
+
+    async fn server_timestamp(&self) -> String {
+        str_field(&self.data["serverTimestamp"])
+    }
+
+    /// The sensor-specific measurements, optionally narrowed to `fields`
+    async fn value(&self, fields: Option<Vec<String>>) -> Json<Value> {
+        let value = &self.data["value"];
+        match (fields, value.as_object()) {
+            (Some(fields), Some(obj)) => Json(Value::Object(
+                obj.iter().filter(|(k, _)| fields.contains(k)).map(|(k, v)| (k.clone(), v.clone())).collect(),
+            )),
+            _ => Json(value.clone()),
+        }
+    }
+
+    async fn data_quality(&self) -> String {
+        str_field(&self.data["dataQuality"])
+    }
+
+    async fn opc_ua_status_code(&self) -> u32 {
+        crate::opcua_status_code_value(&self.data["opcUaStatusCode"])
+    }
+
+    async fn opc_ua_status_name(&self) -> String {
+        str_field(&self.data["opcUaStatusName"])
+    }
+
+    async fn unit(&self) -> UcumUnit {
+        UcumUnit {
+            code: str_field(&self.data["unit"]["code"]),
+            display: str_field(&self.data["unit"]["display"]),
+        }
+    }
+
+    async fn sensor_type(&self) -> String {
+        str_field(&self.data["sensorType"])
+    }
+
+    async fn description(&self) -> String {
+        str_field(&self.data["description"])
+    }
+
+    async fn properties(&self) -> Json<Value> {
+        Json(self.data["properties"].clone())
+    }
+
+    /// The whole reading as JSON, including post-processing fields (peak
+    /// hold, stale cache, faults, ...) not modelled above
+    async fn raw(&self) -> Json<Value> {
+        Json(self.data.clone())
+    }
+}
+
+fn read(state: &SharedState, key: &str) -> Result<SensorReading> {
+    if !is_sensor_key(state, key) {
+        return Err(Error::new(format!("Sensor not found: {}", key)));
+    }
+    generate_sensor_data(state, key)
+        .map(|data| SensorReading { sensor: key.to_string(), data })
+        .ok_or_else(|| Error::new(format!("Sensor not responding: {}", key)))
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// A fresh reading of one sensor
+    async fn sensor(&self, ctx: &Context<'_>, key: String) -> Result<SensorReading> {
+        read(ctx.data_unchecked::<SharedState>(), &key)
+    }
+
+    /// Fresh readings of the given sensors, or of every sensor when `keys` is omitted
+    async fn sensors(&self, ctx: &Context<'_>, keys: Option<Vec<String>>) -> Result<Vec<SensorReading>> {
+        let state = ctx.data_unchecked::<SharedState>();
+        let keys = keys.unwrap_or_else(|| sensor_keys(state).map(str::to_string).collect());
+        keys.iter().map(|key| read(state, key)).collect()
+    }
+
+    /// Names of every simulated sensor
+    async fn available_sensors(&self, ctx: &Context<'_>) -> Vec<&'static str> {
+        sensor_keys(ctx.data_unchecked::<SharedState>()).collect()
+    }
+}
+
+pub struct SubscriptionRoot;
+
+#[Subscription]
+impl SubscriptionRoot {
+    /// A reading of `key` every `interval` ms (100..=60000, default 1000)
+    async fn sensor_stream(
+        &self,
+        ctx: &Context<'_>,
+        key: String,
+        interval: Option<u64>,
+    ) -> Result<impl Stream<Item = Result<SensorReading>>> {
+        let state = ctx.data_unchecked::<SharedState>().clone();
+        if !is_sensor_key(&state, &key) {
+            return Err(Error::new(format!("Sensor not found: {}", key)));
+        }
+        let period = Duration::from_millis(interval.unwrap_or(1000).clamp(100, 60000));
+        Ok(IntervalStream::new(tokio::time::interval(period)).map(move |_| read(&state, &key)))
+    }
+}
@@ -0,0 +1,81 @@
+//! InfluxDB line protocol rendering of sensor readings.
+//!
+//! One line per reading: the measurement is the reading's `sensorType`, tags
+//! come from the ISA-95 equipment hierarchy, and every number in the `value`
+//! object becomes a field (nested objects flatten to dotted names such as
+//! `iso10816Limits.good`). Fields are always written as floats, so a value
+//! that happens to be whole in one reading can't conflict with the field's
+//! type from an earlier write.
+
+use serde_json::Value;
+
+/// Escape a measurement name (commas and spaces)
+fn escape_measurement(s: &str) -> String {
+    s.replace(',', "\\,").replace(' ', "\\ ")
+}
+
+/// Escape a tag key, tag value or field key (commas, equals signs and spaces)
+fn escape_key(s: &str) -> String {
+    s.replace(',', "\\,").replace('=', "\\=").replace(' ', "\\ ")
+}
+
+fn collect_fields(value: &Value, prefix: &str, out: &mut Vec<(String, f64)>) {
+    let Some(obj) = value.as_object() else {
+        return;
+    };
+    for (k, v) in obj {
+        let name = if prefix.is_empty() { k.clone() } else { format!("{}.{}", prefix, k) };
+        match v {
+            Value::Number(n) => {
+                if let Some(f) = n.as_f64().filter(|f| f.is_finite()) {
+                    out.push((name, f));
+                }
+            }
+            Value::Object(_) => collect_fields(v, &name, out),
+            _ => {}
+        }
+    }
+}
+
+/// One reading (the JSON form of `UnifiedSensorData`) as a line, or None if
+/// it has no measurement name or no numeric fields (e.g. an errored entry)
+pub fn line(data: &Value) -> Option<String> {
+    let measurement = data["sensorType"].as_str().filter(|s| !s.is_empty())?;
+    let mut fields = Vec::new();
+    collect_fields(&data["value"], "", &mut fields);
+    if fields.is_empty() {
+        return None;
+    }
+
+    let mut out = escape_measurement(measurement);
+    for tag in ["site", "area", "line", "equipment"] {
+        if let Some(v) = data["equipmentHierarchy"][tag].as_str().filter(|v| !v.is_empty()) {
+            out.push_str(&format!(",{}={}", tag, escape_key(v)));
+        }
+    }
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|(name, v)| format!("{}={}", escape_key(name), v))
+        .collect();
+    out.push(' ');
+    out.push_str(&fields.join(","));
+
+    let timestamp = data["sourceTimestamp"]
+        .as_str()
+        .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+        .and_then(|t| t.timestamp_nanos_opt());
+    if let Some(ns) = timestamp {
+        out.push_str(&format!(" {}", ns));
+    }
+    Some(out)
+}
+
+/// Several readings, one line each (readings without fields are skipped)
+pub fn lines<'a>(readings: impl IntoIterator<Item = &'a Value>) -> String {
+    let mut out = String::new();
+    for line in readings.into_iter().filter_map(line) {
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out
+}
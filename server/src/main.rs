@@ -1,29 +1,48 @@
 use axum::{
     extract::{
         ws::{Message, WebSocket, WebSocketUpgrade},
-        ConnectInfo, Path, Query, State,
+        ConnectInfo, Path, Query, RawQuery, State,
     },
     response::{
         sse::{Event, Sse},
         IntoResponse, Response,
     },
-    routing::get,
+    routing::{delete, get, post},
     Json, Router,
 };
 use chrono::Utc;
 use futures_util::stream::StreamExt;
-use rand::Rng;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
     convert::Infallible,
     net::SocketAddr,
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
     time::Duration,
 };
 use tokio::sync::broadcast;
-use tokio_stream::wrappers::BroadcastStream;
-use tower_http::cors::{Any, CorsLayer};
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream, IntervalStream};
+use tower_http::cors::{AllowHeaders, AllowMethods, Any, CorsLayer};
+
+mod avro;
+mod graphql;
+mod influx;
+mod modbus;
+mod openapi;
+mod proto;
+mod sensor_config;
+mod sparkplug;
+mod ua_server;
+mod units;
+mod xml;
+
+#[cfg(test)]
+mod tests;
 
 // ──────────────────────────────────────────────
 // Models
@@ -33,14 +52,39 @@ use tower_http::cors::{Any, CorsLayer};
 #[serde(rename_all = "camelCase")]
 struct AccessLogEntry {
     id: usize,
+    /// SSE `id` of the entry's access event. Unlike `id` it survives a clear
+    /// of the log, so a reconnecting client's `Last-Event-ID` never points
+    /// into a restarted sequence.
+    #[serde(skip)]
+    event_id: u64,
     timestamp: String,
     ip: String,
     user_agent: String,
     endpoint: String,
     method: String,
     status_code: u16,
+    /// Whole milliseconds, as reported in the log
     response_time: u128,
+    /// The same duration in microseconds, so stats can average sub-millisecond requests
+    #[serde(skip)]
+    response_time_us: u64,
     device_id: Option<String>,
+    /// Id of the API key the request authenticated with (never the secret)
+    api_key_id: Option<String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct AlarmLogEntry {
+    id: usize,
+    timestamp: String,
+    sensor: String,
+    kind: String,
+    event: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    zone: Option<String>,
+    message: String,
+    details: serde_json::Value,
 }
 
 #[derive(Serialize, Clone, Debug)]
@@ -49,6 +93,57 @@ struct AccessLogEntry {
 enum SSEEvent {
     Connected { message: String },
     Access(AccessLogEntry),
+    Alarm(AlarmLogEntry),
+    Sensor {
+        sensor: String,
+        data: serde_json::Value,
+        timestamp: String,
+        sequence: u64,
+    },
+    SensorBatch {
+        readings: HashMap<String, serde_json::Value>,
+        timestamp: String,
+        sequence: u64,
+    },
+    /// The client fell behind the broadcast channel and `skipped` events were dropped
+    Lagged { skipped: u64 },
+    /// Last frame before the server closes the stream on shutdown
+    Shutdown { message: String },
+}
+
+/// Server-side subscription for clients that can't use WebSockets; consumed
+/// over SSE at `/api/v1/subscriptions/:id/events`
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Subscription {
+    id: String,
+    sensors: Vec<String>,
+    interval: u64,
+    /// Effective interval of each subscribed sensor
+    intervals: BTreeMap<String, u64>,
+    batch: bool,
+    created_at: String,
+    sse_url: String,
+}
+
+#[derive(Deserialize)]
+struct CreateSubscriptionRequest {
+    sensors: Option<Vec<String>>,
+    interval: Option<u64>,
+    /// Per-sensor interval overrides (ms), as in the WebSocket Subscribe action
+    intervals: Option<HashMap<String, u64>>,
+    #[serde(default)]
+    batch: bool,
+}
+
+/// WebSocket frame encoding: JSON text frames (default) or MessagePack
+/// binary frames, chosen with `/ws/sensors?encoding=` or a `setEncoding` action
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum WsEncoding {
+    #[default]
+    Json,
+    Msgpack,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -58,12 +153,23 @@ enum WSAction {
     Subscribe {
         sensors: Option<Vec<String>>,
         interval: Option<u64>,
+        /// Per-sensor interval overrides (ms); like `interval`, each is clamped
+        /// to 100–60000. Sensors without one follow `interval`.
+        intervals: Option<HashMap<String, u64>>,
+        /// Send up to this many buffered history samples per sensor before live data
+        replay: Option<usize>,
+        /// Push a `heartbeat` frame every this many ms (100–60000, 0 stops it)
+        heartbeat: Option<u64>,
     },
     Unsubscribe {
         sensors: Option<Vec<String>>,
     },
     List,
     Ping,
+    /// Switch the frames the server sends; takes effect from the reply on
+    SetEncoding {
+        encoding: WsEncoding,
+    },
 }
 
 #[derive(Serialize, Clone, Debug)]
@@ -73,12 +179,18 @@ enum WSMessage {
     Welcome {
         available_sensors: Vec<String>,
         message: String,
+        encoding: WsEncoding,
     },
     Subscribed {
         sensors: Vec<String>,
         interval: u64,
+        /// Effective interval of each subscribed sensor
+        intervals: BTreeMap<String, u64>,
         #[serde(skip_serializing_if = "Option::is_none")]
         unknown: Option<Vec<String>>,
+        /// Heartbeat period in ms, when one is running
+        #[serde(skip_serializing_if = "Option::is_none")]
+        heartbeat: Option<u64>,
     },
     Unsubscribed {
         sensors: Vec<String>,
@@ -88,6 +200,10 @@ enum WSMessage {
         sensor: String,
         data: serde_json::Value,
         timestamp: String,
+        sequence: u64,
+        /// Set on history samples sent by a Subscribe `replay`
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        replay: bool,
     },
     SensorsList {
         sensors: Vec<String>,
@@ -95,18 +211,75 @@ enum WSMessage {
     Pong {
         timestamp: String,
     },
-    #[allow(dead_code)]
+    EncodingSet {
+        encoding: WsEncoding,
+    },
+    /// Server-initiated liveness frame; `seq` counts up from 1 per connection
+    /// so a gap means frames were lost
+    Heartbeat {
+        seq: u64,
+        timestamp: String,
+    },
+    /// Last frame before the server closes the socket on shutdown
+    Shutdown {
+        message: String,
+        timestamp: String,
+    },
     Error {
         message: String,
     },
 }
 
+// ──────────────────────────────────────────────
+// Config
+// ──────────────────────────────────────────────
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+/// Read a probability from env, clamped to [0, 1] so it is always safe for `gen_bool`
+fn env_rate(name: &str, default: f64) -> f64 {
+    let rate: f64 = env_or(name, default);
+    if rate.is_finite() { rate.clamp(0.0, 1.0) } else { default }
+}
+
+/// Fault-injection rates for sensor reads: `ERROR_RATE` fails a read with
+/// `ERROR_STATUS` (503 with a Retry-After by default; 500 for clients built
+/// against the old behaviour), `SLOW_RATE` stretches its latency. Adjustable
+/// at runtime via `PATCH /api/v1/config`.
+#[derive(Serialize, Clone, Copy, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ChaosConfig {
+    error_rate: f64,
+    slow_rate: f64,
+    error_status: u16,
+}
+
+impl ChaosConfig {
+    fn from_env() -> Self {
+        let error_status: u16 = env_or("ERROR_STATUS", 503);
+        Self {
+            error_rate: env_rate("ERROR_RATE", 0.05),
+            slow_rate: env_rate("SLOW_RATE", 0.1),
+            error_status: if (500..=599).contains(&error_status) { error_status } else { 503 },
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ConfigPatch {
+    error_rate: Option<f64>,
+    slow_rate: Option<f64>,
+    error_status: Option<u16>,
+}
+
 // ──────────────────────────────────────────────
 // Sensor Simulators
 // ──────────────────────────────────────────────
 
-fn random_between(min: f64, max: f64) -> f64 {
-    let mut rng = rand::thread_rng();
+fn random_between(rng: &mut impl Rng, min: f64, max: f64) -> f64 {
     rng.gen_range(min..max)
 }
 
@@ -114,7 +287,8 @@ fn random_between(min: f64, max: f64) -> f64 {
 fn temp_to_dewpoint(rh: f64, temp: f64) -> f64 {
     let a = 17.625;
     let b = 243.04;
-    let alpha = (a * temp / (b + temp)).ln() + (rh / 100.0).ln();
+    // Magnus: saturated at 100 %RH (dew point == temperature), below it otherwise
+    let alpha = (rh.clamp(0.1, 100.0) / 100.0).ln() + a * temp / (b + temp);
     (b * alpha) / (a - alpha)
 }
 
@@ -128,12 +302,174 @@ fn calculate_aqi_pm25(pm25: f64) -> i32 {
     else { 300 + ((pm25 - 250.4) / 149.6 * 99.0) as i32 }
 }
 
+// ============================================
+// Stateful Random Walk (SensorState)
+// ============================================
+
+/// Operating band of each walked primary variable: (sensor, min, max)
+const WALK_SPECS: &[(&str, f64, f64)] = &[
+    ("temperature", 18.0, 32.0),
+    ("humidity", 25.0, 75.0),
+    ("oil-level", 15.0, 95.0),
+    ("oil-pressure", 15.0, 200.0),
+    ("air-quality", 5.0, 75.0),
+    ("pressure", 990.0, 1030.0),
+    ("vibration", 0.5, 12.0),
+    ("energy-meter", 5.0, 200.0),
+    ("amr", 500.0, 2500.0),
+    ("gas-detector", 0.0, 50.0),
+    ("ph-sensor", 4.0, 10.0),
+    ("level-sensor", 0.5, 11.5),
+    ("accelerometer", 0.02, 1.2),
+    ("sound-level", 40.0, 100.0),
+    ("wind", 0.0, 15.0),
+    // Not a sensor of its own: the wind sensor's vane, which wraps around
+    ("wind-direction", 0.0, 360.0),
+];
+
+/// Keys a configured sensor can't take: the built-in sensors and the walked
+/// variables, since both live in `sensor_states`
+fn taken_sensor_keys() -> Vec<&'static str> {
+    let mut keys = AVAILABLE_SENSORS.to_vec();
+    keys.extend(WALK_SPECS.iter().map(|&(key, _, _)| key).filter(|key| !AVAILABLE_SENSORS.contains(key)));
+    keys
+}
+
+/// Walks whose band is circular (an angle): a step past one end comes back
+/// in at the other instead of being clamped
+const WRAPPING_WALKS: &[&str] = &["wind-direction"];
+
+/// Share of the previous step's movement carried into the next, so trends
+/// persist for a while instead of reversing every sample
+const WALK_MOMENTUM: f64 = 0.6;
+
+/// Last value of a sensor's primary variable and how it moves: each step
+/// adds N(0, sigma) plus momentum, clamped to the operating band
+#[derive(Clone, Copy)]
+struct SensorState {
+    value: f64,
+    velocity: f64,
+    sigma: f64,
+    min: f64,
+    max: f64,
+    wraps: bool,
+}
+
+impl SensorState {
+    fn step(&mut self, rng: &mut impl Rng) -> f64 {
+        self.velocity = WALK_MOMENTUM * self.velocity + self.sigma * standard_normal(rng);
+        let next = self.value + self.velocity;
+        if self.wraps {
+            self.value = self.min + (next - self.min).rem_euclid(self.max - self.min);
+            return self.value;
+        }
+        if !(self.min..=self.max).contains(&next) {
+            // Hitting a bound kills the momentum rather than pinning the value there
+            self.velocity = 0.0;
+        }
+        self.value = next.clamp(self.min, self.max);
+        self.value
+    }
+
+    /// Start over from rest at a random point of the band, as a newly
+    /// installed device does
+    fn reseed(&mut self, rng: &mut impl Rng) {
+        self.value = random_between(rng, self.min, self.max);
+        self.velocity = 0.0;
+    }
+}
+
+/// Box-Muller transform
+fn standard_normal(rng: &mut impl Rng) -> f64 {
+    let u1: f64 = rng.gen_range(f64::EPSILON..1.0);
+    let u2: f64 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+/// Seed every walked sensor at a random point of its band. Sigma defaults to
+/// 1% of the band; `SIM_WALK_SIGMA` (`sensor=sigma,...`) overrides it.
+fn seed_sensor_states(spec: &str, rng: &mut impl Rng) -> Result<HashMap<String, SensorState>, String> {
+    let mut sigmas = HashMap::new();
+    for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (sensor, sigma) = entry.split_once('=').ok_or_else(|| format!("'{}' is not sensor=sigma", entry))?;
+        let sensor = sensor.trim();
+        if !WALK_SPECS.iter().any(|&(key, _, _)| key == sensor) {
+            return Err(format!("'{}' has no random-walk variable", sensor));
+        }
+        let sigma: f64 = sigma.trim().parse().map_err(|_| format!("'{}' is not a number in '{}'", sigma, entry))?;
+        if !sigma.is_finite() || sigma < 0.0 {
+            return Err(format!("sigma must be non-negative in '{}'", entry));
+        }
+        sigmas.insert(sensor, sigma);
+    }
+
+    Ok(WALK_SPECS
+        .iter()
+        .map(|&(key, min, max)| {
+            let state = SensorState {
+                value: random_between(rng, min, max),
+                velocity: 0.0,
+                sigma: sigmas.get(key).copied().unwrap_or((max - min) * 0.01),
+                min,
+                max,
+                wraps: WRAPPING_WALKS.contains(&key),
+            };
+            (key.to_string(), state)
+        })
+        .collect())
+}
+
+/// Advance a sensor's walk one step and return the new value, with the
+/// sensor's time-of-day cycle (if any) laid over it. The cycle can't push
+/// the value out of the walk's band.
+fn walk(state: &AppState, rng: &mut impl Rng, key: &str) -> f64 {
+    let mut states = state.sensor_states.lock().unwrap();
+    let Some(walk) = states.get_mut(key) else {
+        return 0.0;
+    };
+    let value = walk.step(rng);
+    let offset = diurnal_offset(key, local_hour(state.clock.now(), state.tz_offset_hours));
+    if offset == 0.0 {
+        return value;
+    }
+    (value + offset).clamp(walk.min, walk.max)
+}
+
+// ============================================
+// Diurnal (time-of-day) Cycles
+// ============================================
+
+/// (sensor, amplitude, local hour of the peak, cycles per day). Outdoor air
+/// is warmest mid-afternoon and most humid just before dawn, particulates
+/// peak with the morning rush, barometric pressure follows the atmospheric
+/// tide with twice-daily highs around 10:00 and 22:00, and wind picks up
+/// with afternoon convection.
+const DIURNAL_SPECS: &[(&str, f64, f64, f64)] = &[
+    ("temperature", 4.0, 15.0, 1.0),
+    ("humidity", 12.0, 5.0, 1.0),
+    ("air-quality", 10.0, 8.0, 1.0),
+    ("pressure", 1.2, 10.0, 2.0),
+    ("wind", 1.5, 14.0, 1.0),
+];
+
+/// Offset from the walked value at a local hour: a cosine of the sensor's
+/// amplitude peaking at its peak hour, zero for sensors without a cycle
+fn diurnal_offset(key: &str, hour: f64) -> f64 {
+    DIURNAL_SPECS
+        .iter()
+        .find(|spec| spec.0 == key)
+        .map(|&(_, amplitude, peak_hour, cycles)| {
+            amplitude * (std::f64::consts::TAU * cycles * (hour - peak_hour) / 24.0).cos()
+        })
+        .unwrap_or(0.0)
+}
+
 // ============================================
 // ISA-95 Equipment Hierarchy + OPC UA Standards
 // ============================================
 
 /// ISA-95 Equipment Hierarchy Level
-#[derive(Serialize, Clone, Debug)]
+#[derive(Serialize, JsonSchema, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 struct Isa95Equipment {
     site: String,
@@ -144,7 +480,7 @@ struct Isa95Equipment {
 }
 
 /// OPC UA Node Information
-#[derive(Serialize, Clone, Debug)]
+#[derive(Serialize, JsonSchema, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 struct OpcUaNode {
     node_id: String,
@@ -154,7 +490,7 @@ struct OpcUaNode {
 }
 
 /// MQTT Sparkplug B Topic Structure
-#[derive(Serialize, Clone, Debug)]
+#[derive(Serialize, JsonSchema, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 struct SparkplugTopic {
     version: String,
@@ -165,7 +501,7 @@ struct SparkplugTopic {
 }
 
 /// UCUM Unit Codes (Unified Code for Units of Measure)
-#[derive(Serialize, Clone, Debug)]
+#[derive(Serialize, JsonSchema, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 struct UcumUnit {
     code: String,
@@ -173,7 +509,7 @@ struct UcumUnit {
 }
 
 /// Data Quality Status (OPC UA Standard)
-#[derive(Serialize, Clone, Debug)]
+#[derive(Serialize, JsonSchema, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 enum DataQuality {
     Good,
@@ -182,20 +518,69 @@ enum DataQuality {
     Bad,
 }
 
-/// OPC UA Status Codes
-#[derive(Serialize, Clone, Debug)]
-#[serde(rename_all = "camelCase")]
-enum OpcUaStatusCode {
-    Good = 0x00000000,
-    GoodUncertain = 0x00000001,
-    UncertainInitialValue = 0x00200000,
-    BadSensorFailure = 0x80040000,
-    BadCommunicationError = 0x80050000,
-    BadOutOfService = 0x80080000,
+/// Declares `OpcUaStatusCode` from one table of variant, code and name, so
+/// `name()` and `ALL` (which the JSON Schema lists) can't miss a variant
+macro_rules! opcua_status_codes {
+    ($($(#[$attr:meta])* $variant:ident = $code:literal => $name:literal,)*) => {
+        /// OPC UA Status Codes. Serializes as the numeric code OPC UA clients
+        /// expect; `name()` gives the readable form.
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        #[repr(u32)]
+        enum OpcUaStatusCode {
+            $($(#[$attr])* $variant = $code,)*
+        }
+
+        impl OpcUaStatusCode {
+            const ALL: &'static [Self] = &[$(Self::$variant),*];
+
+            fn name(self) -> &'static str {
+                match self {
+                    $(Self::$variant => $name,)*
+                }
+            }
+        }
+    };
 }
 
-/// Unified Sensor Data Structure (ISA-95 + OPC UA + Sparkplug B)
-#[derive(Serialize, Clone, Debug)]
+opcua_status_codes! {
+    Good = 0x00000000 => "good",
+    GoodUncertain = 0x00000001 => "goodUncertain",
+    UncertainInitialValue = 0x00200000 => "uncertainInitialValue",
+    UncertainLastUsableValue = 0x40900000 => "uncertainLastUsableValue",
+    UncertainEngineeringUnitsExceeded = 0x40940000 => "uncertainEngineeringUnitsExceeded",
+    BadSensorFailure = 0x80040000 => "badSensorFailure",
+    BadCommunicationError = 0x80050000 => "badCommunicationError",
+    BadOutOfService = 0x80080000 => "badOutOfService",
+    /// The process value is beyond its alarm limit (latched detector alarm)
+    BadOutOfRange = 0x803C0000 => "badOutOfRange",
+}
+
+impl Serialize for OpcUaStatusCode {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(*self as u32)
+    }
+}
+
+impl JsonSchema for OpcUaStatusCode {
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        "OpcUaStatusCode".into()
+    }
+
+    fn json_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        let codes: Vec<u32> = Self::ALL.iter().map(|&code| code as u32).collect();
+        schemars::json_schema!({
+            "description": "Numeric OPC UA status code",
+            "type": "integer",
+            "format": "uint32",
+            "enum": codes
+        })
+    }
+}
+
+/// One reading (ISA-95 + OPC UA + Sparkplug B). Besides the fields listed,
+/// post-processing may add peak-hold (`peakMax`, `peakMin`, `peakSince`),
+/// stale-cache (`stale`, `age`), shelving and re-ranging fields.
+#[derive(Serialize, JsonSchema, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 struct UnifiedSensorData {
     // OPC UA Information Model
@@ -208,13 +593,17 @@ struct UnifiedSensorData {
     sparkplug_topic: SparkplugTopic,
     
     // Timestamps
+    #[schemars(extend("format" = "date-time"))]
     source_timestamp: String,
+    #[schemars(extend("format" = "date-time"))]
     server_timestamp: String,
     
-    // Value and Quality
+    // Value and Quality (the value object's shape varies by sensor)
+    #[schemars(with = "serde_json::Map<String, serde_json::Value>")]
     value: serde_json::Value,
     data_quality: DataQuality,
     opc_ua_status_code: OpcUaStatusCode,
+    opc_ua_status_name: &'static str,
     
     // UCUM Unit
     unit: UcumUnit,
@@ -224,6 +613,7 @@ struct UnifiedSensorData {
     description: String,
     
     // Additional Properties (sensor-specific)
+    #[schemars(with = "serde_json::Map<String, serde_json::Value>")]
     properties: serde_json::Value,
 }
 
@@ -269,6 +659,13 @@ fn get_ucum_unit(unit: &str) -> UcumUnit {
         "hPa" => UcumUnit { code: "hPa".to_string(), display: "hPa".to_string() },
         "Pa" => UcumUnit { code: "Pa".to_string(), display: "Pa".to_string() },
         "mm/s" => UcumUnit { code: "mm/s".to_string(), display: "mm/s".to_string() },
+        "dB(A)" => UcumUnit { code: "dB".to_string(), display: "dB(A)".to_string() },
+        "dB" => UcumUnit { code: "dB".to_string(), display: "dB".to_string() },
+        "m/s" => UcumUnit { code: "m/s".to_string(), display: "m/s".to_string() },
+        "°" => UcumUnit { code: "deg".to_string(), display: "°".to_string() },
+        "1" => UcumUnit { code: "1".to_string(), display: "".to_string() },
+        "%/m" => UcumUnit { code: "%/m".to_string(), display: "%/m".to_string() },
+        "g" => UcumUnit { code: "[g]".to_string(), display: "g".to_string() },
         "Hz" => UcumUnit { code: "Hz".to_string(), display: "Hz".to_string() },
         "kW" => UcumUnit { code: "kW".to_string(), display: "kW".to_string() },
         "kVA" => UcumUnit { code: "kVA".to_string(), display: "kVA".to_string() },
@@ -291,21 +688,41 @@ fn get_ucum_unit(unit: &str) -> UcumUnit {
         "%" => UcumUnit { code: "%".to_string(), display: "%".to_string() },
         "RPM" => UcumUnit { code: "rpm".to_string(), display: "RPM".to_string() },
         "dBm" => UcumUnit { code: "dBm".to_string(), display: "dBm".to_string() },
+        "km/h" => UcumUnit { code: "km/h".to_string(), display: "km/h".to_string() },
+        "µε" => UcumUnit { code: "u[strain]".to_string(), display: "µε".to_string() },
+        "persons" => UcumUnit { code: "{persons}".to_string(), display: "persons".to_string() },
         _ => UcumUnit { code: unit.to_string(), display: unit.to_string() },
     }
 }
 
-/// Generate Data Quality based on value and thresholds
+/// Generate Data Quality based on value and thresholds. With span = max - min:
+///
+/// - Good: inside [min, max] and more than 5% of the span from either bound
+/// - GoodUncertain: inside [min, max] but within 5% of the span of a bound
+/// - Uncertain: outside [min, max] by at most 10% of the span
+/// - Bad: further out than that
+///
+/// Margins are absolute, so they widen the same way for ranges that dip
+/// below zero (ORP, panel temperature, compressive strain).
 fn generate_data_quality(value: f64, min: f64, max: f64) -> DataQuality {
-    if value >= min && value <= max {
+    let span = (max - min).abs();
+    let (near, margin) = (span * 0.05, span * 0.1);
+    if (min + near..=max - near).contains(&value) {
         DataQuality::Good
-    } else if value >= min * 0.9 && value <= max * 1.1 {
+    } else if (min..=max).contains(&value) {
+        DataQuality::GoodUncertain
+    } else if (min - margin..=max + margin).contains(&value) {
         DataQuality::Uncertain
     } else {
         DataQuality::Bad
     }
 }
 
+/// Whether a reading's `dataQuality` is one of the usable good variants
+fn is_good_quality(data: &serde_json::Value) -> bool {
+    data["dataQuality"] == "good" || data["dataQuality"] == "goodUncertain"
+}
+
 /// Generate OPC UA Status Code
 fn generate_opcua_status_code(quality: &DataQuality) -> OpcUaStatusCode {
     match quality {
@@ -316,852 +733,8591 @@ fn generate_opcua_status_code(quality: &DataQuality) -> OpcUaStatusCode {
     }
 }
 
+/// Numeric OPC UA status code from the serialized `opcUaStatusCode` field
+fn opcua_status_code_value(v: &serde_json::Value) -> u32 {
+    v.as_u64().unwrap_or(0) as u32
+}
+
+/// Override a reading's status, keeping the code and its name in step
+fn set_opcua_status(data: &mut serde_json::Value, code: OpcUaStatusCode) {
+    data["opcUaStatusCode"] = serde_json::json!(code);
+    data["opcUaStatusName"] = serde_json::json!(code.name());
+}
+
+/// One metering point on the pipeline network. The serial and pipeline id
+/// belong to the station, so every AMR reading from it agrees on them.
+#[derive(Clone, Copy)]
+struct OilStation {
+    province: &'static str,
+    location: &'static str,
+    lat: f64,
+    lng: f64,
+    meter_serial: &'static str,
+    pipeline_id: &'static str,
+}
+
 // ข้อมูลสถานี pipeline และโรงกลั่นน้ำมันในประเทศไทย (อ้างอิงจากข้อมูลจริง)
 // แหล่งที่มา: PTT Pipeline Network, Thaioil, SPRC, โรงกลั่นในประเทศไทย
-const THAI_OIL_STATIONS: &[(&str, &str, f64, f64)] = &[
+const THAI_OIL_STATIONS: &[OilStation] = &[
     // กรุงเทพและปริมณฑล
-    ("กรุงเทพมหานคร", "Bangkok Pipeline Terminal", 13.7563, 100.5018),
-    ("ปทุมธานี", "Region 9 Pipeline Operations Center", 14.0208, 100.5250),
-    ("สมุทรปราการ", "Bang Pa-in Oil Pipeline Station", 13.5951, 100.6114),
-    
+    OilStation { province: "กรุงเทพมหานคร", location: "Bangkok Pipeline Terminal", lat: 13.7563, lng: 100.5018, meter_serial: "AMR-PIPE-2024-01", pipeline_id: "PIPE-BKK-01" },
+    OilStation { province: "ปทุมธานี", location: "Region 9 Pipeline Operations Center", lat: 14.0208, lng: 100.5250, meter_serial: "AMR-PIPE-2024-02", pipeline_id: "PIPE-BKK-01" },
+    OilStation { province: "สมุทรปราการ", location: "Bang Pa-in Oil Pipeline Station", lat: 13.5951, lng: 100.6114, meter_serial: "AMR-PIPE-2024-03", pipeline_id: "PIPE-BKK-01" },
+
     // ภาคตะวันออก - แหล่งอุตสาหกรรมหลัก
-    ("ระยอง", "Map Ta Phut Refinery Station", 12.6517, 101.1595),
-    ("ระยอง", "SPRC Map Ta Phut Terminal", 12.6833, 101.2378),
-    ("ชลบุรี", "Thaioil Sriracha Refinery", 13.1742, 100.9287),
-    ("ชลบุรี", "Sriracha Oil Terminal", 13.1166, 100.8666),
-    ("ชลบุรี", "Si Racha Pipeline Junction", 13.1339, 100.9500),
-    
+    OilStation { province: "ระยอง", location: "Map Ta Phut Refinery Station", lat: 12.6517, lng: 101.1595, meter_serial: "AMR-PIPE-2024-04", pipeline_id: "PIPE-EST-01" },
+    OilStation { province: "ระยอง", location: "SPRC Map Ta Phut Terminal", lat: 12.6833, lng: 101.2378, meter_serial: "AMR-PIPE-2024-05", pipeline_id: "PIPE-EST-01" },
+    OilStation { province: "ชลบุรี", location: "Thaioil Sriracha Refinery", lat: 13.1742, lng: 100.9287, meter_serial: "AMR-PIPE-2024-06", pipeline_id: "PIPE-EST-01" },
+    OilStation { province: "ชลบุรี", location: "Sriracha Oil Terminal", lat: 13.1166, lng: 100.8666, meter_serial: "AMR-PIPE-2024-07", pipeline_id: "PIPE-EST-01" },
+    OilStation { province: "ชลบุรี", location: "Si Racha Pipeline Junction", lat: 13.1339, lng: 100.9500, meter_serial: "AMR-PIPE-2024-08", pipeline_id: "PIPE-EST-01" },
+
     // ภาคกลาง
-    ("สระบุรี", "Saraburi Pipeline Station", 14.5289, 100.9103),
-    ("สระบุรี", "Sao Hai District Oil Terminal", 14.5500, 101.0500),
-    ("ลพบุรี", "Lopburi Pipeline Junction", 14.7995, 100.6537),
-    
+    OilStation { province: "สระบุรี", location: "Saraburi Pipeline Station", lat: 14.5289, lng: 100.9103, meter_serial: "AMR-PIPE-2024-09", pipeline_id: "PIPE-CEN-01" },
+    OilStation { province: "สระบุรี", location: "Sao Hai District Oil Terminal", lat: 14.5500, lng: 101.0500, meter_serial: "AMR-PIPE-2024-10", pipeline_id: "PIPE-CEN-01" },
+    OilStation { province: "ลพบุรี", location: "Lopburi Pipeline Junction", lat: 14.7995, lng: 100.6537, meter_serial: "AMR-PIPE-2024-11", pipeline_id: "PIPE-CEN-01" },
+
     // ภาคตะวันออกเฉียงเหนือ
-    ("ขอนแก่น", "Khon Kaen Distribution Terminal", 16.4419, 102.8356),
-    ("ขอนแก่น", "Ban Phai Pipeline Station", 16.0667, 102.7167),
-    ("นครราชสีมา", "Korat Oil Terminal", 14.9799, 102.0977),
-    ("อุดรธานี", "Udon Thani Pipeline Station", 17.4138, 102.7876),
-    
+    OilStation { province: "ขอนแก่น", location: "Khon Kaen Distribution Terminal", lat: 16.4419, lng: 102.8356, meter_serial: "AMR-PIPE-2024-12", pipeline_id: "PIPE-NE-01" },
+    OilStation { province: "ขอนแก่น", location: "Ban Phai Pipeline Station", lat: 16.0667, lng: 102.7167, meter_serial: "AMR-PIPE-2024-13", pipeline_id: "PIPE-NE-01" },
+    OilStation { province: "นครราชสีมา", location: "Korat Oil Terminal", lat: 14.9799, lng: 102.0977, meter_serial: "AMR-PIPE-2024-14", pipeline_id: "PIPE-NE-01" },
+    OilStation { province: "อุดรธานี", location: "Udon Thani Pipeline Station", lat: 17.4138, lng: 102.7876, meter_serial: "AMR-PIPE-2024-15", pipeline_id: "PIPE-NE-01" },
+
     // ภาคเหนือ
-    ("เชียงใหม่", "Chiang Mai Distribution Center", 18.7883, 98.9853),
-    ("ลำปาง", "Lampang Oil Terminal", 18.2859, 99.5128),
-    ("พิษณุโลก", "Phitsanulok Pipeline Station", 16.8295, 100.2615),
-    ("กำแพงเพชร", "Kamphaeng Phet Terminal", 16.4828, 99.5222),
-    
+    OilStation { province: "เชียงใหม่", location: "Chiang Mai Distribution Center", lat: 18.7883, lng: 98.9853, meter_serial: "AMR-PIPE-2024-16", pipeline_id: "PIPE-NTH-01" },
+    OilStation { province: "ลำปาง", location: "Lampang Oil Terminal", lat: 18.2859, lng: 99.5128, meter_serial: "AMR-PIPE-2024-17", pipeline_id: "PIPE-NTH-01" },
+    OilStation { province: "พิษณุโลก", location: "Phitsanulok Pipeline Station", lat: 16.8295, lng: 100.2615, meter_serial: "AMR-PIPE-2024-18", pipeline_id: "PIPE-NTH-01" },
+    OilStation { province: "กำแพงเพชร", location: "Kamphaeng Phet Terminal", lat: 16.4828, lng: 99.5222, meter_serial: "AMR-PIPE-2024-19", pipeline_id: "PIPE-NTH-01" },
+
     // ภาคใต้
-    ("สงขลา", "Songkhla Refinery Terminal", 7.1898, 100.5954),
-    ("สุราษฎร์ธานี", "Surat Thani Distribution", 9.1347, 99.3331),
-    ("ภูเก็ต", "Phuket Oil Terminal", 7.8804, 98.3923),
-    
+    OilStation { province: "สงขลา", location: "Songkhla Refinery Terminal", lat: 7.1898, lng: 100.5954, meter_serial: "AMR-PIPE-2024-20", pipeline_id: "PIPE-STH-01" },
+    OilStation { province: "สุราษฎร์ธานี", location: "Surat Thani Distribution", lat: 9.1347, lng: 99.3331, meter_serial: "AMR-PIPE-2024-21", pipeline_id: "PIPE-STH-01" },
+    OilStation { province: "ภูเก็ต", location: "Phuket Oil Terminal", lat: 7.8804, lng: 98.3923, meter_serial: "AMR-PIPE-2024-22", pipeline_id: "PIPE-STH-01" },
+
     // ภาคตะวันตก
-    ("สมุทรสาคร", "Mahachai Pipeline Station", 13.5475, 100.2744),
-    ("กาญจนบุรี", "Kanchanaburi Terminal", 14.0228, 99.5328),
-    
+    OilStation { province: "สมุทรสาคร", location: "Mahachai Pipeline Station", lat: 13.5475, lng: 100.2744, meter_serial: "AMR-PIPE-2024-23", pipeline_id: "PIPE-WST-01" },
+    OilStation { province: "กาญจนบุรี", location: "Kanchanaburi Terminal", lat: 14.0228, lng: 99.5328, meter_serial: "AMR-PIPE-2024-24", pipeline_id: "PIPE-WST-01" },
+
     // ภาคตะวันออกเฉียงเหนือตอนล่าง
-    ("นครสวรรค์", "Nakhon Sawan Junction", 15.6930, 100.1225),
-    ("อุบลราชธานี", "Ubon Ratchathani Station", 15.2287, 104.8564),
-    ("บุรีรัมย์", "Buriram Pipeline Terminal", 14.9930, 103.1029),
+    OilStation { province: "นครสวรรค์", location: "Nakhon Sawan Junction", lat: 15.6930, lng: 100.1225, meter_serial: "AMR-PIPE-2024-25", pipeline_id: "PIPE-NE-02" },
+    OilStation { province: "อุบลราชธานี", location: "Ubon Ratchathani Station", lat: 15.2287, lng: 104.8564, meter_serial: "AMR-PIPE-2024-26", pipeline_id: "PIPE-NE-02" },
+    OilStation { province: "บุรีรัมย์", location: "Buriram Pipeline Terminal", lat: 14.9930, lng: 103.1029, meter_serial: "AMR-PIPE-2024-27", pipeline_id: "PIPE-NE-02" },
 ];
 
-fn get_random_oil_station() -> (&'static str, &'static str, f64, f64) {
-    let mut rng = rand::thread_rng();
-    THAI_OIL_STATIONS[rng.gen_range(0..THAI_OIL_STATIONS.len())]
+impl OilStation {
+    /// URL-safe id derived from the location, e.g. `map-ta-phut-refinery-station`
+    fn id(&self) -> String {
+        self.location
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .map(str::to_ascii_lowercase)
+            .collect::<Vec<_>>()
+            .join("-")
+    }
 }
 
-fn generate_sensor_data(key: &str) -> Option<serde_json::Value> {
-    let mut rng = rand::thread_rng();
-    let server_ts = Utc::now().to_rfc3339();
-    
-    match key {
-        "temperature" => {
-            let temp = random_between(18.0, 32.0);
-            let quality = generate_data_quality(temp, 18.0, 27.0);
-            let status_code = generate_opcua_status_code(&quality);
-            let source_ts = Utc::now().to_rfc3339();
-            
-            let unified = UnifiedSensorData {
-                opc_ua: generate_opcua_node("TEMP-001", "Temperature Sensor"),
-                equipment_hierarchy: generate_isa95_hierarchy("TEMP-001", "Production-Line-1", "Factory-Floor-A"),
-                sparkplug_topic: generate_sparkplug_topic("Plant-01", "TEMP-001"),
-                source_timestamp: source_ts,
-                server_timestamp: server_ts,
-                value: serde_json::json!({
-                    "value": format!("{:.1}", temp).parse::<f64>().unwrap(),
-                    "minThreshold": 18.0,
-                    "maxThreshold": 27.0,
-                    "criticalHigh": 32.0,
-                    "criticalLow": 15.0
-                }),
-                data_quality: quality,
-                opc_ua_status_code: status_code,
-                unit: get_ucum_unit("°C"),
-                sensor_type: "temperature".to_string(),
-                description: "Industrial temperature sensor".to_string(),
-                properties: serde_json::json!({}),
-            };
-            Some(serde_json::to_value(unified).unwrap())
+/// One entry of `STATIONS_FILE`. `name` is the station itself and
+/// `location` (alias `province`) where it is; serial and pipeline id are
+/// numbered by position when left out.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StationRecord {
+    name: String,
+    #[serde(alias = "province")]
+    location: String,
+    lat: f64,
+    lng: f64,
+    meter_serial: Option<String>,
+    pipeline_id: Option<String>,
+}
+
+/// Split one CSV line, honouring double-quoted fields (`""` escapes a quote)
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            c => fields.last_mut().unwrap().push(c),
         }
-        "humidity" => {
-            let humidity = random_between(25.0, 75.0);
-            let quality = generate_data_quality(humidity, 40.0, 60.0);
-            let status_code = generate_opcua_status_code(&quality);
-            let source_ts = Utc::now().to_rfc3339();
-            
-            let unified = UnifiedSensorData {
-                opc_ua: generate_opcua_node("HUM-002", "Humidity Sensor"),
-                equipment_hierarchy: generate_isa95_hierarchy("HUM-002", "Server-Room-B", "IT-Infrastructure"),
-                sparkplug_topic: generate_sparkplug_topic("Plant-01", "HUM-002"),
-                source_timestamp: source_ts,
-                server_timestamp: server_ts.clone(),
-                value: serde_json::json!({
-                    "value": format!("{:.1}", humidity).parse::<f64>().unwrap(),
-                    "optimalMin": 40.0,
-                    "optimalMax": 60.0,
-                    "allowableMin": 20.0,
-                    "allowableMax": 80.0,
-                    "dewPoint": format!("{:.1}", temp_to_dewpoint(humidity, random_between(20.0, 30.0))).parse::<f64>().unwrap()
-                }),
-                data_quality: quality,
-                opc_ua_status_code: status_code,
-                unit: get_ucum_unit("%RH"),
-                sensor_type: "humidity".to_string(),
-                description: "Relative humidity sensor".to_string(),
-                properties: serde_json::json!({}),
-            };
-            Some(serde_json::to_value(unified).unwrap())
+    }
+    fields.into_iter().map(|f| f.trim().to_string()).collect()
+}
+
+/// CSV with a header row naming the `StationRecord` columns
+fn parse_station_csv(text: &str) -> Result<Vec<StationRecord>, String> {
+    let mut lines = text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty());
+    let header = lines.next().map(|(_, l)| split_csv_line(l)).ok_or("empty file")?;
+    let column = |name: &str| header.iter().position(|h| h == name);
+    let (Some(name), Some(location), Some(lat), Some(lng)) = (
+        column("name"),
+        column("location").or_else(|| column("province")),
+        column("lat"),
+        column("lng"),
+    ) else {
+        return Err("header must have name, location, lat and lng columns".to_string());
+    };
+    let (serial, pipeline) = (column("meterSerial"), column("pipelineId"));
+
+    lines
+        .map(|(n, line)| {
+            let fields = split_csv_line(line);
+            let get = |i: usize| fields.get(i).cloned().unwrap_or_default();
+            let optional = |i: Option<usize>| i.map(get).filter(|v| !v.is_empty());
+            let coordinate = |i: usize| get(i).parse::<f64>().map_err(|_| format!("line {}: '{}' is not a number", n + 1, get(i)));
+            Ok(StationRecord {
+                name: get(name),
+                location: get(location),
+                lat: coordinate(lat)?,
+                lng: coordinate(lng)?,
+                meter_serial: optional(serial),
+                pipeline_id: optional(pipeline),
+            })
+        })
+        .collect()
+}
+
+/// Read and validate `STATIONS_FILE` (`.csv`, otherwise a JSON array). The
+/// list lives for the whole run, so its strings are leaked to fit the
+/// `&'static` fields the built-in table uses.
+fn load_oil_stations(path: &str) -> Result<&'static [OilStation], String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path, e))?;
+    let records = if path.ends_with(".csv") {
+        parse_station_csv(&text)?
+    } else {
+        serde_json::from_str::<Vec<StationRecord>>(&text).map_err(|e| e.to_string())?
+    };
+    if records.is_empty() {
+        return Err("no stations listed".to_string());
+    }
+
+    let leak = |s: String| -> &'static str { Box::leak(s.into_boxed_str()) };
+    let mut stations = Vec::with_capacity(records.len());
+    for (i, r) in records.into_iter().enumerate() {
+        let label = if r.name.trim().is_empty() { format!("station {}", i + 1) } else { format!("'{}'", r.name) };
+        if r.name.trim().is_empty() || r.location.trim().is_empty() {
+            return Err(format!("{} needs a name and a location", label));
         }
-        "oil-level" => {
-            let capacity_liters = rng.gen_range(10000..50001);
-            let level_percent = random_between(15.0, 95.0);
-            let current_volume = (capacity_liters as f64 * level_percent / 100.0) as i32;
-            let quality = generate_data_quality(level_percent, 20.0, 90.0);
-            let status_code = generate_opcua_status_code(&quality);
-            let source_ts = Utc::now().to_rfc3339();
-            
-            let unified = UnifiedSensorData {
-                opc_ua: generate_opcua_node("OIL-003", "Oil Level Sensor"),
-                equipment_hierarchy: generate_isa95_hierarchy("OIL-003", "Storage-Tank-C", "Tank-Farm"),
-                sparkplug_topic: generate_sparkplug_topic("Plant-01", "OIL-003"),
-                source_timestamp: source_ts,
-                server_timestamp: server_ts.clone(),
-                value: serde_json::json!({
-                    "value": format!("{:.1}", level_percent).parse::<f64>().unwrap(),
-                    "tankCapacityLiters": capacity_liters,
-                    "tankCapacityM3": format!("{:.1}", capacity_liters as f64 / 1000.0).parse::<f64>().unwrap(),
-                    "currentVolumeLiters": current_volume,
-                    "currentVolumeM3": format!("{:.2}", current_volume as f64 / 1000.0).parse::<f64>().unwrap(),
-                    "lowAlarmThreshold": 10.0,
-                    "highAlarmThreshold": 95.0
-                }),
-                data_quality: quality,
-                opc_ua_status_code: status_code,
-                unit: get_ucum_unit("%"),
-                sensor_type: "oil_level".to_string(),
-                description: "Industrial oil level sensor".to_string(),
-                properties: serde_json::json!({}),
-            };
-            Some(serde_json::to_value(unified).unwrap())
+        if !(-90.0..=90.0).contains(&r.lat) || !(-180.0..=180.0).contains(&r.lng) {
+            return Err(format!("{} has invalid coordinates ({}, {})", label, r.lat, r.lng));
         }
-        "oil-pressure" => {
-            let pressure = random_between(15.0, 200.0);
-            let flow_rate = random_between(50.0, 500.0);
-            let quality = generate_data_quality(pressure, 30.0, 180.0);
-            let status_code = generate_opcua_status_code(&quality);
-            let source_ts = Utc::now().to_rfc3339();
-            
-            let unified = UnifiedSensorData {
-                opc_ua: generate_opcua_node("OPR-004", "Oil Pressure Sensor"),
-                equipment_hierarchy: generate_isa95_hierarchy("OPR-004", "Pipeline-D", "Process-Area"),
-                sparkplug_topic: generate_sparkplug_topic("Plant-01", "OPR-004"),
-                source_timestamp: source_ts,
-                server_timestamp: server_ts.clone(),
-                value: serde_json::json!({
-                    "value": format!("{:.2}", pressure).parse::<f64>().unwrap(),
-                    "flowRateLpm": format!("{:.1}", flow_rate).parse::<f64>().unwrap(),
-                    "operatingRange": "10-200 bar",
-                    "maxWorkingPressure": 250.0
-                }),
-                data_quality: quality,
-                opc_ua_status_code: status_code,
-                unit: get_ucum_unit("bar"),
-                sensor_type: "oil_pressure".to_string(),
-                description: "Hydraulic oil pressure sensor".to_string(),
-                properties: serde_json::json!({}),
-            };
-            Some(serde_json::to_value(unified).unwrap())
+        let station = OilStation {
+            province: leak(r.location.trim().to_string()),
+            location: leak(r.name.trim().to_string()),
+            lat: r.lat,
+            lng: r.lng,
+            meter_serial: leak(r.meter_serial.unwrap_or_else(|| format!("AMR-PIPE-2024-{:02}", i + 1))),
+            pipeline_id: leak(r.pipeline_id.unwrap_or_else(|| "PIPE-AMR-01".to_string())),
+        };
+        let id = station.id();
+        if id.is_empty() {
+            return Err(format!("{} has no letters or digits to build an id from", label));
         }
-        "air-quality" => {
-            let pm25 = random_between(5.0, 75.0);
-            let pm10 = pm25 * random_between(1.5, 2.5);
-            let co2 = random_between(400.0, 1500.0);
-            let voc = random_between(0.1, 2.0);
-            let aqi = calculate_aqi_pm25(pm25);
-            let quality = if aqi <= 100 { generate_data_quality(pm25, 0.0, 35.0) } else { DataQuality::Bad };
-            let status_code = generate_opcua_status_code(&quality);
-            let source_ts = Utc::now().to_rfc3339();
-            
-            let unified = UnifiedSensorData {
-                opc_ua: generate_opcua_node("AQI-005", "Air Quality Sensor"),
-                equipment_hierarchy: generate_isa95_hierarchy("AQI-005", "Outdoor-Station-E", "Environment"),
-                sparkplug_topic: generate_sparkplug_topic("Plant-01", "AQI-005"),
-                source_timestamp: source_ts,
-                server_timestamp: server_ts.clone(),
-                value: serde_json::json!({
-                    "pm25": format!("{:.1}", pm25).parse::<f64>().unwrap(),
-                    "pm10": format!("{:.1}", pm10).parse::<f64>().unwrap(),
-                    "co2": format!("{:.0}", co2).parse::<f64>().unwrap(),
-                    "voc": format!("{:.2}", voc).parse::<f64>().unwrap(),
-                    "aqi": aqi,
-                    "whoPm25Guideline": 15.0,
-                    "whoPm10Guideline": 45.0,
-                    "co2Threshold": 1000.0
-                }),
-                data_quality: quality,
-                opc_ua_status_code: status_code,
-                unit: get_ucum_unit("µg/m³"),
-                sensor_type: "air_quality".to_string(),
-                description: "Multi-parameter air quality sensor".to_string(),
-                properties: serde_json::json!({}),
-            };
-            Some(serde_json::to_value(unified).unwrap())
+        if stations.iter().any(|s: &OilStation| s.id() == id) {
+            return Err(format!("{} duplicates the id '{}'", label, id));
         }
-        "pressure" => {
-            let pressure = random_between(990.0, 1030.0);
-            let altitude = random_between(0.0, 100.0);
-            let sea_level_pressure = pressure * (1.0 + (altitude / 44330.0)).powf(5.255);
-            let trend = if rng.gen_bool(0.5) { "rising" } else { "falling" };
-            let quality = generate_data_quality(pressure, 980.0, 1050.0);
-            let status_code = generate_opcua_status_code(&quality);
-            let source_ts = Utc::now().to_rfc3339();
-            
-            let unified = UnifiedSensorData {
-                opc_ua: generate_opcua_node("PRS-006", "Atmospheric Pressure Sensor"),
-                equipment_hierarchy: generate_isa95_hierarchy("PRS-006", "Weather-Station-F", "Environment"),
-                sparkplug_topic: generate_sparkplug_topic("Plant-01", "PRS-006"),
-                source_timestamp: source_ts,
-                server_timestamp: server_ts.clone(),
-                value: serde_json::json!({
-                    "value": format!("{:.1}", pressure).parse::<f64>().unwrap(),
-                    "seaLevelPressure": format!("{:.1}", sea_level_pressure).parse::<f64>().unwrap(),
-                    "altitudeMeters": format!("{:.1}", altitude).parse::<f64>().unwrap(),
-                    "standardPressure": 1013.25,
-                    "trend": trend
-                }),
-                data_quality: quality,
-                opc_ua_status_code: status_code,
-                unit: get_ucum_unit("hPa"),
-                sensor_type: "pressure".to_string(),
-                description: "Atmospheric pressure sensor".to_string(),
-                properties: serde_json::json!({}),
-            };
-            Some(serde_json::to_value(unified).unwrap())
+        stations.push(station);
+    }
+    Ok(Box::leak(stations.into_boxed_slice()))
+}
+
+fn get_random_oil_station(stations: &[OilStation], rng: &mut impl Rng) -> OilStation {
+    stations[rng.gen_range(0..stations.len())]
+}
+
+/// Sensor keys of the per-station meters are `amr/<station id>`; plain
+/// `amr` keeps picking a random station on every read
+const AMR_STATION_PREFIX: &str = "amr/";
+
+fn amr_station(state: &AppState, key: &str) -> Option<&'static OilStation> {
+    let id = key.strip_prefix(AMR_STATION_PREFIX)?;
+    state.oil_stations.iter().find(|s| s.id() == id)
+}
+
+/// The built-in sensor a key simulates (`amr` for the per-station meters)
+fn base_sensor_key<'a>(state: &AppState, key: &'a str) -> &'a str {
+    if amr_station(state, key).is_some() { "amr" } else { key }
+}
+
+/// A built-in sensor, one of the per-station AMR meters or a configured sensor
+fn is_sensor_key(state: &AppState, key: &str) -> bool {
+    AVAILABLE_SENSORS.contains(&key) || amr_station(state, key).is_some() || sensor_config(state, key).is_some()
+}
+
+/// An operator-defined sensor from `SENSORS_CONFIG`
+fn sensor_config(state: &AppState, key: &str) -> Option<&'static sensor_config::SensorConfig> {
+    state.sensor_configs.iter().find(|c| c.key == key)
+}
+
+/// Every sensor a consumer can list or subscribe to without naming it: the
+/// built-in sensors followed by the configured ones
+fn sensor_keys(state: &AppState) -> impl Iterator<Item = &'static str> {
+    AVAILABLE_SENSORS.iter().copied().chain(state.sensor_configs.iter().map(|c| c.key.as_str()))
+}
+
+// ============================================
+// GPS Tracker + Geofencing
+// ============================================
+
+// จุดเริ่มต้นของรถขนส่ง: คลังน้ำมันมาบตาพุด
+const GPS_DEPOT: GeoPoint = GeoPoint { lat: 12.6517, lng: 101.1595 };
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+struct GeoPoint {
+    lat: f64,
+    lng: f64,
+}
+
+/// Simulated fleet vehicle position, advanced on every reading
+struct GpsTrackerState {
+    position: GeoPoint,
+    heading: f64,
+    speed_kmh: f64,
+    odometer_km: f64,
+    last_update: std::time::Instant,
+}
+
+impl GpsTrackerState {
+    fn new(rng: &mut impl Rng) -> Self {
+        GpsTrackerState {
+            position: GPS_DEPOT,
+            heading: random_between(rng, 0.0, 360.0),
+            speed_kmh: random_between(rng, 30.0, 80.0),
+            odometer_km: 0.0,
+            last_update: std::time::Instant::now(),
         }
-        "vibration" => {
-            let velocity_rms = random_between(0.5, 12.0);
-            let frequency = random_between(10.0, 1000.0);
-            let acceleration = velocity_rms * frequency * 2.0 * std::f64::consts::PI / 1000.0;
-            let displacement = velocity_rms / (frequency * 2.0 * std::f64::consts::PI) * 1000.0;
-            let quality = generate_data_quality(velocity_rms, 0.0, 7.1);
-            let status_code = generate_opcua_status_code(&quality);
-            let source_ts = Utc::now().to_rfc3339();
-            
-            let unified = UnifiedSensorData {
-                opc_ua: generate_opcua_node("VIB-007", "Vibration Sensor"),
-                equipment_hierarchy: generate_isa95_hierarchy("VIB-007", "CNC-Machine-02", "Machine-Shop"),
-                sparkplug_topic: generate_sparkplug_topic("Plant-01", "VIB-007"),
-                source_timestamp: source_ts,
-                server_timestamp: server_ts.clone(),
-                value: serde_json::json!({
-                    "velocityRms": format!("{:.3}", velocity_rms).parse::<f64>().unwrap(),
-                    "frequency": format!("{:.1}", frequency).parse::<f64>().unwrap(),
-                    "acceleration": format!("{:.3}", acceleration).parse::<f64>().unwrap(),
-                    "displacement": format!("{:.4}", displacement).parse::<f64>().unwrap(),
-                    "machineType": "Class II (Medium machines)",
-                    "iso10816Limits": {
-                        "good": 2.8,
-                        "satisfactory": 7.1,
-                        "unsatisfactory": 18.0
+    }
+
+    /// Dead-reckon the vehicle forward by the time elapsed since the last reading
+    fn advance(&mut self, rng: &mut impl Rng) {
+        // Cap the step so a long idle gap doesn't teleport the vehicle across the country
+        let dt = self.last_update.elapsed().as_secs_f64().min(60.0);
+        self.last_update = std::time::Instant::now();
+
+        self.heading = (self.heading + random_between(rng, -10.0, 10.0)).rem_euclid(360.0);
+        self.speed_kmh = (self.speed_kmh + random_between(rng, -5.0, 5.0)).clamp(20.0, 90.0);
+
+        let distance_m = self.speed_kmh / 3.6 * dt;
+        let heading_rad = self.heading.to_radians();
+        let dlat = distance_m * heading_rad.cos() / EARTH_RADIUS_M;
+        let dlng = distance_m * heading_rad.sin() / (EARTH_RADIUS_M * self.position.lat.to_radians().cos());
+        self.position.lat += dlat.to_degrees();
+        self.position.lng += dlng.to_degrees();
+        self.odometer_km += distance_m / 1000.0;
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "camelCase", rename_all_fields = "camelCase")]
+enum GeofenceZone {
+    Circle { center: GeoPoint, radius_meters: f64 },
+    Polygon { points: Vec<GeoPoint> },
+}
+
+impl GeofenceZone {
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            GeofenceZone::Circle { radius_meters, .. } if *radius_meters <= 0.0 => {
+                Err("radiusMeters must be greater than 0".to_string())
+            }
+            GeofenceZone::Polygon { points } if points.len() < 3 => {
+                Err("polygon requires at least 3 points".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn contains(&self, p: GeoPoint) -> bool {
+        match self {
+            GeofenceZone::Circle { center, radius_meters } => haversine_meters(*center, p) <= *radius_meters,
+            GeofenceZone::Polygon { points } => {
+                // Ray casting; lat/lng treated as planar, fine at geofence scale
+                let mut inside = false;
+                let mut j = points.len() - 1;
+                for i in 0..points.len() {
+                    let (a, b) = (points[i], points[j]);
+                    if (a.lat > p.lat) != (b.lat > p.lat)
+                        && p.lng < (b.lng - a.lng) * (p.lat - a.lat) / (b.lat - a.lat) + a.lng
+                    {
+                        inside = !inside;
                     }
-                }),
-                data_quality: quality,
-                opc_ua_status_code: status_code,
-                unit: get_ucum_unit("mm/s"),
-                sensor_type: "vibration".to_string(),
-                description: "ISO 10816 vibration monitoring sensor".to_string(),
-                properties: serde_json::json!({}),
-            };
-            Some(serde_json::to_value(unified).unwrap())
+                    j = i;
+                }
+                inside
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase", try_from = "GeofenceRequest")]
+struct Geofence {
+    name: String,
+    #[serde(flatten)]
+    zone: GeofenceZone,
+    #[serde(default, skip_deserializing)]
+    inside: bool,
+}
+
+/// Wire shape of a new geofence. Read field by field rather than through
+/// `#[serde(flatten)]`: with serde_json's `arbitrary_precision` (enabled by a
+/// dependency) buffered numbers don't deserialize as `f64`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeofenceRequest {
+    name: String,
+    #[serde(rename = "type")]
+    kind: String,
+    center: Option<GeoPoint>,
+    radius_meters: Option<f64>,
+    points: Option<Vec<GeoPoint>>,
+}
+
+impl TryFrom<GeofenceRequest> for Geofence {
+    type Error = String;
+
+    fn try_from(req: GeofenceRequest) -> Result<Self, String> {
+        let zone = match (req.kind.as_str(), req.center, req.radius_meters, req.points) {
+            ("circle", Some(center), Some(radius_meters), _) => GeofenceZone::Circle { center, radius_meters },
+            ("circle", ..) => return Err("circle requires center and radiusMeters".to_string()),
+            ("polygon", _, _, Some(points)) => GeofenceZone::Polygon { points },
+            ("polygon", ..) => return Err("polygon requires points".to_string()),
+            (other, ..) => return Err(format!("unknown geofence type '{}'", other)),
+        };
+        Ok(Geofence { name: req.name, zone, inside: false })
+    }
+}
+
+fn haversine_meters(a: GeoPoint, b: GeoPoint) -> f64 {
+    let dlat = (b.lat - a.lat).to_radians();
+    let dlng = (b.lng - a.lng).to_radians();
+    let h = (dlat / 2.0).sin().powi(2)
+        + a.lat.to_radians().cos() * b.lat.to_radians().cos() * (dlng / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_M * h.sqrt().asin()
+}
+
+/// Update each geofence's inside/outside state and raise enter/exit alarms on transitions
+fn evaluate_geofences(state: &AppState, sensor: &str, position: GeoPoint) -> Vec<serde_json::Value> {
+    let mut transitions = Vec::new();
+    let zones: Vec<_> = {
+        let mut geofences = state.geofences.lock().unwrap();
+        geofences
+            .iter_mut()
+            .map(|g| {
+                let inside = g.zone.contains(position);
+                if inside != g.inside {
+                    transitions.push((g.name.clone(), inside));
+                    g.inside = inside;
+                }
+                serde_json::json!({ "name": g.name, "inside": inside })
+            })
+            .collect()
+    };
+
+    for (zone, inside) in transitions {
+        let event = if inside { "enter" } else { "exit" };
+        raise_alarm(
+            state,
+            sensor,
+            "geofence",
+            event,
+            Some(zone.clone()),
+            format!("{} {} geofence '{}'", sensor, if inside { "entered" } else { "exited" }, zone),
+            serde_json::json!({ "lat": position.lat, "lng": position.lng }),
+        );
+    }
+
+    zones
+}
+
+// ============================================
+// Solar Panel (daylight-dependent)
+// ============================================
+
+/// Rooftop PV array configuration, read from env at startup
+struct SolarConfig {
+    capacity_kw: f64,
+    sunrise_hour: f64,
+    sunset_hour: f64,
+}
+
+impl SolarConfig {
+    fn from_env() -> Self {
+        SolarConfig {
+            capacity_kw: env_or("SOLAR_CAPACITY_KW", 50.0),
+            sunrise_hour: env_or("SOLAR_SUNRISE_HOUR", 6.0),
+            sunset_hour: env_or("SOLAR_SUNSET_HOUR", 18.5),
+        }
+    }
+}
+
+/// Current local hour of day (fractional), shifted from UTC by `TZ_OFFSET`
+fn local_hour(now: chrono::DateTime<Utc>, tz_offset_hours: f64) -> f64 {
+    use chrono::Timelike;
+    let utc_hour = now.hour() as f64 + now.minute() as f64 / 60.0 + now.second() as f64 / 3600.0;
+    (utc_hour + tz_offset_hours).rem_euclid(24.0)
+}
+
+/// Clear-sky plane-of-array irradiance (W/m²) for a local hour: a half-sine
+/// between sunrise and sunset peaking at ~1000 W/m² at solar noon, zero at night
+fn clear_sky_irradiance(hour: f64, sunrise: f64, sunset: f64) -> f64 {
+    if hour <= sunrise || hour >= sunset {
+        return 0.0;
+    }
+    let day_fraction = (hour - sunrise) / (sunset - sunrise);
+    1000.0 * (std::f64::consts::PI * day_fraction).sin().powf(1.2)
+}
+
+// ============================================
+// Occupancy / People Counter
+// ============================================
+
+/// (local hour, fraction of capacity) — office day with a lunch dip, empty at night
+const OCCUPANCY_PROFILE: &[(f64, f64)] = &[
+    (0.0, 0.0), (7.0, 0.0), (9.0, 0.6), (11.5, 0.8), (12.5, 0.5),
+    (13.5, 0.85), (17.0, 0.7), (20.0, 0.0), (24.0, 0.0),
+];
+
+fn occupancy_target_fraction(hour: f64) -> f64 {
+    OCCUPANCY_PROFILE
+        .windows(2)
+        .find(|w| hour >= w[0].0 && hour < w[1].0)
+        .map(|w| {
+            let ((h0, f0), (h1, f1)) = (w[0], w[1]);
+            f0 + (f1 - f0) * (hour - h0) / (h1 - h0)
+        })
+        .unwrap_or(0.0)
+}
+
+struct OccupancyState {
+    capacity: u32,
+    count: u32,
+    enter_count: u64,
+    exit_count: u64,
+    last_update: std::time::Instant,
+}
+
+impl OccupancyState {
+    fn new(capacity: u32) -> Self {
+        OccupancyState {
+            capacity: capacity.max(1),
+            count: 0,
+            enter_count: 0,
+            exit_count: 0,
+            last_update: std::time::Instant::now(),
+        }
+    }
+
+    /// Let `n` people in, bounded by capacity. Returns how many actually entered.
+    fn enter(&mut self, n: u32) -> u32 {
+        let admitted = n.min(self.capacity - self.count);
+        self.count += admitted;
+        self.enter_count += admitted as u64;
+        admitted
+    }
+
+    /// Let `n` people out, bounded by the current count. Returns how many actually left.
+    fn exit(&mut self, n: u32) -> u32 {
+        let left = n.min(self.count);
+        self.count -= left;
+        self.exit_count += left as u64;
+        left
+    }
+
+    /// Move the count toward the daily profile with some random churn
+    fn advance(&mut self, hour: f64, rng: &mut impl Rng) {
+        let dt_secs = self.last_update.elapsed().as_secs_f64().min(600.0);
+        self.last_update = std::time::Instant::now();
+
+        let target = self.capacity as f64 * occupancy_target_fraction(hour);
+        // Converge toward the target over roughly five minutes
+        let drift = (target - self.count as f64) * (dt_secs / 300.0).min(1.0);
+        let churn_max = (self.count as f64 * 0.02 * dt_secs / 60.0).ceil() as u32;
+        let churn = rng.gen_range(0..=churn_max);
+
+        self.enter(drift.max(0.0).round() as u32 + churn);
+        self.exit((-drift).max(0.0).round() as u32 + churn);
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum OccupancyDirection {
+    In,
+    Out,
+}
+
+#[derive(Deserialize)]
+struct OccupancyEventRequest {
+    direction: OccupancyDirection,
+    count: Option<u32>,
+}
+
+// ============================================
+// Quadrature Encoder
+// ============================================
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum EncoderDirection {
+    Forward,
+    Reverse,
+}
+
+/// Incremental encoder on a conveyor drive shaft. Counts only ever move in the
+/// current direction: up when turning forward, down when turning in reverse.
+struct EncoderState {
+    counts_per_revolution: u32,
+    counts: i64,
+    direction: EncoderDirection,
+    setpoint_rpm: f64,
+    last_update: std::time::Instant,
+}
+
+impl EncoderState {
+    fn new(counts_per_revolution: u32) -> Self {
+        EncoderState {
+            counts_per_revolution: counts_per_revolution.max(1),
+            counts: 0,
+            direction: EncoderDirection::Forward,
+            setpoint_rpm: 1500.0,
+            last_update: std::time::Instant::now(),
+        }
+    }
+
+    /// Advance the shaft by the elapsed time. Returns (count delta, elapsed seconds).
+    fn advance(&mut self, rng: &mut impl Rng) -> (i64, f64) {
+        let dt = self.last_update.elapsed().as_secs_f64();
+        self.last_update = std::time::Instant::now();
+
+        self.setpoint_rpm = (self.setpoint_rpm + random_between(rng, -20.0, 20.0)).clamp(800.0, 2400.0);
+        let revolutions = self.setpoint_rpm / 60.0 * dt;
+        let magnitude = (revolutions * self.counts_per_revolution as f64).round() as i64;
+        let delta = match self.direction {
+            EncoderDirection::Forward => magnitude,
+            EncoderDirection::Reverse => -magnitude,
+        };
+        self.counts += delta;
+        (delta, dt)
+    }
+}
+
+#[derive(Deserialize)]
+struct EncoderDirectionRequest {
+    direction: EncoderDirection,
+}
+
+// ============================================
+// Storage Tank Geometry (level → volume)
+// ============================================
+
+/// Vertical cylindrical tank the level sensor is mounted on. The geometry is
+/// fixed, so level, fill percentage and volume always agree with each other.
+#[derive(Clone, Copy, Debug)]
+struct TankGeometry {
+    height_m: f64,
+    diameter_m: f64,
+}
+
+/// Storage-Tank-M, the tank LVL-013 measures
+const LEVEL_SENSOR_TANK: TankGeometry = TankGeometry { height_m: 12.0, diameter_m: 8.0 };
+
+impl TankGeometry {
+    /// Footprint in m²
+    fn cross_section(&self) -> f64 {
+        std::f64::consts::PI * (self.diameter_m / 2.0).powi(2)
+    }
+
+    /// Liquid volume in m³ at `level` metres
+    fn volume(&self, level: f64) -> f64 {
+        level * self.cross_section()
+    }
+
+    fn percentage(&self, level: f64) -> f64 {
+        level / self.height_m * 100.0
+    }
+}
+
+// ============================================
+// Control Valve (positioner with finite stroke speed)
+// ============================================
+
+/// Globe valve with a smart positioner. The stem travels toward the commanded
+/// position at a fixed stroke rate, so a new command takes time to settle.
+struct ControlValveState {
+    commanded: f64,
+    position: f64,
+    stroke_rate: f64,
+    last_update: std::time::Instant,
+}
+
+impl ControlValveState {
+    fn new() -> Self {
+        ControlValveState {
+            commanded: 0.0,
+            position: 0.0,
+            // % of travel per second (10 s full stroke)
+            stroke_rate: 10.0,
+            last_update: std::time::Instant::now(),
+        }
+    }
+
+    fn advance(&mut self) {
+        let dt = self.last_update.elapsed().as_secs_f64();
+        self.last_update = std::time::Instant::now();
+        let max_step = self.stroke_rate * dt;
+        self.position += (self.commanded - self.position).clamp(-max_step, max_step);
+    }
+}
+
+#[derive(Deserialize)]
+struct ValveCommandRequest {
+    position: f64,
+}
+
+// ============================================
+// Strain Gauge (structural load + fatigue)
+// ============================================
+
+/// Bridge-girder strain gauge. Every change in load direction is a reversal
+/// (a rainflow half-cycle) and bumps the fatigue counter; crossing the overload
+/// threshold raises an alarm once per excursion.
+struct StrainGaugeState {
+    elastic_modulus_gpa: f64,
+    overload_microstrain: f64,
+    microstrain: f64,
+    trend: f64,
+    last_delta_sign: f64,
+    fatigue_cycles: u64,
+    overloaded: bool,
+    peak_microstrain: f64,
+}
+
+impl StrainGaugeState {
+    fn new(elastic_modulus_gpa: f64, overload_microstrain: f64) -> Self {
+        StrainGaugeState {
+            elastic_modulus_gpa,
+            overload_microstrain,
+            microstrain: 250.0,
+            trend: 1.0,
+            last_delta_sign: 0.0,
+            fatigue_cycles: 0,
+            overloaded: false,
+            peak_microstrain: 250.0,
+        }
+    }
+
+    /// Stress in MPa from Hooke's law: σ = E·ε
+    fn stress_mpa(&self) -> f64 {
+        self.elastic_modulus_gpa * 1000.0 * self.microstrain * 1e-6
+    }
+
+    /// Simulated traffic loading: ramps in one direction and occasionally
+    /// reverses, with rare heavy-vehicle spikes
+    fn next_simulated(&self, rng: &mut impl Rng) -> f64 {
+        let trend = if rng.gen_bool(0.3) { -self.trend } else { self.trend };
+        let spike = if rng.gen_bool(0.03) { trend * random_between(rng, 400.0, 800.0) } else { 0.0 };
+        (self.microstrain + trend * random_between(rng, 5.0, 40.0) + spike).clamp(-300.0, 1500.0)
+    }
+
+    /// Move to a new strain value. Returns Some(true/false) when the overload
+    /// state changes (tripped/cleared).
+    fn apply(&mut self, microstrain: f64) -> Option<bool> {
+        let delta = microstrain - self.microstrain;
+        if delta != 0.0 {
+            let sign = delta.signum();
+            if self.last_delta_sign != 0.0 && sign != self.last_delta_sign {
+                self.fatigue_cycles += 1;
+            }
+            self.last_delta_sign = sign;
+            self.trend = sign;
+        }
+        self.microstrain = microstrain;
+        self.peak_microstrain = self.peak_microstrain.max(microstrain);
+
+        let overloaded = microstrain > self.overload_microstrain;
+        if overloaded != self.overloaded {
+            self.overloaded = overloaded;
+            Some(overloaded)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct StrainLoadRequest {
+    microstrain: f64,
+}
+
+fn raise_strain_overload_alarm(state: &AppState, tripped: bool, microstrain: f64, threshold: f64) {
+    raise_alarm(
+        state,
+        "strain-gauge",
+        "overload",
+        if tripped { "trip" } else { "clear" },
+        None,
+        if tripped {
+            format!("strain-gauge overload: {:.0} µε exceeds {:.0} µε", microstrain, threshold)
+        } else {
+            format!("strain-gauge overload cleared at {:.0} µε", microstrain)
+        },
+        serde_json::json!({ "microstrain": microstrain, "threshold": threshold }),
+    );
+}
+
+// ============================================
+// Sound Level Meter (Leq / Lmax over a sliding window)
+// ============================================
+
+/// Readings the equivalent level and the maximum are taken over
+const SOUND_LEQ_WINDOW: usize = 60;
+
+/// Octave-band centres with the A-weighted shape of typical machine-hall
+/// noise relative to the loudest band (dB)
+const SOUND_OCTAVE_BANDS: &[(&str, f64)] = &[
+    ("63Hz", -18.0),
+    ("125Hz", -11.0),
+    ("250Hz", -6.0),
+    ("500Hz", -3.0),
+    ("1kHz", 0.0),
+    ("2kHz", -1.5),
+    ("4kHz", -5.0),
+    ("8kHz", -12.0),
+];
+
+/// Sum of levels on an energy basis: 10·log10(Σ 10^(L/10))
+fn sum_decibels(levels: impl Iterator<Item = f64>) -> f64 {
+    10.0 * levels.map(|l| 10f64.powf(l / 10.0)).sum::<f64>().log10()
+}
+
+/// Integrating sound level meter: keeps the last `SOUND_LEQ_WINDOW`
+/// A-weighted readings for LAeq (energy average) and LAFmax
+struct SoundLevelState {
+    window: VecDeque<f64>,
+}
+
+impl SoundLevelState {
+    fn new() -> Self {
+        SoundLevelState { window: VecDeque::with_capacity(SOUND_LEQ_WINDOW) }
+    }
+
+    /// Add a reading; returns (Leq, Lmax) over the window
+    fn record(&mut self, level: f64) -> (f64, f64) {
+        if self.window.len() == SOUND_LEQ_WINDOW {
+            self.window.pop_front();
+        }
+        self.window.push_back(level);
+        let leq = sum_decibels(self.window.iter().copied()) - 10.0 * (self.window.len() as f64).log10();
+        let lmax = self.window.iter().copied().fold(f64::MIN, f64::max);
+        (leq, lmax)
+    }
+}
+
+/// Octave-band levels whose energy sum is `level`, each band jittered a little
+fn octave_bands(rng: &mut impl Rng, level: f64) -> Vec<(&'static str, f64)> {
+    let shaped: Vec<(&str, f64)> =
+        SOUND_OCTAVE_BANDS.iter().map(|&(band, rel)| (band, rel + random_between(rng, -1.5, 1.5))).collect();
+    let offset = level - sum_decibels(shaped.iter().map(|&(_, l)| l));
+    shaped.into_iter().map(|(band, l)| (band, l + offset)).collect()
+}
+
+// ============================================
+// Wind (speed, gusts and a wrapping vane)
+// ============================================
+
+/// Eight-point compass name of a bearing in degrees
+fn cardinal_direction(degrees: f64) -> &'static str {
+    const POINTS: [&str; 8] = ["N", "NE", "E", "SE", "S", "SW", "W", "NW"];
+    POINTS[((degrees.rem_euclid(360.0) + 22.5) / 45.0) as usize % 8]
+}
+
+/// Beaufort force from the mean wind speed in m/s
+fn beaufort_number(speed: f64) -> u8 {
+    const UPPER_LIMITS: [f64; 12] = [0.5, 1.5, 3.3, 5.5, 7.9, 10.7, 13.8, 17.1, 20.7, 24.4, 28.4, 32.6];
+    UPPER_LIMITS.iter().take_while(|&&limit| speed >= limit).count() as u8
+}
+
+// ============================================
+// Smoke Detector (latching fire alarm)
+// ============================================
+
+/// Multi-criteria (optical + rate-of-rise heat) smoke detector. A smoke
+/// incident occasionally builds up and dies away again; once either reading
+/// crosses its limit the alarm latches and stays on until reset, like a
+/// real detector on a fire panel.
+struct SmokeDetectorState {
+    obscuration: f64,
+    heat_rise_rate: f64,
+    /// Readings left in the current smoke incident
+    incident_remaining: u32,
+    alarm: bool,
+    alarm_cause: Option<&'static str>,
+    latched_at: Option<String>,
+}
+
+impl SmokeDetectorState {
+    fn new() -> Self {
+        SmokeDetectorState {
+            obscuration: 0.2,
+            heat_rise_rate: 0.0,
+            incident_remaining: 0,
+            alarm: false,
+            alarm_cause: None,
+            latched_at: None,
+        }
+    }
+
+    fn advance(&mut self, rng: &mut impl Rng) {
+        if self.incident_remaining == 0 && rng.gen_bool(0.005) {
+            self.incident_remaining = rng.gen_range(10..40);
+        }
+        if self.incident_remaining > 0 {
+            self.incident_remaining -= 1;
+            self.obscuration += random_between(rng, 0.1, 0.8);
+            self.heat_rise_rate = (self.heat_rise_rate + random_between(rng, 0.0, 1.5)).min(30.0);
+        } else {
+            // Clean air: smoke clears and the ceiling stops warming
+            self.obscuration = (0.2 + (self.obscuration - 0.2) * 0.8 + random_between(rng, -0.05, 0.05)).max(0.0);
+            self.heat_rise_rate = self.heat_rise_rate * 0.7 + random_between(rng, -0.2, 0.2);
+        }
+        self.obscuration = self.obscuration.clamp(0.0, 30.0);
+    }
+
+    /// Latch the alarm (at `now`, simulation time) if a limit is crossed;
+    /// returns the cause when it trips
+    fn evaluate(&mut self, obscuration_limit: f64, heat_rise_limit: f64, now: chrono::DateTime<Utc>) -> Option<&'static str> {
+        if self.alarm {
+            return None;
+        }
+        let cause = if self.obscuration >= obscuration_limit {
+            "smoke"
+        } else if self.heat_rise_rate >= heat_rise_limit {
+            "heat"
+        } else {
+            return None;
+        };
+        self.alarm = true;
+        self.alarm_cause = Some(cause);
+        self.latched_at = Some(now.to_rfc3339());
+        Some(cause)
+    }
+}
+
+fn raise_smoke_alarm(state: &AppState, tripped: bool, cause: &str, obscuration: f64, heat_rise_rate: f64) {
+    raise_alarm(
+        state,
+        "smoke-detector",
+        "fire",
+        if tripped { "trip" } else { "clear" },
+        None,
+        if tripped {
+            format!("smoke-detector alarm ({}): {:.1} %/m obscuration, {:.1} °C/min rise", cause, obscuration, heat_rise_rate)
+        } else {
+            "smoke-detector alarm reset".to_string()
+        },
+        serde_json::json!({ "cause": cause, "obscuration": obscuration, "heatRiseRate": heat_rise_rate }),
+    );
+}
+
+// ============================================
+// Contact (debounced digital input)
+// ============================================
+
+/// Open/closed contact (door switch, e-stop, limit switch). The state only
+/// flips on a slow random schedule, never between two polls in a row, so it
+/// reads like a debounced input rather than noise. Forcing it holds the
+/// state until released, for testing interlocks downstream.
+struct ContactState {
+    closed: bool,
+    transitions: u64,
+    last_change: String,
+    next_change: std::time::Instant,
+    forced: bool,
+}
+
+impl ContactState {
+    /// Closed since `now` (simulation time)
+    fn new(rng: &mut impl Rng, now: chrono::DateTime<Utc>) -> Self {
+        ContactState {
+            closed: true,
+            transitions: 0,
+            last_change: now.to_rfc3339(),
+            next_change: std::time::Instant::now() + Self::dwell(rng),
+            forced: false,
+        }
+    }
+
+    /// How long the contact stays put before it next changes
+    fn dwell(rng: &mut impl Rng) -> Duration {
+        Duration::from_secs_f64(random_between(rng, 20.0, 300.0))
+    }
+
+    /// Move the contact, stamping a change with `now` (simulation time)
+    fn set(&mut self, closed: bool, now: chrono::DateTime<Utc>) {
+        if closed != self.closed {
+            self.closed = closed;
+            self.transitions += 1;
+            self.last_change = now.to_rfc3339();
+        }
+    }
+
+    fn advance(&mut self, rng: &mut impl Rng, now: chrono::DateTime<Utc>) {
+        if self.forced || std::time::Instant::now() < self.next_change {
+            return;
+        }
+        let closed = !self.closed;
+        self.set(closed, now);
+        self.next_change = std::time::Instant::now() + Self::dwell(rng);
+    }
+}
+
+#[derive(Deserialize)]
+struct ContactForceRequest {
+    closed: bool,
+}
+
+// ============================================
+// Power Quality (derived from the energy meter)
+// ============================================
+
+/// Per-phase resistance of the feeder between the main panel and the loads, Ω
+const FEEDER_RESISTANCE_OHMS: f64 = 0.02;
+/// Extra eddy-current / skin-effect loss per unit of squared current THD,
+/// relative to the fundamental I²R loss (a rough K-factor)
+const HARMONIC_LOSS_WEIGHT: f64 = 5.0;
+
+/// The electrical state behind an energy-meter reading. The power-quality
+/// analyser works from the meter's last reading as served, so its figures
+/// agree with the meter's power triangle (faults, injections, waveforms and
+/// replay included) instead of being drawn independently.
+#[derive(Clone, Copy)]
+struct MainsSnapshot {
+    voltage_l1: f64,
+    current: f64,
+    power_factor: f64,
+    frequency: f64,
+    active_power: f64,
+    apparent_power: f64,
+    reactive_power: f64,
+}
+
+impl MainsSnapshot {
+    /// The snapshot an energy-meter reading reports, if it has measurements
+    fn from_reading(data: &serde_json::Value) -> Option<Self> {
+        let field = |name: &str| data["value"][name].as_f64();
+        Some(MainsSnapshot {
+            voltage_l1: field("voltageL1")?,
+            current: field("current")?,
+            power_factor: field("powerFactor")?,
+            frequency: field("frequency")?,
+            active_power: field("activePower")?,
+            apparent_power: field("apparentPower")?,
+            reactive_power: field("reactivePower")?,
+        })
+    }
+}
+
+/// Take a fresh energy-meter measurement
+fn sample_mains(state: &AppState, rng: &mut impl Rng) -> MainsSnapshot {
+    let voltage_l1 = random_between(rng, 218.0, 242.0);
+    let voltage_l3 = voltage_l1 * 1.732;
+    let current = walk(state, rng, "energy-meter");
+    let power_factor = random_between(rng, 0.80, 0.98);
+    let active_power = (voltage_l3 * current * power_factor * 1.732) / 1000.0;
+    let apparent_power = (voltage_l3 * current * 1.732) / 1000.0;
+    MainsSnapshot {
+        voltage_l1,
+        current,
+        power_factor,
+        frequency: random_between(rng, 49.5, 50.5),
+        active_power,
+        apparent_power,
+        reactive_power: (apparent_power.powi(2) - active_power.powi(2)).sqrt(),
+    }
+}
+
+/// The energy meter's last reading as served, reading it if it hasn't been
+/// read yet
+fn latest_mains_reading(state: &AppState) -> Option<serde_json::Value> {
+    let last = state.latest_readings.lock().unwrap().get("energy-meter").cloned();
+    last.or_else(|| generate_sensor_data(state, "energy-meter"))
+}
+
+// ============================================
+// Golden Batch (reference trajectory tracking)
+// ============================================
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+struct GoldenPoint {
+    /// Seconds since the batch started
+    t: f64,
+    value: f64,
+}
+
+#[derive(Deserialize)]
+struct GoldenBatchRequest {
+    /// Numeric field under `value` that follows the reference curve
+    field: String,
+    points: Vec<GoldenPoint>,
+    /// Allowed absolute deviation before the alarm trips
+    band: f64,
+    /// Random tracking error amplitude around the reference
+    #[serde(default)]
+    deviation: f64,
+    /// Constant offset from the reference, e.g. to force the batch out of band
+    #[serde(default)]
+    bias: f64,
+}
+
+struct GoldenBatch {
+    field: String,
+    points: Vec<GoldenPoint>,
+    band: f64,
+    deviation: f64,
+    bias: f64,
+    started_at: std::time::Instant,
+    in_band: bool,
+}
+
+impl GoldenBatch {
+    fn from_request(mut req: GoldenBatchRequest) -> Result<Self, &'static str> {
+        if req.points.is_empty() {
+            return Err("Golden batch needs at least one point");
+        }
+        if req.points.iter().any(|p| !p.t.is_finite() || p.t < 0.0 || !p.value.is_finite()) {
+            return Err("Golden batch points need finite values and non-negative times");
+        }
+        if !req.band.is_finite() || req.band <= 0.0 {
+            return Err("band must be a positive number");
+        }
+        if !req.deviation.is_finite() || req.deviation < 0.0 || !req.bias.is_finite() {
+            return Err("deviation must be non-negative and bias finite");
+        }
+        req.points.sort_by(|a, b| a.t.total_cmp(&b.t));
+
+        Ok(Self {
+            field: req.field,
+            points: req.points,
+            band: req.band,
+            deviation: req.deviation,
+            bias: req.bias,
+            started_at: std::time::Instant::now(),
+            in_band: true,
+        })
+    }
+
+    fn duration(&self) -> f64 {
+        self.points.last().map(|p| p.t).unwrap_or(0.0)
+    }
+
+    /// Linear interpolation along the curve, holding the end values outside it
+    fn reference_at(&self, t: f64) -> f64 {
+        let i = self.points.partition_point(|p| p.t <= t);
+        match (i.checked_sub(1).map(|j| self.points[j]), self.points.get(i)) {
+            (Some(a), Some(b)) => a.value + (b.value - a.value) * (t - a.t) / (b.t - a.t),
+            (Some(a), None) => a.value,
+            (None, Some(b)) => b.value,
+            (None, None) => 0.0,
+        }
+    }
+}
+
+/// Replace the tracked field with a reading along the golden curve and alarm
+/// when it leaves (or re-enters) the band
+fn track_golden_batch(state: &AppState, key: &str, data: &mut serde_json::Value) {
+    let (transition, details) = {
+        let mut batches = state.golden_batches.lock().unwrap();
+        let Some(batch) = batches.get_mut(key) else {
+            return;
+        };
+        let t = batch.started_at.elapsed().as_secs_f64();
+        let reference = batch.reference_at(t);
+        let noise = state.rngs.sensor(key).lock().unwrap().gen_range(-batch.deviation..=batch.deviation);
+        let live = format!("{:.3}", reference + batch.bias + noise).parse::<f64>().unwrap();
+        let deviation = format!("{:.3}", live - reference).parse::<f64>().unwrap();
+        let in_band = deviation.abs() <= batch.band;
+
+        data["value"][&batch.field] = serde_json::json!(live);
+        data["deviationFromGolden"] = serde_json::json!(deviation);
+        data["goldenBatch"] = serde_json::json!({
+            "field": batch.field,
+            "reference": format!("{:.3}", reference).parse::<f64>().unwrap(),
+            "band": batch.band,
+            "batchTimeSeconds": format!("{:.1}", t).parse::<f64>().unwrap(),
+            "complete": t >= batch.duration(),
+            "inBand": in_band
+        });
+
+        let transition = (in_band != batch.in_band).then_some(!in_band);
+        batch.in_band = in_band;
+        (transition, serde_json::json!({
+            "field": batch.field,
+            "value": live,
+            "reference": data["goldenBatch"]["reference"],
+            "deviation": deviation,
+            "band": batch.band
+        }))
+    };
+
+    if let Some(tripped) = transition {
+        raise_alarm(
+            state,
+            key,
+            "golden-batch",
+            if tripped { "trip" } else { "clear" },
+            None,
+            if tripped {
+                format!(
+                    "{} deviates {:+.3} from golden batch (band ±{})",
+                    key,
+                    details["deviation"].as_f64().unwrap_or_default(),
+                    details["band"]
+                )
+            } else {
+                format!("{} back within golden batch band", key)
+            },
+            details,
+        );
+    }
+}
+
+// ============================================
+// Clock Synchronization (PTP / NTP / GNSS)
+// ============================================
+
+#[derive(Serialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+enum SyncSource {
+    Ptp,
+    Ntp,
+    Gnss,
+}
+
+impl SyncSource {
+    fn for_sensor(key: &str) -> Self {
+        match key {
+            // Motion/power-quality sensors need sub-microsecond alignment
+            "vibration" | "accelerometer" | "energy-meter" | "proximity-sensor" => SyncSource::Ptp,
+            "gps-tracker" => SyncSource::Gnss,
+            _ => SyncSource::Ntp,
+        }
+    }
+
+    /// (accuracy immediately after a sync in µs, free-running oscillator drift in ppm)
+    fn characteristics(self) -> (f64, f64) {
+        match self {
+            SyncSource::Ptp => (1.0, 2.0),
+            SyncSource::Ntp => (1000.0, 20.0),
+            SyncSource::Gnss => (0.1, 1.0),
+        }
+    }
+}
+
+/// Per-sensor clock discipline. Accuracy is best right after a sync and
+/// degrades linearly with oscillator drift (1 ppm = 1 µs per second) until
+/// the next sync.
+struct ClockSyncState {
+    source: SyncSource,
+    last_sync: std::time::Instant,
+    last_sync_at: String,
+    sync_count: u64,
+}
+
+impl ClockSyncState {
+    /// Freshly synced at `now` (simulation time)
+    fn new(key: &str, now: chrono::DateTime<Utc>) -> Self {
+        ClockSyncState {
+            source: SyncSource::for_sensor(key),
+            last_sync: std::time::Instant::now(),
+            last_sync_at: now.to_rfc3339(),
+            sync_count: 0,
+        }
+    }
+
+    fn resync(&mut self, now: chrono::DateTime<Utc>) {
+        self.last_sync = std::time::Instant::now();
+        self.last_sync_at = now.to_rfc3339();
+        self.sync_count += 1;
+    }
+
+    fn accuracy_us(&self) -> f64 {
+        let (base, drift_ppm) = self.source.characteristics();
+        base + drift_ppm * self.last_sync.elapsed().as_secs_f64()
+    }
+
+    fn status(&self) -> serde_json::Value {
+        serde_json::json!({
+            "syncSource": self.source,
+            "clockAccuracy": format!("{:.3}", self.accuracy_us()).parse::<f64>().unwrap(),
+            "clockAccuracyUnit": "us",
+            "driftPpm": self.source.characteristics().1,
+            "lastSync": self.last_sync_at,
+            "secondsSinceSync": format!("{:.1}", self.last_sync.elapsed().as_secs_f64()).parse::<f64>().unwrap(),
+            "syncCount": self.sync_count
+        })
+    }
+}
+
+/// Generate a reading and apply the cross-cutting per-sensor behaviour
+/// (clock sync, device identity, boot instability, ...) on top of the
+/// sensor-specific simulation. Every reading, live or cached from an offline
+/// device, is shown to the webhooks and kept as the sensor's latest here.
+fn generate_sensor_data(state: &AppState, key: &str) -> Option<serde_json::Value> {
+    let data = device_reading(state, key)?;
+    notify_webhooks(state, key, &data);
+    state.latest_readings.lock().unwrap().insert(key.to_string(), data.clone());
+    Some(data)
+}
+
+fn device_reading(state: &AppState, key: &str) -> Option<serde_json::Value> {
+    // The gateway can't reach the device: the best it can offer is its cache
+    if is_offline(state, key) {
+        return stale_reading(state, key).or_else(|| not_responding_reading(state, key));
+    }
+
+    let mut data = match replayed_reading(state, key) {
+        Some(data) => data,
+        None => simulate_sensor(state, key)?,
+    };
+    apply_dependency(state, key, &mut data);
+    apply_waveform(state, key, &mut data);
+    track_golden_batch(state, key, &mut data);
+    apply_transport_delay(state, key, &mut data);
+    apply_injection(state, key, &mut data);
+    apply_sensor_fault(state, key, &mut data);
+    let rerange = state.ranges.lock().unwrap().get(key).copied();
+    if let (Some(range), Some(factory)) = (rerange, factory_range(state, key)) {
+        apply_rerange(state, &mut data, key, factory, range);
+    }
+    track_peak(state, key, &mut data);
+    if let Some(shelve) = alarm_shelved(state, key) {
+        data["shelved"] = serde_json::json!(true);
+        data["shelvedUntil"] = serde_json::json!(shelve.until);
+    }
+
+    // A broken or shorted loop means the reading can't be trusted at all
+    if state.wiring_faults.lock().unwrap().contains_key(key) {
+        data["dataQuality"] = serde_json::json!(DataQuality::Bad);
+        set_opcua_status(&mut data, OpcUaStatusCode::BadSensorFailure);
+    }
+
+    let accuracy_us = {
+        let mut clocks = state.clock_sync.lock().unwrap();
+        let clock = clocks.entry(key.to_string()).or_insert_with(|| ClockSyncState::new(key, state.clock.now()));
+        data["syncSource"] = serde_json::json!(clock.source);
+        data["clockAccuracy"] = serde_json::json!(format!("{:.3}", clock.accuracy_us()).parse::<f64>().unwrap());
+        clock.accuracy_us()
+    };
+
+    // The device stamps readings with its own clock, so the source timestamp
+    // carries an error bounded by the current clock accuracy
+    if let Some(ts) = data["sourceTimestamp"].as_str().and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok()) {
+        let offset_ns = (random_between(&mut *state.rngs.sensor(key).lock().unwrap(), -1.0, 1.0) * accuracy_us * 1000.0) as i64;
+        let skewed = ts + chrono::Duration::nanoseconds(offset_ns);
+        data["sourceTimestamp"] = serde_json::json!(skewed.with_timezone(&Utc).to_rfc3339());
+    }
+
+    let boot_rate = {
+        let mut devices = state.devices.lock().unwrap();
+        let device = devices.entry(key.to_string()).or_insert_with(|| DeviceInfo::new(key, sensor_device_id(state, key).unwrap_or(key), state.clock.now()));
+        if let (Some(props), serde_json::Value::Object(device_props)) = (data["properties"].as_object_mut(), device.properties()) {
+            props.extend(device_props);
+        }
+        state.boot_error_rate * device.boot_instability(state.boot_window_secs)
+    };
+
+    // Freshly booted devices haven't settled yet: some good readings are
+    // reported as uncertain initial values until the boot window passes
+    data["properties"]["stabilizing"] = serde_json::json!(boot_rate > 0.0);
+    if is_good_quality(&data) && state.rngs.sensor(key).lock().unwrap().gen_bool(boot_rate) {
+        data["dataQuality"] = serde_json::json!(DataQuality::Uncertain);
+        set_opcua_status(&mut data, OpcUaStatusCode::UncertainInitialValue);
+    }
+
+    if is_good_quality(&data) {
+        state.last_good.lock().unwrap().insert(key.to_string(), (std::time::Instant::now(), data.clone()));
+    }
+    if state.retention.history_enabled(key) {
+        state.unsampled.lock().unwrap().insert(key.to_string(), data.clone());
+    }
+    Some(data)
+}
+
+// ============================================
+// Sensor Descriptors (static configuration)
+// ============================================
+
+/// Band a reading's quality metric must stay inside to be reported Good
+#[derive(Serialize, Clone, Copy, Debug)]
+struct NormalRange {
+    /// Field of the `value` object the band applies to
+    metric: &'static str,
+    min: f64,
+    max: f64,
+}
+
+/// Static description of a simulated sensor: identity, placement, unit and
+/// the alarm/quality thresholds its readings are judged against
+#[derive(Clone, Copy, Debug)]
+struct SensorDescriptor {
+    key: &'static str,
+    device_id: &'static str,
+    display_name: &'static str,
+    line: &'static str,
+    area: &'static str,
+    /// Nominal display unit (flow-meter switches to kg/h for steam)
+    unit: &'static str,
+    sensor_type: &'static str,
+    description: &'static str,
+    /// None where quality comes from alarms or state rather than a fixed band
+    normal: Option<NormalRange>,
+    /// Named limits echoed in the `value` object of every reading
+    thresholds: &'static [(&'static str, f64)],
+    /// Factory range and certified accuracy; None for devices that aren't calibrated
+    calibration: Option<Calibration>,
+}
+
+/// ISO/IEC 17025 calibration of a transmitter over its factory range
+#[derive(Clone, Copy, Debug)]
+struct Calibration {
+    /// Lower range value
+    lrv: f64,
+    /// Upper range value
+    urv: f64,
+    /// Accuracy ±, in the sensor's unit
+    accuracy: f64,
+    /// Reference standard the device was calibrated against
+    standard: &'static str,
+}
+
+impl SensorDescriptor {
+    fn quality(&self, value: f64) -> DataQuality {
+        match self.normal {
+            Some(range) => generate_data_quality(value, range.min, range.max),
+            None => DataQuality::Good,
+        }
+    }
+
+    /// Like `quality`, for a metric that only has a ceiling (harmonic
+    /// distortion): readings near the bottom of the band are as good as it gets
+    fn ceiling_quality(&self, value: f64) -> DataQuality {
+        match self.normal {
+            Some(range) => self.quality(value.max((range.min + range.max) / 2.0)),
+            None => DataQuality::Good,
+        }
+    }
+
+    fn threshold(&self, name: &str) -> Option<f64> {
+        self.thresholds.iter().find(|(n, _)| *n == name).map(|(_, v)| *v)
+    }
+}
+
+const SENSOR_DESCRIPTORS: &[SensorDescriptor] = &[
+    SensorDescriptor {
+        key: "temperature",
+        device_id: "TEMP-001",
+        display_name: "Temperature Sensor",
+        line: "Production-Line-1",
+        area: "Factory-Floor-A",
+        unit: "°C",
+        sensor_type: "temperature",
+        description: "Industrial temperature sensor",
+        normal: Some(NormalRange { metric: "value", min: 18.0, max: 27.0 }),
+        thresholds: &[("minThreshold", 18.0), ("maxThreshold", 27.0), ("criticalHigh", 32.0), ("criticalLow", 15.0)],
+        calibration: Some(Calibration { lrv: 0.0, urv: 50.0, accuracy: 0.2, standard: "Fluke 9142 Field Metrology Well" }),
+    },
+    SensorDescriptor {
+        key: "humidity",
+        device_id: "HUM-002",
+        display_name: "Humidity Sensor",
+        line: "Server-Room-B",
+        area: "IT-Infrastructure",
+        unit: "%RH",
+        sensor_type: "humidity",
+        description: "Relative humidity sensor",
+        normal: Some(NormalRange { metric: "value", min: 40.0, max: 60.0 }),
+        thresholds: &[("optimalMin", 40.0), ("optimalMax", 60.0), ("allowableMin", 20.0), ("allowableMax", 80.0)],
+        calibration: Some(Calibration { lrv: 10.0, urv: 90.0, accuracy: 2.0, standard: "Vaisala HMK15 Humidity Calibrator" }),
+    },
+    SensorDescriptor {
+        key: "oil-level",
+        device_id: "OIL-003",
+        display_name: "Oil Level Sensor",
+        line: "Storage-Tank-C",
+        area: "Tank-Farm",
+        unit: "%",
+        sensor_type: "oil_level",
+        description: "Industrial oil level sensor",
+        normal: Some(NormalRange { metric: "value", min: 20.0, max: 90.0 }),
+        thresholds: &[("lowAlarmThreshold", 10.0), ("highAlarmThreshold", 95.0)],
+        calibration: Some(Calibration { lrv: 0.0, urv: 100.0, accuracy: 0.5, standard: "Tank strapping table + calibrated dip tape" }),
+    },
+    SensorDescriptor {
+        key: "oil-pressure",
+        device_id: "OPR-004",
+        display_name: "Oil Pressure Sensor",
+        line: "Pipeline-D",
+        area: "Process-Area",
+        unit: "bar",
+        sensor_type: "oil_pressure",
+        description: "Hydraulic oil pressure sensor",
+        normal: Some(NormalRange { metric: "value", min: 30.0, max: 180.0 }),
+        thresholds: &[("maxWorkingPressure", 250.0)],
+        calibration: Some(Calibration { lrv: 0.0, urv: 250.0, accuracy: 0.5, standard: "Fluke P3100 Hydraulic Deadweight Tester" }),
+    },
+    SensorDescriptor {
+        key: "air-quality",
+        device_id: "AQI-005",
+        display_name: "Air Quality Sensor",
+        line: "Outdoor-Station-E",
+        area: "Environment",
+        unit: "µg/m³",
+        sensor_type: "air_quality",
+        description: "Multi-parameter air quality sensor",
+        normal: Some(NormalRange { metric: "pm25", min: 0.0, max: 35.0 }),
+        thresholds: &[("whoPm25Guideline", 15.0), ("whoPm10Guideline", 45.0), ("co2Threshold", 1000.0)],
+        calibration: Some(Calibration { lrv: 0.0, urv: 500.0, accuracy: 5.0, standard: "TSI DustTrak II Reference Monitor" }),
+    },
+    SensorDescriptor {
+        key: "pressure",
+        device_id: "PRS-006",
+        display_name: "Atmospheric Pressure Sensor",
+        line: "Weather-Station-F",
+        area: "Environment",
+        unit: "hPa",
+        sensor_type: "pressure",
+        description: "Atmospheric pressure sensor",
+        normal: Some(NormalRange { metric: "value", min: 980.0, max: 1050.0 }),
+        thresholds: &[],
+        calibration: Some(Calibration { lrv: 800.0, urv: 1100.0, accuracy: 0.3, standard: "Vaisala PTB330 Digital Barometer" }),
+    },
+    SensorDescriptor {
+        key: "vibration",
+        device_id: "VIB-007",
+        display_name: "Vibration Sensor",
+        line: "CNC-Machine-02",
+        area: "Machine-Shop",
+        unit: "mm/s",
+        sensor_type: "vibration",
+        description: "ISO 10816 vibration monitoring sensor",
+        normal: Some(NormalRange { metric: "velocityRms", min: 0.0, max: 7.1 }),
+        thresholds: &[("good", 2.8), ("satisfactory", 7.1), ("unsatisfactory", 18.0)],
+        calibration: Some(Calibration { lrv: 0.0, urv: 50.0, accuracy: 0.5, standard: "PCB 9110D Portable Shaker" }),
+    },
+    SensorDescriptor {
+        key: "energy-meter",
+        device_id: "ENR-008",
+        display_name: "Energy Meter",
+        line: "Main-Panel-H",
+        area: "Electrical",
+        unit: "kW",
+        sensor_type: "energy",
+        description: "3-phase power quality meter",
+        normal: Some(NormalRange { metric: "powerFactor", min: 0.85, max: 1.0 }),
+        thresholds: &[],
+        calibration: Some(Calibration { lrv: 0.0, urv: 500.0, accuracy: 1.0, standard: "Fluke 6105A Electrical Power Standard" }),
+    },
+    SensorDescriptor {
+        key: "amr",
+        device_id: "AMR-009",
+        display_name: "AMR Oil Pipeline Meter",
+        line: "Pipeline-Station",
+        area: "Oil-Gas",
+        unit: "L/min",
+        sensor_type: "amr_oil_pipeline",
+        description: "Automatic meter reading for oil pipeline",
+        normal: Some(NormalRange { metric: "inletPressure", min: 30.0, max: 80.0 }),
+        thresholds: &[],
+        calibration: Some(Calibration { lrv: 0.0, urv: 50000.0, accuracy: 50.0, standard: "Bidirectional Pipe Prover" }),
+    },
+    SensorDescriptor {
+        key: "flow-meter",
+        device_id: "FLW-010",
+        display_name: "Flow Meter",
+        line: "Process-Line-J",
+        area: "Process",
+        unit: "m³/h",
+        sensor_type: "flow_meter",
+        description: "Industrial flow measurement",
+        normal: Some(NormalRange { metric: "flowRate", min: 10.0, max: 1000.0 }),
+        thresholds: &[],
+        calibration: Some(Calibration { lrv: 0.0, urv: 1000.0, accuracy: 2.0, standard: "Gravimetric Flow Calibration Rig" }),
+    },
+    SensorDescriptor {
+        key: "gas-detector",
+        device_id: "GAS-011",
+        display_name: "Gas Detector",
+        line: "Confined-Space-K",
+        area: "Safety",
+        unit: "ppm",
+        sensor_type: "gas_detector",
+        description: "4-gas safety monitor",
+        normal: None,
+        thresholds: &[("coAlarmSetpoint", 35.0), ("h2sAlarmSetpoint", 10.0), ("o2LowAlarm", 19.5), ("o2HighAlarm", 23.5), ("lelAlarmSetpoint", 10.0)],
+        calibration: Some(Calibration { lrv: 0.0, urv: 100.0, accuracy: 2.0, standard: "Certified Span Gas Cylinder (CO 50 ppm)" }),
+    },
+    SensorDescriptor {
+        key: "ph-sensor",
+        device_id: "PH-012",
+        display_name: "pH Sensor",
+        line: "Water-Treatment-L",
+        area: "Water",
+        unit: "pH",
+        sensor_type: "ph_sensor",
+        description: "Water quality pH/ORP sensor",
+        normal: Some(NormalRange { metric: "phValue", min: 6.0, max: 8.5 }),
+        thresholds: &[],
+        calibration: Some(Calibration { lrv: 0.0, urv: 14.0, accuracy: 0.05, standard: "NIST-traceable pH Buffers 4/7/10" }),
+    },
+    SensorDescriptor {
+        key: "level-sensor",
+        device_id: "LVL-013",
+        display_name: "Level Sensor",
+        line: "Storage-Tank-M",
+        area: "Tank-Farm",
+        unit: "m",
+        sensor_type: "level_sensor",
+        description: "Tank level measurement sensor",
+        normal: Some(NormalRange { metric: "percentage", min: 10.0, max: 90.0 }),
+        thresholds: &[],
+        calibration: Some(Calibration { lrv: 0.0, urv: 20.0, accuracy: 0.003, standard: "Leica DISTO Laser Distance Reference" }),
+    },
+    SensorDescriptor {
+        key: "proximity-sensor",
+        device_id: "PRX-014",
+        display_name: "Proximity Sensor",
+        line: "Conveyor-Station-N",
+        area: "Material-Handling",
+        unit: "mm",
+        sensor_type: "proximity_sensor",
+        description: "Object detection proximity sensor",
+        normal: None,
+        thresholds: &[],
+        calibration: Some(Calibration { lrv: 0.0, urv: 100.0, accuracy: 0.5, standard: "Mitutoyo Gauge Block Set" }),
+    },
+    SensorDescriptor {
+        key: "gps-tracker",
+        device_id: "GPS-015",
+        display_name: "GPS Fleet Tracker",
+        line: "Fleet-Truck-01",
+        area: "Logistics",
+        unit: "km/h",
+        sensor_type: "gps_tracker",
+        description: "Fleet GPS tracker with geofencing",
+        normal: Some(NormalRange { metric: "hdop", min: 0.5, max: 2.0 }),
+        thresholds: &[],
+        calibration: Some(Calibration { lrv: 0.0, urv: 120.0, accuracy: 0.5, standard: "Spirent GSS7000 GNSS Simulator" }),
+    },
+    SensorDescriptor {
+        key: "solar-panel",
+        device_id: "SOL-016",
+        display_name: "Solar PV Array",
+        line: "Rooftop-Array-P",
+        area: "Renewable-Energy",
+        unit: "kW",
+        sensor_type: "solar_panel",
+        description: "Daylight-dependent rooftop solar PV array",
+        normal: Some(NormalRange { metric: "panelTemperature", min: -10.0, max: 85.0 }),
+        thresholds: &[],
+        calibration: Some(Calibration { lrv: 0.0, urv: 60.0, accuracy: 0.5, standard: "Kipp & Zonen CMP10 Pyranometer + Power Analyzer" }),
+    },
+    SensorDescriptor {
+        key: "occupancy",
+        device_id: "OCC-017",
+        display_name: "People Counter",
+        line: "Office-Building-Q",
+        area: "Facilities",
+        unit: "persons",
+        sensor_type: "occupancy",
+        description: "Smart-building occupancy people counter",
+        normal: None,
+        thresholds: &[],
+        calibration: Some(Calibration { lrv: 0.0, urv: 200.0, accuracy: 1.0, standard: "Manual Headcount Audit (video-verified)" }),
+    },
+    SensorDescriptor {
+        key: "encoder",
+        device_id: "ENC-018",
+        display_name: "Quadrature Encoder",
+        line: "Conveyor-Drive-R",
+        area: "Material-Handling",
+        unit: "RPM",
+        sensor_type: "encoder",
+        description: "Incremental quadrature shaft encoder",
+        normal: Some(NormalRange { metric: "value", min: 0.0, max: 3000.0 }),
+        thresholds: &[],
+        calibration: Some(Calibration { lrv: 0.0, urv: 3000.0, accuracy: 1.0, standard: "Monarch PLT200 Optical Tachometer" }),
+    },
+    SensorDescriptor {
+        key: "control-valve",
+        device_id: "VLV-020",
+        display_name: "Control Valve",
+        line: "Storage-Tank-M",
+        area: "Tank-Farm",
+        unit: "%",
+        sensor_type: "control_valve",
+        description: "Control valve position feedback",
+        normal: Some(NormalRange { metric: "deviation", min: 0.0, max: 5.0 }),
+        thresholds: &[],
+        calibration: Some(Calibration { lrv: 0.0, urv: 100.0, accuracy: 0.5, standard: "Fisher FIELDVUE Valve Signature Test" }),
+    },
+    SensorDescriptor {
+        key: "strain-gauge",
+        device_id: "STR-019",
+        display_name: "Strain Gauge",
+        line: "Bridge-Girder-S",
+        area: "Civil-Infrastructure",
+        unit: "µε",
+        sensor_type: "strain_gauge",
+        description: "Structural strain gauge with fatigue-cycle counting",
+        normal: None,
+        thresholds: &[],
+        calibration: Some(Calibration { lrv: -300.0, urv: 1500.0, accuracy: 2.0, standard: "Vishay 1550B Strain Indicator Calibrator" }),
+    },
+    SensorDescriptor {
+        key: "accelerometer",
+        device_id: "ACC-021",
+        display_name: "Tri-axial Accelerometer",
+        line: "CNC-Machine-02",
+        area: "Machine-Shop",
+        unit: "g",
+        sensor_type: "accelerometer",
+        description: "Three-axis MEMS accelerometer with tilt from the gravity component",
+        normal: Some(NormalRange { metric: "vibrationRms", min: 0.0, max: 0.7 }),
+        thresholds: &[("good", 0.3), ("satisfactory", 0.7), ("unsatisfactory", 1.5)],
+        calibration: Some(Calibration { lrv: -16.0, urv: 16.0, accuracy: 0.02, standard: "PCB 9155D Accelerometer Calibration Workstation" }),
+    },
+    SensorDescriptor {
+        key: "sound-level",
+        device_id: "SND-022",
+        display_name: "Sound Level Meter",
+        line: "Perimeter-Monitoring",
+        area: "Environment",
+        unit: "dB(A)",
+        sensor_type: "sound_level",
+        description: "Class 1 integrating sound level meter with octave-band analysis",
+        normal: None,
+        thresholds: &[("lowerAction", 80.0), ("exposureLimit", 85.0)],
+        calibration: Some(Calibration { lrv: 30.0, urv: 130.0, accuracy: 0.3, standard: "Brüel & Kjær 4231 Sound Calibrator (94/114 dB)" }),
+    },
+    SensorDescriptor {
+        key: "wind",
+        device_id: "WND-023",
+        display_name: "Anemometer",
+        line: "Weather-Station-R",
+        area: "Environment",
+        unit: "m/s",
+        sensor_type: "wind",
+        description: "Cup anemometer and wind vane with gust detection",
+        normal: None,
+        thresholds: &[("gale", 17.2), ("storm", 24.5)],
+        calibration: Some(Calibration { lrv: 0.0, urv: 60.0, accuracy: 0.3, standard: "Closed-circuit Wind Tunnel (MEASNET)" }),
+    },
+    SensorDescriptor {
+        key: "smoke-detector",
+        device_id: "SMK-024",
+        display_name: "Smoke Detector",
+        line: "Fire-Zone-3",
+        area: "Warehouse",
+        unit: "%/m",
+        sensor_type: "smoke_detector",
+        description: "Multi-criteria optical smoke and rate-of-rise heat detector",
+        normal: None,
+        thresholds: &[("obscurationAlarm", 4.0), ("heatRiseAlarm", 8.3)],
+        calibration: Some(Calibration { lrv: 0.0, urv: 20.0, accuracy: 0.2, standard: "Smoke Tunnel (EN 54-7 test aerosol)" }),
+    },
+    SensorDescriptor {
+        key: "contact",
+        device_id: "DIO-025",
+        display_name: "Door Contact",
+        line: "Safety-Interlock-A",
+        area: "Machine-Shop",
+        unit: "1",
+        sensor_type: "contact",
+        description: "Guard door interlock contact (digital input)",
+        normal: None,
+        thresholds: &[],
+        calibration: Some(Calibration { lrv: 0.0, urv: 1.0, accuracy: 0.0, standard: "Fluke 754 Documenting Process Calibrator (switch test)" }),
+    },
+    SensorDescriptor {
+        key: "power-quality",
+        device_id: "PQM-026",
+        display_name: "Power Quality Analyzer",
+        line: "Main-Panel-H",
+        area: "Electrical",
+        unit: "%",
+        sensor_type: "power_quality",
+        description: "Harmonics, crest factor and feeder efficiency derived from the main energy meter",
+        normal: Some(NormalRange { metric: "thdVoltage", min: 0.0, max: 8.0 }),
+        thresholds: &[("thdVoltageLimit", 8.0), ("individualHarmonicLimit", 5.0), ("sinusoidalCrestFactor", std::f64::consts::SQRT_2)],
+        calibration: Some(Calibration { lrv: 0.0, urv: 100.0, accuracy: 0.1, standard: "Fluke 6105A Electrical Power Standard" }),
+    },
+];
+
+fn sensor_descriptor(key: &str) -> Option<&'static SensorDescriptor> {
+    SENSOR_DESCRIPTORS.iter().find(|d| d.key == key)
+}
+
+/// A reading of a `SENSORS_CONFIG` sensor: its walked value plus the
+/// configured thresholds, graded against the configured normal band
+fn simulate_configured_sensor(
+    state: &AppState,
+    rng: &mut impl Rng,
+    config: &sensor_config::SensorConfig,
+    server_ts: String,
+) -> serde_json::Value {
+    let value = walk(state, rng, &config.key);
+    let normal = config.normal();
+    let quality = generate_data_quality(value, normal.min, normal.max);
+    let status_code = generate_opcua_status_code(&quality);
+    let source_ts = state.clock.now().to_rfc3339();
+
+    let mut fields = serde_json::Map::new();
+    fields.insert("value".to_string(), serde_json::json!(format!("{:.*}", config.decimals, value).parse::<f64>().unwrap()));
+    for (name, &limit) in &config.thresholds {
+        fields.insert(name.clone(), serde_json::json!(limit));
+    }
+
+    let unified = UnifiedSensorData {
+        opc_ua: generate_opcua_node(&config.device_id, config.display_name()),
+        equipment_hierarchy: generate_isa95_hierarchy(&config.device_id, &config.line, &config.area),
+        sparkplug_topic: generate_sparkplug_topic("Plant-01", &config.device_id),
+        source_timestamp: source_ts,
+        server_timestamp: server_ts,
+        value: serde_json::Value::Object(fields),
+        data_quality: quality,
+        opc_ua_status_code: status_code,
+        opc_ua_status_name: status_code.name(),
+        unit: get_ucum_unit(&config.unit),
+        sensor_type: config.sensor_type().to_string(),
+        description: config.description.clone(),
+        properties: serde_json::json!({}),
+    };
+    serde_json::to_value(unified).unwrap()
+}
+
+fn simulate_sensor(state: &AppState, key: &str) -> Option<serde_json::Value> {
+    // The sensor's own stream, so concurrent reads of other sensors neither
+    // wait on it nor change what it draws
+    let rng = state.rngs.sensor(key);
+    let mut rng = rng.lock().unwrap();
+    let server_ts = state.clock.now().to_rfc3339();
+    if let Some(config) = sensor_config(state, key) {
+        return Some(simulate_configured_sensor(state, &mut *rng, config, server_ts));
+    }
+    let desc = sensor_descriptor(base_sensor_key(state, key))?;
+    
+    match desc.key {
+        "temperature" => {
+            let temp = walk(state, &mut *rng, key);
+            *state.ambient_temperature.lock().unwrap() = temp;
+            let quality = desc.quality(temp);
+            let status_code = generate_opcua_status_code(&quality);
+            let source_ts = state.clock.now().to_rfc3339();
+            
+            let unified = UnifiedSensorData {
+                opc_ua: generate_opcua_node(desc.device_id, desc.display_name),
+                equipment_hierarchy: generate_isa95_hierarchy(desc.device_id, desc.line, desc.area),
+                sparkplug_topic: generate_sparkplug_topic("Plant-01", desc.device_id),
+                source_timestamp: source_ts,
+                server_timestamp: server_ts,
+                value: serde_json::json!({
+                    "value": format!("{:.1}", temp).parse::<f64>().unwrap(),
+                    "minThreshold": desc.threshold("minThreshold"),
+                    "maxThreshold": desc.threshold("maxThreshold"),
+                    "criticalHigh": desc.threshold("criticalHigh"),
+                    "criticalLow": desc.threshold("criticalLow")
+                }),
+                data_quality: quality,
+                opc_ua_status_code: status_code,
+                opc_ua_status_name: status_code.name(),
+                unit: get_ucum_unit(desc.unit),
+                sensor_type: desc.sensor_type.to_string(),
+                description: desc.description.to_string(),
+                properties: serde_json::json!({}),
+            };
+            Some(serde_json::to_value(unified).unwrap())
+        }
+        "humidity" => {
+            let humidity = walk(state, &mut *rng, key);
+            // Same air the temperature sensor measures, so the dew point agrees with it
+            let ambient = *state.ambient_temperature.lock().unwrap();
+            let quality = desc.quality(humidity);
+            let status_code = generate_opcua_status_code(&quality);
+            let source_ts = state.clock.now().to_rfc3339();
+            
+            let unified = UnifiedSensorData {
+                opc_ua: generate_opcua_node(desc.device_id, desc.display_name),
+                equipment_hierarchy: generate_isa95_hierarchy(desc.device_id, desc.line, desc.area),
+                sparkplug_topic: generate_sparkplug_topic("Plant-01", desc.device_id),
+                source_timestamp: source_ts,
+                server_timestamp: server_ts.clone(),
+                value: serde_json::json!({
+                    "value": format!("{:.1}", humidity).parse::<f64>().unwrap(),
+                    "optimalMin": desc.threshold("optimalMin"),
+                    "optimalMax": desc.threshold("optimalMax"),
+                    "allowableMin": desc.threshold("allowableMin"),
+                    "allowableMax": desc.threshold("allowableMax"),
+                    "ambientTemperature": format!("{:.1}", ambient).parse::<f64>().unwrap(),
+                    "dewPoint": format!("{:.1}", temp_to_dewpoint(humidity, ambient)).parse::<f64>().unwrap()
+                }),
+                data_quality: quality,
+                opc_ua_status_code: status_code,
+                opc_ua_status_name: status_code.name(),
+                unit: get_ucum_unit(desc.unit),
+                sensor_type: desc.sensor_type.to_string(),
+                description: desc.description.to_string(),
+                properties: serde_json::json!({}),
+            };
+            Some(serde_json::to_value(unified).unwrap())
+        }
+        "oil-level" => {
+            let capacity_liters = rng.gen_range(10000..50001);
+            let level_percent = walk(state, &mut *rng, key);
+            let current_volume = (capacity_liters as f64 * level_percent / 100.0) as i32;
+            let quality = desc.quality(level_percent);
+            let status_code = generate_opcua_status_code(&quality);
+            let source_ts = state.clock.now().to_rfc3339();
+            
+            let unified = UnifiedSensorData {
+                opc_ua: generate_opcua_node(desc.device_id, desc.display_name),
+                equipment_hierarchy: generate_isa95_hierarchy(desc.device_id, desc.line, desc.area),
+                sparkplug_topic: generate_sparkplug_topic("Plant-01", desc.device_id),
+                source_timestamp: source_ts,
+                server_timestamp: server_ts.clone(),
+                value: serde_json::json!({
+                    "value": format!("{:.1}", level_percent).parse::<f64>().unwrap(),
+                    "tankCapacityLiters": capacity_liters,
+                    "tankCapacityM3": format!("{:.1}", capacity_liters as f64 / 1000.0).parse::<f64>().unwrap(),
+                    "currentVolumeLiters": current_volume,
+                    "currentVolumeM3": format!("{:.2}", current_volume as f64 / 1000.0).parse::<f64>().unwrap(),
+                    "lowAlarmThreshold": desc.threshold("lowAlarmThreshold"),
+                    "highAlarmThreshold": desc.threshold("highAlarmThreshold")
+                }),
+                data_quality: quality,
+                opc_ua_status_code: status_code,
+                opc_ua_status_name: status_code.name(),
+                unit: get_ucum_unit(desc.unit),
+                sensor_type: desc.sensor_type.to_string(),
+                description: desc.description.to_string(),
+                properties: serde_json::json!({}),
+            };
+            Some(serde_json::to_value(unified).unwrap())
+        }
+        "oil-pressure" => {
+            let pressure = walk(state, &mut *rng, key);
+            let flow_rate = random_between(&mut *rng, 50.0, 500.0);
+            let quality = desc.quality(pressure);
+            let status_code = generate_opcua_status_code(&quality);
+            let source_ts = state.clock.now().to_rfc3339();
+            
+            let unified = UnifiedSensorData {
+                opc_ua: generate_opcua_node(desc.device_id, desc.display_name),
+                equipment_hierarchy: generate_isa95_hierarchy(desc.device_id, desc.line, desc.area),
+                sparkplug_topic: generate_sparkplug_topic("Plant-01", desc.device_id),
+                source_timestamp: source_ts,
+                server_timestamp: server_ts.clone(),
+                value: serde_json::json!({
+                    "value": format!("{:.2}", pressure).parse::<f64>().unwrap(),
+                    "flowRateLpm": format!("{:.1}", flow_rate).parse::<f64>().unwrap(),
+                    "operatingRange": "10-200 bar",
+                    "maxWorkingPressure": desc.threshold("maxWorkingPressure")
+                }),
+                data_quality: quality,
+                opc_ua_status_code: status_code,
+                opc_ua_status_name: status_code.name(),
+                unit: get_ucum_unit(desc.unit),
+                sensor_type: desc.sensor_type.to_string(),
+                description: desc.description.to_string(),
+                properties: serde_json::json!({}),
+            };
+            Some(serde_json::to_value(unified).unwrap())
+        }
+        "air-quality" => {
+            let pm25 = walk(state, &mut *rng, key);
+            let pm10 = pm25 * random_between(&mut *rng, 1.5, 2.5);
+            let co2 = random_between(&mut *rng, 400.0, 1500.0);
+            let voc = random_between(&mut *rng, 0.1, 2.0);
+            let aqi = calculate_aqi_pm25(pm25);
+            // Rolling averages over the standard reporting windows, from history
+            let windows = [("1m", 1), ("15m", 15), ("1h", 60)];
+            let average = |field: &str, minutes: i64| {
+                history_average(state, "air-quality", field, chrono::Duration::minutes(minutes))
+            };
+            let averages_of = |field: &str| -> serde_json::Map<String, serde_json::Value> {
+                windows.iter()
+                    .map(|&(name, minutes)| {
+                        let avg = average(field, minutes).map(|(v, _)| format!("{:.1}", v).parse::<f64>().unwrap());
+                        (name.to_string(), serde_json::json!(avg))
+                    })
+                    .collect()
+            };
+            let samples: serde_json::Map<String, serde_json::Value> = windows.iter()
+                .map(|&(name, minutes)| (name.to_string(), serde_json::json!(average("pm25", minutes).map_or(0, |(_, n)| n))))
+                .collect();
+            // The hourly index is reported on the 1-hour PM2.5 mean (as NowCast
+            // does) rather than on an instantaneous spike
+            let aqi_hourly = average("pm25", 60).map(|(v, _)| calculate_aqi_pm25(v));
+            let quality = if aqi <= 100 { desc.quality(pm25) } else { DataQuality::Bad };
+            let status_code = generate_opcua_status_code(&quality);
+            let source_ts = state.clock.now().to_rfc3339();
+            
+            let unified = UnifiedSensorData {
+                opc_ua: generate_opcua_node(desc.device_id, desc.display_name),
+                equipment_hierarchy: generate_isa95_hierarchy(desc.device_id, desc.line, desc.area),
+                sparkplug_topic: generate_sparkplug_topic("Plant-01", desc.device_id),
+                source_timestamp: source_ts,
+                server_timestamp: server_ts.clone(),
+                value: serde_json::json!({
+                    "pm25": format!("{:.1}", pm25).parse::<f64>().unwrap(),
+                    "pm10": format!("{:.1}", pm10).parse::<f64>().unwrap(),
+                    "co2": format!("{:.0}", co2).parse::<f64>().unwrap(),
+                    "voc": format!("{:.2}", voc).parse::<f64>().unwrap(),
+                    "aqi": aqi,
+                    "aqiHourly": aqi_hourly,
+                    "averages": {
+                        "pm25": averages_of("pm25"),
+                        "pm10": averages_of("pm10"),
+                        "samples": samples
+                    },
+                    "whoPm25Guideline": desc.threshold("whoPm25Guideline"),
+                    "whoPm10Guideline": desc.threshold("whoPm10Guideline"),
+                    "co2Threshold": desc.threshold("co2Threshold")
+                }),
+                data_quality: quality,
+                opc_ua_status_code: status_code,
+                opc_ua_status_name: status_code.name(),
+                unit: get_ucum_unit(desc.unit),
+                sensor_type: desc.sensor_type.to_string(),
+                description: desc.description.to_string(),
+                properties: serde_json::json!({}),
+            };
+            Some(serde_json::to_value(unified).unwrap())
+        }
+        "pressure" => {
+            let pressure = walk(state, &mut *rng, key);
+            let altitude = random_between(&mut *rng, 0.0, 100.0);
+            let sea_level_pressure = pressure * (1.0 + (altitude / 44330.0)).powf(5.255);
+            let trend = if rng.gen_bool(0.5) { "rising" } else { "falling" };
+            let quality = desc.quality(pressure);
+            let status_code = generate_opcua_status_code(&quality);
+            let source_ts = state.clock.now().to_rfc3339();
+            
+            let unified = UnifiedSensorData {
+                opc_ua: generate_opcua_node(desc.device_id, desc.display_name),
+                equipment_hierarchy: generate_isa95_hierarchy(desc.device_id, desc.line, desc.area),
+                sparkplug_topic: generate_sparkplug_topic("Plant-01", desc.device_id),
+                source_timestamp: source_ts,
+                server_timestamp: server_ts.clone(),
+                value: serde_json::json!({
+                    "value": format!("{:.1}", pressure).parse::<f64>().unwrap(),
+                    "seaLevelPressure": format!("{:.1}", sea_level_pressure).parse::<f64>().unwrap(),
+                    "altitudeMeters": format!("{:.1}", altitude).parse::<f64>().unwrap(),
+                    "standardPressure": 1013.25,
+                    "trend": trend
+                }),
+                data_quality: quality,
+                opc_ua_status_code: status_code,
+                opc_ua_status_name: status_code.name(),
+                unit: get_ucum_unit(desc.unit),
+                sensor_type: desc.sensor_type.to_string(),
+                description: desc.description.to_string(),
+                properties: serde_json::json!({}),
+            };
+            Some(serde_json::to_value(unified).unwrap())
+        }
+        "vibration" => {
+            let velocity_rms = walk(state, &mut *rng, key);
+            let frequency = random_between(&mut *rng, 10.0, 1000.0);
+            let acceleration = velocity_rms * frequency * 2.0 * std::f64::consts::PI / 1000.0;
+            let displacement = velocity_rms / (frequency * 2.0 * std::f64::consts::PI) * 1000.0;
+            let quality = desc.quality(velocity_rms);
+            let status_code = generate_opcua_status_code(&quality);
+            let source_ts = state.clock.now().to_rfc3339();
+            
+            let unified = UnifiedSensorData {
+                opc_ua: generate_opcua_node(desc.device_id, desc.display_name),
+                equipment_hierarchy: generate_isa95_hierarchy(desc.device_id, desc.line, desc.area),
+                sparkplug_topic: generate_sparkplug_topic("Plant-01", desc.device_id),
+                source_timestamp: source_ts,
+                server_timestamp: server_ts.clone(),
+                value: serde_json::json!({
+                    "velocityRms": format!("{:.3}", velocity_rms).parse::<f64>().unwrap(),
+                    "frequency": format!("{:.1}", frequency).parse::<f64>().unwrap(),
+                    "acceleration": format!("{:.3}", acceleration).parse::<f64>().unwrap(),
+                    "displacement": format!("{:.4}", displacement).parse::<f64>().unwrap(),
+                    "machineType": "Class II (Medium machines)",
+                    "iso10816Limits": {
+                        "good": desc.threshold("good"),
+                        "satisfactory": desc.threshold("satisfactory"),
+                        "unsatisfactory": desc.threshold("unsatisfactory")
+                    }
+                }),
+                data_quality: quality,
+                opc_ua_status_code: status_code,
+                opc_ua_status_name: status_code.name(),
+                unit: get_ucum_unit(desc.unit),
+                sensor_type: desc.sensor_type.to_string(),
+                description: desc.description.to_string(),
+                properties: serde_json::json!({}),
+            };
+            Some(serde_json::to_value(unified).unwrap())
+        }
+        "energy-meter" => {
+            let MainsSnapshot {
+                voltage_l1, current, power_factor, frequency, active_power, apparent_power, reactive_power,
+            } = sample_mains(state, &mut *rng);
+            let voltage_l3 = voltage_l1 * 1.732;
+            let energy_kwh = accumulate_totalizer(state, key, random_between(&mut *rng, 10000.0, 500000.0), active_power);
+            let quality = desc.quality(power_factor);
+            let status_code = generate_opcua_status_code(&quality);
+            let source_ts = state.clock.now().to_rfc3339();
+            
+            let unified = UnifiedSensorData {
+                opc_ua: generate_opcua_node(desc.device_id, desc.display_name),
+                equipment_hierarchy: generate_isa95_hierarchy(desc.device_id, desc.line, desc.area),
+                sparkplug_topic: generate_sparkplug_topic("Plant-01", desc.device_id),
+                source_timestamp: source_ts,
+                server_timestamp: server_ts.clone(),
+                value: serde_json::json!({
+                    "activePower": format!("{:.2}", active_power).parse::<f64>().unwrap(),
+                    "apparentPower": format!("{:.2}", apparent_power).parse::<f64>().unwrap(),
+                    "reactivePower": format!("{:.2}", reactive_power).parse::<f64>().unwrap(),
+                    "voltageL1": format!("{:.1}", voltage_l1).parse::<f64>().unwrap(),
+                    "voltageL3": format!("{:.1}", voltage_l3).parse::<f64>().unwrap(),
+                    "current": format!("{:.2}", current).parse::<f64>().unwrap(),
+                    "powerFactor": format!("{:.3}", power_factor).parse::<f64>().unwrap(),
+                    "frequency": format!("{:.2}", frequency).parse::<f64>().unwrap(),
+                    "cumulativeEnergy": format!("{:.1}", energy_kwh).parse::<f64>().unwrap()
+                }),
+                data_quality: quality,
+                opc_ua_status_code: status_code,
+                opc_ua_status_name: status_code.name(),
+                unit: get_ucum_unit(desc.unit),
+                sensor_type: desc.sensor_type.to_string(),
+                description: desc.description.to_string(),
+                properties: serde_json::json!({}),
+            };
+            Some(serde_json::to_value(unified).unwrap())
+        }
+        "amr" => {
+            let fixed = amr_station(state, key);
+            let station = fixed.copied().unwrap_or_else(|| get_random_oil_station(state.oil_stations, &mut *rng));
+            // A station's meter walks on its own, seeded like the shared "amr" walk
+            if fixed.is_some() {
+                let mut walks = state.sensor_states.lock().unwrap();
+                if let (false, Some(base)) = (walks.contains_key(key), walks.get("amr")) {
+                    let seeded = SensorState { value: random_between(&mut *rng, base.min, base.max), velocity: 0.0, ..*base };
+                    walks.insert(key.to_string(), seeded);
+                }
+            }
+            let (device_id, display_name) = match fixed {
+                Some(station) => (station.meter_serial, format!("AMR {}", station.location)),
+                None => (desc.device_id, desc.display_name.to_string()),
+            };
+            let flow_rate_m3h = walk(state, &mut *rng, key);
+            let flow_rate_lmin = flow_rate_m3h * 1000.0 / 60.0;
+            let inlet_pressure = random_between(&mut *rng, 30.0, 80.0);
+            let outlet_pressure = inlet_pressure - random_between(&mut *rng, 5.0, 20.0);
+            let temperature = random_between(&mut *rng, 40.0, 70.0);
+            let api_gravity = random_between(&mut *rng, 25.0, 35.0);
+            let density = (141.5 / (api_gravity + 131.5)) * 998.0;
+            let viscosity = random_between(&mut *rng, 10.0, 100.0);
+            let cumulative = accumulate_totalizer(state, key, random_between(&mut *rng, 1000000.0, 50000000.0), flow_rate_m3h * 1000.0);
+            let quality = desc.quality(inlet_pressure);
+            let status_code = generate_opcua_status_code(&quality);
+            let source_ts = state.clock.now().to_rfc3339();
+            
+            let unified = UnifiedSensorData {
+                opc_ua: generate_opcua_node(device_id, &display_name),
+                equipment_hierarchy: generate_isa95_hierarchy(device_id, desc.line, desc.area),
+                sparkplug_topic: generate_sparkplug_topic("Plant-01", device_id),
+                source_timestamp: source_ts,
+                server_timestamp: server_ts.clone(),
+                value: serde_json::json!({
+                    "meterSerial": station.meter_serial,
+                    "pipelineId": station.pipeline_id,
+                    "location": station.location,
+                    "province": station.province,
+                    "coordinates": { "lat": station.lat, "lng": station.lng },
+                    "flowRate": format!("{:.2}", flow_rate_lmin).parse::<f64>().unwrap(),
+                    "flowRateM3H": format!("{:.2}", flow_rate_m3h).parse::<f64>().unwrap(),
+                    "flowDirection": if rng.gen_bool(0.95) { "forward" } else { "reverse" },
+                    "cumulativeFlow": format!("{:.1}", cumulative).parse::<f64>().unwrap(),
+                    "inletPressure": format!("{:.2}", inlet_pressure).parse::<f64>().unwrap(),
+                    "outletPressure": format!("{:.2}", outlet_pressure).parse::<f64>().unwrap(),
+                    "differentialPressure": format!("{:.2}", inlet_pressure - outlet_pressure).parse::<f64>().unwrap(),
+                    "temperature": format!("{:.1}", temperature).parse::<f64>().unwrap(),
+                    "apiGravity": format!("{:.1}", api_gravity).parse::<f64>().unwrap(),
+                    "density": format!("{:.1}", density).parse::<f64>().unwrap(),
+                    "viscosity": format!("{:.2}", viscosity).parse::<f64>().unwrap(),
+                    "waterContent": format!("{:.3}", random_between(&mut *rng, 0.1, 2.0)).parse::<f64>().unwrap(),
+                    "pumpSpeed": rng.gen_range(1200..1800),
+                    "valveStatus": if rng.gen_bool(0.85) { "open" } else { "throttled" },
+                    "valveOpenPercent": format!("{:.1}", random_between(&mut *rng, 60.0, 100.0)).parse::<f64>().unwrap(),
+                    "leakDetected": rng.gen_bool(0.02),
+                    "batteryLevel": format!("{:.1}", random_between(&mut *rng, 70.0, 100.0)).parse::<f64>().unwrap(),
+                    "signalStrength": rng.gen_range(-85..-50),
+                    "lastCalibration": "2025-01-15T08:00:00.000Z",
+                    "nextCalibrationDue": "2025-07-15T08:00:00.000Z"
+                }),
+                data_quality: quality,
+                opc_ua_status_code: status_code,
+                opc_ua_status_name: status_code.name(),
+                unit: get_ucum_unit(desc.unit),
+                sensor_type: desc.sensor_type.to_string(),
+                description: desc.description.to_string(),
+                properties: serde_json::json!({}),
+            };
+            Some(serde_json::to_value(unified).unwrap())
+        }
+        // ============================================
+        // 5 NEW ENDPOINTS - Industrial IoT Sensors
+        // ============================================
+        "flow-meter" => {
+            // อ้างอิงจาก industrial flow meters (Rosemount, Endress+Hauser)
+            // Liquid: 0.3-4950 m³/hr, Gas: 3-46000 m³/hr, Steam: 1.6-540000 kg/hr
+            let flow_type = ["liquid", "gas", "steam"][rng.gen_range(0..3)];
+            let (flow_rate, unit, totalizer) = match flow_type {
+                "liquid" => (random_between(&mut *rng, 10.0, 1000.0), "m³/h", random_between(&mut *rng, 10000.0, 500000.0)),
+                "gas" => (random_between(&mut *rng, 100.0, 10000.0), "m³/h", random_between(&mut *rng, 100000.0, 5000000.0)),
+                "steam" => (random_between(&mut *rng, 500.0, 50000.0), "kg/h", random_between(&mut *rng, 1000000.0, 50000000.0)),
+                _ => (0.0, "m³/h", 0.0)
+            };
+            let totalizer = accumulate_totalizer(state, key, totalizer, flow_rate);
+            let temperature = random_between(&mut *rng, 20.0, 200.0);
+            let pressure = random_between(&mut *rng, 1.0, 20.0);
+            let density = if flow_type == "steam" { random_between(&mut *rng, 1.0, 50.0) } else { random_between(&mut *rng, 800.0, 1000.0) };
+            let meter_types = ["electromagnetic", "vortex", "ultrasonic", "coriolis"];
+            let meter_type = meter_types[rng.gen_range(0..4)];
+            let quality = desc.quality(flow_rate);
+            let status_code = generate_opcua_status_code(&quality);
+            let source_ts = state.clock.now().to_rfc3339();
+            
+            let unified = UnifiedSensorData {
+                opc_ua: generate_opcua_node(desc.device_id, desc.display_name),
+                equipment_hierarchy: generate_isa95_hierarchy(desc.device_id, desc.line, desc.area),
+                sparkplug_topic: generate_sparkplug_topic("Plant-01", desc.device_id),
+                source_timestamp: source_ts,
+                server_timestamp: server_ts.clone(),
+                value: serde_json::json!({
+                    "mediaType": flow_type,
+                    "flowRate": format!("{:.2}", flow_rate).parse::<f64>().unwrap(),
+                    "totalizer": format!("{:.1}", totalizer).parse::<f64>().unwrap(),
+                    "temperature": format!("{:.1}", temperature).parse::<f64>().unwrap(),
+                    "pressure": format!("{:.2}", pressure).parse::<f64>().unwrap(),
+                    "density": format!("{:.1}", density).parse::<f64>().unwrap(),
+                    "pipeSize": rng.gen_range(50..300),
+                    "meterType": meter_type
+                }),
+                data_quality: quality,
+                opc_ua_status_code: status_code,
+                opc_ua_status_name: status_code.name(),
+                unit: get_ucum_unit(unit),
+                sensor_type: desc.sensor_type.to_string(),
+                description: desc.description.to_string(),
+                properties: serde_json::json!({}),
+            };
+            Some(serde_json::to_value(unified).unwrap())
+        }
+        "gas-detector" => {
+            let co = walk(state, &mut *rng, key);
+            let h2s = random_between(&mut *rng, 0.0, 10.0);
+            let o2 = random_between(&mut *rng, 19.5, 23.5);
+            let lel = random_between(&mut *rng, 0.0, 20.0);
+            let co_alarm = co > desc.threshold("coAlarmSetpoint")?;
+            let h2s_alarm = h2s > desc.threshold("h2sAlarmSetpoint")?;
+            let o2_alarm = !(desc.threshold("o2LowAlarm")?..=desc.threshold("o2HighAlarm")?).contains(&o2);
+            let lel_alarm = lel > desc.threshold("lelAlarmSetpoint")?;
+            let quality = if co_alarm || h2s_alarm || o2_alarm || lel_alarm { DataQuality::Bad } else { DataQuality::Good };
+            let status_code = generate_opcua_status_code(&quality);
+            let source_ts = state.clock.now().to_rfc3339();
+            
+            let unified = UnifiedSensorData {
+                opc_ua: generate_opcua_node(desc.device_id, desc.display_name),
+                equipment_hierarchy: generate_isa95_hierarchy(desc.device_id, desc.line, desc.area),
+                sparkplug_topic: generate_sparkplug_topic("Plant-01", desc.device_id),
+                source_timestamp: source_ts,
+                server_timestamp: server_ts.clone(),
+                value: serde_json::json!({
+                    "carbonMonoxide": format!("{:.1}", co).parse::<f64>().unwrap(),
+                    "coAlarmSetpoint": desc.threshold("coAlarmSetpoint"),
+                    "hydrogenSulfide": format!("{:.2}", h2s).parse::<f64>().unwrap(),
+                    "h2sAlarmSetpoint": desc.threshold("h2sAlarmSetpoint"),
+                    "oxygen": format!("{:.1}", o2).parse::<f64>().unwrap(),
+                    "o2LowAlarm": desc.threshold("o2LowAlarm"),
+                    "o2HighAlarm": desc.threshold("o2HighAlarm"),
+                    "lel": format!("{:.1}", lel).parse::<f64>().unwrap(),
+                    "lelAlarmSetpoint": desc.threshold("lelAlarmSetpoint"),
+                    "alarms": {
+                        "co": co_alarm,
+                        "h2s": h2s_alarm,
+                        "o2": o2_alarm,
+                        "lel": lel_alarm
+                    }
+                }),
+                data_quality: quality,
+                opc_ua_status_code: status_code,
+                opc_ua_status_name: status_code.name(),
+                unit: get_ucum_unit(desc.unit),
+                sensor_type: desc.sensor_type.to_string(),
+                description: desc.description.to_string(),
+                properties: serde_json::json!({}),
+            };
+            Some(serde_json::to_value(unified).unwrap())
+        }
+        "ph-sensor" => {
+            let ph = walk(state, &mut *rng, key);
+            let orp = random_between(&mut *rng, -500.0, 500.0);
+            let temperature = random_between(&mut *rng, 15.0, 40.0);
+            let conductivity = random_between(&mut *rng, 100.0, 5000.0);
+            let turbidity = random_between(&mut *rng, 0.1, 100.0);
+            let quality = desc.quality(ph);
+            let status_code = generate_opcua_status_code(&quality);
+            let source_ts = state.clock.now().to_rfc3339();
+            
+            let unified = UnifiedSensorData {
+                opc_ua: generate_opcua_node(desc.device_id, desc.display_name),
+                equipment_hierarchy: generate_isa95_hierarchy(desc.device_id, desc.line, desc.area),
+                sparkplug_topic: generate_sparkplug_topic("Plant-01", desc.device_id),
+                source_timestamp: source_ts,
+                server_timestamp: server_ts.clone(),
+                value: serde_json::json!({
+                    "phValue": format!("{:.2}", ph).parse::<f64>().unwrap(),
+                    "orp": format!("{:.1}", orp).parse::<f64>().unwrap(),
+                    "temperature": format!("{:.1}", temperature).parse::<f64>().unwrap(),
+                    "conductivity": format!("{:.1}", conductivity).parse::<f64>().unwrap(),
+                    "turbidity": format!("{:.2}", turbidity).parse::<f64>().unwrap()
+                }),
+                data_quality: quality,
+                opc_ua_status_code: status_code,
+                opc_ua_status_name: status_code.name(),
+                unit: get_ucum_unit(desc.unit),
+                sensor_type: desc.sensor_type.to_string(),
+                description: desc.description.to_string(),
+                properties: serde_json::json!({}),
+            };
+            Some(serde_json::to_value(unified).unwrap())
+        }
+        "level-sensor" => {
+            let tank = LEVEL_SENSOR_TANK;
+            // A level driven through SIM_SENSOR_DEPENDS replaces the walk, so
+            // percentage and volume follow the same level the reading reports
+            let driven = state.driven_values.lock().unwrap().get(key).copied();
+            let (empty, full) = physical_limits(key)?;
+            let level = driven.unwrap_or_else(|| walk(state, &mut *rng, key)).clamp(empty, full);
+            let percentage = tank.percentage(level);
+            let volume = tank.volume(level);
+            let sensor_type = ["ultrasonic", "radar", "guided_wave", "pressure"][rng.gen_range(0..4)];
+            let quality = desc.quality(percentage);
+            let status_code = generate_opcua_status_code(&quality);
+            let source_ts = state.clock.now().to_rfc3339();
+            
+            let unified = UnifiedSensorData {
+                opc_ua: generate_opcua_node(desc.device_id, desc.display_name),
+                equipment_hierarchy: generate_isa95_hierarchy(desc.device_id, desc.line, desc.area),
+                sparkplug_topic: generate_sparkplug_topic("Plant-01", desc.device_id),
+                source_timestamp: source_ts,
+                server_timestamp: server_ts.clone(),
+                value: serde_json::json!({
+                    "level": format!("{:.3}", level).parse::<f64>().unwrap(),
+                    "tankHeight": tank.height_m,
+                    "tankDiameter": tank.diameter_m,
+                    "crossSectionArea": format!("{:.3}", tank.cross_section()).parse::<f64>().unwrap(),
+                    "percentage": format!("{:.2}", percentage).parse::<f64>().unwrap(),
+                    "volume": format!("{:.2}", volume).parse::<f64>().unwrap(),
+                    "sensorType": sensor_type,
+                    "accuracy": "±3mm"
+                }),
+                data_quality: quality,
+                opc_ua_status_code: status_code,
+                opc_ua_status_name: status_code.name(),
+                unit: get_ucum_unit(desc.unit),
+                sensor_type: desc.sensor_type.to_string(),
+                description: desc.description.to_string(),
+                properties: serde_json::json!({}),
+            };
+            Some(serde_json::to_value(unified).unwrap())
+        }
+        "proximity-sensor" => {
+            let object_detected = rng.gen_bool(0.7);
+            let distance = if object_detected { random_between(&mut *rng, 5.0, 50.0) } else { -1.0 };
+            let sensor_type = ["inductive", "capacitive", "photoelectric", "ultrasonic"][rng.gen_range(0..4)];
+            let detection_count = rng.gen_range(0..10000);
+            let operating_time = random_between(&mut *rng, 1000.0, 50000.0);
+            let quality = if object_detected { DataQuality::Good } else { DataQuality::Uncertain };
+            let status_code = generate_opcua_status_code(&quality);
+            let source_ts = state.clock.now().to_rfc3339();
+
+            let unified = UnifiedSensorData {
+                opc_ua: generate_opcua_node(desc.device_id, desc.display_name),
+                equipment_hierarchy: generate_isa95_hierarchy(desc.device_id, desc.line, desc.area),
+                sparkplug_topic: generate_sparkplug_topic("Plant-01", desc.device_id),
+                source_timestamp: source_ts,
+                server_timestamp: server_ts.clone(),
+                value: serde_json::json!({
+                    "objectDetected": object_detected,
+                    "distance": if distance > 0.0 { Some(format!("{:.1}", distance).parse::<f64>().unwrap()) } else { None },
+                    "sensorType": sensor_type,
+                    "detectionRange": random_between(&mut *rng, 1.0, 100.0),
+                    "responseTime": random_between(&mut *rng, 0.1, 10.0),
+                    "switchingFrequency": rng.gen_range(100..5000),
+                    "detectionCount": detection_count,
+                    "operatingTime": format!("{:.1}", operating_time).parse::<f64>().unwrap()
+                }),
+                data_quality: quality,
+                opc_ua_status_code: status_code,
+                opc_ua_status_name: status_code.name(),
+                unit: get_ucum_unit(desc.unit),
+                sensor_type: desc.sensor_type.to_string(),
+                description: desc.description.to_string(),
+                properties: serde_json::json!({}),
+            };
+            Some(serde_json::to_value(unified).unwrap())
+        }
+        "gps-tracker" => {
+            let (position, heading, speed_kmh, odometer_km) = {
+                let mut gps = state.gps_tracker.lock().unwrap();
+                gps.advance(&mut *rng);
+                (gps.position, gps.heading, gps.speed_kmh, gps.odometer_km)
+            };
+            let geofences = evaluate_geofences(state, key, position);
+            let inside_any = geofences.iter().any(|g| g["inside"].as_bool().unwrap_or(false));
+            let satellites = rng.gen_range(4..15);
+            let hdop = random_between(&mut *rng, 0.6, 3.0);
+            let quality = desc.quality(hdop);
+            let status_code = generate_opcua_status_code(&quality);
+            let source_ts = state.clock.now().to_rfc3339();
+
+            let unified = UnifiedSensorData {
+                opc_ua: generate_opcua_node(desc.device_id, desc.display_name),
+                equipment_hierarchy: generate_isa95_hierarchy(desc.device_id, desc.line, desc.area),
+                sparkplug_topic: generate_sparkplug_topic("Plant-01", desc.device_id),
+                source_timestamp: source_ts,
+                server_timestamp: server_ts.clone(),
+                value: serde_json::json!({
+                    "value": format!("{:.1}", speed_kmh).parse::<f64>().unwrap(),
+                    "coordinates": {
+                        "lat": format!("{:.6}", position.lat).parse::<f64>().unwrap(),
+                        "lng": format!("{:.6}", position.lng).parse::<f64>().unwrap()
+                    },
+                    "heading": format!("{:.1}", heading).parse::<f64>().unwrap(),
+                    "odometerKm": format!("{:.3}", odometer_km).parse::<f64>().unwrap(),
+                    "distanceFromDepotM": format!("{:.1}", haversine_meters(GPS_DEPOT, position)).parse::<f64>().unwrap(),
+                    "satellites": satellites,
+                    "hdop": format!("{:.2}", hdop).parse::<f64>().unwrap(),
+                    "geofences": geofences,
+                    "insideGeofence": inside_any
+                }),
+                data_quality: quality,
+                opc_ua_status_code: status_code,
+                opc_ua_status_name: status_code.name(),
+                unit: get_ucum_unit(desc.unit),
+                sensor_type: desc.sensor_type.to_string(),
+                description: desc.description.to_string(),
+                properties: serde_json::json!({}),
+            };
+            Some(serde_json::to_value(unified).unwrap())
+        }
+        "solar-panel" => {
+            let config = &state.solar;
+            let hour = local_hour(state.clock.now(), state.tz_offset_hours);
+            let ambient = *state.ambient_temperature.lock().unwrap();
+            let cloud_cover = random_between(&mut *rng, 0.0, 0.8);
+            let irradiance = clear_sky_irradiance(hour, config.sunrise_hour, config.sunset_hour)
+                * (1.0 - 0.75 * cloud_cover);
+            // NOCT model: cells run ~25°C above ambient at 800 W/m²
+            let panel_temp = ambient + irradiance / 800.0 * 25.0;
+            // Crystalline silicon loses ~0.4% of output per °C above STC (25°C)
+            let temp_derate = (1.0 - 0.004 * (panel_temp - 25.0)).min(1.0);
+            let dc_power = config.capacity_kw * irradiance / 1000.0 * temp_derate;
+            let (inverter_efficiency, panel_voltage) = if dc_power > 0.0 {
+                (random_between(&mut *rng, 0.94, 0.98), 600.0 * (1.0 - 0.003 * (panel_temp - 25.0)))
+            } else {
+                (0.0, 0.0)
+            };
+            let panel_current = if panel_voltage > 0.0 { dc_power * 1000.0 / panel_voltage } else { 0.0 };
+            let ac_power = dc_power * inverter_efficiency;
+            let quality = desc.quality(panel_temp);
+            let status_code = generate_opcua_status_code(&quality);
+            let source_ts = state.clock.now().to_rfc3339();
+
+            let unified = UnifiedSensorData {
+                opc_ua: generate_opcua_node(desc.device_id, desc.display_name),
+                equipment_hierarchy: generate_isa95_hierarchy(desc.device_id, desc.line, desc.area),
+                sparkplug_topic: generate_sparkplug_topic("Plant-01", desc.device_id),
+                source_timestamp: source_ts,
+                server_timestamp: server_ts.clone(),
+                value: serde_json::json!({
+                    "value": format!("{:.3}", ac_power).parse::<f64>().unwrap(),
+                    "dcPower": format!("{:.3}", dc_power).parse::<f64>().unwrap(),
+                    "irradiance": format!("{:.1}", irradiance).parse::<f64>().unwrap(),
+                    "cloudCover": format!("{:.2}", cloud_cover).parse::<f64>().unwrap(),
+                    "panelTemperature": format!("{:.1}", panel_temp).parse::<f64>().unwrap(),
+                    "ambientTemperature": format!("{:.1}", ambient).parse::<f64>().unwrap(),
+                    "panelVoltage": format!("{:.1}", panel_voltage).parse::<f64>().unwrap(),
+                    "panelCurrent": format!("{:.2}", panel_current).parse::<f64>().unwrap(),
+                    "inverterEfficiency": format!("{:.3}", inverter_efficiency).parse::<f64>().unwrap(),
+                    "ratedCapacity": config.capacity_kw,
+                    "localHour": format!("{:.2}", hour).parse::<f64>().unwrap(),
+                    "daylight": irradiance > 0.0
+                }),
+                data_quality: quality,
+                opc_ua_status_code: status_code,
+                opc_ua_status_name: status_code.name(),
+                unit: get_ucum_unit(desc.unit),
+                sensor_type: desc.sensor_type.to_string(),
+                description: desc.description.to_string(),
+                properties: serde_json::json!({}),
+            };
+            Some(serde_json::to_value(unified).unwrap())
+        }
+        "occupancy" => {
+            let (count, capacity, enter_count, exit_count) = {
+                let mut occupancy = state.occupancy.lock().unwrap();
+                occupancy.advance(local_hour(state.clock.now(), state.tz_offset_hours), &mut *rng);
+                (occupancy.count, occupancy.capacity, occupancy.enter_count, occupancy.exit_count)
+            };
+            let utilization = count as f64 / capacity as f64 * 100.0;
+            let quality = generate_data_quality(count as f64, 0.0, capacity as f64 * 0.9);
+            let status_code = generate_opcua_status_code(&quality);
+            let source_ts = state.clock.now().to_rfc3339();
+
+            let unified = UnifiedSensorData {
+                opc_ua: generate_opcua_node(desc.device_id, desc.display_name),
+                equipment_hierarchy: generate_isa95_hierarchy(desc.device_id, desc.line, desc.area),
+                sparkplug_topic: generate_sparkplug_topic("Plant-01", desc.device_id),
+                source_timestamp: source_ts,
+                server_timestamp: server_ts.clone(),
+                value: serde_json::json!({
+                    "value": count,
+                    "capacity": capacity,
+                    "utilization": format!("{:.1}", utilization).parse::<f64>().unwrap(),
+                    "enterCount": enter_count,
+                    "exitCount": exit_count,
+                    "atCapacity": count >= capacity
+                }),
+                data_quality: quality,
+                opc_ua_status_code: status_code,
+                opc_ua_status_name: status_code.name(),
+                unit: get_ucum_unit(desc.unit),
+                sensor_type: desc.sensor_type.to_string(),
+                description: desc.description.to_string(),
+                properties: serde_json::json!({}),
+            };
+            Some(serde_json::to_value(unified).unwrap())
+        }
+        "encoder" => {
+            let (counts, delta, dt, cpr, direction) = {
+                let mut encoder = state.encoder.lock().unwrap();
+                let (delta, dt) = encoder.advance(&mut *rng);
+                (encoder.counts, delta, dt, encoder.counts_per_revolution, encoder.direction)
+            };
+            // Speed is derived purely from the count delta, as a drive controller would
+            let rpm = if dt > 0.0 { delta.abs() as f64 / cpr as f64 / dt * 60.0 } else { 0.0 };
+            let roller_diameter_m = 0.2;
+            let surface_speed = rpm / 60.0 * std::f64::consts::PI * roller_diameter_m;
+            // Quadrature state sequence (A,B): 00 → 10 → 11 → 01
+            let phase = counts.rem_euclid(4);
+            let quality = desc.quality(rpm);
+            let status_code = generate_opcua_status_code(&quality);
+            let source_ts = state.clock.now().to_rfc3339();
+
+            let unified = UnifiedSensorData {
+                opc_ua: generate_opcua_node(desc.device_id, desc.display_name),
+                equipment_hierarchy: generate_isa95_hierarchy(desc.device_id, desc.line, desc.area),
+                sparkplug_topic: generate_sparkplug_topic("Plant-01", desc.device_id),
+                source_timestamp: source_ts,
+                server_timestamp: server_ts.clone(),
+                value: serde_json::json!({
+                    "value": format!("{:.1}", rpm).parse::<f64>().unwrap(),
+                    "counts": counts,
+                    "deltaCounts": delta,
+                    "deltaTimeMs": format!("{:.1}", dt * 1000.0).parse::<f64>().unwrap(),
+                    "countsPerRevolution": cpr,
+                    "revolutions": format!("{:.3}", counts as f64 / cpr as f64).parse::<f64>().unwrap(),
+                    "direction": direction,
+                    "surfaceSpeed": format!("{:.3}", surface_speed).parse::<f64>().unwrap(),
+                    "pulseFrequency": format!("{:.1}", rpm / 60.0 * cpr as f64 / 4.0).parse::<f64>().unwrap(),
+                    "channelA": phase == 1 || phase == 2,
+                    "channelB": phase == 2 || phase == 3
+                }),
+                data_quality: quality,
+                opc_ua_status_code: status_code,
+                opc_ua_status_name: status_code.name(),
+                unit: get_ucum_unit(desc.unit),
+                sensor_type: desc.sensor_type.to_string(),
+                description: desc.description.to_string(),
+                properties: serde_json::json!({}),
+            };
+            Some(serde_json::to_value(unified).unwrap())
+        }
+        "control-valve" => {
+            let (position, commanded, stroke_rate) = {
+                let mut valve = state.control_valve.lock().unwrap();
+                valve.advance();
+                (valve.position, valve.commanded, valve.stroke_rate)
+            };
+            // Positioner feedback carries a little stem friction/hysteresis noise
+            let position = (position + random_between(&mut *rng, -0.2, 0.2)).clamp(0.0, 100.0);
+            let deviation = position - commanded;
+            let quality = desc.quality(deviation.abs());
+            let status_code = generate_opcua_status_code(&quality);
+            let source_ts = state.clock.now().to_rfc3339();
+
+            let unified = UnifiedSensorData {
+                opc_ua: generate_opcua_node(desc.device_id, desc.display_name),
+                equipment_hierarchy: generate_isa95_hierarchy(desc.device_id, desc.line, desc.area),
+                sparkplug_topic: generate_sparkplug_topic("Plant-01", desc.device_id),
+                source_timestamp: source_ts,
+                server_timestamp: server_ts.clone(),
+                value: serde_json::json!({
+                    "value": format!("{:.1}", position).parse::<f64>().unwrap(),
+                    "commandedPosition": format!("{:.1}", commanded).parse::<f64>().unwrap(),
+                    "deviation": format!("{:.2}", deviation).parse::<f64>().unwrap(),
+                    "travelling": deviation.abs() > 0.5,
+                    "strokeTimeSeconds": format!("{:.1}", 100.0 / stroke_rate).parse::<f64>().unwrap(),
+                    "failAction": "fail-closed",
+                    "valveType": "globe"
+                }),
+                data_quality: quality,
+                opc_ua_status_code: status_code,
+                opc_ua_status_name: status_code.name(),
+                unit: get_ucum_unit(desc.unit),
+                sensor_type: desc.sensor_type.to_string(),
+                description: desc.description.to_string(),
+                properties: serde_json::json!({}),
+            };
+            Some(serde_json::to_value(unified).unwrap())
+        }
+        "strain-gauge" => {
+            let (microstrain, stress, cycles, overloaded, peak, modulus, threshold, transition) = {
+                let mut gauge = state.strain_gauge.lock().unwrap();
+                let next = gauge.next_simulated(&mut *rng);
+                let transition = gauge.apply(next);
+                (gauge.microstrain, gauge.stress_mpa(), gauge.fatigue_cycles, gauge.overloaded,
+                 gauge.peak_microstrain, gauge.elastic_modulus_gpa, gauge.overload_microstrain, transition)
+            };
+            if let Some(tripped) = transition {
+                raise_strain_overload_alarm(state, tripped, microstrain, threshold);
+            }
+            let quality = generate_data_quality(microstrain, -300.0, threshold);
+            let status_code = generate_opcua_status_code(&quality);
+            let source_ts = state.clock.now().to_rfc3339();
+
+            let unified = UnifiedSensorData {
+                opc_ua: generate_opcua_node(desc.device_id, desc.display_name),
+                equipment_hierarchy: generate_isa95_hierarchy(desc.device_id, desc.line, desc.area),
+                sparkplug_topic: generate_sparkplug_topic("Plant-01", desc.device_id),
+                source_timestamp: source_ts,
+                server_timestamp: server_ts.clone(),
+                value: serde_json::json!({
+                    "value": format!("{:.1}", microstrain).parse::<f64>().unwrap(),
+                    "stress": format!("{:.2}", stress).parse::<f64>().unwrap(),
+                    "elasticModulusGpa": modulus,
+                    "fatigueCycles": cycles,
+                    "peakMicrostrain": format!("{:.1}", peak).parse::<f64>().unwrap(),
+                    "overloadThreshold": threshold,
+                    "overloadAlarm": overloaded,
+                    "gaugeFactor": 2.1,
+                    "bridgeConfiguration": "full-bridge"
+                }),
+                data_quality: quality,
+                opc_ua_status_code: status_code,
+                opc_ua_status_name: status_code.name(),
+                unit: get_ucum_unit(desc.unit),
+                sensor_type: desc.sensor_type.to_string(),
+                description: desc.description.to_string(),
+                properties: serde_json::json!({}),
+            };
+            Some(serde_json::to_value(unified).unwrap())
+        }
+        "accelerometer" => {
+            // Machine vibration (walked RMS, in g) on top of gravity; the
+            // housing sits a few degrees off level and flexes slightly under load
+            let vibration_rms = walk(state, &mut *rng, key);
+            let tilt = (4.0 + random_between(&mut *rng, -0.3, 0.3)).to_radians();
+            let azimuth = 35.0_f64.to_radians();
+            let gravity = [tilt.sin() * azimuth.cos(), tilt.sin() * azimuth.sin(), tilt.cos()];
+            let axes = gravity.map(|g| g + vibration_rms * standard_normal(&mut *rng));
+            let magnitude = axes.iter().map(|a| a * a).sum::<f64>().sqrt();
+            // Tilt from vertical of the static (gravity) component, as an
+            // inclinometer reads it after low-pass filtering the axes
+            let tilt_angle = gravity[0].hypot(gravity[1]).atan2(gravity[2]).to_degrees();
+            let quality = desc.quality(vibration_rms);
+            let status_code = generate_opcua_status_code(&quality);
+            let source_ts = state.clock.now().to_rfc3339();
+
+            let unified = UnifiedSensorData {
+                opc_ua: generate_opcua_node(desc.device_id, desc.display_name),
+                equipment_hierarchy: generate_isa95_hierarchy(desc.device_id, desc.line, desc.area),
+                sparkplug_topic: generate_sparkplug_topic("Plant-01", desc.device_id),
+                source_timestamp: source_ts,
+                server_timestamp: server_ts.clone(),
+                value: serde_json::json!({
+                    "xAxis": format!("{:.4}", axes[0]).parse::<f64>().unwrap(),
+                    "yAxis": format!("{:.4}", axes[1]).parse::<f64>().unwrap(),
+                    "zAxis": format!("{:.4}", axes[2]).parse::<f64>().unwrap(),
+                    "magnitude": format!("{:.4}", magnitude).parse::<f64>().unwrap(),
+                    "vibrationRms": format!("{:.4}", vibration_rms).parse::<f64>().unwrap(),
+                    "tiltAngle": format!("{:.2}", tilt_angle).parse::<f64>().unwrap(),
+                    "measurementRange": 16.0,
+                    "sampleRateHz": 3200,
+                    // Site limits on vibrationRms in g. ISO 20816 zones are velocity
+                    // (mm/s), which a MEMS accelerometer doesn't report.
+                    "vibrationRmsLimits": {
+                        "good": desc.threshold("good"),
+                        "satisfactory": desc.threshold("satisfactory"),
+                        "unsatisfactory": desc.threshold("unsatisfactory")
+                    }
+                }),
+                data_quality: quality,
+                opc_ua_status_code: status_code,
+                opc_ua_status_name: status_code.name(),
+                unit: get_ucum_unit(desc.unit),
+                sensor_type: desc.sensor_type.to_string(),
+                description: desc.description.to_string(),
+                properties: serde_json::json!({}),
+            };
+            Some(serde_json::to_value(unified).unwrap())
+        }
+        "sound-level" => {
+            let level = walk(state, &mut *rng, key);
+            let (leq, lmax) = state.sound_level.lock().unwrap().record(level);
+            let (lower_action, exposure_limit) = (desc.threshold("lowerAction")?, desc.threshold("exposureLimit")?);
+            // Graded against occupational exposure rather than a symmetric band:
+            // at or above the exposure limit the reading is Bad
+            let quality = if level >= exposure_limit {
+                DataQuality::Bad
+            } else if level >= lower_action {
+                DataQuality::Uncertain
+            } else {
+                DataQuality::Good
+            };
+            let status_code = generate_opcua_status_code(&quality);
+            let source_ts = state.clock.now().to_rfc3339();
+            let bands: Vec<serde_json::Value> = octave_bands(&mut *rng, level)
+                .into_iter()
+                .map(|(band, l)| serde_json::json!({ "band": band, "level": format!("{:.1}", l).parse::<f64>().unwrap() }))
+                .collect();
+
+            let unified = UnifiedSensorData {
+                opc_ua: generate_opcua_node(desc.device_id, desc.display_name),
+                equipment_hierarchy: generate_isa95_hierarchy(desc.device_id, desc.line, desc.area),
+                sparkplug_topic: generate_sparkplug_topic("Plant-01", desc.device_id),
+                source_timestamp: source_ts,
+                server_timestamp: server_ts.clone(),
+                value: serde_json::json!({
+                    "value": format!("{:.1}", level).parse::<f64>().unwrap(),
+                    "leq": format!("{:.1}", leq).parse::<f64>().unwrap(),
+                    "lmax": format!("{:.1}", lmax).parse::<f64>().unwrap(),
+                    "leqWindowSamples": SOUND_LEQ_WINDOW,
+                    "octaveBands": bands,
+                    "frequencyWeighting": "A",
+                    "timeWeighting": "fast",
+                    "exceedsExposureLimit": level >= exposure_limit,
+                    "occupationalLimits": {
+                        "lowerAction": lower_action,
+                        "exposureLimit": exposure_limit
+                    }
+                }),
+                data_quality: quality,
+                opc_ua_status_code: status_code,
+                opc_ua_status_name: status_code.name(),
+                unit: get_ucum_unit(desc.unit),
+                sensor_type: desc.sensor_type.to_string(),
+                description: desc.description.to_string(),
+                properties: serde_json::json!({}),
+            };
+            Some(serde_json::to_value(unified).unwrap())
+        }
+        "wind" => {
+            let speed = walk(state, &mut *rng, key).max(0.0);
+            // Gusts run 10-60% above the mean, a little more in light air
+            let gust = speed * random_between(&mut *rng, 1.1, 1.6) + random_between(&mut *rng, 0.0, 1.0);
+            let direction = walk(state, &mut *rng, "wind-direction");
+            let (gale, storm) = (desc.threshold("gale")?, desc.threshold("storm")?);
+            let quality = if gust >= storm {
+                DataQuality::Bad
+            } else if gust >= gale {
+                DataQuality::Uncertain
+            } else {
+                DataQuality::Good
+            };
+            let status_code = generate_opcua_status_code(&quality);
+            let source_ts = state.clock.now().to_rfc3339();
+
+            let unified = UnifiedSensorData {
+                opc_ua: generate_opcua_node(desc.device_id, desc.display_name),
+                equipment_hierarchy: generate_isa95_hierarchy(desc.device_id, desc.line, desc.area),
+                sparkplug_topic: generate_sparkplug_topic("Plant-01", desc.device_id),
+                source_timestamp: source_ts,
+                server_timestamp: server_ts.clone(),
+                value: serde_json::json!({
+                    "value": format!("{:.1}", speed).parse::<f64>().unwrap(),
+                    "gust": format!("{:.1}", gust).parse::<f64>().unwrap(),
+                    "direction": format!("{:.0}", direction).parse::<f64>().unwrap() % 360.0,
+                    "directionUnit": get_ucum_unit("°"),
+                    "cardinal": cardinal_direction(direction),
+                    "beaufort": beaufort_number(speed),
+                    "limits": {
+                        "gale": gale,
+                        "storm": storm
+                    }
+                }),
+                data_quality: quality,
+                opc_ua_status_code: status_code,
+                opc_ua_status_name: status_code.name(),
+                unit: get_ucum_unit(desc.unit),
+                sensor_type: desc.sensor_type.to_string(),
+                description: desc.description.to_string(),
+                properties: serde_json::json!({}),
+            };
+            Some(serde_json::to_value(unified).unwrap())
+        }
+        "smoke-detector" => {
+            let (obscuration_limit, heat_rise_limit) = (desc.threshold("obscurationAlarm")?, desc.threshold("heatRiseAlarm")?);
+            let (obscuration, heat_rise_rate, alarm, cause, latched_at, tripped) = {
+                let mut detector = state.smoke_detector.lock().unwrap();
+                detector.advance(&mut *rng);
+                let tripped = detector.evaluate(obscuration_limit, heat_rise_limit, state.clock.now());
+                (detector.obscuration, detector.heat_rise_rate, detector.alarm, detector.alarm_cause,
+                 detector.latched_at.clone(), tripped)
+            };
+            if let Some(cause) = tripped {
+                raise_smoke_alarm(state, true, cause, obscuration, heat_rise_rate);
+            }
+            // A latched alarm holds the point Bad until the panel resets it
+            let (quality, status_code) = if alarm {
+                (DataQuality::Bad, OpcUaStatusCode::BadOutOfRange)
+            } else {
+                (DataQuality::Good, OpcUaStatusCode::Good)
+            };
+            let source_ts = state.clock.now().to_rfc3339();
+
+            let unified = UnifiedSensorData {
+                opc_ua: generate_opcua_node(desc.device_id, desc.display_name),
+                equipment_hierarchy: generate_isa95_hierarchy(desc.device_id, desc.line, desc.area),
+                sparkplug_topic: generate_sparkplug_topic("Plant-01", desc.device_id),
+                source_timestamp: source_ts,
+                server_timestamp: server_ts.clone(),
+                value: serde_json::json!({
+                    "value": format!("{:.2}", obscuration).parse::<f64>().unwrap(),
+                    "heatRiseRate": format!("{:.2}", heat_rise_rate).parse::<f64>().unwrap(),
+                    "heatRiseRateUnit": "°C/min",
+                    "alarm": alarm,
+                    "alarmCause": cause,
+                    "latchedAt": latched_at,
+                    "limits": {
+                        "obscurationAlarm": obscuration_limit,
+                        "heatRiseAlarm": heat_rise_limit
+                    }
+                }),
+                data_quality: quality,
+                opc_ua_status_code: status_code,
+                opc_ua_status_name: status_code.name(),
+                unit: get_ucum_unit(desc.unit),
+                sensor_type: desc.sensor_type.to_string(),
+                description: desc.description.to_string(),
+                properties: serde_json::json!({}),
+            };
+            Some(serde_json::to_value(unified).unwrap())
+        }
+        "contact" => {
+            let (closed, transitions, last_change, forced) = {
+                let mut contact = state.contact.lock().unwrap();
+                contact.advance(&mut *rng, state.clock.now());
+                (contact.closed, contact.transitions, contact.last_change.clone(), contact.forced)
+            };
+            let quality = DataQuality::Good;
+            let status_code = generate_opcua_status_code(&quality);
+            let source_ts = state.clock.now().to_rfc3339();
+
+            let unified = UnifiedSensorData {
+                opc_ua: generate_opcua_node(desc.device_id, desc.display_name),
+                equipment_hierarchy: generate_isa95_hierarchy(desc.device_id, desc.line, desc.area),
+                sparkplug_topic: generate_sparkplug_topic("Plant-01", desc.device_id),
+                source_timestamp: source_ts,
+                server_timestamp: server_ts.clone(),
+                value: serde_json::json!({
+                    "value": closed,
+                    "state": if closed { "closed" } else { "open" },
+                    "transitionCount": transitions,
+                    "lastChange": last_change,
+                    "forced": forced,
+                    "contactType": "normally-closed",
+                    "debounceMs": 50
+                }),
+                data_quality: quality,
+                opc_ua_status_code: status_code,
+                opc_ua_status_name: status_code.name(),
+                unit: get_ucum_unit(desc.unit),
+                sensor_type: desc.sensor_type.to_string(),
+                description: desc.description.to_string(),
+                properties: serde_json::json!({}),
+            };
+            Some(serde_json::to_value(unified).unwrap())
+        }
+        "power-quality" => {
+            let source = latest_mains_reading(state);
+            // Nothing to derive from while the meter isn't answering
+            let Some(mains) = source.as_ref().and_then(MainsSnapshot::from_reading) else {
+                return not_responding_reading(state, key);
+            };
+            // The meter's power factor is the true one; the gap to the
+            // displacement (fundamental) power factor is the harmonic content.
+            // A replayed or injected meter can report anything, so keep the
+            // triangle solvable.
+            let true_pf = mains.power_factor.clamp(0.01, 1.0);
+            let displacement_pf = (true_pf + random_between(&mut *rng, 0.0, 0.04)).min(0.999).max(true_pf);
+            let distortion_factor = true_pf / displacement_pf;
+            let thd_current = (distortion_factor.powi(-2) - 1.0).sqrt() * 100.0;
+            let thd_voltage = thd_current * random_between(&mut *rng, 0.08, 0.12);
+            let crest_factor = std::f64::consts::SQRT_2 * (1.0 + 0.5 * thd_current / 100.0);
+            let copper_losses = 3.0 * mains.current.powi(2) * FEEDER_RESISTANCE_OHMS / 1000.0;
+            let harmonic_losses = copper_losses * (thd_current / 100.0).powi(2) * HARMONIC_LOSS_WEIGHT;
+            let total_losses = copper_losses + harmonic_losses;
+            let efficiency = mains.active_power / (mains.active_power + total_losses) * 100.0;
+            let quality = desc.ceiling_quality(thd_voltage);
+            let status_code = generate_opcua_status_code(&quality);
+            let source_ts = state.clock.now().to_rfc3339();
+
+            let unified = UnifiedSensorData {
+                opc_ua: generate_opcua_node(desc.device_id, desc.display_name),
+                equipment_hierarchy: generate_isa95_hierarchy(desc.device_id, desc.line, desc.area),
+                sparkplug_topic: generate_sparkplug_topic("Plant-01", desc.device_id),
+                source_timestamp: source_ts,
+                server_timestamp: server_ts.clone(),
+                value: serde_json::json!({
+                    "efficiency": format!("{:.2}", efficiency).parse::<f64>().unwrap(),
+                    "thdCurrent": format!("{:.1}", thd_current).parse::<f64>().unwrap(),
+                    "thdVoltage": format!("{:.2}", thd_voltage).parse::<f64>().unwrap(),
+                    "crestFactor": format!("{:.3}", crest_factor).parse::<f64>().unwrap(),
+                    "displacementPowerFactor": format!("{:.3}", displacement_pf).parse::<f64>().unwrap(),
+                    "distortionFactor": format!("{:.3}", distortion_factor).parse::<f64>().unwrap(),
+                    "losses": {
+                        "copper": format!("{:.3}", copper_losses).parse::<f64>().unwrap(),
+                        "harmonic": format!("{:.3}", harmonic_losses).parse::<f64>().unwrap(),
+                        "total": format!("{:.3}", total_losses).parse::<f64>().unwrap(),
+                        "unit": "kW"
+                    },
+                    "source": {
+                        "sensor": "energy-meter",
+                        "deviceId": sensor_device_id(state, "energy-meter"),
+                        "activePower": format!("{:.2}", mains.active_power).parse::<f64>().unwrap(),
+                        "apparentPower": format!("{:.2}", mains.apparent_power).parse::<f64>().unwrap(),
+                        "reactivePower": format!("{:.2}", mains.reactive_power).parse::<f64>().unwrap(),
+                        "current": format!("{:.2}", mains.current).parse::<f64>().unwrap(),
+                        "powerFactor": format!("{:.3}", mains.power_factor).parse::<f64>().unwrap(),
+                        "frequency": format!("{:.2}", mains.frequency).parse::<f64>().unwrap()
+                    },
+                    "limits": {
+                        "thdVoltage": desc.threshold("thdVoltageLimit"),
+                        "individualHarmonic": desc.threshold("individualHarmonicLimit")
+                    }
+                }),
+                data_quality: quality,
+                opc_ua_status_code: status_code,
+                opc_ua_status_name: status_code.name(),
+                unit: get_ucum_unit(desc.unit),
+                sensor_type: desc.sensor_type.to_string(),
+                description: desc.description.to_string(),
+                properties: serde_json::json!({}),
+            };
+            let mut data = serde_json::to_value(unified).unwrap();
+            // Figures derived from an uncertain or bad meter reading are no better than it
+            let severity = |d: &serde_json::Value| d["dataQuality"].as_str().and_then(alarm_severity_rank);
+            if let Some(source) = source.filter(|source| severity(source) > severity(&data)) {
+                for field in ["dataQuality", "opcUaStatusCode", "opcUaStatusName"] {
+                    data[field] = source[field].clone();
+                }
+            }
+            Some(data)
+        }
+        _ => None,
+    }
+}
+
+const AVAILABLE_SENSORS: &[&str] = &[
+    "temperature", "humidity", "oil-level", "oil-pressure",
+    "air-quality", "pressure", "vibration", "energy-meter", "amr",
+    "flow-meter", "gas-detector", "ph-sensor", "level-sensor", "proximity-sensor",
+    "gps-tracker", "solar-panel", "occupancy", "encoder", "strain-gauge",
+    "control-valve", "accelerometer", "sound-level", "wind", "smoke-detector",
+    "contact", "power-quality"
+];
+
+// ============================================
+// Sensor Dependencies (cascade control)
+// ============================================
+
+/// One edge of `SIM_SENSOR_DEPENDS`, written `target=source[:gain[:outflow]]`.
+/// Each tick the target's primary variable integrates
+/// `gain × source fraction of range − outflow` per second, e.g. a tank level
+/// filled through a control valve and drained at a fixed rate.
+#[derive(Clone, Debug)]
+struct SensorDependency {
+    target: String,
+    source: String,
+    gain: f64,
+    outflow: f64,
+}
+
+/// Parse `SIM_SENSOR_DEPENDS` and return the edges in evaluation order
+/// (sources before the sensors they drive). Cycles are rejected.
+fn parse_sensor_dependencies(spec: &str) -> Result<Vec<SensorDependency>, String> {
+    let mut deps: Vec<SensorDependency> = Vec::new();
+    for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (target, rest) = entry.split_once('=').ok_or_else(|| format!("'{}' is not target=source", entry))?;
+        let mut parts = rest.split(':');
+        let source = parts.next().unwrap_or_default().trim();
+        let number = |v: Option<&str>, default: f64| -> Result<f64, String> {
+            v.map_or(Ok(default), |v| v.trim().parse().map_err(|_| format!("'{}' is not a number in '{}'", v, entry)))
+        };
+        let gain = number(parts.next(), 0.05)?;
+        let outflow = number(parts.next(), 0.02)?;
+
+        for key in [target, source] {
+            if !AVAILABLE_SENSORS.contains(&key) {
+                return Err(format!("unknown sensor '{}'", key));
+            }
+        }
+        if deps.iter().any(|d| d.target == target) {
+            return Err(format!("'{}' is driven by more than one source", target));
+        }
+        deps.push(SensorDependency { target: target.to_string(), source: source.to_string(), gain, outflow });
+    }
+
+    // Kahn's algorithm: repeatedly take edges whose source isn't driven by a
+    // pending edge; anything left over is part of a cycle
+    let mut ordered = Vec::with_capacity(deps.len());
+    while !deps.is_empty() {
+        let ready = deps.iter().position(|d| !deps.iter().any(|other| other.target == d.source));
+        match ready {
+            Some(i) => ordered.push(deps.remove(i)),
+            None => {
+                let cycle: Vec<_> = deps.iter().map(|d| format!("{}<-{}", d.target, d.source)).collect();
+                return Err(format!("cyclic dependency: {}", cycle.join(", ")));
+            }
+        }
+    }
+    Ok(ordered)
+}
+
+fn primary_value(state: &AppState, data: &serde_json::Value, key: &str) -> Option<f64> {
+    data["value"][primary_variable_field(state, key)?].as_f64()
+}
+
+/// Bounds the process itself puts on a sensor's primary variable, tighter
+/// than a transmitter range can be: the level can't leave the tank, however
+/// far past its top the gauge is ranged
+fn physical_limits(key: &str) -> Option<(f64, f64)> {
+    match key {
+        "level-sensor" => Some((0.0, LEVEL_SENSOR_TANK.height_m)),
+        _ => None,
+    }
+}
+
+/// Evaluate the dependency graph in order, integrating each driven sensor
+/// over `dt` seconds
+fn step_sensor_dependencies(state: &AppState, dt: f64) {
+    for dep in &state.dependencies {
+        let driven_source = state.driven_values.lock().unwrap().get(&dep.source).copied();
+        let Some(source) = driven_source.or_else(|| primary_value(state, &simulate_sensor(state, &dep.source)?, &dep.source)) else {
+            continue;
+        };
+        let (Some((src_lrv, src_urv)), Some((lrv, urv))) = (sensor_range(state, &dep.source), sensor_range(state, &dep.target)) else {
+            continue;
+        };
+        let fraction = ((source - src_lrv) / (src_urv - src_lrv)).clamp(0.0, 1.0);
+
+        let current = state.driven_values.lock().unwrap().get(&dep.target).copied();
+        let Some(current) = current.or_else(|| primary_value(state, &simulate_sensor(state, &dep.target)?, &dep.target)) else {
+            continue;
+        };
+        let next = (current + (dep.gain * fraction - dep.outflow) * dt).clamp(lrv, urv);
+        let (min, max) = physical_limits(&dep.target).unwrap_or((lrv, urv));
+        state.driven_values.lock().unwrap().insert(dep.target.clone(), next.clamp(min, max));
+    }
+}
+
+async fn run_sensor_dependencies(state: SharedState) {
+    if state.dependencies.is_empty() {
+        return;
+    }
+    let mut ticker = tokio::time::interval(Duration::from_secs(1));
+    let mut last_tick = std::time::Instant::now();
+    loop {
+        ticker.tick().await;
+        let dt = last_tick.elapsed().as_secs_f64();
+        last_tick = std::time::Instant::now();
+        step_sensor_dependencies(&state, dt);
+    }
+}
+
+/// Replace a driven sensor's primary variable with its integrated value
+fn apply_dependency(state: &AppState, key: &str, data: &mut serde_json::Value) {
+    let Some(dep) = state.dependencies.iter().find(|d| d.target == key) else {
+        return;
+    };
+    let Some(value) = state.driven_values.lock().unwrap().get(key).copied() else {
+        return;
+    };
+    let Some(field) = primary_variable_field(state, key) else {
+        return;
+    };
+    data["value"][field] = serde_json::json!(format!("{:.3}", value).parse::<f64>().unwrap());
+    data["drivenBy"] = serde_json::json!({
+        "source": dep.source,
+        "gain": dep.gain,
+        "outflow": dep.outflow
+    });
+}
+
+// ============================================
+// Transport Delay (process dead-time)
+// ============================================
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TransportDelayRequest {
+    transport_delay_ms: u64,
+}
+
+/// Dead-time between the process and the sensor: readings report the primary
+/// variable as it was `delay` ago, taken from a buffer of recent values
+struct TransportDelay {
+    delay: Duration,
+    buffer: VecDeque<(std::time::Instant, f64)>,
+}
+
+impl TransportDelay {
+    fn new(delay: Duration) -> Self {
+        TransportDelay { delay, buffer: VecDeque::new() }
+    }
+
+    /// Record the live value and return the one from `delay` ago. Until the
+    /// buffer covers the delay, the oldest known value is held.
+    fn delayed(&mut self, value: f64) -> f64 {
+        let now = std::time::Instant::now();
+        self.buffer.push_back((now, value));
+        let cutoff = now.checked_sub(self.delay).unwrap_or(now);
+        // Keep the newest sample at or before the cutoff; it's the one on display
+        while self.buffer.get(1).is_some_and(|&(t, _)| t <= cutoff) {
+            self.buffer.pop_front();
+        }
+        self.buffer.front().map(|&(_, v)| v).unwrap_or(value)
+    }
+}
+
+fn apply_transport_delay(state: &AppState, key: &str, data: &mut serde_json::Value) {
+    let Some(field) = primary_variable_field(state, key) else {
+        return;
+    };
+    let Some(live) = data["value"][field].as_f64() else {
+        return;
+    };
+    let mut delays = state.transport_delays.lock().unwrap();
+    let Some(delay) = delays.get_mut(key) else {
+        return;
+    };
+    data["value"][field] = serde_json::json!(delay.delayed(live));
+    data["transportDelayMs"] = serde_json::json!(delay.delay.as_millis() as u64);
+}
+
+// ============================================
+// Peak Hold (min/max latching)
+// ============================================
+
+/// Extremes of the primary variable latched since the last reset
+#[derive(Clone, Copy)]
+struct PeakHold {
+    max: f64,
+    min: f64,
+    since: chrono::DateTime<Utc>,
+}
+
+fn track_peak(state: &AppState, key: &str, data: &mut serde_json::Value) {
+    let Some(value) = primary_value(state, data, key) else {
+        return;
+    };
+    let mut peaks = state.peaks.lock().unwrap();
+    let peak = peaks
+        .entry(key.to_string())
+        .and_modify(|p| {
+            p.max = p.max.max(value);
+            p.min = p.min.min(value);
+        })
+        .or_insert(PeakHold { max: value, min: value, since: state.clock.now() });
+    data["peakMax"] = serde_json::json!(peak.max);
+    data["peakMin"] = serde_json::json!(peak.min);
+    data["peakSince"] = serde_json::json!(peak.since.to_rfc3339());
+}
+
+// ============================================
+// Calibration Certificates (ISO/IEC 17025)
+// ============================================
+
+/// FNV-1a: stable across builds and platforms, unlike `DefaultHasher`
+fn stable_hash(s: &str) -> u64 {
+    s.bytes().fold(0xcbf29ce484222325, |h, b| (h ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+/// Build the sensor's calibration certificate. All figures are derived from a
+/// generator seeded by the sensor key, so the certificate is identical across reads.
+fn generate_calibration_certificate(key: &str) -> Option<serde_json::Value> {
+    use rand::SeedableRng;
+
+    let desc = sensor_descriptor(key)?;
+    let (device_id, unit) = (desc.device_id, desc.unit);
+    let Calibration { lrv, urv, accuracy, standard } = desc.calibration?;
+    let seed = stable_hash(key);
+    let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+
+    let calibration_date = chrono::NaiveDate::from_ymd_opt(2025, 1, 6).unwrap()
+        + chrono::Duration::days((seed % 180) as i64);
+    let due_date = calibration_date + chrono::Duration::days(365);
+    // Reference standard is chosen for a 4:1 test uncertainty ratio
+    let uncertainty = accuracy / 4.0;
+
+    // A digital input is only ever checked open and closed
+    let fractions: &[f64] = if DIGITAL_SENSORS.contains(&key) { &[0.0, 1.0] } else { &[0.0, 0.25, 0.5, 0.75, 1.0] };
+    let reference_points: Vec<_> = fractions
+        .iter()
+        .map(|&fraction| {
+            let nominal = lrv + (urv - lrv) * fraction;
+            let as_found_error = rng.gen_range(-0.8..0.8) * accuracy;
+            let as_left_error = rng.gen_range(-0.3..0.3) * accuracy;
+            serde_json::json!({
+                "percentOfSpan": fraction * 100.0,
+                "nominal": nominal,
+                "asFound": format!("{:.4}", nominal + as_found_error).parse::<f64>().unwrap(),
+                "asLeft": format!("{:.4}", nominal + as_left_error).parse::<f64>().unwrap(),
+                "asFoundDeviation": format!("{:.4}", as_found_error).parse::<f64>().unwrap(),
+                "asLeftDeviation": format!("{:.4}", as_left_error).parse::<f64>().unwrap(),
+                "tolerance": accuracy,
+                "pass": as_found_error.abs() <= accuracy && as_left_error.abs() <= accuracy
+            })
+        })
+        .collect();
+    let all_pass = reference_points.iter().all(|p| p["pass"].as_bool().unwrap_or(false));
+    let technician = ["S. Wongsawat", "P. Charoenkul", "K. Srisuk"][(seed % 3) as usize];
+
+    Some(serde_json::json!({
+        "certificateNumber": format!("CAL-{}-{}-{:04}", calibration_date.format("%Y"), device_id, seed % 10000),
+        "sensor": key,
+        "deviceId": device_id,
+        "nodeId": generate_opcua_node(device_id, device_id).node_id,
+        "calibrationDate": calibration_date.to_string(),
+        "dueDate": due_date.to_string(),
+        "procedure": "ISO/IEC 17025 comparison calibration",
+        "standardUsed": standard,
+        "traceability": "NIMT (National Institute of Metrology Thailand)",
+        "range": { "lrv": lrv, "urv": urv },
+        "unit": get_ucum_unit(unit),
+        "accuracySpec": accuracy,
+        "expandedUncertainty": { "value": uncertainty, "coverageFactor": 2, "confidenceLevel": "95%" },
+        "environment": {
+            "temperature": format!("{:.1}", rng.gen_range(21.0..25.0)).parse::<f64>().unwrap(),
+            "humidity": format!("{:.1}", rng.gen_range(40.0..55.0)).parse::<f64>().unwrap()
+        },
+        "referencePoints": reference_points,
+        "result": if all_pass { "PASS" } else { "FAIL" },
+        "technician": technician
+    }))
+}
+
+// ============================================
+// Loop Diagnostics (HART-style 4-20 mA)
+// ============================================
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum WiringFault {
+    Open,
+    Short,
+}
+
+#[derive(Deserialize)]
+struct WiringFaultRequest {
+    wiring: WiringFault,
+}
+
+/// Simulated transmitter loop diagnostics. The loop current follows the
+/// NAMUR NE 43 convention: 4-20 mA for the measuring range, ~0 mA for a broken
+/// wire and >21 mA when the loop is shorted. Digital inputs have no loop.
+fn generate_diagnostics(key: &str, (lrv, urv): (f64, f64), fault: Option<WiringFault>, rng: &mut impl Rng) -> Option<serde_json::Value> {
+    let desc = sensor_descriptor(key).filter(|desc| desc.calibration.is_some() && !DIGITAL_SENSORS.contains(&desc.key))?;
+    let (device_id, unit) = (desc.device_id, desc.unit);
+
+    let percent_of_range = rng.gen_range(5.0..95.0);
+    let supply_voltage = 24.0 + rng.gen_range(-0.3..0.3);
+    let (loop_current, terminal_voltage) = match fault {
+        None => {
+            let current = 4.0 + 16.0 * percent_of_range / 100.0;
+            // 250 Ω HART sense resistor drops part of the supply
+            (current, supply_voltage - current * 0.25)
+        }
+        // No current flows, so the full supply appears across the open terminals
+        Some(WiringFault::Open) => (rng.gen_range(0.0..0.1), supply_voltage),
+        Some(WiringFault::Short) => (rng.gen_range(22.0..25.0), rng.gen_range(0.0..0.2)),
+    };
+    let self_test_passed = fault.is_none();
+
+    Some(serde_json::json!({
+        "deviceId": device_id,
+        "loopCurrentMa": format!("{:.3}", loop_current).parse::<f64>().unwrap(),
+        "primaryVariable": fault.is_none().then(|| {
+            format!("{:.3}", lrv + (urv - lrv) * percent_of_range / 100.0).parse::<f64>().unwrap()
+        }),
+        "primaryVariableUnit": unit,
+        "percentOfRange": fault.is_none().then(|| format!("{:.2}", percent_of_range).parse::<f64>().unwrap()),
+        "lowerRangeValue": lrv,
+        "upperRangeValue": urv,
+        "supplyVoltage": format!("{:.2}", supply_voltage).parse::<f64>().unwrap(),
+        "terminalVoltage": format!("{:.2}", terminal_voltage).parse::<f64>().unwrap(),
+        "wiringFault": fault.is_some(),
+        "faultType": fault,
+        "selfTest": {
+            "result": if self_test_passed { "pass" } else { "fail" },
+            "ram": "pass",
+            "rom": "pass",
+            "adc": if self_test_passed { "pass" } else { "fail" },
+            "sensorLoop": match fault {
+                None => "pass",
+                Some(WiringFault::Open) => "open-circuit",
+                Some(WiringFault::Short) => "short-circuit",
+            }
+        },
+        "hartStatus": {
+            "fieldDeviceMalfunction": fault.is_some(),
+            "loopCurrentSaturated": fault == Some(WiringFault::Short),
+            "loopCurrentFixed": false,
+            "primaryVariableOutOfLimits": fault.is_some()
+        }
+    }))
+}
+
+// ============================================
+// Stale Cache (last-known-good on comm fault)
+// ============================================
+
+/// The last good reading, re-served the way a gateway does when the device
+/// stops answering: flagged `stale` with its `age` in seconds. `None` when
+/// stale data is disabled (`SIM_PREFER_STALE=false`) or nothing is cached.
+fn stale_reading(state: &AppState, key: &str) -> Option<serde_json::Value> {
+    if !state.prefer_stale {
+        return None;
+    }
+    let (cached_at, mut data) = state.last_good.lock().unwrap().get(key).cloned()?;
+    data["stale"] = serde_json::json!(true);
+    data["age"] = serde_json::json!(format!("{:.3}", cached_at.elapsed().as_secs_f64()).parse::<f64>().unwrap());
+    data["serverTimestamp"] = serde_json::json!(state.clock.now().to_rfc3339());
+    data["dataQuality"] = serde_json::json!(DataQuality::Uncertain);
+    set_opcua_status(&mut data, OpcUaStatusCode::UncertainLastUsableValue);
+    Some(data)
+}
+
+/// What the gateway publishes for a device it can't reach and has nothing
+/// cached for: the device's identity with an empty value, `bad` quality and
+/// `BadCommunicationError`, so streams show the outage instead of going quiet
+fn not_responding_reading(state: &AppState, key: &str) -> Option<serde_json::Value> {
+    let (device_id, display_name, line, area, unit, sensor_type, description) = match sensor_config(state, key) {
+        Some(c) => (c.device_id.as_str(), c.display_name().to_string(), c.line.as_str(), c.area.as_str(), c.unit.as_str(), c.sensor_type(), c.description.as_str()),
+        None => {
+            let desc = sensor_descriptor(base_sensor_key(state, key))?;
+            let display_name = match amr_station(state, key) {
+                Some(station) => format!("AMR {}", station.location),
+                None => desc.display_name.to_string(),
+            };
+            let device_id = sensor_device_id(state, key).unwrap_or(desc.device_id);
+            (device_id, display_name, desc.line, desc.area, desc.unit, desc.sensor_type, desc.description)
+        }
+    };
+    let now = state.clock.now().to_rfc3339();
+    let status_code = OpcUaStatusCode::BadCommunicationError;
+    let unified = UnifiedSensorData {
+        opc_ua: generate_opcua_node(device_id, &display_name),
+        equipment_hierarchy: generate_isa95_hierarchy(device_id, line, area),
+        sparkplug_topic: generate_sparkplug_topic("Plant-01", device_id),
+        source_timestamp: now.clone(),
+        server_timestamp: now,
+        value: serde_json::json!({}),
+        data_quality: DataQuality::Bad,
+        opc_ua_status_code: status_code,
+        opc_ua_status_name: status_code.name(),
+        unit: get_ucum_unit(unit),
+        sensor_type: sensor_type.to_string(),
+        description: description.to_string(),
+        properties: serde_json::json!({}),
+    };
+    let mut data = serde_json::to_value(unified).unwrap();
+    data["notResponding"] = serde_json::json!(true);
+    Some(data)
+}
+
+// ============================================
+// Recorded Replay (REPLAY_FILE)
+// ============================================
+
+/// A recorded dataset played back on a loop in place of the simulation.
+/// Each track holds one sensor's readings with their offset in ms from the
+/// start of the recording; the loop restarts one average sample gap after
+/// the last reading so the seam looks like any other step.
+struct ReplayDataset {
+    tracks: HashMap<String, Vec<(i64, serde_json::Value)>>,
+    period_ms: i64,
+    started: chrono::DateTime<Utc>,
+}
+
+/// Sensor a recorded line belongs to: its `sensor` field, or the sensor
+/// whose device id matches `sparkplugTopic.deviceId`
+fn replay_line_sensor(state: &AppState, line: &serde_json::Value) -> Option<String> {
+    if let Some(key) = line["sensor"].as_str() {
+        return Some(key.to_string());
+    }
+    let device_id = line["sparkplugTopic"]["deviceId"].as_str()?;
+    sensor_keys(state)
+        .find(|&key| {
+            sensor_device_id(state, key).or_else(|| sensor_config(state, key).map(|c| c.device_id.as_str()))
+                == Some(device_id)
+        })
+        .map(str::to_string)
+}
+
+/// Parse an NDJSON recording: one reading per line, as the NDJSON stream and
+/// `/history` entries (`{"timestamp", "data"}`) produce them. Lines for
+/// sensors this simulator doesn't know are counted and skipped.
+fn parse_replay(state: &AppState, text: &str) -> Result<(ReplayDataset, usize), String> {
+    let mut frames: Vec<(String, i64, serde_json::Value)> = Vec::new();
+    let mut skipped = 0;
+    for (n, line) in text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+        let line: serde_json::Value = serde_json::from_str(line).map_err(|e| format!("line {}: {}", n + 1, e))?;
+        let data = if line["data"].is_object() { line["data"].clone() } else { line.clone() };
+        if !data["value"].is_object() {
+            return Err(format!("line {}: reading has no value object", n + 1));
+        }
+        let ts = data["sourceTimestamp"]
+            .as_str()
+            .or_else(|| line["timestamp"].as_str())
+            .and_then(|ts| chrono::DateTime::parse_from_rfc3339(ts).ok())
+            .ok_or_else(|| format!("line {}: no RFC 3339 sourceTimestamp", n + 1))?;
+        match replay_line_sensor(state, &line).or_else(|| replay_line_sensor(state, &data)) {
+            Some(key) if is_sensor_key(state, &key) => frames.push((key, ts.timestamp_millis(), data)),
+            _ => skipped += 1,
+        }
+    }
+    if frames.is_empty() {
+        return Err("no readings for known sensors".to_string());
+    }
+
+    let first = frames.iter().map(|f| f.1).min().unwrap_or_default();
+    let span = frames.iter().map(|f| f.1).max().unwrap_or_default() - first;
+    let gap = if frames.len() > 1 { span / (frames.len() as i64 - 1) } else { 0 };
+    let mut tracks: HashMap<String, Vec<(i64, serde_json::Value)>> = HashMap::new();
+    for (key, ts, data) in frames {
+        tracks.entry(key).or_default().push((ts - first, data));
+    }
+    for track in tracks.values_mut() {
+        track.sort_by_key(|(offset, _)| *offset);
+    }
+    let dataset = ReplayDataset { tracks, period_ms: (span + gap).max(1000), started: state.clock.now() };
+    Ok((dataset, skipped))
+}
+
+/// Load `REPLAY_FILE` at startup, before anything reads a sensor, so the
+/// first reading served is already a recorded one
+fn load_replay(state: &AppState, path: &str) -> Result<(), String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path, e))?;
+    let (dataset, skipped) = parse_replay(state, &text)?;
+    let sensors: Vec<_> = dataset.tracks.keys().cloned().collect();
+    tracing::info!(path, ?sensors, skipped, loop_secs = dataset.period_ms / 1000, "replaying recorded dataset");
+    *state.replay.lock().unwrap() = Some(dataset);
+    Ok(())
+}
+
+/// The recorded reading due now for `key`, its timestamps rebased so the
+/// recording appears to be happening live. `None` when nothing is being
+/// replayed for this sensor.
+fn replayed_reading(state: &AppState, key: &str) -> Option<serde_json::Value> {
+    let replay = state.replay.lock().unwrap();
+    let dataset = replay.as_ref()?;
+    let track = dataset.tracks.get(key)?;
+
+    let now = state.clock.now();
+    let elapsed = (now - dataset.started).num_milliseconds().max(0);
+    let (cycle, position) = (elapsed / dataset.period_ms, elapsed % dataset.period_ms);
+    // Before this track's first reading of the loop, the previous loop's last one still holds
+    let (cycle, (offset, data)) = match track.iter().rev().find(|(offset, _)| *offset <= position) {
+        Some(frame) => (cycle, frame),
+        None => (cycle - 1, track.last()?),
+    };
+    let mut data = data.clone();
+    let source = dataset.started + chrono::Duration::milliseconds(cycle * dataset.period_ms + offset);
+    data["sourceTimestamp"] = serde_json::json!(source.to_rfc3339());
+    data["serverTimestamp"] = serde_json::json!(now.to_rfc3339());
+    if let Some(props) = data["properties"].as_object_mut() {
+        props.insert("replayed".to_string(), serde_json::json!(true));
+    }
+    Some(data)
+}
+
+// ============================================
+// Spike Injection (alert-rule testing)
+// ============================================
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InjectRequest {
+    value: f64,
+    #[serde(alias = "duration_ms")]
+    duration_ms: u64,
+}
+
+/// A forced primary-variable value, held until `until`
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Injection {
+    value: f64,
+    injected_at: String,
+    until: String,
+    #[serde(skip)]
+    expires: chrono::DateTime<Utc>,
+}
+
+/// The sensor's active injection; expired ones are dropped here, which is
+/// what returns the sensor to normal simulation
+fn active_injection(state: &AppState, key: &str) -> Option<Injection> {
+    let mut injections = state.injections.lock().unwrap();
+    let injection = injections.get(key)?.clone();
+    if injection.expires <= state.clock.now() {
+        injections.remove(key);
+        return None;
+    }
+    Some(injection)
+}
+
+/// Force the primary variable to the injected value and judge it like a
+/// simulated one: against the sensor's normal band when that band is on the
+/// primary variable, otherwise against the instrument's calibrated range
+fn apply_injection(state: &AppState, key: &str, data: &mut serde_json::Value) {
+    let Some(injection) = active_injection(state, key) else {
+        return;
+    };
+    let Some(field) = primary_variable_field(state, key) else {
+        return;
+    };
+    override_primary_variable(state, key, data, field, injection.value);
+    data["injected"] = serde_json::json!(true);
+    data["injectedUntil"] = serde_json::json!(injection.until);
+}
+
+/// Replace the primary variable and re-judge the reading's quality for it
+fn override_primary_variable(state: &AppState, key: &str, data: &mut serde_json::Value, field: &str, value: f64) {
+    data["value"][field] = serde_json::json!(format!("{:.3}", value).parse::<f64>().unwrap());
+    let quality = match sensor_descriptor(key).and_then(|d| d.normal) {
+        Some(range) if range.metric == field => generate_data_quality(value, range.min, range.max),
+        _ => match sensor_range(state, key) {
+            Some((lrv, urv)) => generate_data_quality(value, lrv, urv),
+            None => return,
+        },
+    };
+    set_opcua_status(data, generate_opcua_status_code(&quality));
+    data["dataQuality"] = serde_json::json!(quality);
+}
+
+// ============================================
+// Waveform Generator (deterministic test signals)
+// ============================================
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum WaveShape {
+    Sine,
+    Square,
+    Sawtooth,
+}
+
+/// Replaces the primary variable with `offset + amplitude × shape(phase)`,
+/// the phase taken from wall-clock time so every consumer sees the same wave
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Waveform {
+    shape: WaveShape,
+    amplitude: f64,
+    #[serde(alias = "period_ms")]
+    period_ms: u64,
+    #[serde(default)]
+    offset: f64,
+}
+
+impl Waveform {
+    fn value_at(&self, t: chrono::DateTime<Utc>) -> f64 {
+        let period = self.period_ms as i64;
+        let phase = t.timestamp_millis().rem_euclid(period) as f64 / period as f64;
+        let unit = match self.shape {
+            WaveShape::Sine => (2.0 * std::f64::consts::PI * phase).sin(),
+            WaveShape::Square => if phase < 0.5 { 1.0 } else { -1.0 },
+            WaveShape::Sawtooth => 2.0 * phase - 1.0,
+        };
+        self.offset + self.amplitude * unit
+    }
+}
+
+fn apply_waveform(state: &AppState, key: &str, data: &mut serde_json::Value) {
+    let Some(waveform) = state.waveforms.lock().unwrap().get(key).cloned() else {
+        return;
+    };
+    let Some(field) = primary_variable_field(state, key) else {
+        return;
+    };
+    override_primary_variable(state, key, data, field, waveform.value_at(state.clock.now()));
+    data["waveform"] = serde_json::json!(waveform);
+}
+
+// ============================================
+// Persistent Sensor Faults (offline / stuck / drift)
+// ============================================
+
+/// A fault that holds until cleared. Offline is a communication fault: the
+/// gateway can't reach the device and serves its cache, if any. Stuck
+/// freezes the primary variable at the first value read after the fault was
+/// set; drift adds a bias that grows by `rate` per second since then.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "mode", rename_all = "camelCase", rename_all_fields = "camelCase", try_from = "SensorFaultRequest")]
+enum SensorFault {
+    Offline,
+    Stuck {
+        value: Option<f64>,
+    },
+    Drift {
+        /// Bias added per second; defaults to 0.1% of the sensor's span
+        rate: Option<f64>,
+        bias: f64,
+    },
+}
+
+/// Wire form of `SensorFault`. Internally tagged enums buffer their content,
+/// which loses `f64`s under serde_json's `arbitrary_precision`, so the body
+/// is read flat and checked here
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SensorFaultRequest {
+    mode: String,
+    #[serde(default)]
+    rate: Option<f64>,
+}
+
+impl TryFrom<SensorFaultRequest> for SensorFault {
+    type Error = String;
+
+    fn try_from(req: SensorFaultRequest) -> Result<Self, String> {
+        match (req.mode.as_str(), req.rate) {
+            ("drift", Some(rate)) if !rate.is_finite() => Err("rate must be a finite number".to_string()),
+            ("drift", rate) => Ok(SensorFault::Drift { rate, bias: 0.0 }),
+            (_, Some(_)) => Err("rate only applies to drift".to_string()),
+            ("offline", None) => Ok(SensorFault::Offline),
+            ("stuck", None) => Ok(SensorFault::Stuck { value: None }),
+            (mode, None) => Err(format!("unknown mode '{}' (expected offline, stuck or drift)", mode)),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ActiveFault {
+    since: String,
+    #[serde(skip)]
+    started: chrono::DateTime<Utc>,
+    #[serde(flatten)]
+    fault: SensorFault,
+}
+
+impl ActiveFault {
+    fn new(fault: SensorFault, started: chrono::DateTime<Utc>) -> Self {
+        ActiveFault { since: started.to_rfc3339(), started, fault }
+    }
+}
+
+/// Whether the device is unreachable (an offline fault, set through either
+/// `/fault` or `/comm-fault`)
+fn is_offline(state: &AppState, key: &str) -> bool {
+    matches!(state.sensor_faults.lock().unwrap().get(key), Some(ActiveFault { fault: SensorFault::Offline, .. }))
+}
+
+/// Stuck and drift faults; offline devices never get this far (see `generate_sensor_data`)
+fn apply_sensor_fault(state: &AppState, key: &str, data: &mut serde_json::Value) {
+    let mut faults = state.sensor_faults.lock().unwrap();
+    let Some(active) = faults.get_mut(key) else {
+        return;
+    };
+    let field = primary_variable_field(state, key).unwrap_or("value");
+    let live = data["value"][field].as_f64();
+    let elapsed = (state.clock.now() - active.started).num_milliseconds().max(0) as f64 / 1000.0;
+    let forced = match &mut active.fault {
+        SensorFault::Offline => return,
+        SensorFault::Stuck { value } => live.map(|live| *value.get_or_insert(live)),
+        SensorFault::Drift { rate, bias } => {
+            *bias = rate.unwrap_or_default() * elapsed;
+            live.map(|live| live + *bias)
+        }
+    };
+    data["fault"] = serde_json::json!(active);
+    drop(faults);
+
+    if let Some(value) = forced {
+        override_primary_variable(state, key, data, field, value);
+    }
+}
+
+// ============================================
+// Transmitter Re-ranging (LRV / URV)
+// ============================================
+
+/// Digital-input sensors: the primary variable is an on/off state rather
+/// than a number, so numeric overrides (injection, waveforms) don't apply
+const DIGITAL_SENSORS: &[&str] = &["contact"];
+
+/// Field carrying each sensor's primary variable, i.e. the one the 4-20 mA
+/// loop represents
+fn primary_variable_field(state: &AppState, key: &str) -> Option<&'static str> {
+    if sensor_config(state, key).is_some() {
+        return Some("value");
+    }
+    Some(match base_sensor_key(state, key) {
+        "air-quality" => "pm25",
+        "vibration" => "velocityRms",
+        "accelerometer" => "magnitude",
+        "energy-meter" => "activePower",
+        "amr" | "flow-meter" => "flowRate",
+        "gas-detector" => "carbonMonoxide",
+        "ph-sensor" => "phValue",
+        "level-sensor" => "level",
+        "proximity-sensor" => "distance",
+        "power-quality" => "efficiency",
+        k if AVAILABLE_SENSORS.contains(&k) => "value",
+        _ => return None,
+    })
+}
+
+#[derive(Deserialize)]
+struct RerangeRequest {
+    lrv: f64,
+    urv: f64,
+}
+
+/// Range the transmitter ships with
+fn factory_range(state: &AppState, key: &str) -> Option<(f64, f64)> {
+    if let Some(config) = sensor_config(state, key) {
+        return Some((config.min, config.max));
+    }
+    let calibration = sensor_descriptor(base_sensor_key(state, key))?.calibration?;
+    Some((calibration.lrv, calibration.urv))
+}
+
+/// Current range: a field re-range if one was applied, otherwise the factory range
+fn sensor_range(state: &AppState, key: &str) -> Option<(f64, f64)> {
+    let factory = factory_range(state, key)?;
+    Some(state.ranges.lock().unwrap().get(key).copied().unwrap_or(factory))
+}
+
+/// Pass the primary variable through a re-ranged 4-20 mA loop: its fraction
+/// of the factory span drives the loop current, which the new LRV/URV turn
+/// back into engineering units. The output saturates at the NAMUR NE 43
+/// limits (3.8 mA / 20.5 mA), so the signal clips to -1.25% / +103.125% of span.
+fn apply_rerange(state: &AppState, data: &mut serde_json::Value, key: &str, (factory_lrv, factory_urv): (f64, f64), (lrv, urv): (f64, f64)) {
+    let Some(field) = primary_variable_field(state, key) else {
+        return;
+    };
+    let Some(pv) = data["value"][field].as_f64() else {
+        return;
+    };
+    let fraction = (pv - factory_lrv) / (factory_urv - factory_lrv);
+    let span = urv - lrv;
+    let clipped = lrv + fraction.clamp(-0.0125, 1.03125) * span;
+    let out_of_range = !(0.0..=1.0).contains(&fraction);
+
+    data["value"][field] = serde_json::json!(format!("{:.4}", clipped).parse::<f64>().unwrap());
+    data["range"] = serde_json::json!({
+        "lrv": lrv,
+        "urv": urv,
+        "span": span,
+        "percentOfRange": format!("{:.2}", (clipped - lrv) / span * 100.0).parse::<f64>().unwrap(),
+        "loopCurrentMa": format!("{:.3}", 4.0 + 16.0 * (clipped - lrv) / span).parse::<f64>().unwrap(),
+        "overrange": fraction > 1.0,
+        "underrange": fraction < 0.0
+    });
+    if out_of_range && is_good_quality(data) {
+        data["dataQuality"] = serde_json::json!(DataQuality::Uncertain);
+        set_opcua_status(data, OpcUaStatusCode::UncertainEngineeringUnitsExceeded);
+    }
+}
+
+// ============================================
+// Network Discovery (commissioning scan)
+// ============================================
+
+/// Unconfigured devices that also answer on the plant network
+const UNCONFIGURED_DEVICES: &[(&str, &str, &str)] = &[
+    ("UNK-101", "Siemens", "SITRANS P320"),
+    ("UNK-102", "Endress+Hauser", "Promag 10W"),
+    ("UNK-103", "ABB", "TTF300"),
+    ("UNK-104", "Moxa", "ioLogik E1210"),
+    ("UNK-105", "Yokogawa", "EJA110E"),
+];
+
+/// A device's simulated response to a discovery probe. Everything is derived
+/// from the device ID, so the same device answers the same way on every scan
+/// (apart from a little jitter); `None` means it never answers.
+fn discovery_probe(device_id: &str, jitter: &mut impl Rng) -> (serde_json::Value, Option<u64>) {
+    use rand::SeedableRng;
+    let seed = stable_hash(device_id);
+    let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+
+    let (protocol, port) = [("modbus-tcp", 502), ("opc-ua", 4840), ("mqtt-sparkplug", 1883), ("hart-ip", 5094), ("bacnet-ip", 47808)]
+        [rng.gen_range(0..5)];
+    let latency_ms = match rng.gen_range(0..10) {
+        0 => None,
+        1..=2 => Some(rng.gen_range(500..4000)),
+        _ => Some(rng.gen_range(5..300)),
+    };
+    let info = serde_json::json!({
+        "ip": format!("10.20.{}.{}", (seed >> 8) % 4 + 1, seed % 250 + 2),
+        "mac": format!("02:5e:{:02x}:{:02x}:{:02x}:{:02x}", (seed >> 24) as u8, (seed >> 16) as u8, (seed >> 8) as u8, seed as u8),
+        "protocol": protocol,
+        "port": port
+    });
+    (info, latency_ms.map(|ms| ms + jitter.gen_range(0..20)))
+}
+
+// ============================================
+// Device Identity + Replacement
+// ============================================
+
+const LATEST_FIRMWARE: &str = "3.1.0";
+
+/// The physical device currently installed behind a sensor endpoint
+struct DeviceInfo {
+    serial: String,
+    firmware: String,
+    installed_at: std::time::Instant,
+    installed_at_ts: String,
+    booted_at: std::time::Instant,
+    totalizer: Option<f64>,
+    last_totalized: std::time::Instant,
+}
+
+impl DeviceInfo {
+    /// Installed at `now` (simulation time)
+    fn new(key: &str, device_id: &str, now: chrono::DateTime<Utc>) -> Self {
+        let seed = stable_hash(key);
+        DeviceInfo {
+            serial: format!("SN-{}-{:06}", device_id, seed % 1_000_000),
+            firmware: ["2.4.1", "2.5.0", "3.0.2"][(seed % 3) as usize].to_string(),
+            installed_at: std::time::Instant::now(),
+            installed_at_ts: now.to_rfc3339(),
+            booted_at: std::time::Instant::now(),
+            totalizer: None,
+            last_totalized: std::time::Instant::now(),
+        }
+    }
+
+    fn age_seconds(&self) -> f64 {
+        self.installed_at.elapsed().as_secs_f64()
+    }
+
+    fn uptime_seconds(&self) -> f64 {
+        self.booted_at.elapsed().as_secs_f64()
+    }
+
+    /// 1.0 right after power-up, decaying quadratically to 0.0 once the
+    /// device has been up for `window_secs`
+    fn boot_instability(&self, window_secs: f64) -> f64 {
+        if window_secs <= 0.0 {
+            return 0.0;
+        }
+        let t = (self.uptime_seconds() / window_secs).min(1.0);
+        (1.0 - t).powi(2)
+    }
+
+    /// Battery drains 0.5% per hour of service, bottoming out at 5%
+    fn battery_level(&self) -> f64 {
+        (100.0 - self.age_seconds() / 3600.0 * 0.5).max(5.0)
+    }
+
+    fn properties(&self) -> serde_json::Value {
+        serde_json::json!({
+            "serialNumber": self.serial,
+            "firmwareVersion": self.firmware,
+            "installedAt": self.installed_at_ts,
+            "ageSeconds": format!("{:.1}", self.age_seconds()).parse::<f64>().unwrap(),
+            "uptimeSeconds": format!("{:.1}", self.uptime_seconds()).parse::<f64>().unwrap(),
+            "batteryLevel": format!("{:.1}", self.battery_level()).parse::<f64>().unwrap()
+        })
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ReplacementRecord {
+    sensor: String,
+    timestamp: String,
+    old_serial: String,
+    new_serial: String,
+    old_firmware: String,
+    new_firmware: String,
+    old_age_seconds: f64,
+    totalizers_reset: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReplaceRequest {
+    new_serial: String,
+    firmware: Option<String>,
+    #[serde(default)]
+    reset_totalizers: bool,
+    reason: Option<String>,
+}
+
+/// Current cold-boot instability of a sensor's device, scaled to its extra
+/// error/uncertain probability
+fn boot_error_rate(state: &AppState, key: &str) -> f64 {
+    state.devices.lock().unwrap()
+        .get(key)
+        .map(|d| state.boot_error_rate * d.boot_instability(state.boot_window_secs))
+        .unwrap_or(0.0)
+}
+
+fn sensor_device_id(state: &AppState, key: &str) -> Option<&'static str> {
+    if let Some(station) = amr_station(state, key) {
+        return Some(station.meter_serial);
+    }
+    if let Some(config) = sensor_config(state, key) {
+        return Some(&config.device_id);
+    }
+    sensor_descriptor(key).map(|d| d.device_id)
+}
+
+/// Advance a cumulative counter (flow/energy totalizer) by `rate_per_hour`
+/// over the time since it was last read. The counter lives on the device so
+/// it survives across readings and can be zeroed on replacement.
+fn accumulate_totalizer(state: &AppState, key: &str, initial: f64, rate_per_hour: f64) -> f64 {
+    let mut devices = state.devices.lock().unwrap();
+    let device = devices.entry(key.to_string()).or_insert_with(|| DeviceInfo::new(key, sensor_device_id(state, key).unwrap_or(key), state.clock.now()));
+    let dt_hours = device.last_totalized.elapsed().as_secs_f64() / 3600.0;
+    device.last_totalized = std::time::Instant::now();
+    let total = match device.totalizer {
+        Some(total) => total + rate_per_hour * dt_hours,
+        None => initial,
+    };
+    device.totalizer = Some(total);
+    total
+}
+
+// ============================================
+// Consumer Watermarking (data provenance)
+// ============================================
+//
+// Each consumer maps to a 16-bit code split into four hex nibbles. Every
+// fractional numeric leaf of a reading's `value` object carries one nibble in
+// its 5th/6th decimal places as `position * 16 + nibble` (0..63), so the
+// watermark sits below the precision any sensor reports and each leaf is
+// self-describing. Decoding collects votes per position across all supplied
+// readings and takes the majority nibble.
+
+/// Leaves larger than this lose the 6th decimal to f64 precision and are left untouched
+const WATERMARK_MAX_MAGNITUDE: f64 = 1e9;
+
+fn watermark_code(consumer: &str) -> u16 {
+    (stable_hash(consumer) & 0xFFFF) as u16
+}
+
+fn embed_watermark(value: &mut serde_json::Value, code: u16, leaf_index: &mut usize) {
+    match value {
+        serde_json::Value::Object(obj) => {
+            for v in obj.values_mut() {
+                embed_watermark(v, code, leaf_index);
+            }
+        }
+        serde_json::Value::Number(n) if n.is_f64() => {
+            let x = n.as_f64().unwrap_or(0.0);
+            if x.abs() >= WATERMARK_MAX_MAGNITUDE {
+                return;
+            }
+            let position = (*leaf_index % 4) as u16;
+            let nibble = (code >> (position * 4)) & 0xF;
+            let digits = (position * 16 + nibble) as f64;
+            let base = (x.abs() * 1e4).trunc() / 1e4;
+            let marked = (base + digits / 1e6).copysign(x);
+            *value = serde_json::json!(marked);
+            *leaf_index += 1;
+        }
+        _ => {}
+    }
+}
+
+fn collect_watermark_votes(value: &serde_json::Value, votes: &mut [[u32; 16]; 4]) {
+    match value {
+        serde_json::Value::Object(obj) => {
+            for v in obj.values() {
+                collect_watermark_votes(v, votes);
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for v in items {
+                collect_watermark_votes(v, votes);
+            }
+        }
+        serde_json::Value::Number(n) if n.is_f64() => {
+            let x = n.as_f64().unwrap_or(0.0).abs();
+            if x >= WATERMARK_MAX_MAGNITUDE {
+                return;
+            }
+            let digits = ((x * 1e6).round() as u64 % 100) as usize;
+            if digits < 64 {
+                votes[digits / 16][digits % 16] += 1;
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Walk readings in any envelope (single, all-sensors map, arrays of either)
+/// and collect votes from each reading's `value` object only
+fn find_watermarked_values(value: &serde_json::Value, votes: &mut [[u32; 16]; 4]) {
+    match value {
+        serde_json::Value::Object(obj) => {
+            for (k, v) in obj {
+                if k == "value" && v.is_object() {
+                    collect_watermark_votes(v, votes);
+                } else {
+                    find_watermarked_values(v, votes);
+                }
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for v in items {
+                find_watermarked_values(v, votes);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Recover the 16-bit consumer code from a set of readings. Returns the code
+/// and the fraction of votes that agreed with the winning nibbles.
+fn decode_watermark(readings: &serde_json::Value) -> Option<(u16, f64)> {
+    let mut votes = [[0u32; 16]; 4];
+    find_watermarked_values(readings, &mut votes);
+
+    let mut code = 0u16;
+    let (mut agreeing, mut total) = (0u32, 0u32);
+    for (position, counts) in votes.iter().enumerate() {
+        let (nibble, &best) = counts.iter().enumerate().max_by_key(|(_, &c)| c)?;
+        if best == 0 {
+            return None;
+        }
+        code |= (nibble as u16) << (position * 4);
+        agreeing += best;
+        total += counts.iter().sum::<u32>();
+    }
+    Some((code, agreeing as f64 / total as f64))
+}
+
+/// Resolve the consumer a response should be watermarked for: an explicit
+/// `?consumer=`, or (with `SIM_WATERMARK=true`) the caller's API key
+fn watermark_consumer(state: &AppState, params: &HashMap<String, String>, headers: &axum::http::HeaderMap) -> Option<u16> {
+    let consumer = params.get("consumer").filter(|c| !c.is_empty()).cloned().or_else(|| {
+        if !state.watermark_api_keys {
+            return None;
+        }
+        headers.get("x-api-key")
+            .and_then(|h| h.to_str().ok())
+            .map(|key| format!("api-key#{:04x}", watermark_code(key)))
+    })?;
+
+    let code = watermark_code(&consumer);
+    state.watermark_consumers.lock().unwrap().insert(code, consumer);
+    Some(code)
+}
+
+fn apply_watermark(data: &mut serde_json::Value, code: Option<u16>) {
+    if let Some(code) = code {
+        embed_watermark(&mut data["value"], code, &mut 0);
+    }
+}
+
+// ──────────────────────────────────────────────
+// Randomness
+// ──────────────────────────────────────────────
+
+/// Independent random streams: one per sensor, and one per consumer of
+/// randomness outside the readings (error injection, redirects, ...). With
+/// `RNG_SEED` every stream is derived from the seed and its own name, so a
+/// stream replays the same draws however the others are interleaved: two
+/// clients reading different sensors can't shift each other's values.
+struct RngStreams {
+    seed: Option<u64>,
+    streams: Mutex<HashMap<String, Arc<Mutex<StdRng>>>>,
+}
+
+impl RngStreams {
+    fn new(seed: Option<u64>) -> Self {
+        RngStreams { seed, streams: Mutex::new(HashMap::new()) }
+    }
+
+    fn stream(&self, name: String) -> Arc<Mutex<StdRng>> {
+        let mut streams = self.streams.lock().unwrap();
+        let seed = self.seed;
+        streams
+            .entry(name)
+            .or_insert_with_key(|name| {
+                let rng = match seed {
+                    Some(seed) => StdRng::seed_from_u64(seed ^ stable_hash(name)),
+                    None => StdRng::from_entropy(),
+                };
+                Arc::new(Mutex::new(rng))
+            })
+            .clone()
+    }
+
+    /// The stream behind one sensor's readings
+    fn sensor(&self, key: &str) -> Arc<Mutex<StdRng>> {
+        self.stream(format!("sensor/{}", key))
+    }
+
+    /// The stream of a subsystem that isn't a sensor (`chaos`, `redirect`, ...)
+    fn consumer(&self, name: &str) -> Arc<Mutex<StdRng>> {
+        self.stream(format!("consumer/{}", name))
+    }
+}
+
+/// The wall clock every stamp, expiry and window is taken from: the system
+/// clock, or, with `SIM_START_TIME` (RFC 3339), a clock that starts at that
+/// instant and runs at real speed, so a seeded run also replays the same
+/// times of day
+struct SimClock {
+    origin: Option<(chrono::DateTime<Utc>, std::time::Instant)>,
+}
+
+impl SimClock {
+    fn system() -> Self {
+        SimClock { origin: None }
+    }
+
+    fn starting_at(start: chrono::DateTime<Utc>) -> Self {
+        SimClock { origin: Some((start, std::time::Instant::now())) }
+    }
+
+    fn now(&self) -> chrono::DateTime<Utc> {
+        match self.origin {
+            Some((start, since)) => start + chrono::Duration::from_std(since.elapsed()).unwrap_or_default(),
+            None => Utc::now(),
+        }
+    }
+}
+
+// ──────────────────────────────────────────────
+// State
+// ──────────────────────────────────────────────
+
+struct AppState {
+    /// Source of all simulated randomness; seeded by `RNG_SEED`
+    rngs: RngStreams,
+    /// Wall clock behind every timestamp, expiry and time window, and the
+    /// time of day readings are shaped by
+    clock: SimClock,
+    /// Set once the background sampler has ticked; gates `/readyz`
+    sim_running: Mutex<bool>,
+    chaos: Mutex<ChaosConfig>,
+    /// Newest-first ring of the last ACCESS_LOG_CAPACITY requests; written
+    /// once per request, so readers share it rather than queueing
+    access_log: parking_lot::RwLock<VecDeque<AccessLogEntry>>,
+    /// Cumulative counters behind `/metrics`
+    request_metrics: RequestMetrics,
+    request_counter: Mutex<usize>,
+    /// Last SSE event id handed to an access entry; never reset
+    access_event_counter: AtomicU64,
+    alarm_log: Mutex<Vec<AlarmLogEntry>>,
+    alarm_counter: Mutex<usize>,
+    shelved_alarms: Mutex<HashMap<String, ShelvedAlarm>>,
+    injections: Mutex<HashMap<String, Injection>>,
+    waveforms: Mutex<HashMap<String, Waveform>>,
+    /// Pipeline stations the AMR meters sit on: STATIONS_FILE or the built-in list
+    oil_stations: &'static [OilStation],
+    /// Operator-defined sensors from SENSORS_CONFIG, served alongside the built-ins
+    sensor_configs: &'static [sensor_config::SensorConfig],
+    sensor_faults: Mutex<HashMap<String, ActiveFault>>,
+    gps_tracker: Mutex<GpsTrackerState>,
+    geofences: Mutex<Vec<Geofence>>,
+    ambient_temperature: Mutex<f64>,
+    solar: SolarConfig,
+    tz_offset_hours: f64,
+    clock_sync: Mutex<HashMap<String, ClockSyncState>>,
+    devices: Mutex<HashMap<String, DeviceInfo>>,
+    replacements: Mutex<Vec<ReplacementRecord>>,
+    occupancy: Mutex<OccupancyState>,
+    encoder: Mutex<EncoderState>,
+    strain_gauge: Mutex<StrainGaugeState>,
+    control_valve: Mutex<ControlValveState>,
+    sound_level: Mutex<SoundLevelState>,
+    smoke_detector: Mutex<SmokeDetectorState>,
+    contact: Mutex<ContactState>,
+    redirect_rate: f64,
+    redirect_max_depth: u32,
+    bandwidth_bytes_per_sec: Option<u64>,
+    boot_window_secs: f64,
+    boot_error_rate: f64,
+    watermark_api_keys: bool,
+    watermark_consumers: Mutex<HashMap<u16, String>>,
+    subscriptions: Mutex<HashMap<String, Subscription>>,
+    webhooks: Mutex<HashMap<String, Webhook>>,
+    webhook_config: WebhookConfig,
+    history: Mutex<HashMap<String, VecDeque<(String, serde_json::Value)>>>,
+    /// Newest reading of each sensor served since the history sampler last ran
+    unsampled: Mutex<HashMap<String, serde_json::Value>>,
+    /// Newest reading of each sensor, whoever it went to; what the alarm
+    /// panel is judged on and what power quality is derived from
+    latest_readings: Mutex<HashMap<String, serde_json::Value>>,
+    /// Most-recently-requested first; only used with `SIM_HISTORY_MAX_SENSORS`
+    history_lru: Mutex<VecDeque<String>>,
+    retention: RetentionConfig,
+    golden_batches: Mutex<HashMap<String, GoldenBatch>>,
+    wiring_faults: Mutex<HashMap<String, WiringFault>>,
+    reporting: Mutex<HashMap<String, ReportingConfig>>,
+    ranges: Mutex<HashMap<String, (f64, f64)>>,
+    sparkplug_seq: Mutex<u8>,
+    avro_schemas: Mutex<HashMap<String, serde_json::Value>>,
+    cluster: Mutex<ClusterState>,
+    /// `SIM_SENSOR_DEPENDS` edges in evaluation order
+    dependencies: Vec<SensorDependency>,
+    driven_values: Mutex<HashMap<String, f64>>,
+    transport_delays: Mutex<HashMap<String, TransportDelay>>,
+    stream_sequence: Mutex<u64>,
+    duplicate_rate: f64,
+    peaks: Mutex<HashMap<String, PeakHold>>,
+    sensor_states: Mutex<HashMap<String, SensorState>>,
+    last_good: Mutex<HashMap<String, (std::time::Instant, serde_json::Value)>>,
+    /// Dataset from `REPLAY_FILE` served instead of simulated readings
+    replay: Mutex<Option<ReplayDataset>>,
+    prefer_stale: bool,
+    /// `X-Admin-Token` required by destructive admin endpoints; unset disables them
+    admin_token: Option<String>,
+    /// API_KEYS secret -> key id; empty leaves the data endpoints open
+    api_keys: HashMap<String, String>,
+    /// MAX_WS_SENSORS: sensors one WebSocket may subscribe to (default 32)
+    max_ws_sensors: usize,
+    /// MAX_WS_CONNECTIONS: concurrent WebSockets before upgrades get 503 (default 256)
+    max_ws_connections: usize,
+    ws_connections: Mutex<usize>,
+    rate_limit: Option<RateLimit>,
+    rate_buckets: parking_lot::Mutex<lru::LruCache<String, TokenBucket>>,
+    /// TRUSTED_PROXIES: peers whose X-Forwarded-For names the client
+    trusted_proxies: Vec<std::net::IpAddr>,
+    /// Response-time samples per matched route, behind `routeLatency` in the stats
+    latency: parking_lot::Mutex<HashMap<String, LatencyReservoir>>,
+    sse_tx: broadcast::Sender<SSEEvent>,
+    /// Flipped to true on SIGINT/SIGTERM; streams close when they see it
+    shutdown: tokio::sync::watch::Sender<bool>,
+    /// WebSockets and streaming responses still open
+    open_streams: Mutex<usize>,
+    /// Streams that closed after shutdown began, within the grace period
+    drained_streams: Mutex<usize>,
+}
+
+type SharedState = Arc<AppState>;
+
+// ──────────────────────────────────────────────
+// Alarms
+// ──────────────────────────────────────────────
+
+fn raise_alarm(
+    state: &AppState,
+    sensor: &str,
+    kind: &str,
+    event: &str,
+    zone: Option<String>,
+    message: String,
+    details: serde_json::Value,
+) {
+    // The condition still shows in the reading; only the event is held back
+    if alarm_shelved(state, sensor).is_some() {
+        if let Some(shelve) = state.shelved_alarms.lock().unwrap().get_mut(sensor) {
+            shelve.suppressed += 1;
+        }
+        return;
+    }
+
+    let id = {
+        let mut counter = state.alarm_counter.lock().unwrap();
+        *counter += 1;
+        *counter
+    };
+
+    let entry = AlarmLogEntry {
+        id,
+        timestamp: state.clock.now().to_rfc3339(),
+        sensor: sensor.to_string(),
+        kind: kind.to_string(),
+        event: event.to_string(),
+        zone,
+        message,
+        details,
+    };
+
+    {
+        let mut logs = state.alarm_log.lock().unwrap();
+        logs.insert(0, entry.clone());
+        if logs.len() > 500 {
+            logs.truncate(500);
+        }
+    }
+
+    // Sending only fails when no SSE client is connected, which is routine
+    if state.sse_tx.send(SSEEvent::Alarm(entry)).is_err() {
+        tracing::debug!("no SSE subscribers for alarm event");
+    }
+}
+
+/// An ISA-18.2 shelve: the sensor's alarm events are held back until `until`
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ShelvedAlarm {
+    sensor: String,
+    shelved_at: String,
+    until: String,
+    #[serde(skip)]
+    expires: chrono::DateTime<Utc>,
+    /// Alarm events dropped while shelved
+    suppressed: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ShelveRequest {
+    sensor: String,
+    duration_ms: u64,
+}
+
+/// Whether a sensor's alarms are shelved right now; expired shelves are
+/// dropped here, which is what unshelves them
+fn alarm_shelved(state: &AppState, sensor: &str) -> Option<ShelvedAlarm> {
+    let mut shelved = state.shelved_alarms.lock().unwrap();
+    let shelve = shelved.get(sensor)?.clone();
+    if shelve.expires <= state.clock.now() {
+        shelved.remove(sensor);
+        return None;
+    }
+    Some(shelve)
+}
+
+// ──────────────────────────────────────────────
+// History + Retention
+// ──────────────────────────────────────────────
+
+/// Count caps always apply; a TTL additionally prunes anything older than it,
+/// so whichever limit is stricter wins.
+struct RetentionConfig {
+    history_capacity: usize,
+    history_interval: Duration,
+    history_ttl: Option<chrono::Duration>,
+    access_log_ttl: Option<chrono::Duration>,
+    /// `SIM_HISTORY_ENABLED_SENSORS`; `None` keeps history for every sensor
+    history_sensors: Option<HashSet<String>>,
+    /// `SIM_HISTORY_MAX_SENSORS`; only this many most-recently-requested sensors keep history
+    history_max_sensors: Option<usize>,
+}
+
+impl RetentionConfig {
+    fn from_env() -> Self {
+        // 0 or unset disables the TTL
+        let ttl = |name| Some(env_or(name, 0i64)).filter(|&s| s > 0).map(chrono::Duration::seconds);
+        Self {
+            history_capacity: env_or("SIM_HISTORY_SIZE", 1000usize).max(1),
+            history_interval: Duration::from_millis(env_or("SIM_HISTORY_INTERVAL_MS", 1000u64).max(100)),
+            history_ttl: ttl("SIM_HISTORY_TTL_SECS"),
+            access_log_ttl: ttl("SIM_ACCESS_LOG_TTL_SECS"),
+            history_sensors: std::env::var("SIM_HISTORY_ENABLED_SENSORS").ok().map(|list| {
+                list.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect()
+            }),
+            history_max_sensors: Some(env_or("SIM_HISTORY_MAX_SENSORS", 0usize)).filter(|&n| n > 0),
+        }
+    }
+
+    /// Buffer size for a sensor. Air quality keeps at least an hour of samples
+    /// for its rolling averages.
+    fn capacity_for(&self, key: &str) -> usize {
+        match key {
+            "air-quality" => {
+                let hour = (3600.0 / self.history_interval.as_secs_f64()).ceil() as usize + 1;
+                self.history_capacity.max(hour)
+            }
+            _ => self.history_capacity,
+        }
+    }
+
+    fn history_enabled(&self, key: &str) -> bool {
+        self.history_sensors.as_ref().is_none_or(|sensors| sensors.contains(key))
+    }
+}
+
+fn is_expired(timestamp: &str, cutoff: chrono::DateTime<Utc>) -> bool {
+    chrono::DateTime::parse_from_rfc3339(timestamp).is_ok_and(|t| t < cutoff)
+}
+
+/// Mark a sensor's history as recently requested. With `SIM_HISTORY_MAX_SENSORS`
+/// set, this admits the sensor to the sampled set and evicts the
+/// least-recently-requested sensor's buffer once the set is full.
+fn touch_history(state: &AppState, key: &str) {
+    let Some(max) = state.retention.history_max_sensors else {
+        return;
+    };
+    if !state.retention.history_enabled(key) {
+        return;
+    }
+    let evicted = {
+        let mut lru = state.history_lru.lock().unwrap();
+        lru.retain(|k| k != key);
+        lru.push_front(key.to_string());
+        let keep = max.min(lru.len());
+        lru.split_off(keep)
+    };
+    if !evicted.is_empty() {
+        let mut history = state.history.lock().unwrap();
+        for key in evicted {
+            history.remove(&key);
+        }
+    }
+}
+
+/// Whether the background sampler should keep history for this sensor
+fn history_tracked(state: &AppState, key: &str) -> bool {
+    state.retention.history_enabled(key)
+        && (state.retention.history_max_sensors.is_none()
+            || state.history_lru.lock().unwrap().iter().any(|k| k == key))
+}
+
+fn record_history(state: &AppState, key: &str, data: serde_json::Value) {
+    let mut history = state.history.lock().unwrap();
+    let buffer = history.entry(key.to_string()).or_default();
+    buffer.push_back((state.clock.now().to_rfc3339(), data));
+    while buffer.len() > state.retention.capacity_for(key) {
+        buffer.pop_front();
+    }
+}
+
+/// Mean of a numeric `value` field over the trailing `window` of history,
+/// with the number of samples it covers
+fn history_average(state: &AppState, key: &str, field: &str, window: chrono::Duration) -> Option<(f64, usize)> {
+    let cutoff = state.clock.now() - window;
+    let history = state.history.lock().unwrap();
+    let (sum, n) = history
+        .get(key)?
+        .iter()
+        .rev()
+        .take_while(|(ts, _)| !is_expired(ts, cutoff))
+        .filter_map(|(_, data)| data["value"][field].as_f64())
+        .fold((0.0, 0), |(sum, n), v| (sum + v, n + 1));
+    (n > 0).then(|| (sum / n as f64, n))
+}
+
+/// Drop history samples and access-log entries older than their TTL as of `now`
+fn prune_expired(state: &AppState, now: chrono::DateTime<Utc>) {
+    if let Some(ttl) = state.retention.history_ttl {
+        let cutoff = now - ttl;
+        for buffer in state.history.lock().unwrap().values_mut() {
+            // Oldest samples sit at the front
+            while buffer.front().is_some_and(|(ts, _)| is_expired(ts, cutoff)) {
+                buffer.pop_front();
+            }
+        }
+    }
+
+    if let Some(ttl) = state.retention.access_log_ttl {
+        let cutoff = now - ttl;
+        let mut logs = state.access_log.write();
+        // Newest-first, so everything from the first expired entry on is stale
+        if let Some(first_expired) = logs.iter().position(|e| is_expired(&e.timestamp, cutoff)) {
+            logs.truncate(first_expired);
+        }
+    }
+}
+
+/// Move the newest reading of each sensor served since the last sample into
+/// its history. Nothing is simulated here, so sampling never advances a
+/// sensor or raises its alarms; a sensor nobody reads gains no history.
+fn sample_history(state: &AppState) {
+    let readings = std::mem::take(&mut *state.unsampled.lock().unwrap());
+    for (key, data) in readings {
+        if history_tracked(state, &key) {
+            record_history(state, &key, data);
+        }
+    }
+}
+
+async fn run_history_sampler(state: SharedState) {
+    let mut ticker = tokio::time::interval(state.retention.history_interval);
+    loop {
+        ticker.tick().await;
+        *state.sim_running.lock().unwrap() = true;
+        sample_history(&state);
+    }
+}
+
+async fn run_retention_maintenance(state: SharedState) {
+    if state.retention.history_ttl.is_none() && state.retention.access_log_ttl.is_none() {
+        return;
+    }
+    let mut ticker = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        ticker.tick().await;
+        prune_expired(&state, state.clock.now());
+    }
+}
+
+// ──────────────────────────────────────────────
+// Reporting Modes (polled vs report-by-exception)
+// ──────────────────────────────────────────────
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum ReportingMode {
+    /// Every tick is reported
+    #[default]
+    Polled,
+    /// Only report when the value moves by more than the deadband (DNP3-style unsolicited)
+    Exception,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct ReportingConfig {
+    reporting_mode: ReportingMode,
+    #[serde(default)]
+    deadband: f64,
+}
+
+/// Whether `current` differs from `last` by more than the deadband. Like DNP3,
+/// analog points (numbers) use the deadband and binary points (booleans)
+/// report on any change. Strings are descriptive labels derived from the
+/// points (`trend`, `status`) and don't count on their own.
+fn exceeds_deadband(last: &serde_json::Value, current: &serde_json::Value, deadband: f64) -> bool {
+    use serde_json::Value;
+    match (last, current) {
+        (Value::String(_), Value::String(_)) => false,
+        (Value::Number(a), Value::Number(b)) => match (a.as_f64(), b.as_f64()) {
+            (Some(a), Some(b)) => (a - b).abs() > deadband,
+            _ => a != b,
+        },
+        (Value::Object(a), Value::Object(b)) => {
+            a.len() != b.len()
+                || b.iter().any(|(k, v)| a.get(k).is_none_or(|old| exceeds_deadband(old, v, deadband)))
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() != b.len() || a.iter().zip(b).any(|(old, v)| exceeds_deadband(old, v, deadband))
+        }
+        (a, b) => a != b,
+    }
+}
+
+/// Per-stream memory of the last primary variable reported for each sensor.
+/// Each stream keeps its own, so one consumer's updates never suppress another's.
+#[derive(Default)]
+struct ExceptionFilter {
+    last_reported: HashMap<String, serde_json::Value>,
+}
+
+impl ExceptionFilter {
+    /// Only the primary variable is held against the deadband: secondary
+    /// fields (derived values, counters, noise) moving on their own would
+    /// otherwise report nearly every tick
+    fn should_report(&mut self, state: &AppState, key: &str, data: &serde_json::Value) -> bool {
+        let config = state.reporting.lock().unwrap().get(key).copied().unwrap_or_default();
+        if config.reporting_mode == ReportingMode::Polled {
+            return true;
+        }
+        let field = primary_variable_field(state, key).unwrap_or("value");
+        let current = &data["value"][field];
+        let changed = self
+            .last_reported
+            .get(key)
+            .is_none_or(|last| exceeds_deadband(last, current, config.deadband));
+        if changed {
+            self.last_reported.insert(key.to_string(), current.clone());
+        }
+        changed
+    }
+}
+
+// ──────────────────────────────────────────────
+// Cluster (simulated redundant-server failover)
+// ──────────────────────────────────────────────
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum ClusterRole {
+    Primary,
+    Standby,
+}
+
+impl ClusterRole {
+    fn flipped(self) -> Self {
+        match self {
+            ClusterRole::Primary => ClusterRole::Standby,
+            ClusterRole::Standby => ClusterRole::Primary,
+        }
+    }
+}
+
+/// This instance's place in a simulated HA pair. A failover makes sensor
+/// reads unavailable for `failover_window`, after which the roles are flipped.
+struct ClusterState {
+    node_id: String,
+    role: ClusterRole,
+    peers: Vec<String>,
+    failover_window: Duration,
+    failover_until: Option<std::time::Instant>,
+    failover_count: u64,
+    last_failover_at: Option<String>,
+}
+
+impl ClusterState {
+    fn from_env() -> Self {
+        let role = match std::env::var("SIM_CLUSTER_ROLE").as_deref() {
+            Ok("standby") => ClusterRole::Standby,
+            _ => ClusterRole::Primary,
+        };
+        let peers = std::env::var("SIM_CLUSTER_PEERS")
+            .unwrap_or_else(|_| "simmurator-b:4040".to_string())
+            .split(',')
+            .map(|p| p.trim().to_string())
+            .filter(|p| !p.is_empty())
+            .collect();
+        Self {
+            node_id: std::env::var("SIM_CLUSTER_NODE_ID").unwrap_or_else(|_| "simmurator-a".to_string()),
+            role,
+            peers,
+            failover_window: Duration::from_secs_f64(env_or("SIM_FAILOVER_SECS", 5.0f64).clamp(0.0, 3600.0)),
+            failover_until: None,
+            failover_count: 0,
+            last_failover_at: None,
+        }
+    }
+
+    /// Complete a failover whose window has elapsed; returns the time left otherwise
+    fn failover_remaining(&mut self) -> Option<Duration> {
+        let until = self.failover_until?;
+        let now = std::time::Instant::now();
+        if now < until {
+            return Some(until - now);
+        }
+        self.failover_until = None;
+        self.role = self.role.flipped();
+        None
+    }
+
+    fn status(&mut self) -> serde_json::Value {
+        let remaining = self.failover_remaining();
+        // In a pair, the first peer holds whichever role we don't
+        let peers: Vec<_> = self.peers
+            .iter()
+            .enumerate()
+            .map(|(i, address)| {
+                let role = if i == 0 { self.role.flipped() } else { ClusterRole::Standby };
+                serde_json::json!({
+                    "address": address,
+                    "role": if remaining.is_some() { serde_json::json!("transitioning") } else { serde_json::json!(role) },
+                    "healthy": true
+                })
+            })
+            .collect();
+        serde_json::json!({
+            "nodeId": self.node_id,
+            "role": self.role,
+            "failoverInProgress": remaining.is_some(),
+            "failoverRemainingMs": remaining.map(|r| r.as_millis() as u64),
+            "failoverCount": self.failover_count,
+            "lastFailoverAt": self.last_failover_at,
+            "peers": peers
+        })
+    }
+}
+
+/// 503 for sensor reads while a failover is in progress
+fn failover_unavailable(state: &AppState) -> Option<Response> {
+    let remaining = state.cluster.lock().unwrap().failover_remaining()?;
+    Some((
+        axum::http::StatusCode::SERVICE_UNAVAILABLE,
+        [(axum::http::header::RETRY_AFTER, remaining.as_secs_f64().ceil().max(1.0).to_string())],
+        Json(serde_json::json!({
+            "status": "error",
+            "error": "Failover in progress",
+            "timestamp": state.clock.now().to_rfc3339()
+        })),
+    ).into_response())
+}
+
+// ──────────────────────────────────────────────
+// Handlers
+// ──────────────────────────────────────────────
+
+async fn get_endpoints(State(state): State<SharedState>) -> Response {
+    let endpoints: Vec<_> = sensor_keys(&state)
+        .map(|key| serde_json::json!({
+            "name": key,
+            "url": format!("/api/v1/sensors/{}", key),
+            "method": "GET",
+            "description": format!("Returns simulated {} IoT sensor data", key.replace('-', " "))
+        }))
+        .collect();
+
+    Json(serde_json::json!({
+        "status": "ok",
+        "endpoints": endpoints
+    })).into_response()
+}
+
+/// GraphiQL IDE for `/graphql` (subscriptions over `/graphql/ws`)
+async fn graphiql() -> Response {
+    axum::response::Html(
+        async_graphql::http::GraphiQLSource::build()
+            .endpoint("/graphql")
+            .subscription_endpoint("/graphql/ws")
+            .finish(),
+    )
+    .into_response()
+}
+
+/// GraphQL subscriptions over WebSocket (graphql-ws or the older
+/// subscriptions-transport-ws). When shutdown begins the client's side of the
+/// socket is cut off, which ends its subscriptions, and the socket closes
+/// with 1001 (going away) like `/ws/sensors`.
+async fn graphql_ws_handler(
+    State(state): State<SharedState>,
+    schema: graphql::SensorSchema,
+    protocol: async_graphql_axum::GraphQLProtocol,
+    ws: WebSocketUpgrade,
+) -> Response {
+    ws.protocols(async_graphql::http::ALL_WEBSOCKET_PROTOCOLS)
+        .on_upgrade(move |socket| async move {
+            let _open = OpenStream::new(&state);
+            let (mut sink, stream) = socket.split();
+            let input = stream.take_until(shutdown_signaled(&state));
+            async_graphql_axum::GraphQLWebSocket::new_with_pair(&mut sink, input, schema, protocol).serve().await;
+            if *state.shutdown.borrow() {
+                let close = axum::extract::ws::CloseFrame {
+                    code: axum::extract::ws::close_code::AWAY,
+                    reason: "server shutting down".into(),
+                };
+                let _ = futures_util::SinkExt::send(&mut sink, Message::Close(Some(close))).await;
+            }
+        })
+}
+
+async fn get_openapi(State(state): State<SharedState>) -> Response {
+    let sensors: Vec<&str> = sensor_keys(&state).collect();
+    Json(openapi::spec(&sensors)).into_response()
+}
+
+/// JSON Schema of a reading (`UnifiedSensorData`), derived from the types
+/// that produce it. `value` and `properties` vary by sensor, so they are
+/// described as generic objects; post-processing fields (peak hold, stale
+/// cache, ...) are allowed as additional properties.
+async fn get_reading_schema() -> Response {
+    Json(schemars::schema_for!(UnifiedSensorData)).into_response()
+}
+
+/// Swagger UI for `/api/openapi.json`, loaded from the unpkg CDN
+async fn get_docs() -> Response {
+    axum::response::Html(
+        r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8" />
+  <title>Simmurator API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css" />
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>
+    window.ui = SwaggerUIBundle({ url: "/api/openapi.json", dom_id: "#swagger-ui" });
+  </script>
+</body>
+</html>
+"##,
+    )
+    .into_response()
+}
+
+/// Static configuration of a sensor; nothing is simulated or sampled
+async fn get_sensor_meta(Path(key): Path<String>, State(state): State<SharedState>) -> Response {
+    if let Some(config) = sensor_config(&state, &key) {
+        let normal = config.normal();
+        return Json(serde_json::json!({
+            "status": "ok",
+            "sensor": config.key,
+            "deviceId": config.device_id,
+            "sensorType": config.sensor_type(),
+            "description": config.description,
+            "unit": get_ucum_unit(&config.unit),
+            "opcUa": generate_opcua_node(&config.device_id, config.display_name()),
+            "equipmentHierarchy": generate_isa95_hierarchy(&config.device_id, &config.line, &config.area),
+            "sparkplugTopic": generate_sparkplug_topic("Plant-01", &config.device_id),
+            "normalRange": NormalRange { metric: "value", min: normal.min, max: normal.max },
+            "thresholds": config.thresholds
+        })).into_response();
+    }
+    let Some(desc) = sensor_descriptor(base_sensor_key(&state, &key)) else {
+        return sensor_not_found();
+    };
+    let (device_id, display_name) = match amr_station(&state, &key) {
+        Some(station) => (station.meter_serial, format!("AMR {}", station.location)),
+        None => (desc.device_id, desc.display_name.to_string()),
+    };
+    let thresholds: serde_json::Map<String, serde_json::Value> = desc
+        .thresholds
+        .iter()
+        .map(|&(name, value)| (name.to_string(), serde_json::json!(value)))
+        .collect();
+
+    Json(serde_json::json!({
+        "status": "ok",
+        "sensor": key,
+        "deviceId": device_id,
+        "sensorType": desc.sensor_type,
+        "description": desc.description,
+        "unit": get_ucum_unit(desc.unit),
+        "opcUa": generate_opcua_node(device_id, &display_name),
+        "equipmentHierarchy": generate_isa95_hierarchy(device_id, desc.line, desc.area),
+        "sparkplugTopic": generate_sparkplug_topic("Plant-01", device_id),
+        "normalRange": desc.normal,
+        "thresholds": thresholds
+    })).into_response()
+}
+
+#[axum::debug_handler]
+async fn get_sensor_data(
+    Path(key): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    RawQuery(query): RawQuery,
+    headers: axum::http::HeaderMap,
+    State(state): State<SharedState>,
+) -> Response {
+    if let Some(redirect) = maybe_redirect(&state, &key, 0, query.as_deref()) {
+        return redirect;
+    }
+    let format = match ResponseFormat::from_request(&params, &headers) {
+        Ok(format) => format,
+        Err(error) => {
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "status": "error",
+                    "error": error
+                })),
+            ).into_response()
+        }
+    };
+    let units = match units::UnitSystem::from_param(params.get("units").map(String::as_str)) {
+        Ok(units) => units,
+        Err(error) => {
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "status": "error",
+                    "error": error
+                })),
+            ).into_response()
+        }
+    };
+    let watermark = watermark_consumer(&state, &params, &headers);
+    serve_sensor_data(&state, &key, watermark, format, units).await
+}
+
+/// One pipeline station's own AMR meter (sensor key `amr/<station id>`); the
+/// same reading is served at `/api/v1/sensors/amr/<station id>` (see `route_amr_stations`)
+async fn get_amr_station_data(
+    Path(station_id): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    headers: axum::http::HeaderMap,
+    State(state): State<SharedState>,
+) -> Response {
+    let key = format!("{}{}", AMR_STATION_PREFIX, station_id);
+    if amr_station(&state, &key).is_none() {
+        return sensor_not_found();
+    }
+    let format = match ResponseFormat::from_request(&params, &headers) {
+        Ok(format) => format,
+        Err(error) => {
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "status": "error",
+                    "error": error
+                })),
+            ).into_response()
+        }
+    };
+    let units = match units::UnitSystem::from_param(params.get("units").map(String::as_str)) {
+        Ok(units) => units,
+        Err(error) => {
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "status": "error",
+                    "error": error
+                })),
+            ).into_response()
+        }
+    };
+    let watermark = watermark_consumer(&state, &params, &headers);
+    serve_sensor_data(&state, &key, watermark, format, units).await
+}
+
+/// Every pipeline station with an addressable AMR meter
+async fn get_amr_stations(State(state): State<SharedState>) -> Response {
+    let stations: Vec<_> = state
+        .oil_stations
+        .iter()
+        .map(|station| {
+            let id = station.id();
+            serde_json::json!({
+                "id": id,
+                "sensor": format!("{}{}", AMR_STATION_PREFIX, id),
+                "url": format!("/api/v1/sensors/amr/{}", id),
+                "location": station.location,
+                "province": station.province,
+                "coordinates": { "lat": station.lat, "lng": station.lng },
+                "meterSerial": station.meter_serial,
+                "pipelineId": station.pipeline_id
+            })
+        })
+        .collect();
+
+    Json(serde_json::json!({
+        "status": "ok",
+        "count": stations.len(),
+        "stations": stations
+    })).into_response()
+}
+
+/// Redirect target for `SIM_REDIRECT_RATE`; serves the same payload as `/api/v1/sensors/:key`
+async fn get_sensor_data_raw(
+    Path(key): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    RawQuery(query): RawQuery,
+    headers: axum::http::HeaderMap,
+    State(state): State<SharedState>,
+) -> Response {
+    let hop = params.get("hop").and_then(|h| h.parse().ok()).unwrap_or(0);
+    if let Some(redirect) = maybe_redirect(&state, &key, hop, query.as_deref()) {
+        return redirect;
+    }
+    let format = match ResponseFormat::from_request(&params, &headers) {
+        Ok(format) => format,
+        Err(error) => {
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "status": "error",
+                    "error": error
+                })),
+            ).into_response()
+        }
+    };
+    let units = match units::UnitSystem::from_param(params.get("units").map(String::as_str)) {
+        Ok(units) => units,
+        Err(error) => {
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "status": "error",
+                    "error": error
+                })),
+            ).into_response()
+        }
+    };
+    let watermark = watermark_consumer(&state, &params, &headers);
+    serve_sensor_data(&state, &key, watermark, format, units).await
+}
+
+/// With probability `SIM_REDIRECT_RATE`, answer with a 307/308 to the raw
+/// endpoint instead of data. `hop` counts redirects already followed; once it
+/// reaches `SIM_REDIRECT_MAX_DEPTH` the data is served so chains always end.
+/// The rest of the query string is carried over to the redirect target.
+fn maybe_redirect(state: &AppState, key: &str, hop: u32, query: Option<&str>) -> Option<Response> {
+    if hop >= state.redirect_max_depth || !is_sensor_key(state, key) {
+        return None;
+    }
+    let rng = state.rngs.consumer("redirect");
+    let mut rng = rng.lock().unwrap();
+    if !rng.gen_bool(state.redirect_rate) {
+        return None;
+    }
+
+    let status = if rng.gen_bool(0.5) {
+        axum::http::StatusCode::TEMPORARY_REDIRECT
+    } else {
+        axum::http::StatusCode::PERMANENT_REDIRECT
+    };
+    let mut location = format!("/api/v1/sensors/{}/raw?hop={}", key, hop + 1);
+    for pair in query.unwrap_or_default().split('&') {
+        if !pair.is_empty() && !pair.starts_with("hop=") {
+            location.push('&');
+            location.push_str(pair);
+        }
+    }
+    Some((status, [(axum::http::header::LOCATION, location)]).into_response())
+}
+
+/// Body encoding for sensor reads, chosen with `?format=` or, failing that,
+/// the `Accept` header
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum ResponseFormat {
+    #[default]
+    Json,
+    /// Avro object container file with the sensor's schema embedded
+    Avro,
+    /// XML for legacy SCADA integrations
+    Xml,
+    /// Sparkplug B (Eclipse Tahu) DDATA protobuf payload
+    Sparkplug,
+    /// InfluxDB line protocol, one line per reading
+    Influx,
+}
+
+impl ResponseFormat {
+    fn from_request(params: &HashMap<String, String>, headers: &axum::http::HeaderMap) -> Result<Self, String> {
+        match params.get("format").map(String::as_str) {
+            Some("json") => Ok(Self::Json),
+            Some("avro") => Ok(Self::Avro),
+            Some("xml") => Ok(Self::Xml),
+            Some("sparkplug") => Ok(Self::Sparkplug),
+            Some("influx") => Ok(Self::Influx),
+            Some(other) => Err(format!("Unsupported format '{}'", other)),
+            None => {
+                let accept = headers
+                    .get(axum::http::header::ACCEPT)
+                    .and_then(|h| h.to_str().ok())
+                    .unwrap_or_default();
+                if accept.contains("application/xml") || accept.contains("text/xml") {
+                    Ok(Self::Xml)
+                } else {
+                    Ok(Self::Json)
+                }
+            }
+        }
+    }
+}
+
+fn xml_response(body: std::io::Result<String>) -> Response {
+    match body {
+        Ok(body) => ([(axum::http::header::CONTENT_TYPE, "application/xml")], body).into_response(),
+        Err(e) => (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({
+                "status": "error",
+                "error": format!("XML encoding failed: {}", e)
+            })),
+        ).into_response(),
+    }
+}
+
+fn influx_response(body: String) -> Response {
+    ([(axum::http::header::CONTENT_TYPE, "text/plain; charset=utf-8")], body).into_response()
+}
+
+/// A single reading in the requested encoding
+fn reading_response(state: &AppState, key: &str, data: serde_json::Value, format: ResponseFormat) -> Response {
+    match format {
+        ResponseFormat::Avro => avro_response(state, key, &data),
+        ResponseFormat::Xml => xml_response(xml::reading(key, &data)),
+        ResponseFormat::Influx => influx_response(influx::lines([&data])),
+        ResponseFormat::Sparkplug => (
+            [
+                (axum::http::header::CONTENT_TYPE, "application/octet-stream".to_string()),
+                (axum::http::HeaderName::from_static("x-sparkplug-topic"), sparkplug_topic(&data, "DDATA")),
+            ],
+            sparkplug::sparkplug_payload(&data, next_sparkplug_seq(state), state.clock.now().timestamp_millis() as u64),
+        ).into_response(),
+        ResponseFormat::Json => Json(serde_json::json!({
+            "status": "ok",
+            "timestamp": state.clock.now().to_rfc3339(),
+            "data": data
+        })).into_response(),
+    }
+}
+
+/// The sensor's Avro schema: inferred from its first reading, then widened
+/// whenever a reading brings a field or type it lacks, so `/avsc` always
+/// describes everything the sensor has sent
+fn avro_schema(state: &AppState, key: &str, data: &serde_json::Value) -> serde_json::Value {
+    let mut schemas = state.avro_schemas.lock().unwrap();
+    match schemas.get_mut(key) {
+        Some(schema) => {
+            avro::widen(schema, data);
+            schema.clone()
+        }
+        None => {
+            let schema = avro::schema_for(key, data);
+            schemas.insert(key.to_string(), schema.clone());
+            schema
+        }
+    }
+}
+
+fn avro_response(state: &AppState, key: &str, data: &serde_json::Value) -> Response {
+    let schema = avro_schema(state, key, data);
+    match avro::encode(&schema, data) {
+        Ok(bytes) => ([(axum::http::header::CONTENT_TYPE, "application/avro")], bytes).into_response(),
+        Err(e) => (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({
+                "status": "error",
+                "error": format!("Avro encoding failed: {}", e)
+            })),
+        ).into_response(),
+    }
+}
+
+/// Response for a read the error simulation failed. A 503 says when to
+/// retry (a few seconds, like a device that's rebooting); other statuses
+/// are sent bare.
+fn simulated_read_error(state: &AppState) -> Response {
+    let status = state.chaos.lock().unwrap().error_status;
+    let status = axum::http::StatusCode::from_u16(status).unwrap_or(axum::http::StatusCode::SERVICE_UNAVAILABLE);
+    let mut response = (
+        status,
+        Json(serde_json::json!({
+            "status": "error",
+            "error": "Sensor temporarily unavailable",
+            "timestamp": state.clock.now().to_rfc3339()
+        })),
+    ).into_response();
+    if status == axum::http::StatusCode::SERVICE_UNAVAILABLE {
+        let retry_after: u64 = state.rngs.consumer("chaos").lock().unwrap().gen_range(1..=5);
+        response.headers_mut().insert(axum::http::header::RETRY_AFTER, retry_after.into());
+    }
+    response
+}
+
+/// Slow response & error simulation for one sensor read: how long the read
+/// takes and whether it fails
+fn simulate_read_fault(state: &AppState, key: &str) -> (Duration, bool) {
+    let chaos = *state.chaos.lock().unwrap();
+    let rng = state.rngs.consumer("chaos");
+    let mut rng = rng.lock().unwrap();
+    let delay = if rng.gen_bool(chaos.slow_rate) { rng.gen_range(200..800) } else { rng.gen_range(5..50) };
+    let is_error = rng.gen_bool(chaos.error_rate) || rng.gen_bool(boot_error_rate(state, key));
+    (Duration::from_millis(delay), is_error)
+}
+
+/// Why a sensor read produced no reading
+#[derive(Clone, Copy)]
+enum ReadFailure {
+    /// Comm fault with nothing cached to fall back on
+    NotResponding,
+    /// The error simulation failed the read
+    Unavailable,
+    NotFound,
+}
+
+impl ReadFailure {
+    fn message(self) -> &'static str {
+        match self {
+            Self::NotResponding => "Sensor not responding",
+            Self::Unavailable => "Sensor temporarily unavailable",
+            Self::NotFound => "Sensor not found",
+        }
+    }
+
+    fn into_response(self, state: &AppState) -> Response {
+        match self {
+            Self::NotResponding => (
+                axum::http::StatusCode::GATEWAY_TIMEOUT,
+                Json(serde_json::json!({
+                    "status": "error",
+                    "error": self.message(),
+                    "timestamp": state.clock.now().to_rfc3339()
+                })),
+            ).into_response(),
+            Self::Unavailable => simulated_read_error(state),
+            Self::NotFound => sensor_not_found(),
+        }
+    }
+}
+
+/// One sensor read as the single-sensor endpoints serve it: stale cache on
+/// a comm fault, simulated latency and errors, then a fresh reading in the
+/// requested units, watermarked for the consumer
+async fn read_sensor(
+    state: &AppState,
+    key: &str,
+    watermark: Option<u16>,
+    units: units::UnitSystem,
+) -> Result<serde_json::Value, ReadFailure> {
+    let mut data = if is_offline(state, key) {
+        stale_reading(state, key).ok_or(ReadFailure::NotResponding)?
+    } else {
+        let (delay, is_error) = simulate_read_fault(state, key);
+        tokio::time::sleep(delay).await;
+        if is_error {
+            return Err(ReadFailure::Unavailable);
+        }
+        generate_sensor_data(state, key).ok_or(ReadFailure::NotFound)?
+    };
+    units::convert(&mut data, &reading_unit_fields(state, key), units);
+    apply_watermark(&mut data, watermark);
+    Ok(data)
+}
+
+/// Fields of a reading's `value` object in the reading's own unit that only
+/// the sensor's definition knows: its primary variable and configured thresholds
+fn reading_unit_fields(state: &AppState, key: &str) -> Vec<&'static str> {
+    match sensor_config(state, key) {
+        Some(config) => std::iter::once("value").chain(config.thresholds.keys().map(String::as_str)).collect(),
+        None => primary_variable_field(state, key).into_iter().collect(),
+    }
+}
+
+async fn serve_sensor_data(
+    state: &AppState,
+    key: &str,
+    watermark: Option<u16>,
+    format: ResponseFormat,
+    units: units::UnitSystem,
+) -> Response {
+    if is_sensor_key(state, key) {
+        touch_history(state, key);
+    }
+    if let Some(unavailable) = failover_unavailable(state) {
+        return unavailable;
+    }
+    match read_sensor(state, key, watermark, units).await {
+        Ok(data) => reading_response(state, key, data, format),
+        Err(failure) => failure.into_response(state),
+    }
+}
+
+/// 422 for a numeric override on a sensor whose primary variable isn't a number
+fn primary_not_numeric(key: &str) -> Response {
+    (
+        axum::http::StatusCode::UNPROCESSABLE_ENTITY,
+        Json(serde_json::json!({
+            "status": "error",
+            "error": format!("The primary variable of '{}' is not numeric", key)
+        })),
+    ).into_response()
+}
+
+fn sensor_not_found() -> Response {
+    (
+        axum::http::StatusCode::NOT_FOUND,
+        Json(serde_json::json!({
+            "status": "error",
+            "error": "Sensor not found"
+        })),
+    ).into_response()
+}
+
+async fn get_all_sensors(
+    Query(params): Query<HashMap<String, String>>,
+    headers: axum::http::HeaderMap,
+    State(state): State<SharedState>,
+) -> Response {
+    if let Some(unavailable) = failover_unavailable(&state) {
+        return unavailable;
+    }
+    // Avro and Sparkplug payloads describe one device, so a combined read is
+    // JSON, XML or line protocol only
+    let format = match ResponseFormat::from_request(&params, &headers) {
+        Ok(format @ (ResponseFormat::Json | ResponseFormat::Xml | ResponseFormat::Influx)) => format,
+        Ok(ResponseFormat::Avro | ResponseFormat::Sparkplug) => {
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "status": "error",
+                    "error": "Avro and Sparkplug are only available per sensor"
+                })),
+            ).into_response()
+        }
+        Err(error) => {
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "status": "error",
+                    "error": error
+                })),
+            ).into_response()
+        }
+    };
+    // `?only=` / `?exclude=` (comma-separated) narrow the set; names that
+    // aren't sensors are reported back, as the WebSocket Subscribe action does
+    let list = |name: &str| -> Option<Vec<&str>> {
+        params.get(name).map(|v| v.split(',').map(str::trim).filter(|s| !s.is_empty()).collect())
+    };
+    let only = list("only");
+    let exclude = list("exclude").unwrap_or_default();
+    let mut unknown: Vec<&str> = Vec::new();
+    for &name in only.iter().flatten().chain(&exclude) {
+        if !is_sensor_key(&state, name) && !unknown.contains(&name) {
+            unknown.push(name);
+        }
+    }
+
+    let watermark = watermark_consumer(&state, &params, &headers);
+    let selected: Vec<&str> = sensor_keys(&state)
+        .filter(|key| only.as_ref().is_none_or(|only| only.contains(key)) && !exclude.contains(key))
+        .collect();
+
+    // Sensors are read side by side: the batch takes as long as its slowest
+    // read, and a failed read errors that sensor's entry, not the whole batch.
+    // Readings are generated concurrently on the blocking pool; every sensor
+    // draws from its own seeded stream, so the order they finish in doesn't
+    // matter. The map keeps keys sorted.
+    let faults: Vec<_> = selected.iter().map(|&key| simulate_read_fault(&state, key)).collect();
+    if let Some(slowest) = faults.iter().map(|&(delay, _)| delay).max() {
+        tokio::time::sleep(slowest).await;
+    }
+    let unavailable = |error: &str| serde_json::json!({ "status": "error", "error": error });
+    let mut all = BTreeMap::new();
+    let mut tasks = HashMap::new();
+    let mut set = tokio::task::JoinSet::new();
+    for (&key, &(_, is_error)) in selected.iter().zip(&faults) {
+        if is_error {
+            all.insert(key, unavailable("Sensor temporarily unavailable"));
+            continue;
+        }
+        let state = state.clone();
+        let task = set.spawn_blocking(move || generate_sensor_data(&state, key));
+        tasks.insert(task.id(), key);
+    }
+    let mut readings = Vec::new();
+    while let Some(joined) = set.join_next_with_id().await {
+        match joined {
+            Ok((id, Some(data))) => readings.push((tasks[&id], data)),
+            Ok((id, None)) => {
+                all.insert(tasks[&id], unavailable("Sensor produced no reading"));
+            }
+            Err(e) => {
+                tracing::error!(sensor = tasks[&e.id()], error = %e, "sensor reading task failed");
+                all.insert(tasks[&e.id()], unavailable("Sensor reading failed"));
+            }
+        }
+    }
+    for (key, mut data) in readings {
+        apply_watermark(&mut data, watermark);
+        all.insert(key, data);
+    }
+
+    let timestamp = state.clock.now().to_rfc3339();
+    if format == ResponseFormat::Xml {
+        return xml_response(xml::readings(&timestamp, all.iter().map(|(&key, data)| (key, data))));
+    }
+    if format == ResponseFormat::Influx {
+        return influx_response(influx::lines(all.values()));
+    }
+
+    let mut body = serde_json::json!({
+        "status": "ok",
+        "timestamp": timestamp,
+        "data": all
+    });
+    if !unknown.is_empty() {
+        body["unknown"] = serde_json::json!(unknown);
+    }
+    Json(body).into_response()
+}
+
+#[derive(Deserialize)]
+struct SensorBatchRequest {
+    sensors: Vec<String>,
+    units: Option<String>,
+    format: Option<String>,
+}
+
+/// Fresh readings of an arbitrary set of sensors in one round trip, keyed by
+/// name. Each sensor goes through the same read path as `GET :key` (side by
+/// side, so the batch takes as long as its slowest read); a name that isn't
+/// a sensor, or a read that fails, gets an error object in its place.
+async fn get_sensor_batch(
+    Query(params): Query<HashMap<String, String>>,
+    headers: axum::http::HeaderMap,
+    State(state): State<SharedState>,
+    Json(req): Json<SensorBatchRequest>,
+) -> Response {
+    let bad_request = |error: String| {
+        (
+            axum::http::StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "status": "error",
+                "error": error
+            })),
+        ).into_response()
+    };
+    if req.sensors.is_empty() {
+        return bad_request("sensors must list at least one sensor".to_string());
+    }
+    // Readings are the unified JSON envelope; the binary and text encodings
+    // are per sensor (or the GET endpoints)
+    if let Some(format) = req.format.as_deref().filter(|&f| f != "unified" && f != "json") {
+        return bad_request(format!("Unsupported format '{}' (batch readings are unified JSON)", format));
+    }
+    let units = match units::UnitSystem::from_param(req.units.as_deref()) {
+        Ok(units) => units,
+        Err(error) => return bad_request(error),
+    };
+    if let Some(unavailable) = failover_unavailable(&state) {
+        return unavailable;
+    }
+
+    let watermark = watermark_consumer(&state, &params, &headers);
+    let mut keys: Vec<&str> = req.sensors.iter().map(String::as_str).collect();
+    keys.sort_unstable();
+    keys.dedup();
+    let reads = keys.iter().map(|&key| {
+        let state = &state;
+        async move {
+            if !is_sensor_key(state, key) {
+                return Err(ReadFailure::NotFound);
+            }
+            touch_history(state, key);
+            read_sensor(state, key, watermark, units).await
+        }
+    });
+    let results = futures_util::future::join_all(reads).await;
+
+    let mut errors = 0;
+    let data: BTreeMap<&str, serde_json::Value> = keys
+        .iter()
+        .zip(results)
+        .map(|(&key, result)| {
+            let entry = result.unwrap_or_else(|failure| {
+                errors += 1;
+                serde_json::json!({
+                    "status": "error",
+                    "error": failure.message()
+                })
+            });
+            (key, entry)
+        })
+        .collect();
+
+    Json(serde_json::json!({
+        "status": "ok",
+        "timestamp": state.clock.now().to_rfc3339(),
+        "count": data.len(),
+        "errors": errors,
+        "data": data
+    })).into_response()
+}
+
+async fn decode_watermark_handler(
+    State(state): State<SharedState>,
+    Json(readings): Json<serde_json::Value>,
+) -> Response {
+    let Some((code, confidence)) = decode_watermark(&readings) else {
+        return (
+            axum::http::StatusCode::UNPROCESSABLE_ENTITY,
+            Json(serde_json::json!({
+                "status": "error",
+                "error": "No watermark found in the supplied readings"
+            })),
+        ).into_response();
+    };
+    let consumer = state.watermark_consumers.lock().unwrap().get(&code).cloned();
+
+    Json(serde_json::json!({
+        "status": "ok",
+        "watermark": format!("{:04x}", code),
+        "consumer": consumer,
+        "confidence": format!("{:.3}", confidence).parse::<f64>().unwrap()
+    })).into_response()
+}
+
+/// Parse an access-log `?status=` filter: an exact code (`503`) or an
+/// inclusive range (`500-599`)
+fn parse_status_filter(raw: &str) -> Option<std::ops::RangeInclusive<u16>> {
+    match raw.split_once('-') {
+        Some((lo, hi)) => {
+            let (lo, hi) = (lo.trim().parse().ok()?, hi.trim().parse().ok()?);
+            (lo <= hi).then_some(lo..=hi)
+        }
+        None => raw.trim().parse().ok().map(|code| code..=code),
+    }
+}
+
+/// Access-log query filters shared by the log endpoint and `/events`:
+/// `?status=` (exact or `500-599`), `?min_status=`, `?endpoint=`
+/// (substring) and `?ip=` (exact), ANDed together
+struct AccessLogFilter {
+    status: Option<std::ops::RangeInclusive<u16>>,
+    min_status: Option<u16>,
+    endpoint: Option<String>,
+    ip: Option<String>,
+}
+
+impl AccessLogFilter {
+    fn from_params(params: &HashMap<String, String>) -> Result<Self, &'static str> {
+        let status = match params.get("status") {
+            Some(raw) => Some(parse_status_filter(raw).ok_or("status must be a code (e.g. 503) or a range (e.g. 500-599)")?),
+            None => None,
+        };
+        let min_status = match params.get("min_status") {
+            Some(raw) => Some(raw.trim().parse().map_err(|_| "min_status must be a status code (e.g. 400)")?),
+            None => None,
+        };
+        Ok(AccessLogFilter {
+            status,
+            min_status,
+            endpoint: params.get("endpoint").cloned(),
+            ip: params.get("ip").cloned(),
+        })
+    }
+
+    fn matches(&self, entry: &AccessLogEntry) -> bool {
+        self.status.as_ref().is_none_or(|range| range.contains(&entry.status_code))
+            && self.min_status.is_none_or(|min| entry.status_code >= min)
+            && self.endpoint.as_ref().is_none_or(|needle| entry.endpoint.contains(needle.as_str()))
+            && self.ip.as_ref().is_none_or(|ip| entry.ip == *ip)
+    }
+}
+
+/// Newest-first page of the access log buffer, narrowed by
+/// `AccessLogFilter`; `?offset=` skips matches before `?limit=` is applied. `total` counts every
+/// request served, while `pagination.total` is how many buffered entries
+/// matched the filters.
+async fn get_access_log(
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<SharedState>,
+) -> Response {
+    let limit = params.get("limit")
+        .and_then(|l| l.parse::<usize>().ok())
+        .unwrap_or(50);
+    let offset = params.get("offset")
+        .and_then(|o| o.parse::<usize>().ok())
+        .unwrap_or(0);
+    let filter = match AccessLogFilter::from_params(&params) {
+        Ok(filter) => filter,
+        Err(e) => {
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({ "status": "error", "error": e })),
+            ).into_response();
+        }
+    };
+    let csv = match params.get("format").map(String::as_str) {
+        None | Some("json") => false,
+        Some("csv") => true,
+        Some(other) => {
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "status": "error",
+                    "error": format!("Unsupported format '{}'", other)
+                })),
+            ).into_response();
+        }
+    };
+
+    let (matched, entries) = {
+        let logs = state.access_log.read();
+        let matching: Vec<_> = logs.iter().filter(|e| filter.matches(e)).collect();
+        (matching.len(), matching.iter().skip(offset).take(limit).map(|&e| e.clone()).collect::<Vec<_>>())
+    };
+    if csv {
+        return (
+            [
+                (axum::http::header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+                (axum::http::header::CONTENT_DISPOSITION, "attachment; filename=\"access-log.csv\""),
+            ],
+            access_log_csv(&entries),
+        ).into_response();
+    }
+    let total = *state.request_counter.lock().unwrap();
+
+    Json(serde_json::json!({
+        "status": "ok",
+        "total": total,
+        "pagination": {
+            "total": matched,
+            "offset": offset,
+            "limit": limit,
+            "returned": entries.len()
+        },
+        "entries": entries
+    })).into_response()
+}
+
+/// `/api/v1/access-log.csv`: the access log as CSV, same filters as the JSON form
+async fn get_access_log_csv(
+    Query(mut params): Query<HashMap<String, String>>,
+    state: State<SharedState>,
+) -> Response {
+    params.insert("format".to_string(), "csv".to_string());
+    get_access_log(Query(params), state).await
+}
+
+/// Quote a CSV field if it holds a delimiter, quote or line break (RFC 4180)
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// `csv_field` for client-supplied text: a leading `=`, `+`, `-` or `@`
+/// would make a spreadsheet evaluate the cell as a formula, so it's
+/// prefixed with `'` to keep it text
+fn csv_text_field(s: &str) -> String {
+    if s.starts_with(['=', '+', '-', '@']) {
+        csv_field(&format!("'{}", s))
+    } else {
+        csv_field(s)
+    }
+}
+
+fn access_log_csv(entries: &[AccessLogEntry]) -> String {
+    let mut out = String::from("id,timestamp,ip,userAgent,endpoint,method,statusCode,responseTime,deviceId,apiKeyId\r\n");
+    for e in entries {
+        out.push_str(&format!(
+            "{},{},{},{},{},{},{},{},{},{}\r\n",
+            e.id,
+            csv_field(&e.timestamp),
+            csv_field(&e.ip),
+            csv_text_field(&e.user_agent),
+            csv_text_field(&e.endpoint),
+            csv_field(&e.method),
+            e.status_code,
+            e.response_time,
+            csv_field(e.device_id.as_deref().unwrap_or_default()),
+            csv_field(e.api_key_id.as_deref().unwrap_or_default()),
+        ));
+    }
+    out
+}
+
+/// A 401 response unless the request carries `X-Admin-Token` matching ADMIN_TOKEN
+fn admin_denied(state: &AppState, headers: &axum::http::HeaderMap) -> Option<Response> {
+    let unauthorized = |error: &str| {
+        (
+            axum::http::StatusCode::UNAUTHORIZED,
+            Json(serde_json::json!({
+                "status": "error",
+                "error": error
+            })),
+        ).into_response()
+    };
+    let Some(expected) = state.admin_token.as_deref() else {
+        return Some(unauthorized("Admin endpoints are disabled (ADMIN_TOKEN is not set)"));
+    };
+    match headers.get("x-admin-token").and_then(|h| h.to_str().ok()) {
+        Some(token) if token == expected => None,
+        _ => Some(unauthorized("Missing or invalid X-Admin-Token")),
+    }
+}
+
+async fn clear_access_log(
+    headers: axum::http::HeaderMap,
+    State(state): State<SharedState>,
+) -> Response {
+    if let Some(denied) = admin_denied(&state, &headers) {
+        return denied;
+    }
+
+    // Same lock order as log_middleware (counter, then log), so a request
+    // finishing mid-clear waits rather than deadlocking
+    let removed = {
+        let mut counter = state.request_counter.lock().unwrap();
+        let mut logs = state.access_log.write();
+        *counter = 0;
+        let removed = logs.len();
+        logs.clear();
+        removed
+    };
+    // The percentiles restart along with the log they sit beside
+    state.latency.lock().clear();
+
+    Json(serde_json::json!({
+        "status": "ok",
+        "removed": removed
+    })).into_response()
+}
+
+/// Buffered samples newest-first; `?since=` (RFC3339) keeps only samples
+/// taken after it and `?limit=` caps how many are returned
+async fn get_sensor_history(
+    Path(key): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<SharedState>,
+) -> Response {
+    if !is_sensor_key(&state, &key) {
+        return sensor_not_found();
+    }
+    let since = match params.get("since").map(|s| chrono::DateTime::parse_from_rfc3339(s)) {
+        None => None,
+        Some(Ok(since)) => Some(since.with_timezone(&Utc)),
+        Some(Err(_)) => {
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "status": "error",
+                    "error": "since must be an RFC3339 timestamp"
+                })),
+            ).into_response()
+        }
+    };
+    let limit = params.get("limit")
+        .and_then(|l| l.parse::<usize>().ok())
+        .unwrap_or(usize::MAX);
+
+    touch_history(&state, &key);
+    let history = state.history.lock().unwrap();
+    let samples: Vec<_> = history
+        .get(&key)
+        .into_iter()
+        .flat_map(|buffer| buffer.iter().rev())
+        .take_while(|(timestamp, _)| {
+            since.is_none_or(|since| chrono::DateTime::parse_from_rfc3339(timestamp).is_ok_and(|t| t > since))
+        })
+        .take(limit)
+        .map(|(timestamp, data)| serde_json::json!({ "timestamp": timestamp, "data": data }))
+        .collect();
+
+    Json(serde_json::json!({
+        "status": "ok",
+        "sensor": key,
+        "historyEnabled": state.retention.history_enabled(&key),
+        "count": samples.len(),
+        "samples": samples
+    })).into_response()
+}
+
+/// Window length such as `90s`, `5m`, `2h` or `1d` (bare numbers are seconds)
+fn parse_window(window: &str) -> Result<chrono::Duration, String> {
+    let window = window.trim();
+    let split = window.find(|c: char| !c.is_ascii_digit()).unwrap_or(window.len());
+    let (amount, unit) = window.split_at(split);
+    let amount: i64 = amount.parse().map_err(|_| format!("'{}' is not a window like 30s, 5m or 1h", window))?;
+    let seconds = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return Err(format!("'{}' has an unknown unit (expected s, m, h or d)", window)),
+    };
+    if amount == 0 {
+        return Err("window must be longer than zero".to_string());
+    }
+    amount
+        .checked_mul(seconds)
+        .and_then(chrono::TimeDelta::try_seconds)
+        .ok_or_else(|| format!("'{}' is too long a window", window))
+}
+
+/// Summary statistics of one numeric field over the trailing `?window=`
+/// (default 5m) of history. `?field=` is a dotted path into the `value`
+/// object (`aqi`, `alarms.co`, `averages.pm25.1h`); it defaults to the
+/// sensor's primary variable.
+async fn get_sensor_aggregate(
+    Path(key): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<SharedState>,
+) -> Response {
+    if !is_sensor_key(&state, &key) {
+        return sensor_not_found();
+    }
+    let error = |status: axum::http::StatusCode, error: String| (
+        status,
+        Json(serde_json::json!({
+            "status": "error",
+            "error": error
+        })),
+    ).into_response();
+
+    let window_param = params.get("window").map(String::as_str).unwrap_or("5m");
+    let window = match parse_window(window_param) {
+        Ok(window) => window,
+        Err(e) => return error(axum::http::StatusCode::BAD_REQUEST, e),
+    };
+    // A digital input's state can't be averaged; its numeric fields
+    // (`transitionCount`) can, when asked for by name
+    let field = match params.get("field") {
+        Some(field) => field.clone(),
+        None if DIGITAL_SENSORS.contains(&key.as_str()) => return primary_not_numeric(&key),
+        None => primary_variable_field(&state, &key).unwrap_or("value").to_string(),
+    };
+    let pointer = format!("/{}", field.replace('.', "/"));
+
+    let Some(cutoff) = state.clock.now().checked_sub_signed(window) else {
+        return error(axum::http::StatusCode::BAD_REQUEST, format!("'{}' is too long a window", window_param));
+    };
+    touch_history(&state, &key);
+    let (values, latest) = {
+        let history = state.history.lock().unwrap();
+        let samples: Vec<_> = history
+            .get(&key)
+            .into_iter()
+            .flat_map(|buffer| buffer.iter().rev())
+            .take_while(|(timestamp, _)| !is_expired(timestamp, cutoff))
+            .collect();
+        let values: Vec<f64> = samples.iter().filter_map(|(_, data)| data["value"].pointer(&pointer)?.as_f64()).collect();
+        let latest = history.get(&key).and_then(|buffer| buffer.back()).map(|(_, data)| data.clone());
+        (values, latest)
+    };
+    // The field is judged on the newest sample, or the newest reading served
+    // before any history exists. Nothing is simulated: with neither, the
+    // window is simply empty.
+    let sample = latest.or_else(|| state.unsampled.lock().unwrap().get(&key).cloned());
+    if sample.is_some_and(|sample| !sample["value"].pointer(&pointer).is_some_and(serde_json::Value::is_number)) {
+        return error(
+            axum::http::StatusCode::UNPROCESSABLE_ENTITY,
+            format!("{} has no numeric value field '{}'", key, field),
+        );
+    }
+
+    let count = values.len();
+    let round = |v: f64| format!("{:.4}", v).parse::<f64>().unwrap();
+    let stats = (count > 0).then(|| {
+        let mean = values.iter().sum::<f64>() / count as f64;
+        let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / count as f64;
+        let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        (round(min), round(max), round(mean), round(variance.sqrt()))
+    });
+
+    Json(serde_json::json!({
+        "status": "ok",
+        "sensor": key,
+        "field": field,
+        "window": window_param,
+        "windowSeconds": window.num_seconds(),
+        "count": count,
+        "min": stats.map(|s| s.0),
+        "max": stats.map(|s| s.1),
+        "mean": stats.map(|s| s.2),
+        "stddev": stats.map(|s| s.3),
+        "historyEnabled": state.retention.history_enabled(&key)
+    })).into_response()
+}
+
+async fn upload_golden_batch(
+    Path(key): Path<String>,
+    State(state): State<SharedState>,
+    Json(req): Json<GoldenBatchRequest>,
+) -> Response {
+    if !is_sensor_key(&state, &key) {
+        return sensor_not_found();
+    }
+    let bad_request = |error: String| (
+        axum::http::StatusCode::BAD_REQUEST,
+        Json(serde_json::json!({
+            "status": "error",
+            "error": error
+        })),
+    ).into_response();
+
+    // Checked against what's already known rather than a fresh simulation,
+    // which would advance the sensor: the primary variable always exists,
+    // other fields must be numeric in the last good reading
+    let known = primary_variable_field(&state, &key) == Some(req.field.as_str())
+        || state.last_good.lock().unwrap().get(&key).is_some_and(|(_, data)| data["value"][&req.field].is_number());
+    if !known {
+        return bad_request(format!("{} has no numeric value field '{}'", key, req.field));
+    }
+    let batch = match GoldenBatch::from_request(req) {
+        Ok(batch) => batch,
+        Err(e) => return bad_request(e.to_string()),
+    };
+
+    let body = serde_json::json!({
+        "status": "ok",
+        "sensor": key,
+        "field": batch.field,
+        "points": batch.points.len(),
+        "durationSeconds": batch.duration(),
+        "band": batch.band
+    });
+    state.golden_batches.lock().unwrap().insert(key, batch);
+    Json(body).into_response()
+}
+
+async fn clear_golden_batch(
+    Path(key): Path<String>,
+    State(state): State<SharedState>,
+) -> Response {
+    if !is_sensor_key(&state, &key) {
+        return sensor_not_found();
+    }
+    let removed = state.golden_batches.lock().unwrap().remove(&key).is_some();
+    Json(serde_json::json!({
+        "status": "ok",
+        "sensor": key,
+        "removed": removed
+    })).into_response()
+}
+
+async fn get_diagnostics(
+    Path(key): Path<String>,
+    State(state): State<SharedState>,
+) -> Response {
+    let fault = state.wiring_faults.lock().unwrap().get(&key).copied();
+    let Some(range) = sensor_range(&state, &key) else {
+        return sensor_not_found();
+    };
+    let rng = state.rngs.sensor(&key);
+    let diagnostics = generate_diagnostics(&key, range, fault, &mut *rng.lock().unwrap());
+    match diagnostics {
+        Some(diagnostics) => Json(serde_json::json!({
+            "status": "ok",
+            "sensor": key,
+            "timestamp": state.clock.now().to_rfc3339(),
+            "diagnostics": diagnostics
+        })).into_response(),
+        None => sensor_not_found(),
+    }
+}
+
+async fn inject_wiring_fault(
+    Path(key): Path<String>,
+    State(state): State<SharedState>,
+    Json(req): Json<WiringFaultRequest>,
+) -> Response {
+    if !is_sensor_key(&state, &key) {
+        return sensor_not_found();
+    }
+    state.wiring_faults.lock().unwrap().insert(key.clone(), req.wiring);
+    Json(serde_json::json!({
+        "status": "ok",
+        "sensor": key,
+        "wiringFault": req.wiring
+    })).into_response()
+}
+
+async fn clear_wiring_fault(
+    Path(key): Path<String>,
+    State(state): State<SharedState>,
+) -> Response {
+    if !is_sensor_key(&state, &key) {
+        return sensor_not_found();
+    }
+    let cleared = state.wiring_faults.lock().unwrap().remove(&key);
+    Json(serde_json::json!({
+        "status": "ok",
+        "sensor": key,
+        "cleared": cleared
+    })).into_response()
+}
+
+async fn get_reporting_mode(
+    Path(key): Path<String>,
+    State(state): State<SharedState>,
+) -> Response {
+    if !is_sensor_key(&state, &key) {
+        return sensor_not_found();
+    }
+    let config = state.reporting.lock().unwrap().get(&key).copied().unwrap_or_default();
+    Json(serde_json::json!({
+        "status": "ok",
+        "sensor": key,
+        "reporting": config
+    })).into_response()
+}
+
+async fn set_reporting_mode(
+    Path(key): Path<String>,
+    State(state): State<SharedState>,
+    Json(config): Json<ReportingConfig>,
+) -> Response {
+    if !is_sensor_key(&state, &key) {
+        return sensor_not_found();
+    }
+    if !config.deadband.is_finite() || config.deadband < 0.0 {
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "status": "error",
+                "error": "deadband must be a non-negative number"
+            })),
+        ).into_response();
+    }
+    // Exception reporting still applies, on every change of state
+    if config.deadband > 0.0 && DIGITAL_SENSORS.contains(&key.as_str()) {
+        return primary_not_numeric(&key);
+    }
+    state.reporting.lock().unwrap().insert(key.clone(), config);
+    Json(serde_json::json!({
+        "status": "ok",
+        "sensor": key,
+        "reporting": config
+    })).into_response()
+}
+
+async fn rerange_sensor(
+    Path(key): Path<String>,
+    State(state): State<SharedState>,
+    Json(req): Json<RerangeRequest>,
+) -> Response {
+    let Some(previous) = sensor_range(&state, &key) else {
+        return sensor_not_found();
+    };
+    if DIGITAL_SENSORS.contains(&key.as_str()) {
+        return primary_not_numeric(&key);
+    }
+    if !req.lrv.is_finite() || !req.urv.is_finite() || req.urv <= req.lrv {
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "status": "error",
+                "error": "lrv and urv must be finite with urv > lrv"
+            })),
+        ).into_response();
+    }
+    state.ranges.lock().unwrap().insert(key.clone(), (req.lrv, req.urv));
+
+    Json(serde_json::json!({
+        "status": "ok",
+        "sensor": key,
+        "field": primary_variable_field(&state, &key),
+        "previous": { "lrv": previous.0, "urv": previous.1 },
+        "range": { "lrv": req.lrv, "urv": req.urv, "span": req.urv - req.lrv }
+    })).into_response()
+}
+
+/// Restore the factory range
+async fn reset_sensor_range(
+    Path(key): Path<String>,
+    State(state): State<SharedState>,
+) -> Response {
+    if !is_sensor_key(&state, &key) {
+        return sensor_not_found();
+    }
+    state.ranges.lock().unwrap().remove(&key);
+    let (lrv, urv) = sensor_range(&state, &key).unwrap_or_default();
+    Json(serde_json::json!({
+        "status": "ok",
+        "sensor": key,
+        "range": { "lrv": lrv, "urv": urv, "span": urv - lrv }
+    })).into_response()
+}
+
+/// Sparkplug sequence numbers wrap at 256 across all messages from the edge node
+fn next_sparkplug_seq(state: &AppState) -> u64 {
+    let mut seq = state.sparkplug_seq.lock().unwrap();
+    let current = *seq;
+    *seq = seq.wrapping_add(1);
+    current as u64
+}
+
+/// Protobuf if asked for via `?format=protobuf` or an `Accept` header
+fn wants_protobuf(params: &HashMap<String, String>, headers: &axum::http::HeaderMap) -> bool {
+    params.get("format").is_some_and(|f| f == "protobuf")
+        || headers
+            .get(axum::http::header::ACCEPT)
+            .and_then(|h| h.to_str().ok())
+            .is_some_and(|accept| {
+                ["application/x-protobuf", "application/protobuf", "application/octet-stream"]
+                    .iter()
+                    .any(|t| accept.contains(t))
+            })
+}
+
+/// Sparkplug B DBIRTH for one device: the complete metric definition (name,
+/// alias, datatype, initial value) a consumer needs before processing DDATA
+async fn get_sensor_dbirth(
+    Path(key): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    headers: axum::http::HeaderMap,
+    State(state): State<SharedState>,
+) -> Response {
+    let Some(data) = generate_sensor_data(&state, &key) else {
+        return sensor_not_found();
+    };
+    let metrics = sparkplug::metric_set(&data);
+    let timestamp = state.clock.now().timestamp_millis() as u64;
+    let seq = next_sparkplug_seq(&state);
+    let topic = sparkplug_topic(&data, "DBIRTH");
+
+    if wants_protobuf(&params, &headers) {
+        return (
+            [
+                (axum::http::header::CONTENT_TYPE, "application/x-protobuf".to_string()),
+                (axum::http::HeaderName::from_static("x-sparkplug-topic"), topic),
+            ],
+            sparkplug::encode_birth(&metrics, timestamp, seq),
+        ).into_response();
+    }
+
+    Json(serde_json::json!({
+        "status": "ok",
+        "sensor": key,
+        "topic": topic,
+        "payload": {
+            "timestamp": timestamp,
+            "seq": seq,
+            "metrics": metrics
+        }
+    })).into_response()
+}
+
+async fn get_avro_schema(
+    Path(key): Path<String>,
+    State(state): State<SharedState>,
+) -> Response {
+    let cached = state.avro_schemas.lock().unwrap().get(&key).cloned();
+    let schema = match cached {
+        Some(schema) => schema,
+        None => match generate_sensor_data(&state, &key) {
+            Some(data) => avro_schema(&state, &key, &data),
+            None => return sensor_not_found(),
+        },
+    };
+    ([(axum::http::header::CONTENT_TYPE, "application/json")], schema.to_string()).into_response()
+}
+
+async fn get_transport_delay(
+    Path(key): Path<String>,
+    State(state): State<SharedState>,
+) -> Response {
+    if !is_sensor_key(&state, &key) {
+        return sensor_not_found();
+    }
+    let delay_ms = state.transport_delays.lock().unwrap().get(&key).map_or(0, |d| d.delay.as_millis() as u64);
+    Json(serde_json::json!({
+        "status": "ok",
+        "sensor": key,
+        "transportDelayMs": delay_ms
+    })).into_response()
+}
+
+/// Set a sensor's dead-time; 0 removes it
+async fn set_transport_delay(
+    Path(key): Path<String>,
+    State(state): State<SharedState>,
+    Json(req): Json<TransportDelayRequest>,
+) -> Response {
+    if !is_sensor_key(&state, &key) {
+        return sensor_not_found();
+    }
+    let delay_ms = req.transport_delay_ms.min(600_000);
+    let mut delays = state.transport_delays.lock().unwrap();
+    if delay_ms == 0 {
+        delays.remove(&key);
+    } else {
+        delays
+            .entry(key.clone())
+            .and_modify(|d| d.delay = Duration::from_millis(delay_ms))
+            .or_insert_with(|| TransportDelay::new(Duration::from_millis(delay_ms)));
+    }
+    Json(serde_json::json!({
+        "status": "ok",
+        "sensor": key,
+        "transportDelayMs": delay_ms
+    })).into_response()
+}
+
+/// Readings on a wall-clock grid: the last `count` multiples of `boundary` ms
+/// (oldest first), each stamped exactly on its grid instant. Values come from
+/// the history buffer where it reaches back that far; older grid points are
+/// backfilled along a walk trajectory that leads into the current reading.
+async fn get_aligned_readings(
+    Path(key): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<SharedState>,
+) -> Response {
+    if !is_sensor_key(&state, &key) {
+        return sensor_not_found();
+    }
+    let boundary_ms = params.get("boundary")
+        .and_then(|b| b.parse::<i64>().ok())
+        .unwrap_or(60_000)
+        .clamp(1000, 86_400_000);
+    let count = params.get("count")
+        .and_then(|c| c.parse::<i64>().ok())
+        .unwrap_or(10)
+        .clamp(1, 1000);
+
+    let now_ms = state.clock.now().timestamp_millis();
+    let last_boundary = now_ms - now_ms.rem_euclid(boundary_ms);
+    let grid: Vec<_> = (0..count).rev().map(|i| last_boundary - i * boundary_ms).collect();
+
+    // Latest history sample at or before each grid instant
+    let recorded: Vec<Option<serde_json::Value>> = {
+        let history = state.history.lock().unwrap();
+        let buffer = history.get(&key);
+        grid.iter()
+            .map(|&t| {
+                buffer?.iter().rev().find_map(|(ts, data)| {
+                    let ts = chrono::DateTime::parse_from_rfc3339(ts).ok()?;
+                    (ts.timestamp_millis() <= t).then(|| data.clone())
+                })
+            })
+            .collect()
+    };
+
+    let missing = recorded.iter().filter(|r| r.is_none()).count();
+    let template = if missing > 0 { generate_sensor_data(&state, &key) } else { None };
+    let mut offsets = backfill_offsets(&state, base_sensor_key(&state, &key), missing);
+    let field = primary_variable_field(&state, &key).unwrap_or("value");
+
+    let readings: Vec<_> = grid.iter()
+        .zip(recorded)
+        .filter_map(|(&t, recorded)| {
+            let mut data = match recorded {
+                Some(data) => data,
+                None => {
+                    let mut data = template.clone()?;
+                    let offset = offsets.pop().unwrap_or_default();
+                    if let Some(pv) = data["value"][field].as_f64() {
+                        data["value"][field] = serde_json::json!(format!("{:.3}", pv + offset).parse::<f64>().unwrap());
+                    }
+                    data
+                }
+            };
+            let timestamp = chrono::DateTime::from_timestamp_millis(t)?
+                .to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+            data["sourceTimestamp"] = serde_json::json!(timestamp);
+            data["serverTimestamp"] = serde_json::json!(timestamp);
+            Some(serde_json::json!({
+                "timestamp": timestamp,
+                "epochMs": t,
+                "data": data
+            }))
+        })
+        .collect();
+
+    Json(serde_json::json!({
+        "status": "ok",
+        "sensor": key,
+        "boundaryMs": boundary_ms,
+        "count": readings.len(),
+        "readings": readings
+    })).into_response()
+}
+
+/// Offsets from the current primary variable for `n` backfilled points, from
+/// a clone of the sensor's walk stepped `n` times, so consecutive points move
+/// like the live walk does. The k-th offset is k steps from the current value;
+/// `pop` hands out the farthest first, for the oldest grid point. The live
+/// walk itself is left untouched. Sensors without a walk hold their value.
+fn backfill_offsets(state: &AppState, key: &str, n: usize) -> Vec<f64> {
+    let Some(mut walk) = state.sensor_states.lock().unwrap().get(key).copied() else {
+        return vec![0.0; n];
+    };
+    let start = walk.value;
+    let rng = state.rngs.sensor(key);
+    let mut rng = rng.lock().unwrap();
+    (0..n).map(|_| walk.step(&mut *rng) - start).collect()
+}
+
+async fn reset_peak(
+    Path(key): Path<String>,
+    State(state): State<SharedState>,
+) -> Response {
+    if !is_sensor_key(&state, &key) {
+        return sensor_not_found();
+    }
+    // The next reading starts a fresh hold
+    let previous = state.peaks.lock().unwrap().remove(&key);
+    Json(serde_json::json!({
+        "status": "ok",
+        "sensor": key,
+        "previous": previous.map(|p| serde_json::json!({
+            "peakMax": p.max,
+            "peakMin": p.min,
+            "peakSince": p.since.to_rfc3339()
+        }))
+    })).into_response()
+}
+
+/// Shorthand for an offline `/fault`
+async fn set_comm_fault(
+    Path(key): Path<String>,
+    State(state): State<SharedState>,
+) -> Response {
+    if !is_sensor_key(&state, &key) {
+        return sensor_not_found();
+    }
+    let fault = ActiveFault::new(SensorFault::Offline, state.clock.now());
+    state.sensor_faults.lock().unwrap().insert(key.clone(), fault);
+    Json(serde_json::json!({
+        "status": "ok",
+        "sensor": key,
+        "commFault": true,
+        "preferStale": state.prefer_stale
+    })).into_response()
+}
+
+async fn clear_comm_fault(
+    Path(key): Path<String>,
+    State(state): State<SharedState>,
+) -> Response {
+    if !is_sensor_key(&state, &key) {
+        return sensor_not_found();
+    }
+    // Stuck and drift faults aren't communication faults; leave them be
+    let cleared = {
+        let mut faults = state.sensor_faults.lock().unwrap();
+        let offline = matches!(faults.get(&key), Some(ActiveFault { fault: SensorFault::Offline, .. }));
+        offline && faults.remove(&key).is_some()
+    };
+    Json(serde_json::json!({
+        "status": "ok",
+        "sensor": key,
+        "commFault": false,
+        "cleared": cleared
+    })).into_response()
+}
+
+async fn inject_value(
+    Path(key): Path<String>,
+    State(state): State<SharedState>,
+    Json(req): Json<InjectRequest>,
+) -> Response {
+    if !is_sensor_key(&state, &key) {
+        return sensor_not_found();
+    }
+    if DIGITAL_SENSORS.contains(&key.as_str()) {
+        return primary_not_numeric(&key);
+    }
+    if req.duration_ms == 0 || !req.value.is_finite() {
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "status": "error",
+                "error": "value must be finite and durationMs positive"
+            })),
+        ).into_response();
+    }
+    let now = state.clock.now();
+    // Like shelves, injections are test aids and never permanent: cap at a day
+    let expires = now + chrono::Duration::milliseconds(req.duration_ms.min(86_400_000) as i64);
+    let injection = Injection {
+        value: req.value,
+        injected_at: now.to_rfc3339(),
+        until: expires.to_rfc3339(),
+        expires,
+    };
+    state.injections.lock().unwrap().insert(key.clone(), injection.clone());
+    Json(serde_json::json!({
+        "status": "ok",
+        "sensor": key,
+        "field": primary_variable_field(&state, &key),
+        "injection": injection
+    })).into_response()
+}
+
+async fn clear_injection(
+    Path(key): Path<String>,
+    State(state): State<SharedState>,
+) -> Response {
+    if !is_sensor_key(&state, &key) {
+        return sensor_not_found();
+    }
+    let cleared = state.injections.lock().unwrap().remove(&key);
+    Json(serde_json::json!({
+        "status": "ok",
+        "sensor": key,
+        "cleared": cleared.is_some()
+    })).into_response()
+}
+
+async fn set_waveform(
+    Path(key): Path<String>,
+    State(state): State<SharedState>,
+    Json(waveform): Json<Waveform>,
+) -> Response {
+    if !is_sensor_key(&state, &key) {
+        return sensor_not_found();
+    }
+    if DIGITAL_SENSORS.contains(&key.as_str()) {
+        return primary_not_numeric(&key);
+    }
+    if waveform.period_ms == 0 || !waveform.amplitude.is_finite() || !waveform.offset.is_finite() {
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "status": "error",
+                "error": "amplitude and offset must be finite and periodMs positive"
+            })),
+        ).into_response();
+    }
+    state.waveforms.lock().unwrap().insert(key.clone(), waveform.clone());
+    Json(serde_json::json!({
+        "status": "ok",
+        "sensor": key,
+        "field": primary_variable_field(&state, &key),
+        "waveform": waveform
+    })).into_response()
+}
+
+async fn clear_waveform(
+    Path(key): Path<String>,
+    State(state): State<SharedState>,
+) -> Response {
+    if !is_sensor_key(&state, &key) {
+        return sensor_not_found();
+    }
+    let cleared = state.waveforms.lock().unwrap().remove(&key);
+    Json(serde_json::json!({
+        "status": "ok",
+        "sensor": key,
+        "cleared": cleared.is_some()
+    })).into_response()
+}
+
+async fn set_sensor_fault(
+    Path(key): Path<String>,
+    State(state): State<SharedState>,
+    Json(mut fault): Json<SensorFault>,
+) -> Response {
+    if !is_sensor_key(&state, &key) {
+        return sensor_not_found();
+    }
+    if let SensorFault::Drift { rate: rate @ None, .. } = &mut fault {
+        let Some((lrv, urv)) = sensor_range(&state, &key) else {
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "status": "error",
+                    "error": format!("'{}' has no calibrated range to default the drift rate from; give a rate", key)
+                })),
+            ).into_response();
+        };
+        *rate = Some((urv - lrv) * 0.001);
+    }
+    let active = ActiveFault::new(fault, state.clock.now());
+    state.sensor_faults.lock().unwrap().insert(key.clone(), active.clone());
+    Json(serde_json::json!({
+        "status": "ok",
+        "sensor": key,
+        "fault": active
+    })).into_response()
+}
+
+async fn clear_sensor_fault(
+    Path(key): Path<String>,
+    State(state): State<SharedState>,
+) -> Response {
+    if !is_sensor_key(&state, &key) {
+        return sensor_not_found();
+    }
+    let cleared = state.sensor_faults.lock().unwrap().remove(&key);
+    Json(serde_json::json!({
+        "status": "ok",
+        "sensor": key,
+        "cleared": cleared.is_some()
+    })).into_response()
+}
+
+async fn get_calibration_certificate(Path(key): Path<String>) -> Response {
+    match generate_calibration_certificate(&key) {
+        Some(certificate) => Json(serde_json::json!({
+            "status": "ok",
+            "certificate": certificate
+        })).into_response(),
+        None => sensor_not_found(),
+    }
+}
+
+async fn replace_sensor(
+    Path(key): Path<String>,
+    State(state): State<SharedState>,
+    Json(req): Json<ReplaceRequest>,
+) -> Response {
+    if !is_sensor_key(&state, &key) {
+        return sensor_not_found();
+    }
+    if req.new_serial.trim().is_empty() {
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "status": "error",
+                "error": "newSerial is required"
+            })),
+        ).into_response();
+    }
+
+    let record = {
+        let mut devices = state.devices.lock().unwrap();
+        let device = devices.entry(key.clone()).or_insert_with(|| DeviceInfo::new(&key, sensor_device_id(&state, &key).unwrap_or(&key), state.clock.now()));
+        let new_firmware = req.firmware.unwrap_or_else(|| LATEST_FIRMWARE.to_string());
+        let record = ReplacementRecord {
+            sensor: key.clone(),
+            timestamp: state.clock.now().to_rfc3339(),
+            old_serial: std::mem::replace(&mut device.serial, req.new_serial),
+            new_serial: device.serial.clone(),
+            old_firmware: std::mem::replace(&mut device.firmware, new_firmware),
+            new_firmware: device.firmware.clone(),
+            old_age_seconds: format!("{:.1}", device.age_seconds()).parse::<f64>().unwrap(),
+            totalizers_reset: req.reset_totalizers,
+            reason: req.reason,
+        };
+        device.installed_at = std::time::Instant::now();
+        device.installed_at_ts = record.timestamp.clone();
+        device.booted_at = std::time::Instant::now();
+        if req.reset_totalizers {
+            device.totalizer = Some(0.0);
+        }
+        device.last_totalized = std::time::Instant::now();
+        record
+    };
+
+    // A new physical device starts from its own position and reading, not the old one's
+    {
+        let rng = state.rngs.sensor(&key);
+        let mut rng = rng.lock().unwrap();
+        if key == "gps-tracker" {
+            *state.gps_tracker.lock().unwrap() = GpsTrackerState::new(&mut *rng);
+        }
+        let mut walks = state.sensor_states.lock().unwrap();
+        let vane = (key == "wind").then_some("wind-direction");
+        for walked in std::iter::once(key.as_str()).chain(vane) {
+            if let Some(walk) = walks.get_mut(walked) {
+                walk.reseed(&mut *rng);
+            }
+        }
+    }
+
+    state.replacements.lock().unwrap().push(record.clone());
+
+    Json(serde_json::json!({
+        "status": "ok",
+        "replacement": record
+    })).into_response()
+}
+
+async fn reboot_sensor(
+    Path(key): Path<String>,
+    State(state): State<SharedState>,
+) -> Response {
+    if !is_sensor_key(&state, &key) {
+        return sensor_not_found();
+    }
+
+    let mut devices = state.devices.lock().unwrap();
+    let device = devices.entry(key.clone()).or_insert_with(|| DeviceInfo::new(&key, sensor_device_id(&state, &key).unwrap_or(&key), state.clock.now()));
+    let previous_uptime = device.uptime_seconds();
+    device.booted_at = std::time::Instant::now();
+
+    Json(serde_json::json!({
+        "status": "ok",
+        "sensor": key,
+        "previousUptimeSeconds": format!("{:.1}", previous_uptime).parse::<f64>().unwrap(),
+        "rebootedAt": state.clock.now().to_rfc3339(),
+        "stabilizationWindowSecs": state.boot_window_secs
+    })).into_response()
+}
+
+async fn get_replacement_history(
+    Path(key): Path<String>,
+    State(state): State<SharedState>,
+) -> Response {
+    if !is_sensor_key(&state, &key) {
+        return sensor_not_found();
+    }
+
+    let history: Vec<_> = state.replacements.lock().unwrap()
+        .iter()
+        .filter(|r| r.sensor == key)
+        .cloned()
+        .collect();
+    let current = state.devices.lock().unwrap().get(&key).map(|d| d.properties());
+
+    Json(serde_json::json!({
+        "status": "ok",
+        "sensor": key,
+        "currentDevice": current,
+        "history": history
+    })).into_response()
+}
+
+async fn get_sync_status(
+    Path(key): Path<String>,
+    State(state): State<SharedState>,
+) -> Response {
+    if !is_sensor_key(&state, &key) {
+        return sensor_not_found();
+    }
+
+    let mut clocks = state.clock_sync.lock().unwrap();
+    let clock = clocks.entry(key.clone()).or_insert_with(|| ClockSyncState::new(&key, state.clock.now()));
+    Json(serde_json::json!({
+        "status": "ok",
+        "sensor": key,
+        "sync": clock.status()
+    })).into_response()
+}
+
+async fn sync_sensor_clock(
+    Path(key): Path<String>,
+    State(state): State<SharedState>,
+) -> Response {
+    if !is_sensor_key(&state, &key) {
+        return sensor_not_found();
+    }
+
+    let mut clocks = state.clock_sync.lock().unwrap();
+    let clock = clocks.entry(key.clone()).or_insert_with(|| ClockSyncState::new(&key, state.clock.now()));
+    let accuracy_before = clock.accuracy_us();
+    clock.resync(state.clock.now());
+    Json(serde_json::json!({
+        "status": "ok",
+        "sensor": key,
+        "previousAccuracy": format!("{:.3}", accuracy_before).parse::<f64>().unwrap(),
+        "sync": clock.status()
+    })).into_response()
+}
+
+async fn post_occupancy_event(
+    State(state): State<SharedState>,
+    Json(req): Json<OccupancyEventRequest>,
+) -> Response {
+    let requested = req.count.unwrap_or(1);
+    let mut occupancy = state.occupancy.lock().unwrap();
+    let applied = match req.direction {
+        OccupancyDirection::In => occupancy.enter(requested),
+        OccupancyDirection::Out => occupancy.exit(requested),
+    };
+
+    Json(serde_json::json!({
+        "status": "ok",
+        "requested": requested,
+        "applied": applied,
+        "rejected": requested - applied,
+        "count": occupancy.count,
+        "capacity": occupancy.capacity,
+        "enterCount": occupancy.enter_count,
+        "exitCount": occupancy.exit_count
+    })).into_response()
+}
+
+async fn set_encoder_direction(
+    State(state): State<SharedState>,
+    Json(req): Json<EncoderDirectionRequest>,
+) -> Response {
+    // Same lock order as simulate_sensor: rng, then encoder
+    let rng = state.rngs.sensor("encoder");
+    let mut rng = rng.lock().unwrap();
+    let mut encoder = state.encoder.lock().unwrap();
+    // Settle any motion up to now in the old direction before reversing
+    encoder.advance(&mut *rng);
+    encoder.direction = req.direction;
+
+    Json(serde_json::json!({
+        "status": "ok",
+        "direction": encoder.direction,
+        "counts": encoder.counts
+    })).into_response()
+}
+
+async fn command_control_valve(
+    State(state): State<SharedState>,
+    Json(req): Json<ValveCommandRequest>,
+) -> Response {
+    if !req.position.is_finite() {
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "status": "error",
+                "error": "position must be a number between 0 and 100"
+            })),
+        ).into_response();
+    }
+    let mut valve = state.control_valve.lock().unwrap();
+    valve.advance();
+    valve.commanded = req.position.clamp(0.0, 100.0);
+
+    Json(serde_json::json!({
+        "status": "ok",
+        "commandedPosition": valve.commanded,
+        "position": format!("{:.1}", valve.position).parse::<f64>().unwrap()
+    })).into_response()
+}
+
+async fn apply_strain_load(
+    State(state): State<SharedState>,
+    Json(req): Json<StrainLoadRequest>,
+) -> Response {
+    let (transition, microstrain, threshold, cycles, overloaded) = {
+        let mut gauge = state.strain_gauge.lock().unwrap();
+        let transition = gauge.apply(req.microstrain);
+        (transition, gauge.microstrain, gauge.overload_microstrain, gauge.fatigue_cycles, gauge.overloaded)
+    };
+    if let Some(tripped) = transition {
+        raise_strain_overload_alarm(&state, tripped, microstrain, threshold);
+    }
+
+    Json(serde_json::json!({
+        "status": "ok",
+        "microstrain": microstrain,
+        "fatigueCycles": cycles,
+        "overloadAlarm": overloaded
+    })).into_response()
+}
+
+/// Reset the smoke detector's latched alarm, as from the fire panel. If the
+/// smoke or heat is still there the next reading trips it again.
+async fn reset_smoke_detector(State(state): State<SharedState>) -> Response {
+    let (was_latched, obscuration, heat_rise_rate, cause) = {
+        let mut detector = state.smoke_detector.lock().unwrap();
+        let was_latched = detector.alarm;
+        let cause = detector.alarm_cause.take();
+        detector.alarm = false;
+        detector.latched_at = None;
+        (was_latched, detector.obscuration, detector.heat_rise_rate, cause)
+    };
+    if was_latched {
+        raise_smoke_alarm(&state, false, cause.unwrap_or_default(), obscuration, heat_rise_rate);
+    }
+
+    Json(serde_json::json!({
+        "status": "ok",
+        "wasLatched": was_latched,
+        "alarm": false
+    })).into_response()
+}
+
+/// Force the contact open or closed and hold it there until released
+async fn force_contact(
+    State(state): State<SharedState>,
+    Json(req): Json<ContactForceRequest>,
+) -> Response {
+    let mut contact = state.contact.lock().unwrap();
+    contact.set(req.closed, state.clock.now());
+    contact.forced = true;
+
+    Json(serde_json::json!({
+        "status": "ok",
+        "closed": contact.closed,
+        "transitionCount": contact.transitions,
+        "forced": true
+    })).into_response()
+}
+
+/// Release a forced contact; it resumes changing on its own schedule
+async fn release_contact(State(state): State<SharedState>) -> Response {
+    let dwell = ContactState::dwell(&mut *state.rngs.sensor("contact").lock().unwrap());
+    let mut contact = state.contact.lock().unwrap();
+    let was_forced = contact.forced;
+    contact.forced = false;
+    contact.next_change = std::time::Instant::now() + dwell;
+
+    Json(serde_json::json!({
+        "status": "ok",
+        "closed": contact.closed,
+        "released": was_forced
+    })).into_response()
+}
+
+async fn get_geofences(State(state): State<SharedState>) -> Response {
+    let geofences = state.geofences.lock().unwrap().clone();
+    Json(serde_json::json!({
+        "status": "ok",
+        "geofences": geofences
+    })).into_response()
+}
+
+async fn create_geofence(
+    State(state): State<SharedState>,
+    Json(mut geofence): Json<Geofence>,
+) -> Response {
+    if geofence.name.trim().is_empty() {
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "status": "error",
+                "error": "Geofence name is required"
+            })),
+        ).into_response();
+    }
+    if let Err(e) = geofence.zone.validate() {
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "status": "error",
+                "error": e
+            })),
+        ).into_response();
+    }
+
+    // Seed the inside/outside state from the tracker's current position so the
+    // first transition after creation is reported as a real enter/exit
+    let position = state.gps_tracker.lock().unwrap().position;
+    geofence.inside = geofence.zone.contains(position);
+
+    let mut geofences = state.geofences.lock().unwrap();
+    geofences.retain(|g| g.name != geofence.name);
+    geofences.push(geofence.clone());
+
+    (
+        axum::http::StatusCode::CREATED,
+        Json(serde_json::json!({
+            "status": "ok",
+            "geofence": geofence
+        })),
+    ).into_response()
+}
+
+async fn delete_geofence(
+    Path(name): Path<String>,
+    State(state): State<SharedState>,
+) -> Response {
+    let mut geofences = state.geofences.lock().unwrap();
+    let before = geofences.len();
+    geofences.retain(|g| g.name != name);
+    if geofences.len() == before {
+        return (
+            axum::http::StatusCode::NOT_FOUND,
+            Json(serde_json::json!({
+                "status": "error",
+                "error": "Geofence not found"
+            })),
+        ).into_response();
+    }
+
+    Json(serde_json::json!({
+        "status": "ok",
+        "deleted": name
+    })).into_response()
+}
+
+async fn get_alarm_log(
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<SharedState>,
+) -> Response {
+    let limit = params.get("limit")
+        .and_then(|l| l.parse::<usize>().ok())
+        .unwrap_or(50);
+
+    let logs = state.alarm_log.lock().unwrap();
+    let entries: Vec<_> = logs.iter().take(limit).cloned().collect();
+    let total = *state.alarm_counter.lock().unwrap();
+
+    Json(serde_json::json!({
+        "status": "ok",
+        "total": total,
+        "entries": entries
+    })).into_response()
+}
+
+async fn shelve_alarms(
+    State(state): State<SharedState>,
+    Json(req): Json<ShelveRequest>,
+) -> Response {
+    if !is_sensor_key(&state, &req.sensor) {
+        return sensor_not_found();
+    }
+    if req.duration_ms == 0 {
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "status": "error",
+                "error": "durationMs must be positive"
+            })),
+        ).into_response();
+    }
+    let now = state.clock.now();
+    // Shelves are operator conveniences, never permanent: cap at a day
+    let expires = now + chrono::Duration::milliseconds(req.duration_ms.min(86_400_000) as i64);
+    let shelve = ShelvedAlarm {
+        sensor: req.sensor.clone(),
+        shelved_at: now.to_rfc3339(),
+        until: expires.to_rfc3339(),
+        expires,
+        suppressed: 0,
+    };
+    state.shelved_alarms.lock().unwrap().insert(req.sensor, shelve.clone());
+    Json(serde_json::json!({
+        "status": "ok",
+        "shelved": shelve
+    })).into_response()
+}
+
+async fn unshelve_alarms(
+    Path(key): Path<String>,
+    State(state): State<SharedState>,
+) -> Response {
+    if !is_sensor_key(&state, &key) {
+        return sensor_not_found();
+    }
+    let removed = state.shelved_alarms.lock().unwrap().remove(&key);
+    Json(serde_json::json!({
+        "status": "ok",
+        "sensor": key,
+        "unshelved": removed.is_some()
+    })).into_response()
+}
+
+async fn list_shelved_alarms(State(state): State<SharedState>) -> Response {
+    let keys: Vec<String> = state.shelved_alarms.lock().unwrap().keys().cloned().collect();
+    let mut shelved: Vec<_> = keys.iter().filter_map(|key| alarm_shelved(&state, key)).collect();
+    shelved.sort_by_key(|s| s.expires);
+    Json(serde_json::json!({
+        "status": "ok",
+        "count": shelved.len(),
+        "shelved": shelved
+    })).into_response()
+}
+
+/// Data qualities from least to most severe; `good` never alarms
+const ALARM_SEVERITIES: &[&str] = &["good", "goodUncertain", "uncertain", "bad"];
+
+fn alarm_severity_rank(quality: &str) -> Option<usize> {
+    ALARM_SEVERITIES.iter().position(|&q| q == quality)
+}
+
+/// Field, low and high limit a sensor's quality is graded against, if it
+/// has a fixed band
+fn alarm_band(state: &AppState, key: &str) -> Option<(&'static str, f64, f64)> {
+    match sensor_descriptor(key) {
+        Some(desc) => desc.normal.map(|n| (n.metric, n.min, n.max)),
+        None => sensor_config(state, key).map(|c| ("value", c.normal().min, c.normal().max)),
+    }
+}
+
+/// Every sensor whose latest reading isn't good, most severe first: the view
+/// an alarm panel polls. Polling reads nothing itself; it judges the newest
+/// reading any consumer (REST, streams, MQTT, OPC UA, the history sampler)
+/// was served, so a sensor nobody has read yet isn't listed. Severity is the
+/// reading's `dataQuality`, so each sensor is judged by its own quality
+/// rules; a device that doesn't answer shows up through its
+/// `badCommunicationError` reading. `?min_severity=` defaults to `uncertain`;
+/// `goodUncertain` adds the mildest, `bad` keeps only the worst.
+async fn get_active_alarms(
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<SharedState>,
+) -> Response {
+    let min_severity = params.get("min_severity").map_or("uncertain", String::as_str);
+    let min_rank = match alarm_severity_rank(min_severity) {
+        Some(rank) => rank.max(1),
+        None => {
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "status": "error",
+                    "error": "min_severity must be one of goodUncertain, uncertain, bad"
+                })),
+            ).into_response();
+        }
+    };
+
+    let latest = state.latest_readings.lock().unwrap().clone();
+    let mut alarms: Vec<(usize, serde_json::Value)> = Vec::new();
+    for key in sensor_keys(&state) {
+        let Some(data) = latest.get(key) else {
+            continue;
+        };
+        let shelved = alarm_shelved(&state, key).is_some();
+        let quality = data["dataQuality"].as_str().unwrap_or_default();
+        let Some(rank) = alarm_severity_rank(quality).filter(|&rank| rank >= min_rank) else {
+            continue;
+        };
+
+        let band = alarm_band(&state, key);
+        let field = band.map(|(metric, _, _)| metric).or_else(|| primary_variable_field(&state, key));
+        let value = field.map(|f| data["value"][f].clone()).unwrap_or(serde_json::Value::Null);
+        let message = match (band, value.as_f64()) {
+            (Some((metric, min, max)), Some(v)) if v < min || v > max => {
+                format!("{} {} {} outside normal {}..{} ({})", key, metric, v, min, max, quality)
+            }
+            _ => format!("{} reading is {} ({})", key, quality, data["opcUaStatusName"].as_str().unwrap_or_default()),
+        };
+        let mut alarm = serde_json::json!({
+            "sensor": key,
+            "severity": quality,
+            "message": message,
+            "field": field,
+            "value": value,
+            "opcUaStatusName": data["opcUaStatusName"],
+            "timestamp": data["sourceTimestamp"],
+            "shelved": shelved
+        });
+        if let Some((_, min, max)) = band {
+            alarm["limits"] = serde_json::json!({ "min": min, "max": max });
+        }
+        alarms.push((rank, alarm));
+    }
+    alarms.sort_by_key(|(rank, _)| std::cmp::Reverse(*rank));
+    let alarms: Vec<_> = alarms.into_iter().map(|(_, alarm)| alarm).collect();
+
+    Json(serde_json::json!({
+        "status": "ok",
+        "count": alarms.len(),
+        "alarms": alarms
+    })).into_response()
+}
+
+/// Simulated network discovery scan. Devices whose probe latency exceeds
+/// `?timeout=` (ms) aren't reported, and the scan takes as long as its
+/// slowest reply.
+async fn discover_devices(
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<SharedState>,
+) -> Response {
+    let timeout_ms = params.get("timeout")
+        .and_then(|t| t.parse::<u64>().ok())
+        .unwrap_or(2000)
+        .clamp(50, 30000);
+
+    let configured = sensor_keys(&state)
+        .filter_map(|key| Some((sensor_device_id(&state, key)?, Some(key), "Simmurator", key)));
+    let unconfigured = UNCONFIGURED_DEVICES.iter().map(|&(id, vendor, model)| (id, None, vendor, model));
+
+    let mut devices = Vec::new();
+    let mut not_responding = 0;
+    let mut scan_ms = 0;
+    for (device_id, sensor, vendor, model) in configured.chain(unconfigured) {
+        let (mut info, latency) = discovery_probe(device_id, &mut *state.rngs.consumer("discovery").lock().unwrap());
+        match latency.filter(|&ms| ms <= timeout_ms) {
+            Some(ms) => {
+                scan_ms = scan_ms.max(ms);
+                info["deviceId"] = serde_json::json!(device_id);
+                info["sensor"] = serde_json::json!(sensor);
+                info["configured"] = serde_json::json!(sensor.is_some());
+                info["vendor"] = serde_json::json!(vendor);
+                info["model"] = serde_json::json!(model);
+                info["responseTimeMs"] = serde_json::json!(ms);
+                devices.push(info);
+            }
+            None => not_responding += 1,
+        }
+    }
+    // Waiting on unanswered probes runs the scan to its full timeout
+    if not_responding > 0 {
+        scan_ms = timeout_ms;
+    }
+    tokio::time::sleep(Duration::from_millis(scan_ms)).await;
+
+    Json(serde_json::json!({
+        "status": "ok",
+        "timestamp": state.clock.now().to_rfc3339(),
+        "timeoutMs": timeout_ms,
+        "durationMs": scan_ms,
+        "found": devices.len(),
+        "notResponding": not_responding,
+        "devices": devices
+    })).into_response()
+}
+
+async fn get_cluster_status(State(state): State<SharedState>) -> Response {
+    let status = state.cluster.lock().unwrap().status();
+    Json(serde_json::json!({
+        "status": "ok",
+        "cluster": status
+    })).into_response()
+}
+
+async fn trigger_failover(State(state): State<SharedState>) -> Response {
+    let mut cluster = state.cluster.lock().unwrap();
+    if cluster.failover_remaining().is_some() {
+        return (
+            axum::http::StatusCode::CONFLICT,
+            Json(serde_json::json!({
+                "status": "error",
+                "error": "Failover already in progress"
+            })),
+        ).into_response();
+    }
+    cluster.failover_until = Some(std::time::Instant::now() + cluster.failover_window);
+    cluster.failover_count += 1;
+    cluster.last_failover_at = Some(state.clock.now().to_rfc3339());
+    let target_role = cluster.role.flipped();
+
+    Json(serde_json::json!({
+        "status": "ok",
+        "targetRole": target_role,
+        "failoverWindowMs": cluster.failover_window.as_millis() as u64,
+        "cluster": cluster.status()
+    })).into_response()
+}
+
+/// Liveness probe: answering at all means the process is up
+async fn healthz() -> Response {
+    Json(serde_json::json!({ "status": "ok" })).into_response()
+}
+
+/// Readiness probe: ready once the background simulation is running
+async fn readyz(State(state): State<SharedState>) -> Response {
+    let running = *state.sim_running.lock().unwrap();
+    let simulating = sensor_keys(&state).filter(|k| history_tracked(&state, k)).count();
+    let status = if running { axum::http::StatusCode::OK } else { axum::http::StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(serde_json::json!({
+        "status": if running { "ready" } else { "starting" },
+        "sensors": simulating
+    }))).into_response()
+}
+
+async fn get_config(State(state): State<SharedState>) -> Response {
+    let chaos = *state.chaos.lock().unwrap();
+    Json(serde_json::json!({
+        "status": "ok",
+        "config": chaos
+    })).into_response()
+}
+
+/// Change the fault-injection rates of a running simulator; omitted fields keep their value
+async fn patch_config(
+    State(state): State<SharedState>,
+    Json(patch): Json<ConfigPatch>,
+) -> Response {
+    for (name, rate) in [("errorRate", patch.error_rate), ("slowRate", patch.slow_rate)] {
+        if rate.is_some_and(|r| !(0.0..=1.0).contains(&r)) {
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "status": "error",
+                    "error": format!("{} must be between 0 and 1", name)
+                })),
+            ).into_response();
+        }
+    }
+
+    if patch.error_status.is_some_and(|s| !(500..=599).contains(&s)) {
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "status": "error",
+                "error": "errorStatus must be a 5xx status code"
+            })),
+        ).into_response();
+    }
+
+    let mut chaos = state.chaos.lock().unwrap();
+    if let Some(status) = patch.error_status {
+        chaos.error_status = status;
+    }
+    if let Some(rate) = patch.error_rate {
+        chaos.error_rate = rate;
+    }
+    if let Some(rate) = patch.slow_rate {
+        chaos.slow_rate = rate;
+    }
+    Json(serde_json::json!({
+        "status": "ok",
+        "config": *chaos
+    })).into_response()
+}
+
+/// Requests, response time and errors of one endpoint in the access log
+#[derive(Default, Debug, PartialEq)]
+struct EndpointTotals {
+    count: u64,
+    total_ms: u64,
+    total_us: u64,
+    errors: u64,
+}
+
+impl EndpointTotals {
+    /// Mean response time from the microsecond timings, so it isn't
+    /// truncated to whole milliseconds per request or by the division
+    fn avg_ms(&self) -> f64 {
+        self.total_us as f64 / self.count.max(1) as f64 / 1000.0
+    }
+}
+
+/// Sum the access log per endpoint
+fn endpoint_totals<'a>(entries: impl IntoIterator<Item = &'a AccessLogEntry>) -> HashMap<&'a str, EndpointTotals> {
+    let mut totals: HashMap<&str, EndpointTotals> = HashMap::new();
+    for entry in entries {
+        let t = totals.entry(&entry.endpoint).or_default();
+        t.count += 1;
+        t.total_ms += entry.response_time as u64;
+        t.total_us += entry.response_time_us;
+        if entry.status_code >= 400 {
+            t.errors += 1;
+        }
+    }
+    totals
+}
+
+async fn get_stats(State(state): State<SharedState>) -> Response {
+    // Counter first and released before the log: log_middleware takes the
+    // log while holding the counter
+    let total_requests = *state.request_counter.lock().unwrap();
+    let logs = state.access_log.read();
+
+    // Everything per endpoint covers the access log's window
+    let per_endpoint: HashMap<&str, serde_json::Value> = endpoint_totals(logs.iter())
+        .into_iter()
+        .map(|(endpoint, totals)| {
+            let stats = serde_json::json!({
+                "count": totals.count,
+                "totalTime": totals.total_ms,
+                "errors": totals.errors,
+                "avgResponseTime": format!("{:.2}", totals.avg_ms()).parse::<f64>().unwrap()
+            });
+            (endpoint, stats)
+        })
+        .collect();
+
+    // Everything per route covers its latency reservoir's lifetime: every
+    // request since startup or the last clear
+    let route_latency: HashMap<String, serde_json::Value> = state
+        .latency
+        .lock()
+        .iter()
+        .filter_map(|(route, reservoir)| {
+            let p = reservoir.percentiles_ms(&[50.0, 95.0, 99.0])?;
+            let stats = serde_json::json!({
+                "count": reservoir.seen,
+                "avgResponseTime": reservoir.mean_ms(),
+                "p50ResponseTime": p[0],
+                "p95ResponseTime": p[1],
+                "p99ResponseTime": p[2]
+            });
+            Some((route.clone(), stats))
+        })
+        .collect();
+
+    Json(serde_json::json!({
+        "status": "ok",
+        "totalRequests": total_requests,
+        "activeConnections": state.sse_tx.receiver_count(),
+        "endpointStats": per_endpoint,
+        "routeLatency": route_latency
+    })).into_response()
+}
+
+/// Upper bounds (ms) of the response-time histogram buckets
+const RESPONSE_TIME_BUCKETS_MS: &[u128] = &[5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000];
+
+/// Escape a Prometheus label value
+fn prom_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Cumulative request counters for `/metrics`. They count the same requests
+/// the access log records, but unlike the log (capped, and clearable) they
+/// never go down, as Prometheus counters must not.
+#[derive(Default)]
+struct RequestMetrics {
+    requests: Mutex<BTreeMap<(String, String, u16), u64>>,
+    buckets: [AtomicU64; RESPONSE_TIME_BUCKETS_MS.len()],
+    count: AtomicU64,
+    sum_ms: AtomicU64,
+}
+
+impl RequestMetrics {
+    fn record(&self, endpoint: &str, method: &str, status_code: u16, response_time_ms: u128) {
+        let path = endpoint.split('?').next().unwrap_or_default().to_string();
+        *self.requests.lock().unwrap().entry((path, method.to_string(), status_code)).or_default() += 1;
+        for (bucket, &bound) in self.buckets.iter().zip(RESPONSE_TIME_BUCKETS_MS) {
+            if response_time_ms <= bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_ms.fetch_add(response_time_ms as u64, Ordering::Relaxed);
+    }
+}
+
+/// Prometheus text exposition of the request counters
+async fn get_metrics(State(state): State<SharedState>) -> Response {
+    let metrics = &state.request_metrics;
+    let mut out = String::new();
+    out.push_str("# HELP simmurator_requests_total Requests served, by endpoint, method and status.\n");
+    out.push_str("# TYPE simmurator_requests_total counter\n");
+    for ((endpoint, method, status), count) in metrics.requests.lock().unwrap().iter() {
+        out.push_str(&format!(
+            "simmurator_requests_total{{endpoint=\"{}\",method=\"{}\",status=\"{}\"}} {}\n",
+            prom_label(endpoint), prom_label(method), status, count
+        ));
+    }
+
+    // Buckets are read after the total and capped by it, so a request
+    // recorded meanwhile can't leave one above +Inf
+    let count = metrics.count.load(Ordering::Relaxed);
+    out.push_str("# HELP simmurator_response_time_ms Response time in milliseconds.\n");
+    out.push_str("# TYPE simmurator_response_time_ms histogram\n");
+    for (bound, bucket) in RESPONSE_TIME_BUCKETS_MS.iter().zip(&metrics.buckets) {
+        out.push_str(&format!("simmurator_response_time_ms_bucket{{le=\"{}\"}} {}\n", bound, bucket.load(Ordering::Relaxed).min(count)));
+    }
+    out.push_str(&format!("simmurator_response_time_ms_bucket{{le=\"+Inf\"}} {}\n", count));
+    out.push_str(&format!("simmurator_response_time_ms_sum {}\n", metrics.sum_ms.load(Ordering::Relaxed)));
+    out.push_str(&format!("simmurator_response_time_ms_count {}\n", count));
+
+    out.push_str("# HELP simmurator_active_sse_connections Connected SSE clients.\n");
+    out.push_str("# TYPE simmurator_active_sse_connections gauge\n");
+    out.push_str(&format!("simmurator_active_sse_connections {}\n", state.sse_tx.receiver_count()));
+
+    ([(axum::http::header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")], out).into_response()
+}
+
+// ──────────────────────────────────────────────
+// Delivery Semantics (sequencing + duplicates)
+// ──────────────────────────────────────────────
+
+/// Monotonic sequence number shared by every streamed reading, so consumers
+/// can detect gaps and duplicates
+fn next_stream_sequence(state: &AppState) -> u64 {
+    let mut sequence = state.stream_sequence.lock().unwrap();
+    *sequence += 1;
+    *sequence
+}
+
+/// Mimic at-least-once delivery: with probability `SIM_DUPLICATE_RATE` the
+/// message goes out twice, identical down to its sequence number
+fn with_duplicates<T: Clone>(state: &AppState, msg: T) -> Vec<T> {
+    if state.rngs.consumer("duplicates").lock().unwrap().gen_bool(state.duplicate_rate) {
+        vec![msg.clone(), msg]
+    } else {
+        vec![msg]
+    }
+}
+
+// ──────────────────────────────────────────────
+// Bandwidth Throttling
+// ──────────────────────────────────────────────
+
+/// Paces one connection's outgoing frames to `SIM_BANDWIDTH_BYTES_PER_SEC`,
+/// simulating a narrowband link. Each frame occupies the link for
+/// `len / rate` seconds after the previous one finishes and is only released
+/// once that time has passed, so throughput never exceeds the cap; frames
+/// produced faster than that queue up behind the link.
+struct BandwidthPacer {
+    bytes_per_sec: Option<u64>,
+    link_free_at: tokio::time::Instant,
+}
+
+impl BandwidthPacer {
+    fn new(bytes_per_sec: Option<u64>) -> Self {
+        BandwidthPacer {
+            bytes_per_sec,
+            link_free_at: tokio::time::Instant::now(),
         }
-        "energy-meter" => {
-            let voltage_l1 = random_between(218.0, 242.0);
-            let voltage_l3 = voltage_l1 * 1.732;
-            let current = random_between(5.0, 200.0);
-            let power_factor = random_between(0.80, 0.98);
-            let active_power = (voltage_l3 * current * power_factor * 1.732) / 1000.0;
-            let apparent_power = (voltage_l3 * current * 1.732) / 1000.0;
-            let reactive_power = (apparent_power.powi(2) - active_power.powi(2)).sqrt();
-            let frequency = random_between(49.5, 50.5);
-            let energy_kwh = random_between(10000.0, 500000.0);
-            let quality = generate_data_quality(power_factor, 0.85, 1.0);
-            let status_code = generate_opcua_status_code(&quality);
-            let source_ts = Utc::now().to_rfc3339();
-            
-            let unified = UnifiedSensorData {
-                opc_ua: generate_opcua_node("ENR-008", "Energy Meter"),
-                equipment_hierarchy: generate_isa95_hierarchy("ENR-008", "Main-Panel-H", "Electrical"),
-                sparkplug_topic: generate_sparkplug_topic("Plant-01", "ENR-008"),
-                source_timestamp: source_ts,
-                server_timestamp: server_ts.clone(),
-                value: serde_json::json!({
-                    "activePower": format!("{:.2}", active_power).parse::<f64>().unwrap(),
-                    "apparentPower": format!("{:.2}", apparent_power).parse::<f64>().unwrap(),
-                    "reactivePower": format!("{:.2}", reactive_power).parse::<f64>().unwrap(),
-                    "voltageL1": format!("{:.1}", voltage_l1).parse::<f64>().unwrap(),
-                    "voltageL3": format!("{:.1}", voltage_l3).parse::<f64>().unwrap(),
-                    "current": format!("{:.2}", current).parse::<f64>().unwrap(),
-                    "powerFactor": format!("{:.3}", power_factor).parse::<f64>().unwrap(),
-                    "frequency": format!("{:.2}", frequency).parse::<f64>().unwrap(),
-                    "cumulativeEnergy": format!("{:.1}", energy_kwh).parse::<f64>().unwrap()
-                }),
-                data_quality: quality,
-                opc_ua_status_code: status_code,
-                unit: get_ucum_unit("kW"),
-                sensor_type: "energy".to_string(),
-                description: "3-phase power quality meter".to_string(),
-                properties: serde_json::json!({}),
-            };
-            Some(serde_json::to_value(unified).unwrap())
+    }
+
+    async fn pace(&mut self, bytes: usize) {
+        let Some(rate) = self.bytes_per_sec else {
+            return;
+        };
+        let start = self.link_free_at.max(tokio::time::Instant::now());
+        self.link_free_at = start + Duration::from_secs_f64(bytes as f64 / rate as f64);
+        tokio::time::sleep_until(self.link_free_at).await;
+    }
+}
+
+/// An SSE event paired with its payload size, for pacing
+fn sse_frame<T: Serialize>(msg: &T) -> (Event, usize) {
+    let data = serde_json::to_string(msg).unwrap();
+    let len = data.len();
+    (Event::default().data(data), len)
+}
+
+fn paced_sse<S>(stream: S, bytes_per_sec: Option<u64>) -> impl tokio_stream::Stream<Item = Result<Event, Infallible>>
+where
+    S: tokio_stream::Stream<Item = (Event, usize)>,
+{
+    let pacer = Arc::new(tokio::sync::Mutex::new(BandwidthPacer::new(bytes_per_sec)));
+    stream.then(move |(event, len)| {
+        let pacer = pacer.clone();
+        async move {
+            pacer.lock().await.pace(len).await;
+            Ok(event)
         }
-        "amr" => {
-            let (province, location, lat, lng) = get_random_oil_station();
-            let flow_rate_m3h = random_between(500.0, 2500.0);
-            let flow_rate_lmin = flow_rate_m3h * 1000.0 / 60.0;
-            let inlet_pressure = random_between(30.0, 80.0);
-            let outlet_pressure = inlet_pressure - random_between(5.0, 20.0);
-            let temperature = random_between(40.0, 70.0);
-            let api_gravity = random_between(25.0, 35.0);
-            let density = (141.5 / (api_gravity + 131.5)) * 998.0;
-            let viscosity = random_between(10.0, 100.0);
-            let cumulative = random_between(1000000.0, 50000000.0);
-            let quality = generate_data_quality(inlet_pressure, 30.0, 80.0);
-            let status_code = generate_opcua_status_code(&quality);
-            let source_ts = Utc::now().to_rfc3339();
-            
-            let unified = UnifiedSensorData {
-                opc_ua: generate_opcua_node("AMR-009", "AMR Oil Pipeline Meter"),
-                equipment_hierarchy: generate_isa95_hierarchy("AMR-009", "Pipeline-Station", "Oil-Gas"),
-                sparkplug_topic: generate_sparkplug_topic("Plant-01", "AMR-009"),
-                source_timestamp: source_ts,
-                server_timestamp: server_ts.clone(),
-                value: serde_json::json!({
-                    "meterSerial": "AMR-PIPE-2024-09",
-                    "pipelineId": "PIPE-AMR-01",
-                    "location": location,
-                    "province": province,
-                    "coordinates": { "lat": lat, "lng": lng },
-                    "flowRate": format!("{:.2}", flow_rate_lmin).parse::<f64>().unwrap(),
-                    "flowRateM3H": format!("{:.2}", flow_rate_m3h).parse::<f64>().unwrap(),
-                    "flowDirection": if rng.gen_bool(0.95) { "forward" } else { "reverse" },
-                    "cumulativeFlow": format!("{:.1}", cumulative).parse::<f64>().unwrap(),
-                    "inletPressure": format!("{:.2}", inlet_pressure).parse::<f64>().unwrap(),
-                    "outletPressure": format!("{:.2}", outlet_pressure).parse::<f64>().unwrap(),
-                    "differentialPressure": format!("{:.2}", inlet_pressure - outlet_pressure).parse::<f64>().unwrap(),
-                    "temperature": format!("{:.1}", temperature).parse::<f64>().unwrap(),
-                    "apiGravity": format!("{:.1}", api_gravity).parse::<f64>().unwrap(),
-                    "density": format!("{:.1}", density).parse::<f64>().unwrap(),
-                    "viscosity": format!("{:.2}", viscosity).parse::<f64>().unwrap(),
-                    "waterContent": format!("{:.3}", random_between(0.1, 2.0)).parse::<f64>().unwrap(),
-                    "pumpSpeed": rng.gen_range(1200..1800),
-                    "valveStatus": if rng.gen_bool(0.85) { "open" } else { "throttled" },
-                    "valveOpenPercent": format!("{:.1}", random_between(60.0, 100.0)).parse::<f64>().unwrap(),
-                    "leakDetected": rng.gen_bool(0.02),
-                    "batteryLevel": format!("{:.1}", random_between(70.0, 100.0)).parse::<f64>().unwrap(),
-                    "signalStrength": rng.gen_range(-85..-50),
-                    "lastCalibration": "2025-01-15T08:00:00.000Z",
-                    "nextCalibrationDue": "2025-07-15T08:00:00.000Z"
-                }),
-                data_quality: quality,
-                opc_ua_status_code: status_code,
-                unit: get_ucum_unit("L/min"),
-                sensor_type: "amr_oil_pipeline".to_string(),
-                description: "Automatic meter reading for oil pipeline".to_string(),
-                properties: serde_json::json!({}),
-            };
-            Some(serde_json::to_value(unified).unwrap())
+    })
+}
+
+/// The last `count` history samples of a sensor as replayed `Data` frames,
+/// oldest first; nothing if no history has been buffered yet
+fn replay_frames(state: &AppState, sensor: &str, count: usize) -> Vec<WSMessage> {
+    let samples: Vec<(String, serde_json::Value)> = {
+        let history = state.history.lock().unwrap();
+        let Some(buffer) = history.get(sensor) else {
+            return Vec::new();
+        };
+        buffer.iter().skip(buffer.len().saturating_sub(count)).cloned().collect()
+    };
+    samples
+        .into_iter()
+        .map(|(timestamp, data)| WSMessage::Data {
+            sensor: sensor.to_string(),
+            data,
+            timestamp,
+            sequence: next_stream_sequence(state),
+            replay: true,
+        })
+        .collect()
+}
+
+/// Explain why a client message isn't a valid `WSAction`
+fn ws_action_error(text: &str, err: &serde_json::Error) -> String {
+    let known = ["subscribe", "unsubscribe", "list", "ping", "setEncoding"];
+    match serde_json::from_str::<serde_json::Value>(text).ok().as_ref().map(|v| &v["action"]) {
+        Some(serde_json::Value::String(action)) if !known.contains(&action.as_str()) => {
+            format!("Unknown action '{}' (expected one of: {})", action, known.join(", "))
         }
-        // ============================================
-        // 5 NEW ENDPOINTS - Industrial IoT Sensors
-        // ============================================
-        "flow-meter" => {
-            // อ้างอิงจาก industrial flow meters (Rosemount, Endress+Hauser)
-            // Liquid: 0.3-4950 m³/hr, Gas: 3-46000 m³/hr, Steam: 1.6-540000 kg/hr
-            let flow_type = ["liquid", "gas", "steam"][rng.gen_range(0..3)];
-            let (flow_rate, unit, totalizer) = match flow_type {
-                "liquid" => (random_between(10.0, 1000.0), "m³/h", random_between(10000.0, 500000.0)),
-                "gas" => (random_between(100.0, 10000.0), "m³/h", random_between(100000.0, 5000000.0)),
-                "steam" => (random_between(500.0, 50000.0), "kg/h", random_between(1000000.0, 50000000.0)),
-                _ => (0.0, "m³/h", 0.0)
-            };
-            let temperature = random_between(20.0, 200.0);
-            let pressure = random_between(1.0, 20.0);
-            let density = if flow_type == "steam" { random_between(1.0, 50.0) } else { random_between(800.0, 1000.0) };
-            let meter_types = ["electromagnetic", "vortex", "ultrasonic", "coriolis"];
-            let meter_type = meter_types[rng.gen_range(0..4)];
-            let quality = generate_data_quality(flow_rate, 10.0, 1000.0);
-            let status_code = generate_opcua_status_code(&quality);
-            let source_ts = Utc::now().to_rfc3339();
-            
-            let unified = UnifiedSensorData {
-                opc_ua: generate_opcua_node("FLW-010", "Flow Meter"),
-                equipment_hierarchy: generate_isa95_hierarchy("FLW-010", "Process-Line-J", "Process"),
-                sparkplug_topic: generate_sparkplug_topic("Plant-01", "FLW-010"),
-                source_timestamp: source_ts,
-                server_timestamp: server_ts.clone(),
-                value: serde_json::json!({
-                    "mediaType": flow_type,
-                    "flowRate": format!("{:.2}", flow_rate).parse::<f64>().unwrap(),
-                    "totalizer": format!("{:.1}", totalizer).parse::<f64>().unwrap(),
-                    "temperature": format!("{:.1}", temperature).parse::<f64>().unwrap(),
-                    "pressure": format!("{:.2}", pressure).parse::<f64>().unwrap(),
-                    "density": format!("{:.1}", density).parse::<f64>().unwrap(),
-                    "pipeSize": rng.gen_range(50..300),
-                    "meterType": meter_type
-                }),
-                data_quality: quality,
-                opc_ua_status_code: status_code,
-                unit: get_ucum_unit(unit),
-                sensor_type: "flow_meter".to_string(),
-                description: "Industrial flow measurement".to_string(),
-                properties: serde_json::json!({}),
-            };
-            Some(serde_json::to_value(unified).unwrap())
+        _ => format!("Invalid action message: {}", err),
+    }
+}
+
+/// Serialize and send a WebSocket message, paced to the connection's bandwidth cap
+async fn send_ws(socket: &mut WebSocket, pacer: &mut BandwidthPacer, encoding: WsEncoding, msg: &WSMessage) -> Result<(), axum::Error> {
+    let frame = match encoding {
+        WsEncoding::Json => Message::Text(serde_json::to_string(msg).unwrap()),
+        WsEncoding::Msgpack => Message::Binary(rmp_serde::to_vec_named(msg).unwrap()),
+    };
+    let len = match &frame {
+        Message::Text(text) => text.len(),
+        other => other.clone().into_data().len(),
+    };
+    pacer.pace(len).await;
+    let sent = socket.send(frame).await;
+    if let Err(e) = &sent {
+        tracing::warn!(error = %e, "WebSocket send failed");
+    }
+    sent
+}
+
+/// An access-log event carrying the entry's event id as its SSE `id`, so a
+/// reconnecting browser sends it back as `Last-Event-ID`
+fn sse_access_frame(entry: AccessLogEntry) -> (Event, usize) {
+    let id = entry.event_id;
+    let (event, len) = sse_frame(&SSEEvent::Access(entry));
+    (event.id(id.to_string()), len)
+}
+
+/// Access-log entries after event `last_id` still in the buffer, oldest first
+fn access_log_since(state: &AppState, last_id: u64, filter: &AccessLogFilter) -> Vec<AccessLogEntry> {
+    state.access_log.read().iter().rev().filter(|e| e.event_id > last_id && filter.matches(e)).cloned().collect()
+}
+
+/// Live access-log and alarm feed. A reconnect carrying `Last-Event-ID`
+/// first replays the buffered access-log entries after that id (only as
+/// far back as the buffer goes), then continues live. Only access events
+/// carry ids; alarms leave the client's last id untouched.
+///
+/// The access-log filters (`?status=`, `?min_status=`, `?endpoint=`,
+/// `?ip=`) are applied here, so non-matching entries are never sent; the
+/// welcome, alarms and lag notices always go out.
+async fn sse_handler(
+    headers: axum::http::HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<SharedState>,
+) -> Response {
+    let filter = match AccessLogFilter::from_params(&params) {
+        Ok(filter) => filter,
+        Err(e) => {
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({ "status": "error", "error": e })),
+            ).into_response();
         }
-        "gas-detector" => {
-            let co = random_between(0.0, 50.0);
-            let h2s = random_between(0.0, 10.0);
-            let o2 = random_between(19.5, 23.5);
-            let lel = random_between(0.0, 20.0);
-            let co_alarm = co > 35.0;
-            let h2s_alarm = h2s > 10.0;
-            let o2_alarm = o2 < 19.5 || o2 > 23.5;
-            let lel_alarm = lel > 10.0;
-            let quality = if co_alarm || h2s_alarm || o2_alarm || lel_alarm { DataQuality::Bad } else { DataQuality::Good };
-            let status_code = generate_opcua_status_code(&quality);
-            let source_ts = Utc::now().to_rfc3339();
-            
-            let unified = UnifiedSensorData {
-                opc_ua: generate_opcua_node("GAS-011", "Gas Detector"),
-                equipment_hierarchy: generate_isa95_hierarchy("GAS-011", "Confined-Space-K", "Safety"),
-                sparkplug_topic: generate_sparkplug_topic("Plant-01", "GAS-011"),
-                source_timestamp: source_ts,
-                server_timestamp: server_ts.clone(),
-                value: serde_json::json!({
-                    "carbonMonoxide": format!("{:.1}", co).parse::<f64>().unwrap(),
-                    "coAlarmSetpoint": 35.0,
-                    "hydrogenSulfide": format!("{:.2}", h2s).parse::<f64>().unwrap(),
-                    "h2sAlarmSetpoint": 10.0,
-                    "oxygen": format!("{:.1}", o2).parse::<f64>().unwrap(),
-                    "o2LowAlarm": 19.5,
-                    "o2HighAlarm": 23.5,
-                    "lel": format!("{:.1}", lel).parse::<f64>().unwrap(),
-                    "lelAlarmSetpoint": 10.0,
-                    "alarms": {
-                        "co": co_alarm,
-                        "h2s": h2s_alarm,
-                        "o2": o2_alarm,
-                        "lel": lel_alarm
+    };
+    // Subscribe before reading the buffer so nothing falls between the two
+    let rx = state.sse_tx.subscribe();
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok());
+    let replay = last_event_id.map(|last| access_log_since(&state, last, &filter)).unwrap_or_default();
+    // Entries logged after subscribing arrive twice; the replayed copy wins
+    let replayed_up_to = replay.last().map(|e| e.event_id);
+
+    // Initial welcome message
+    let initial_stream = tokio_stream::once(sse_frame(&SSEEvent::Connected {
+        message: "SSE stream connected".to_string(),
+    }));
+    let replay_stream = tokio_stream::iter(replay.into_iter().map(sse_access_frame));
+
+    // A slow client that overruns the channel gets told how much it missed
+    let broadcast_stream = BroadcastStream::new(rx).filter_map(move |msg| {
+        futures_util::future::ready(match msg {
+            Ok(SSEEvent::Access(entry)) if replayed_up_to.is_some_and(|up_to| entry.event_id <= up_to) => None,
+            Ok(SSEEvent::Access(entry)) if !filter.matches(&entry) => None,
+            Ok(SSEEvent::Access(entry)) => Some(sse_access_frame(entry)),
+            Ok(event) => Some(sse_frame(&event)),
+            Err(BroadcastStreamRecvError::Lagged(skipped)) => Some(sse_frame(&SSEEvent::Lagged { skipped })),
+        })
+    });
+
+    let stream = until_shutdown(&state, initial_stream.chain(replay_stream).chain(broadcast_stream));
+    Sse::new(paced_sse(stream, state.bandwidth_bytes_per_sec))
+        .keep_alive(axum::response::sse::KeepAlive::new().interval(Duration::from_secs(15)))
+        .into_response()
+}
+
+#[derive(Deserialize)]
+struct SensorStreamParams {
+    interval: Option<u64>,
+    encoding: Option<String>,
+}
+
+/// Live SSE feed of one sensor's readings.
+///
+/// `?encoding=protobuf` switches each frame to a base64-encoded
+/// `proto::SensorReading` under the `sensor-protobuf` event type. SSE is a
+/// text-only protocol (`data:` lines are UTF-8 and split on newlines), so raw
+/// protobuf bytes can't be sent as-is; base64 keeps the frame intact.
+async fn sensor_sse_handler(
+    Path(key): Path<String>,
+    Query(params): Query<SensorStreamParams>,
+    State(state): State<SharedState>,
+) -> Response {
+    if !is_sensor_key(&state, &key) {
+        return sensor_not_found();
+    }
+
+    let interval_ms = params.interval.unwrap_or(1000).clamp(100, 60000);
+    let protobuf = params.encoding.as_deref() == Some("protobuf");
+    let bandwidth = state.bandwidth_bytes_per_sec;
+    let shutdown_state = state.clone();
+
+    let mut filter = ExceptionFilter::default();
+    let stream = IntervalStream::new(tokio::time::interval(Duration::from_millis(interval_ms)))
+        .map(move |_| {
+            let frame = generate_sensor_data(&state, &key)
+                .filter(|data| filter.should_report(&state, &key, data))
+                .map(|data| {
+                    let sequence = next_stream_sequence(&state);
+                    if protobuf {
+                        use base64::Engine;
+                        let status_code = opcua_status_code_value(&data["opcUaStatusCode"]);
+                        let bytes = proto::encode_sensor_reading(&key, &data, status_code, sequence);
+                        let encoded = base64::engine::general_purpose::STANDARD.encode(bytes);
+                        let len = encoded.len();
+                        (Event::default().event("sensor-protobuf").data(encoded), len)
+                    } else {
+                        sse_frame(&SSEEvent::Sensor {
+                            sensor: key.clone(),
+                            data,
+                            timestamp: state.clock.now().to_rfc3339(),
+                            sequence,
+                        })
                     }
-                }),
-                data_quality: quality,
-                opc_ua_status_code: status_code,
-                unit: get_ucum_unit("ppm"),
-                sensor_type: "gas_detector".to_string(),
-                description: "4-gas safety monitor".to_string(),
-                properties: serde_json::json!({}),
-            };
-            Some(serde_json::to_value(unified).unwrap())
-        }
-        "ph-sensor" => {
-            let ph = random_between(4.0, 10.0);
-            let orp = random_between(-500.0, 500.0);
-            let temperature = random_between(15.0, 40.0);
-            let conductivity = random_between(100.0, 5000.0);
-            let turbidity = random_between(0.1, 100.0);
-            let quality = generate_data_quality(ph, 6.0, 8.5);
-            let status_code = generate_opcua_status_code(&quality);
-            let source_ts = Utc::now().to_rfc3339();
-            
-            let unified = UnifiedSensorData {
-                opc_ua: generate_opcua_node("PH-012", "pH Sensor"),
-                equipment_hierarchy: generate_isa95_hierarchy("PH-012", "Water-Treatment-L", "Water"),
-                sparkplug_topic: generate_sparkplug_topic("Plant-01", "PH-012"),
-                source_timestamp: source_ts,
-                server_timestamp: server_ts.clone(),
-                value: serde_json::json!({
-                    "phValue": format!("{:.2}", ph).parse::<f64>().unwrap(),
-                    "orp": format!("{:.1}", orp).parse::<f64>().unwrap(),
-                    "temperature": format!("{:.1}", temperature).parse::<f64>().unwrap(),
-                    "conductivity": format!("{:.1}", conductivity).parse::<f64>().unwrap(),
-                    "turbidity": format!("{:.2}", turbidity).parse::<f64>().unwrap()
-                }),
-                data_quality: quality,
-                opc_ua_status_code: status_code,
-                unit: get_ucum_unit("pH"),
-                sensor_type: "ph_sensor".to_string(),
-                description: "Water quality pH/ORP sensor".to_string(),
-                properties: serde_json::json!({}),
-            };
-            Some(serde_json::to_value(unified).unwrap())
+                });
+            futures_util::stream::iter(frame.map(|f| with_duplicates(&state, f)).unwrap_or_default())
+        })
+        .flatten();
+
+    Sse::new(paced_sse(until_shutdown(&shutdown_state, stream), bandwidth))
+        .keep_alive(axum::response::sse::KeepAlive::new().interval(Duration::from_secs(15)))
+        .into_response()
+}
+
+/// Newline-delimited JSON feed of one sensor's readings over a single long
+/// response, one reading per line every `?interval=` ms. The stream ends when
+/// the client goes away; the access log records the request once.
+async fn sensor_ndjson_handler(
+    Path(key): Path<String>,
+    Query(params): Query<SensorStreamParams>,
+    State(state): State<SharedState>,
+) -> Response {
+    if !is_sensor_key(&state, &key) {
+        return sensor_not_found();
+    }
+
+    let interval_ms = params.interval.unwrap_or(1000).clamp(100, 60000);
+    let pacer = Arc::new(tokio::sync::Mutex::new(BandwidthPacer::new(state.bandwidth_bytes_per_sec)));
+    let open = OpenStream::new(&state);
+    let shutdown = shutdown_signaled(&state);
+
+    let mut filter = ExceptionFilter::default();
+    let stream = IntervalStream::new(tokio::time::interval(Duration::from_millis(interval_ms)))
+        .map(move |_| {
+            let line = generate_sensor_data(&state, &key)
+                .filter(|data| filter.should_report(&state, &key, data))
+                .map(|data| format!("{}\n", data));
+            futures_util::stream::iter(line.map(|l| with_duplicates(&state, l)).unwrap_or_default())
+        })
+        .flatten()
+        // No notice line: every line of this feed is a reading, so it just ends
+        .take_until(shutdown)
+        .then(move |line| {
+            let _ = &open;
+            let pacer = pacer.clone();
+            async move {
+                pacer.lock().await.pace(line.len()).await;
+                Ok::<_, Infallible>(line)
+            }
+        });
+
+    (
+        [(axum::http::header::CONTENT_TYPE, "application/x-ndjson")],
+        axum::body::Body::from_stream(stream),
+    ).into_response()
+}
+
+async fn create_subscription(
+    State(state): State<SharedState>,
+    Json(req): Json<CreateSubscriptionRequest>,
+) -> Response {
+    // Same validation rules as the WebSocket Subscribe action: sensors given
+    // only an interval override are subscribed too
+    let overrides = req.intervals.unwrap_or_default();
+    let requested = req.sensors.unwrap_or_else(|| {
+        if overrides.is_empty() {
+            sensor_keys(&state).map(str::to_string).collect()
+        } else {
+            Vec::new()
         }
-        "level-sensor" => {
-            let tank_height = random_between(5.0, 20.0);
-            let level = random_between(0.5, tank_height - 0.5);
-            let percentage = (level / tank_height) * 100.0;
-            let volume = level * random_between(10.0, 100.0);
-            let sensor_type = ["ultrasonic", "radar", "guided_wave", "pressure"][rng.gen_range(0..4)];
-            let quality = generate_data_quality(percentage, 10.0, 90.0);
-            let status_code = generate_opcua_status_code(&quality);
-            let source_ts = Utc::now().to_rfc3339();
-            
-            let unified = UnifiedSensorData {
-                opc_ua: generate_opcua_node("LVL-013", "Level Sensor"),
-                equipment_hierarchy: generate_isa95_hierarchy("LVL-013", "Storage-Tank-M", "Tank-Farm"),
-                sparkplug_topic: generate_sparkplug_topic("Plant-01", "LVL-013"),
-                source_timestamp: source_ts,
-                server_timestamp: server_ts.clone(),
-                value: serde_json::json!({
-                    "level": format!("{:.3}", level).parse::<f64>().unwrap(),
-                    "tankHeight": format!("{:.1}", tank_height).parse::<f64>().unwrap(),
-                    "percentage": format!("{:.2}", percentage).parse::<f64>().unwrap(),
-                    "volume": format!("{:.2}", volume).parse::<f64>().unwrap(),
-                    "sensorType": sensor_type,
-                    "accuracy": "±3mm"
-                }),
-                data_quality: quality,
-                opc_ua_status_code: status_code,
-                unit: get_ucum_unit("m"),
-                sensor_type: "level_sensor".to_string(),
-                description: "Tank level measurement sensor".to_string(),
-                properties: serde_json::json!({}),
-            };
-            Some(serde_json::to_value(unified).unwrap())
+    });
+    let (mut valid, mut unknown) = (Vec::new(), Vec::new());
+    for sensor in requested.into_iter().chain(overrides.keys().cloned()) {
+        let list = if is_sensor_key(&state, &sensor) { &mut valid } else { &mut unknown };
+        if !list.contains(&sensor) {
+            list.push(sensor);
         }
-        "proximity-sensor" => {
-            let object_detected = rng.gen_bool(0.7);
-            let distance = if object_detected { random_between(5.0, 50.0) } else { -1.0 };
-            let sensor_type = ["inductive", "capacitive", "photoelectric", "ultrasonic"][rng.gen_range(0..4)];
-            let detection_count = rng.gen_range(0..10000);
-            let operating_time = random_between(1000.0, 50000.0);
-            let quality = if object_detected { DataQuality::Good } else { DataQuality::Uncertain };
-            let status_code = generate_opcua_status_code(&quality);
-            let source_ts = Utc::now().to_rfc3339();
+    }
+    if valid.is_empty() {
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "status": "error",
+                "error": "No known sensors requested",
+                "unknown": unknown
+            })),
+        ).into_response();
+    }
+
+    let id = uuid::Uuid::new_v4().to_string();
+    let interval = req.interval.unwrap_or(1000).clamp(100, 60000);
+    let intervals = valid
+        .iter()
+        .map(|s| (s.clone(), overrides.get(s).map_or(interval, |&ms| ms.clamp(100, 60000))))
+        .collect();
+    let subscription = Subscription {
+        sse_url: format!("/api/v1/subscriptions/{}/events", id),
+        id: id.clone(),
+        sensors: valid,
+        interval,
+        intervals,
+        batch: req.batch,
+        created_at: state.clock.now().to_rfc3339(),
+    };
+    state.subscriptions.lock().unwrap().insert(id, subscription.clone());
+
+    let mut body = serde_json::json!({
+        "status": "ok",
+        "subscription": subscription
+    });
+    if !unknown.is_empty() {
+        body["unknown"] = serde_json::json!(unknown);
+    }
+    (axum::http::StatusCode::CREATED, Json(body)).into_response()
+}
+
+async fn list_subscriptions(State(state): State<SharedState>) -> Response {
+    let subscriptions: Vec<_> = state.subscriptions.lock().unwrap().values().cloned().collect();
+    Json(serde_json::json!({
+        "status": "ok",
+        "subscriptions": subscriptions
+    })).into_response()
+}
+
+fn subscription_not_found() -> Response {
+    (
+        axum::http::StatusCode::NOT_FOUND,
+        Json(serde_json::json!({
+            "status": "error",
+            "error": "Subscription not found"
+        })),
+    ).into_response()
+}
+
+async fn get_subscription(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+) -> Response {
+    match state.subscriptions.lock().unwrap().get(&id) {
+        Some(subscription) => Json(serde_json::json!({
+            "status": "ok",
+            "subscription": subscription
+        })).into_response(),
+        None => subscription_not_found(),
+    }
+}
 
-            let unified = UnifiedSensorData {
-                opc_ua: generate_opcua_node("PRX-014", "Proximity Sensor"),
-                equipment_hierarchy: generate_isa95_hierarchy("PRX-014", "Conveyor-Station-N", "Material-Handling"),
-                sparkplug_topic: generate_sparkplug_topic("Plant-01", "PRX-014"),
-                source_timestamp: source_ts,
-                server_timestamp: server_ts.clone(),
-                value: serde_json::json!({
-                    "objectDetected": object_detected,
-                    "distance": if distance > 0.0 { Some(format!("{:.1}", distance).parse::<f64>().unwrap()) } else { None },
-                    "sensorType": sensor_type,
-                    "detectionRange": random_between(1.0, 100.0),
-                    "responseTime": random_between(0.1, 10.0),
-                    "switchingFrequency": rng.gen_range(100..5000),
-                    "detectionCount": detection_count,
-                    "operatingTime": format!("{:.1}", operating_time).parse::<f64>().unwrap()
-                }),
-                data_quality: quality,
-                opc_ua_status_code: status_code,
-                unit: get_ucum_unit("mm"),
-                sensor_type: "proximity_sensor".to_string(),
-                description: "Object detection proximity sensor".to_string(),
-                properties: serde_json::json!({}),
-            };
-            Some(serde_json::to_value(unified).unwrap())
+async fn delete_subscription(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+) -> Response {
+    match state.subscriptions.lock().unwrap().remove(&id) {
+        Some(_) => Json(serde_json::json!({
+            "status": "ok",
+            "deleted": id
+        })).into_response(),
+        None => subscription_not_found(),
+    }
+}
+
+/// One tick of a multi-sensor SSE stream: a `sensor` frame per reading, or
+/// a single `sensorBatch` frame when `batch` is set
+fn sensor_frames(state: &AppState, sensors: &[String], batch: bool, filter: &mut ExceptionFilter) -> Vec<(Event, usize)> {
+    let timestamp = state.clock.now().to_rfc3339();
+    let readings = sensors
+        .iter()
+        .filter_map(|sensor| generate_sensor_data(state, sensor).map(|data| (sensor.clone(), data)))
+        .filter(|(sensor, data)| filter.should_report(state, sensor, data));
+    if batch {
+        let readings: HashMap<_, _> = readings.collect();
+        if readings.is_empty() {
+            return Vec::new();
         }
-        _ => None,
+        let sequence = next_stream_sequence(state);
+        return with_duplicates(state, sse_frame(&SSEEvent::SensorBatch { readings, timestamp, sequence }));
     }
+    readings
+        .flat_map(|(sensor, data)| {
+            let sequence = next_stream_sequence(state);
+            let frame = sse_frame(&SSEEvent::Sensor { sensor, data, timestamp: timestamp.clone(), sequence });
+            with_duplicates(state, frame)
+        })
+        .collect()
 }
 
-const AVAILABLE_SENSORS: &[&str] = &[
-    "temperature", "humidity", "oil-level", "oil-pressure",
-    "air-quality", "pressure", "vibration", "energy-meter", "amr",
-    "flow-meter", "gas-detector", "ph-sensor", "level-sensor", "proximity-sensor"
-];
+#[derive(Deserialize)]
+struct SensorsStreamParams {
+    /// Comma-separated sensor keys; every sensor when omitted
+    sensors: Option<String>,
+    interval: Option<u64>,
+    #[serde(default)]
+    batch: bool,
+}
 
-// ──────────────────────────────────────────────
-// State
-// ──────────────────────────────────────────────
+/// Live SSE feed of several sensors at once, without creating a
+/// subscription first: `?sensors=a,b` (default all), `?interval=` ms and
+/// `?batch=true` for one `sensorBatch` frame per tick. The stream ends when
+/// the client disconnects.
+async fn sensors_sse_handler(
+    Query(params): Query<SensorsStreamParams>,
+    State(state): State<SharedState>,
+) -> Response {
+    let requested: Vec<String> = match &params.sensors {
+        Some(list) => list.split(',').map(str::trim).filter(|s| !s.is_empty()).map(str::to_string).collect(),
+        None => sensor_keys(&state).map(str::to_string).collect(),
+    };
+    let unknown: Vec<&String> = requested.iter().filter(|s| !is_sensor_key(&state, s)).collect();
+    if requested.is_empty() || !unknown.is_empty() {
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "status": "error",
+                "error": if requested.is_empty() { "No sensors requested" } else { "Unknown sensors requested" },
+                "unknown": unknown
+            })),
+        ).into_response();
+    }
 
-struct AppState {
-    access_log: Mutex<Vec<AccessLogEntry>>,
-    request_counter: Mutex<usize>,
-    sse_tx: broadcast::Sender<SSEEvent>,
-}
+    let interval_ms = params.interval.unwrap_or(1000).clamp(100, 60000);
+    let bandwidth = state.bandwidth_bytes_per_sec;
+    let shutdown_state = state.clone();
+    let mut filter = ExceptionFilter::default();
+    let stream = IntervalStream::new(tokio::time::interval(Duration::from_millis(interval_ms)))
+        .map(move |_| futures_util::stream::iter(sensor_frames(&state, &requested, params.batch, &mut filter)))
+        .flatten();
 
-type SharedState = Arc<AppState>;
+    Sse::new(paced_sse(until_shutdown(&shutdown_state, stream), bandwidth))
+        .keep_alive(axum::response::sse::KeepAlive::new().interval(Duration::from_secs(15)))
+        .into_response()
+}
 
-// ──────────────────────────────────────────────
-// Handlers
-// ──────────────────────────────────────────────
+/// SSE feed for a REST subscription. Each sensor is read on its own
+/// interval, as over the WebSocket, and reporting modes apply per stream.
+/// Sensors and intervals are fixed when the subscription is created; the
+/// stream ends once it's deleted.
+async fn subscription_sse_handler(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+) -> Response {
+    let Some(subscription) = state.subscriptions.lock().unwrap().get(&id).cloned() else {
+        return subscription_not_found();
+    };
 
-async fn get_endpoints() -> Response {
-    let endpoints: Vec<_> = AVAILABLE_SENSORS
+    let bandwidth = state.bandwidth_bytes_per_sec;
+    let lookup_state = state.clone();
+    let shutdown_state = state.clone();
+    let mut filter = ExceptionFilter::default();
+    // (sensor, period, next due); every sensor is read on the first tick
+    let start = tokio::time::Instant::now();
+    let schedule: Vec<(String, Duration, tokio::time::Instant)> = subscription
+        .intervals
         .iter()
-        .map(|&key| serde_json::json!({
-            "name": key,
-            "url": format!("/api/v1/sensors/{}", key),
-            "method": "GET",
-            "description": format!("Returns simulated {} IoT sensor data", key.replace('-', " "))
-        }))
+        .map(|(sensor, &ms)| (sensor.clone(), Duration::from_millis(ms), start))
         .collect();
+    let stream = futures_util::stream::unfold(schedule, move |mut schedule| {
+        let (state, id) = (lookup_state.clone(), id.clone());
+        async move {
+            let next = schedule.iter().map(|&(_, _, due)| due).min()?;
+            tokio::time::sleep_until(next).await;
+            state.subscriptions.lock().unwrap().get(&id)?;
+            let now = tokio::time::Instant::now();
+            let mut due = Vec::new();
+            for (sensor, period, next_due) in schedule.iter_mut().filter(|(_, _, d)| *d <= now) {
+                due.push(sensor.clone());
+                while *next_due <= now {
+                    *next_due += *period;
+                }
+            }
+            Some((due, schedule))
+        }
+    })
+    .map(move |due| futures_util::stream::iter(sensor_frames(&state, &due, subscription.batch, &mut filter)))
+    .flatten();
+
+    Sse::new(paced_sse(until_shutdown(&shutdown_state, stream), bandwidth))
+        .keep_alive(axum::response::sse::KeepAlive::new().interval(Duration::from_secs(15)))
+        .into_response()
+}
+
+fn webhook_not_found() -> Response {
+    (
+        axum::http::StatusCode::NOT_FOUND,
+        Json(serde_json::json!({
+            "status": "error",
+            "error": "Webhook not found"
+        })),
+    ).into_response()
+}
+
+/// Register a webhook: `{url, sensors?, on_quality?}`. Sensors default to all
+/// of them and `on_quality` to `["bad"]`. A reading is delivered when it
+/// moves its sensor into one of those qualities.
+async fn create_webhook(
+    State(state): State<SharedState>,
+    Json(req): Json<CreateWebhookRequest>,
+) -> Response {
+    let bad_request = |error: String| (
+        axum::http::StatusCode::BAD_REQUEST,
+        Json(serde_json::json!({
+            "status": "error",
+            "error": error
+        })),
+    ).into_response();
+
+    match reqwest::Url::parse(&req.url) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+        _ => return bad_request(format!("'{}' is not an http(s) URL", req.url)),
+    }
+    let sensors = req.sensors.unwrap_or_default();
+    let unknown: Vec<_> = sensors.iter().filter(|s| !is_sensor_key(&state, s)).collect();
+    if !unknown.is_empty() {
+        return bad_request(format!("Unknown sensors: {:?}", unknown));
+    }
+    let on_quality = req.on_quality.unwrap_or_else(|| vec!["bad".to_string()]);
+    if on_quality.is_empty() {
+        return bad_request("on_quality must name at least one quality".to_string());
+    }
+    if let Some(q) = on_quality.iter().find(|q| !WEBHOOK_QUALITIES.contains(&q.as_str())) {
+        return bad_request(format!("Unknown quality '{}' (expected one of {})", q, WEBHOOK_QUALITIES.join(", ")));
+    }
+
+    let id = uuid::Uuid::new_v4().to_string();
+    let (queue, deliveries) = tokio::sync::mpsc::channel(state.webhook_config.queue_capacity);
+    let webhook = Webhook {
+        id: id.clone(),
+        url: req.url,
+        sensors,
+        on_quality,
+        created_at: state.clock.now().to_rfc3339(),
+        delivered: 0,
+        failed: 0,
+        dropped: 0,
+        queue,
+        last_quality: HashMap::new(),
+    };
+    state.webhooks.lock().unwrap().insert(id.clone(), webhook.clone());
+    tokio::spawn(run_webhook_worker(state.clone(), id, webhook.url.clone(), deliveries));
+    (
+        axum::http::StatusCode::CREATED,
+        Json(serde_json::json!({
+            "status": "ok",
+            "webhook": webhook
+        })),
+    ).into_response()
+}
 
+async fn list_webhooks(State(state): State<SharedState>) -> Response {
+    let webhooks: Vec<_> = state.webhooks.lock().unwrap().values().cloned().collect();
     Json(serde_json::json!({
         "status": "ok",
-        "endpoints": endpoints
+        "webhooks": webhooks
     })).into_response()
 }
 
-#[axum::debug_handler]
-async fn get_sensor_data(
-    Path(key): Path<String>,
+async fn delete_webhook(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
 ) -> Response {
-    // Simulation logic (slow response & error simulation)
-    let (delay, is_error) = {
-        let mut rng = rand::thread_rng();
-        let delay = if rng.gen_bool(0.1) { rng.gen_range(200..800) } else { rng.gen_range(5..50) };
-        let is_error = rng.gen_bool(0.05);
-        (delay, is_error)
-    };
-    tokio::time::sleep(Duration::from_millis(delay)).await;
+    match state.webhooks.lock().unwrap().remove(&id) {
+        Some(_) => Json(serde_json::json!({
+            "status": "ok",
+            "deleted": id
+        })).into_response(),
+        None => webhook_not_found(),
+    }
+}
+
+/// Holds one of the MAX_WS_CONNECTIONS slots until the socket (or a failed upgrade) is dropped
+struct WsConnectionSlot(SharedState);
+
+impl WsConnectionSlot {
+    fn acquire(state: &SharedState) -> Option<Self> {
+        let mut open = state.ws_connections.lock().unwrap();
+        if *open >= state.max_ws_connections {
+            return None;
+        }
+        *open += 1;
+        Some(WsConnectionSlot(state.clone()))
+    }
+}
 
-    if is_error {
+impl Drop for WsConnectionSlot {
+    fn drop(&mut self) {
+        *self.0.ws_connections.lock().unwrap() -= 1;
+    }
+}
+
+async fn ws_handler(
+    ws: WebSocketUpgrade,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<SharedState>,
+) -> Response {
+    let encoding = match params.get("encoding").map(String::as_str) {
+        None | Some("json") => WsEncoding::Json,
+        Some("msgpack") => WsEncoding::Msgpack,
+        Some(other) => {
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "status": "error",
+                    "error": format!("Unsupported encoding '{}' (expected json or msgpack)", other)
+                })),
+            ).into_response();
+        }
+    };
+    let Some(slot) = WsConnectionSlot::acquire(&state) else {
         return (
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            axum::http::StatusCode::SERVICE_UNAVAILABLE,
             Json(serde_json::json!({
                 "status": "error",
-                "error": "Sensor temporarily unavailable",
-                "timestamp": Utc::now().to_rfc3339()
+                "error": format!("Too many WebSocket connections (limit {})", state.max_ws_connections)
             })),
         ).into_response();
+    };
+    ws.on_upgrade(move |socket| async move {
+        handle_socket(socket, state, encoding).await;
+        drop(slot);
+    })
+}
+
+async fn handle_socket(mut socket: WebSocket, state: SharedState, mut encoding: WsEncoding) {
+    // Each subscribed sensor fires on its own schedule: (period, next fire time)
+    let mut schedule: HashMap<String, (Duration, tokio::time::Instant)> = HashMap::new();
+    // Sensors with their own interval, which the global interval doesn't override
+    let mut pinned: HashSet<String> = HashSet::new();
+    let mut interval_ms = 1000;
+    let mut pacer = BandwidthPacer::new(state.bandwidth_bytes_per_sec);
+    let mut filter = ExceptionFilter::default();
+    // Heartbeat (period, next fire time) and the last sequence number sent
+    let mut heartbeat: Option<(Duration, tokio::time::Instant)> = None;
+    let mut heartbeat_seq: u64 = 0;
+    let _open = OpenStream::new(&state);
+    let mut shutdown = state.shutdown.subscribe();
+    
+    // Welcome message
+    let welcome = WSMessage::Welcome {
+        available_sensors: sensor_keys(&state).map(str::to_string).collect(),
+        message: "Connected to Simmurator WebSocket. Send subscribe action to start.".to_string(),
+        encoding,
+    };
+    let _ = send_ws(&mut socket, &mut pacer, encoding, &welcome).await;
+
+    loop {
+        let next_due = schedule.values().map(|&(_, next)| next).min();
+
+        tokio::select! {
+            // Check for client messages
+            msg = socket.next() => {
+                let msg = match msg {
+                    Some(Ok(msg)) => msg,
+                    _ => break, // client disconnected
+                };
+
+                // Actions arrive as JSON text or, from MessagePack clients, binary frames
+                let action = match msg {
+                    Message::Text(text) => serde_json::from_str::<WSAction>(&text).map_err(|e| ws_action_error(&text, &e)),
+                    Message::Binary(bytes) => rmp_serde::from_slice::<WSAction>(&bytes).map_err(|e| format!("Invalid action message: {}", e)),
+                    _ => continue,
+                };
+                match action {
+                    Ok(action) => match action {
+                        WSAction::Subscribe { sensors, interval, intervals, replay, heartbeat: heartbeat_ms } => {
+                            let intervals = intervals.unwrap_or_default();
+                            let requested = sensors.unwrap_or_else(|| {
+                                if intervals.is_empty() {
+                                    sensor_keys(&state).map(str::to_string).collect()
+                                } else {
+                                    intervals.keys().cloned().collect()
+                                }
+                            });
+                            let mut unknown = Vec::new();
+
+                            let added: HashSet<&String> = requested
+                                .iter()
+                                .chain(intervals.keys())
+                                .filter(|s| is_sensor_key(&state, s) && !schedule.contains_key(*s))
+                                .collect();
+                            if schedule.len() + added.len() > state.max_ws_sensors {
+                                let resp = WSMessage::Error {
+                                    message: format!(
+                                        "Subscription rejected: {} sensor(s) already subscribed, {} more requested, limit is {}",
+                                        schedule.len(),
+                                        added.len(),
+                                        state.max_ws_sensors
+                                    ),
+                                };
+                                let _ = send_ws(&mut socket, &mut pacer, encoding, &resp).await;
+                                continue;
+                            }
+
+                            if let Some(i) = interval {
+                                interval_ms = i.clamp(100, 60000);
+                                let period = Duration::from_millis(interval_ms);
+                                for (sensor, entry) in schedule.iter_mut() {
+                                    if !pinned.contains(sensor) {
+                                        entry.0 = period;
+                                    }
+                                }
+                            }
+
+                            let now = tokio::time::Instant::now();
+                            match heartbeat_ms {
+                                Some(0) => heartbeat = None,
+                                Some(ms) => {
+                                    let period = Duration::from_millis(ms.clamp(100, 60000));
+                                    heartbeat = Some((period, now + period));
+                                }
+                                None => {}
+                            }
+                            let mut subscribed: Vec<String> = Vec::new();
+                            for s in requested.into_iter().chain(intervals.keys().cloned()) {
+                                if !is_sensor_key(&state, &s) {
+                                    if !unknown.contains(&s) {
+                                        unknown.push(s);
+                                    }
+                                    continue;
+                                }
+                                if !subscribed.contains(&s) {
+                                    subscribed.push(s.clone());
+                                }
+                                let period_ms = match intervals.get(&s) {
+                                    Some(&ms) => {
+                                        pinned.insert(s.clone());
+                                        ms.clamp(100, 60000)
+                                    }
+                                    None if pinned.contains(&s) => continue,
+                                    None => interval_ms,
+                                };
+                                let period = Duration::from_millis(period_ms);
+                                schedule.entry(s).and_modify(|e| e.0 = period).or_insert((period, now));
+                            }
+
+                            let resp = WSMessage::Subscribed {
+                                sensors: schedule.keys().cloned().collect(),
+                                interval: interval_ms,
+                                intervals: schedule.iter().map(|(s, &(period, _))| (s.clone(), period.as_millis() as u64)).collect(),
+                                unknown: if unknown.is_empty() { None } else { Some(unknown) },
+                                heartbeat: heartbeat.map(|(period, _)| period.as_millis() as u64),
+                            };
+                            let _ = send_ws(&mut socket, &mut pacer, encoding, &resp).await;
+
+                            // Backfill from history, oldest first, so a reconnecting chart fills at once
+                            let replay = replay.unwrap_or_default();
+                            for sensor in subscribed.iter().filter(|_| replay > 0) {
+                                for msg in replay_frames(&state, sensor, replay) {
+                                    if send_ws(&mut socket, &mut pacer, encoding, &msg).await.is_err() {
+                                        return; // connection closed
+                                    }
+                                }
+                            }
+                        }
+                        WSAction::Unsubscribe { sensors } => {
+                            let targets = sensors.unwrap_or_else(|| schedule.keys().cloned().collect());
+                            for s in &targets {
+                                schedule.remove(s);
+                                pinned.remove(s);
+                            }
+                            let resp = WSMessage::Unsubscribed {
+                                sensors: targets,
+                                remaining: schedule.keys().cloned().collect(),
+                            };
+                            let _ = send_ws(&mut socket, &mut pacer, encoding, &resp).await;
+                        }
+                        WSAction::List => {
+                            let resp = WSMessage::SensorsList {
+                                sensors: sensor_keys(&state).map(str::to_string).collect(),
+                            };
+                            let _ = send_ws(&mut socket, &mut pacer, encoding, &resp).await;
+                        }
+                        WSAction::Ping => {
+                            let resp = WSMessage::Pong { timestamp: state.clock.now().to_rfc3339() };
+                            let _ = send_ws(&mut socket, &mut pacer, encoding, &resp).await;
+                        }
+                        WSAction::SetEncoding { encoding: requested } => {
+                            encoding = requested;
+                            let resp = WSMessage::EncodingSet { encoding };
+                            let _ = send_ws(&mut socket, &mut pacer, encoding, &resp).await;
+                        }
+                    },
+                    Err(message) => {
+                        let resp = WSMessage::Error { message };
+                        let _ = send_ws(&mut socket, &mut pacer, encoding, &resp).await;
+                    }
+                }
+            }
+            // Server going down: say so, then close with 1001 (going away)
+            Ok(()) = async { shutdown.wait_for(|&stopping| stopping).await.map(drop) } => {
+                let msg = WSMessage::Shutdown {
+                    message: "Server is shutting down; reconnect shortly".to_string(),
+                    timestamp: state.clock.now().to_rfc3339(),
+                };
+                let _ = send_ws(&mut socket, &mut pacer, encoding, &msg).await;
+                let close = axum::extract::ws::CloseFrame {
+                    code: axum::extract::ws::close_code::AWAY,
+                    reason: "server shutting down".into(),
+                };
+                let _ = socket.send(Message::Close(Some(close))).await;
+                return;
+            }
+            // Liveness frame, independent of data
+            _ = tokio::time::sleep_until(heartbeat.map_or_else(tokio::time::Instant::now, |(_, next)| next)), if heartbeat.is_some() => {
+                if let Some((period, next)) = heartbeat.as_mut() {
+                    *next = tokio::time::Instant::now() + *period;
+                }
+                heartbeat_seq += 1;
+                let msg = WSMessage::Heartbeat { seq: heartbeat_seq, timestamp: state.clock.now().to_rfc3339() };
+                if send_ws(&mut socket, &mut pacer, encoding, &msg).await.is_err() {
+                    return; // connection closed
+                }
+            }
+            // Send data for every sensor that's due
+            _ = tokio::time::sleep_until(next_due.unwrap_or_else(tokio::time::Instant::now)), if next_due.is_some() => {
+                let now = tokio::time::Instant::now();
+                let mut due: Vec<String> = Vec::new();
+                for (sensor, (period, next)) in schedule.iter_mut() {
+                    if *next <= now {
+                        due.push(sensor.clone());
+                        *next += *period;
+                        // Skip missed slots rather than bursting to catch up
+                        if *next <= now {
+                            *next = now + *period;
+                        }
+                    }
+                }
+                due.sort();
+                for sensor in &due {
+                    if let Some(data) = generate_sensor_data(&state, sensor).filter(|data| filter.should_report(&state, sensor, data)) {
+                        let msg = WSMessage::Data {
+                            sensor: sensor.clone(),
+                            data,
+                            timestamp: state.clock.now().to_rfc3339(),
+                            sequence: next_stream_sequence(&state),
+                            replay: false,
+                        };
+                        for msg in with_duplicates(&state, msg) {
+                            if send_ws(&mut socket, &mut pacer, encoding, &msg).await.is_err() {
+                                return; // connection closed
+                            }
+                        }
+                    }
+                }
+            }
+        }
     }
+}
 
-    if let Some(data) = generate_sensor_data(&key) {
-        Json(serde_json::json!({
-            "status": "ok",
-            "timestamp": Utc::now().to_rfc3339(),
-            "data": data
-        })).into_response()
-    } else {
-        (
-            axum::http::StatusCode::NOT_FOUND,
-            Json(serde_json::json!({
-                "status": "error",
-                "error": "Sensor not found"
-            })),
-        ).into_response()
+// ──────────────────────────────────────────────
+// Graceful Shutdown
+// ──────────────────────────────────────────────
+
+/// Counts a WebSocket or streaming HTTP connection as open while alive, so
+/// shutdown knows what it is waiting for
+struct OpenStream(SharedState);
+
+impl OpenStream {
+    fn new(state: &SharedState) -> Self {
+        *state.open_streams.lock().unwrap() += 1;
+        OpenStream(state.clone())
     }
 }
 
-async fn get_all_sensors() -> Response {
-    let mut all = HashMap::new();
-    for &key in AVAILABLE_SENSORS {
-        if let Some(data) = generate_sensor_data(key) {
-            all.insert(key, data);
+impl Drop for OpenStream {
+    fn drop(&mut self) {
+        *self.0.open_streams.lock().unwrap() -= 1;
+        if *self.0.shutdown.borrow() {
+            *self.0.drained_streams.lock().unwrap() += 1;
         }
     }
+}
 
-    Json(serde_json::json!({
-        "status": "ok",
-        "timestamp": Utc::now().to_rfc3339(),
-        "data": all
-    })).into_response()
+/// Resolves once shutdown has begun
+fn shutdown_signaled(state: &AppState) -> impl std::future::Future<Output = ()> {
+    let mut rx = state.shutdown.subscribe();
+    async move {
+        let _ = rx.wait_for(|&stopping| stopping).await;
+    }
+}
+
+/// End an SSE stream when shutdown begins, with a final `shutdown` event so
+/// clients know to reconnect rather than treat it as a network error
+fn until_shutdown<S>(state: &SharedState, stream: S) -> impl tokio_stream::Stream<Item = (Event, usize)>
+where
+    S: tokio_stream::Stream<Item = (Event, usize)>,
+{
+    let open = OpenStream::new(state);
+    let notice = futures_util::stream::once(async move {
+        // Only when shutdown ended the stream, not when it ran out on its own
+        let stopping = *open.0.shutdown.borrow();
+        stopping.then(|| sse_frame(&SSEEvent::Shutdown { message: "Server is shutting down".to_string() }))
+    })
+    .filter_map(futures_util::future::ready);
+    stream.take_until(shutdown_signaled(state)).chain(notice)
+}
+
+/// Wait for SIGINT/SIGTERM, then tell every stream to wind down. Streams get
+/// `grace` to finish before the process exits regardless.
+async fn shutdown_signal(state: SharedState, grace: Duration) {
+    let interrupt = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    let signal = tokio::select! {
+        _ = interrupt => "SIGINT",
+        _ = terminate => "SIGTERM",
+    };
+    let open = *state.open_streams.lock().unwrap();
+    tracing::info!(signal, open_streams = open, grace_secs = grace.as_secs_f64(), "shutting down, draining connections");
+    state.shutdown.send_replace(true);
+
+    tokio::spawn(async move {
+        tokio::time::sleep(grace).await;
+        let remaining = *state.open_streams.lock().unwrap();
+        let drained = *state.drained_streams.lock().unwrap();
+        tracing::warn!(drained, timed_out = remaining, "grace period elapsed, exiting with connections still open");
+        std::process::exit(0);
+    });
+}
+
+/// After the listener has stopped: wait for upgraded WebSockets, which the
+/// server no longer tracks, to finish closing
+async fn drain_streams(state: &AppState) {
+    while *state.open_streams.lock().unwrap() > 0 {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+// ──────────────────────────────────────────────
+// MQTT Publisher (Sparkplug B topics)
+// ──────────────────────────────────────────────
+
+/// Sparkplug B topic of a reading: `spBv1.0/{group}/{type}/{edge}/{device}`
+fn sparkplug_topic(data: &serde_json::Value, message_type: &str) -> String {
+    let t = &data["sparkplugTopic"];
+    format!(
+        "{}/{}/{}/{}/{}",
+        t["version"].as_str().unwrap_or("spBv1.0"),
+        t["groupId"].as_str().unwrap_or_default(),
+        message_type,
+        t["edgeNodeId"].as_str().unwrap_or_default(),
+        t["deviceId"].as_str().unwrap_or_default(),
+    )
+}
+
+struct MqttConfig {
+    options: rumqttc::MqttOptions,
+    sensors: Vec<String>,
+    interval: Duration,
+    /// `MQTT_PAYLOAD=sparkplug` publishes Tahu protobuf instead of JSON
+    sparkplug: bool,
+}
+
+impl MqttConfig {
+    /// `None` unless `MQTT_BROKER_URL` is set. `MQTT_SENSORS` restricts the
+    /// published sensors (built-in, configured or `amr/<station>`);
+    /// `MQTT_INTERVAL_MS` sets the publish period and `MQTT_PAYLOAD` (`json`
+    /// or `sparkplug`) the encoding.
+    fn from_env(configs: &[sensor_config::SensorConfig], stations: &[OilStation]) -> Result<Option<Self>, String> {
+        let Ok(url) = std::env::var("MQTT_BROKER_URL") else {
+            return Ok(None);
+        };
+        // rumqttc takes the client id from the URL
+        let url = if url.contains("client_id=") {
+            url
+        } else {
+            let separator = if url.contains('?') { '&' } else { '?' };
+            format!("{}{}client_id=simmurator-{}", url, separator, &uuid::Uuid::new_v4().simple().to_string()[..8])
+        };
+        let mut options = rumqttc::MqttOptions::parse_url(url).map_err(|e| format!("MQTT_BROKER_URL: {}", e))?;
+        options.set_keep_alive(Duration::from_secs(30));
+
+        let configured = configs.iter().map(|c| c.key.as_str());
+        let sensors = match std::env::var("MQTT_SENSORS") {
+            Err(_) => AVAILABLE_SENSORS.iter().copied().chain(configured).map(str::to_string).collect(),
+            Ok(list) => list
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(|s| {
+                    let station = s.strip_prefix(AMR_STATION_PREFIX).is_some_and(|id| stations.iter().any(|st| st.id() == id));
+                    if AVAILABLE_SENSORS.iter().copied().chain(configured.clone()).any(|k| k == s) || station {
+                        Ok(s.to_string())
+                    } else {
+                        Err(format!("MQTT_SENSORS: unknown sensor '{}'", s))
+                    }
+                })
+                .collect::<Result<_, _>>()?,
+        };
+
+        let sparkplug = match std::env::var("MQTT_PAYLOAD").as_deref() {
+            Err(_) | Ok("json") => false,
+            Ok("sparkplug") => true,
+            Ok(other) => return Err(format!("MQTT_PAYLOAD: unsupported payload '{}'", other)),
+        };
+
+        Ok(Some(Self {
+            options,
+            sensors,
+            interval: Duration::from_millis(env_or("MQTT_INTERVAL_MS", 1000u64).max(100)),
+            sparkplug,
+        }))
+    }
+}
+
+/// Publish every configured sensor's reading as DDATA each interval. The
+/// broker connection is kept up by polling the event loop, which reconnects
+/// after errors; publishing never blocks on a dead connection.
+async fn run_mqtt_publisher(state: SharedState, config: MqttConfig) {
+    let (client, mut eventloop) = rumqttc::AsyncClient::new(config.options, 100);
+    tokio::spawn(async move {
+        loop {
+            match eventloop.poll().await {
+                Ok(rumqttc::Event::Incoming(rumqttc::Packet::ConnAck(_))) => tracing::info!("MQTT connected"),
+                Ok(_) => {}
+                Err(e) => {
+                    tracing::warn!(error = %e, "MQTT connection error, retrying in 5s");
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                }
+            }
+        }
+    });
+
+    let mut ticker = tokio::time::interval(config.interval);
+    // Only log the first failure of a run, not one per reading
+    let mut failing = false;
+    loop {
+        ticker.tick().await;
+        for key in &config.sensors {
+            let Some(data) = generate_sensor_data(&state, key) else {
+                continue;
+            };
+            let payload = if config.sparkplug {
+                sparkplug::sparkplug_payload(&data, next_sparkplug_seq(&state), state.clock.now().timestamp_millis() as u64)
+            } else {
+                data.to_string().into_bytes()
+            };
+            match client.try_publish(sparkplug_topic(&data, "DDATA"), rumqttc::QoS::AtMostOnce, false, payload) {
+                Ok(()) => failing = false,
+                Err(e) => {
+                    if !failing {
+                        tracing::warn!(error = %e, "MQTT publish failed");
+                    }
+                    failing = true;
+                }
+            }
+        }
+    }
+}
+
+// ──────────────────────────────────────────────
+// Webhooks
+// ──────────────────────────────────────────────
+
+/// Quality names a webhook can filter on (`DataQuality` as serialized)
+const WEBHOOK_QUALITIES: &[&str] = &["good", "goodUncertain", "uncertain", "bad"];
+
+/// A registered receiver of the readings where a sensor's quality changes to
+/// one of `on_quality`
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Webhook {
+    id: String,
+    url: String,
+    /// Empty means every sensor
+    sensors: Vec<String>,
+    on_quality: Vec<String>,
+    created_at: String,
+    delivered: u64,
+    failed: u64,
+    /// Readings dropped because the delivery queue was full
+    dropped: u64,
+    /// This webhook's own delivery queue, so a hanging receiver only backs up itself
+    #[serde(skip)]
+    queue: tokio::sync::mpsc::Sender<WebhookDelivery>,
+    /// Quality of the last reading seen from each sensor
+    #[serde(skip)]
+    last_quality: HashMap<String, String>,
+}
+
+impl Webhook {
+    /// Whether this reading moves the sensor into one of `on_quality`. A
+    /// sensor's first reading counts as a change; staying put doesn't.
+    fn transitioned(&mut self, key: &str, data: &serde_json::Value) -> bool {
+        if !(self.sensors.is_empty() || self.sensors.iter().any(|s| s == key)) {
+            return false;
+        }
+        let Some(quality) = data["dataQuality"].as_str() else {
+            return false;
+        };
+        let previous = self.last_quality.insert(key.to_string(), quality.to_string());
+        previous.as_deref() != Some(quality) && self.on_quality.iter().any(|f| f == quality)
+    }
 }
 
-async fn get_access_log(
-    Query(params): Query<HashMap<String, String>>,
-    State(state): State<SharedState>,
-) -> Response {
-    let limit = params.get("limit")
-        .and_then(|l| l.parse::<usize>().ok())
-        .unwrap_or(50);
+#[derive(Deserialize)]
+struct CreateWebhookRequest {
+    url: String,
+    sensors: Option<Vec<String>>,
+    #[serde(alias = "onQuality")]
+    on_quality: Option<Vec<String>>,
+}
 
-    let logs = state.access_log.lock().unwrap();
-    let entries: Vec<_> = logs.iter().take(limit).cloned().collect();
-    let total = *state.request_counter.lock().unwrap();
+/// One reading waiting to be POSTed
+#[derive(Debug)]
+struct WebhookDelivery {
+    sensor: String,
+    body: serde_json::Value,
+}
 
-    Json(serde_json::json!({
-        "status": "ok",
-        "total": total,
-        "entries": entries
-    })).into_response()
+/// Timeout and retry policy for deliveries (`WEBHOOK_TIMEOUT_MS`,
+/// `WEBHOOK_MAX_RETRIES`); each webhook's queue holds
+/// `WEBHOOK_QUEUE_CAPACITY` readings
+#[derive(Clone, Copy, Debug)]
+struct WebhookConfig {
+    timeout: Duration,
+    max_retries: u32,
+    queue_capacity: usize,
 }
 
-async fn get_stats(State(state): State<SharedState>) -> Response {
-    let logs = state.access_log.lock().unwrap();
-    let total_requests = *state.request_counter.lock().unwrap();
-    
-    let mut per_endpoint: HashMap<String, serde_json::Value> = HashMap::new();
-    
-    for entry in logs.iter() {
-        let ep = entry.endpoint.clone();
-        let stats = per_endpoint.entry(ep).or_insert(serde_json::json!({
-            "count": 0,
-            "totalTime": 0,
-            "errors": 0
-        }));
-        
-        let count = stats["count"].as_u64().unwrap_or(0) + 1;
-        let total_time = stats["totalTime"].as_u64().unwrap_or(0) + entry.response_time as u64;
-        let mut errors = stats["errors"].as_u64().unwrap_or(0);
-        if entry.status_code >= 400 {
-            errors += 1;
-        }
-        
-        *stats = serde_json::json!({
-            "count": count,
-            "totalTime": total_time,
-            "errors": errors,
-            "avgResponseTime": if count > 0 { total_time / count } else { 0 }
-        });
+impl WebhookConfig {
+    fn from_env() -> Self {
+        Self {
+            timeout: Duration::from_millis(env_or("WEBHOOK_TIMEOUT_MS", 5000u64).max(100)),
+            max_retries: env_or("WEBHOOK_MAX_RETRIES", 3u32),
+            queue_capacity: env_or("WEBHOOK_QUEUE_CAPACITY", 256usize).max(1),
+        }
     }
-
-    Json(serde_json::json!({
-        "status": "ok",
-        "totalRequests": total_requests,
-        "activeConnections": state.sse_tx.receiver_count(),
-        "endpointStats": per_endpoint
-    })).into_response()
 }
 
-async fn sse_handler(State(state): State<SharedState>) -> Sse<impl tokio_stream::Stream<Item = Result<Event, Infallible>>> {
-    let rx = state.sse_tx.subscribe();
-    
-    // Initial welcome message
-    let initial_stream = tokio_stream::once(Ok(Event::default().data(serde_json::to_string(&SSEEvent::Connected {
-        message: "SSE stream connected".to_string(),
-    }).unwrap())));
+/// Queue a reading for every webhook whose sensor it moves into a watched
+/// quality. Never waits: when a webhook's queue is full the reading is
+/// dropped and counted against it, so a hanging receiver can't slow sensor
+/// generation down.
+fn notify_webhooks(state: &AppState, key: &str, data: &serde_json::Value) {
+    let mut webhooks = state.webhooks.lock().unwrap();
+    for webhook in webhooks.values_mut() {
+        if !webhook.transitioned(key, data) {
+            continue;
+        }
+        let delivery = WebhookDelivery { sensor: key.to_string(), body: data.clone() };
+        if webhook.queue.try_send(delivery).is_err() {
+            webhook.dropped += 1;
+        }
+    }
+}
 
-    let broadcast_stream = BroadcastStream::new(rx).filter_map(|msg| async move {
-        match msg {
-            Ok(event) => Some(Ok(Event::default().data(serde_json::to_string(&event).unwrap()))),
-            _ => None,
+/// POST one reading, retrying with exponential backoff (500 ms, 1 s, 2 s, ...)
+/// on connection errors and non-2xx responses
+async fn deliver_webhook(client: &reqwest::Client, config: WebhookConfig, id: &str, url: &str, delivery: &WebhookDelivery) -> bool {
+    let body = delivery.body.to_string();
+    for attempt in 0..=config.max_retries {
+        if attempt > 0 {
+            tokio::time::sleep(Duration::from_millis(500 << (attempt - 1).min(6))).await;
         }
-    });
+        let sent = client
+            .post(url)
+            .timeout(config.timeout)
+            .header(axum::http::header::CONTENT_TYPE, "application/json")
+            .header("X-Simmurator-Webhook", id)
+            .header("X-Simmurator-Sensor", &delivery.sensor)
+            .body(body.clone())
+            .send()
+            .await;
+        if sent.is_ok_and(|r| r.status().is_success()) {
+            return true;
+        }
+    }
+    false
+}
 
-    Sse::new(initial_stream.chain(broadcast_stream))
-        .keep_alive(axum::response::sse::KeepAlive::new().interval(Duration::from_secs(15)))
+/// Deliver one webhook's queue in order. Ends once the webhook is deleted,
+/// dropping whatever it still had queued.
+async fn run_webhook_worker(state: SharedState, id: String, url: String, mut queue: tokio::sync::mpsc::Receiver<WebhookDelivery>) {
+    let client = reqwest::Client::new();
+    while let Some(delivery) = queue.recv().await {
+        if !state.webhooks.lock().unwrap().contains_key(&id) {
+            return;
+        }
+        let ok = deliver_webhook(&client, state.webhook_config, &id, &url, &delivery).await;
+        if !ok {
+            tracing::warn!(webhook = %id, url = %url, sensor = %delivery.sensor, "webhook delivery failed");
+        }
+        // The webhook may have been deleted while this was in flight
+        let mut webhooks = state.webhooks.lock().unwrap();
+        let Some(webhook) = webhooks.get_mut(&id) else {
+            return;
+        };
+        if ok {
+            webhook.delivered += 1;
+        } else {
+            webhook.failed += 1;
+        }
+    }
 }
 
-async fn ws_handler(
-    ws: WebSocketUpgrade,
-    State(state): State<SharedState>,
-) -> impl IntoResponse {
-    ws.on_upgrade(|socket| handle_socket(socket, state))
+// ──────────────────────────────────────────────
+// Middleware: Request tracing
+// ──────────────────────────────────────────────
+
+/// Span around each request; status and latency are recorded once the
+/// response is ready
+fn request_span(req: &axum::http::Request<axum::body::Body>) -> tracing::Span {
+    tracing::info_span!(
+        "request",
+        method = %req.method(),
+        path = %req.uri().path(),
+        status = tracing::field::Empty,
+        latency_ms = tracing::field::Empty,
+    )
 }
 
-async fn handle_socket(mut socket: WebSocket, _state: SharedState) {
-    let mut subscriptions = HashSet::new();
-    let mut interval_ms = 1000;
-    
-    // Welcome message
-    let welcome = WSMessage::Welcome {
-        available_sensors: AVAILABLE_SENSORS.iter().map(|&s| s.to_string()).collect(),
-        message: "Connected to Simmurator WebSocket. Send subscribe action to start.".to_string(),
-    };
-    let _ = socket.send(Message::Text(serde_json::to_string(&welcome).unwrap())).await;
+fn log_response(res: &Response, latency: Duration, span: &tracing::Span) {
+    span.record("status", res.status().as_u16());
+    span.record("latency_ms", latency.as_millis() as u64);
+    if res.status().is_server_error() {
+        tracing::warn!("request failed");
+    } else {
+        tracing::info!("request completed");
+    }
+}
 
-    let mut send_interval = tokio::time::interval(Duration::from_millis(interval_ms));
+// ──────────────────────────────────────────────
+// Middleware: Log access
+// ──────────────────────────────────────────────
 
-    loop {
-        tokio::select! {
-            // Check for client messages
-            msg = socket.next() => {
-                let msg = match msg {
-                    Some(Ok(msg)) => msg,
-                    _ => break, // client disconnected
-                };
+/// Entries kept in the access log before the oldest are evicted
+const ACCESS_LOG_CAPACITY: usize = 500;
+/// Response times kept per route for the latency percentiles
+const LATENCY_RESERVOIR_SIZE: usize = 1024;
 
-                if let Message::Text(text) = msg {
-                    if let Ok(action) = serde_json::from_str::<WSAction>(&text) {
-                        match action {
-                            WSAction::Subscribe { sensors, interval } => {
-                                let requested = sensors.unwrap_or_else(|| AVAILABLE_SENSORS.iter().map(|&s| s.to_string()).collect());
-                                let mut valid = Vec::new();
-                                let mut unknown = Vec::new();
-                                
-                                for s in requested {
-                                    if AVAILABLE_SENSORS.contains(&s.as_str()) {
-                                        subscriptions.insert(s.clone());
-                                        valid.push(s);
-                                    } else {
-                                        unknown.push(s);
-                                    }
-                                }
-                                
-                                if let Some(i) = interval {
-                                    interval_ms = i.clamp(100, 60000);
-                                    send_interval = tokio::time::interval(Duration::from_millis(interval_ms));
-                                }
+/// Uniform sample of a route's response times (Vitter's Algorithm R):
+/// the first `LATENCY_RESERVOIR_SIZE` are kept, after which the n-th
+/// response replaces a random slot with probability size/n. Memory stays
+/// fixed however much traffic the route sees, and the sample covers its
+/// whole lifetime rather than just the access-log window. The running total
+/// gives the exact mean over that same lifetime.
+#[derive(Default)]
+struct LatencyReservoir {
+    samples_us: Vec<u64>,
+    seen: u64,
+    total_us: u64,
+}
 
-                                let resp = WSMessage::Subscribed {
-                                    sensors: subscriptions.iter().cloned().collect(),
-                                    interval: interval_ms,
-                                    unknown: if unknown.is_empty() { None } else { Some(unknown) },
-                                };
-                                let _ = socket.send(Message::Text(serde_json::to_string(&resp).unwrap())).await;
-                            }
-                            WSAction::Unsubscribe { sensors } => {
-                                let targets = sensors.unwrap_or_else(|| subscriptions.iter().cloned().collect());
-                                for s in &targets {
-                                    subscriptions.remove(s);
-                                }
-                                let resp = WSMessage::Unsubscribed {
-                                    sensors: targets,
-                                    remaining: subscriptions.iter().cloned().collect(),
-                                };
-                                let _ = socket.send(Message::Text(serde_json::to_string(&resp).unwrap())).await;
-                            }
-                            WSAction::List => {
-                                let resp = WSMessage::SensorsList {
-                                    sensors: AVAILABLE_SENSORS.iter().map(|&s| s.to_string()).collect(),
-                                };
-                                let _ = socket.send(Message::Text(serde_json::to_string(&resp).unwrap())).await;
-                            }
-                            WSAction::Ping => {
-                                let resp = WSMessage::Pong { timestamp: Utc::now().to_rfc3339() };
-                                let _ = socket.send(Message::Text(serde_json::to_string(&resp).unwrap())).await;
-                            }
-                        }
-                    }
-                }
-            }
-            // Send periodic sensor data
-            _ = send_interval.tick() => {
-                if !subscriptions.is_empty() {
-                    for sensor in &subscriptions {
-                        if let Some(data) = generate_sensor_data(sensor) {
-                            let msg = WSMessage::Data {
-                                sensor: sensor.clone(),
-                                data,
-                                timestamp: Utc::now().to_rfc3339(),
-                            };
-                            if let Err(_) = socket.send(Message::Text(serde_json::to_string(&msg).unwrap())).await {
-                                return; // connection closed
-                            }
-                        }
-                    }
-                }
+impl LatencyReservoir {
+    fn record(&mut self, micros: u64, rng: &mut impl Rng) {
+        self.seen += 1;
+        self.total_us += micros;
+        if self.samples_us.len() < LATENCY_RESERVOIR_SIZE {
+            self.samples_us.push(micros);
+        } else {
+            let slot = rng.gen_range(0..self.seen);
+            if let Some(sample) = self.samples_us.get_mut(slot as usize) {
+                *sample = micros;
             }
         }
     }
+
+    /// Mean response time in milliseconds, `None` before any sample
+    fn mean_ms(&self) -> Option<f64> {
+        let mean = self.total_us as f64 / self.seen as f64 / 1000.0;
+        (self.seen > 0).then(|| format!("{:.2}", mean).parse::<f64>().unwrap())
+    }
+
+    /// Nearest-rank percentiles in milliseconds, `None` before any sample
+    fn percentiles_ms(&self, ranks: &[f64]) -> Option<Vec<f64>> {
+        if self.samples_us.is_empty() {
+            return None;
+        }
+        let mut sorted = self.samples_us.clone();
+        sorted.sort_unstable();
+        Some(
+            ranks
+                .iter()
+                .map(|p| {
+                    let rank = ((p / 100.0) * sorted.len() as f64).ceil().max(1.0) as usize;
+                    let ms = sorted[rank.min(sorted.len()) - 1] as f64 / 1000.0;
+                    format!("{:.2}", ms).parse::<f64>().unwrap()
+                })
+                .collect(),
+        )
+    }
 }
 
-// ──────────────────────────────────────────────
-// Middleware: Log access
-// ──────────────────────────────────────────────
+/// Parse `TRUSTED_PROXIES`: comma-separated IP addresses of reverse proxies
+/// whose X-Forwarded-For is believed
+fn parse_trusted_proxies(spec: &str) -> Result<Vec<std::net::IpAddr>, String> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(|p| p.parse().map_err(|_| format!("'{}' is not an IP address", p)))
+        .collect()
+}
+
+/// The socket IP, unless it's a trusted proxy: then the nearest X-Forwarded-For
+/// hop that isn't one. Anyone else's X-Forwarded-For is ignored, since a
+/// client can put whatever it likes there.
+fn client_ip(headers: &axum::http::HeaderMap, addr: SocketAddr, trusted: &[std::net::IpAddr]) -> String {
+    if !trusted.contains(&addr.ip()) {
+        return addr.ip().to_string();
+    }
+    let forwarded = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(','))
+        .map(str::trim)
+        .collect::<Vec<_>>();
+    forwarded
+        .into_iter()
+        .rev()
+        .map_while(|hop| hop.parse::<std::net::IpAddr>().ok())
+        .find(|ip| !trusted.contains(ip))
+        .unwrap_or(addr.ip())
+        .to_string()
+}
 
 async fn log_middleware(
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
@@ -1172,12 +9328,9 @@ async fn log_middleware(
     let start = std::time::Instant::now();
     let method = req.method().to_string();
     let endpoint = req.uri().to_string();
-    // Prefer X-Forwarded-For (set by reverse proxy), fall back to real socket IP
-    let ip = req.headers().get("x-forwarded-for")
-        .and_then(|h| h.to_str().ok())
-        .and_then(|s| s.split(',').next())
-        .map(|s| s.trim().to_string())
-        .unwrap_or_else(|| addr.ip().to_string());
+    // The route matched (`/api/v1/sensors/:key`), or `*` for static files and unknown paths
+    let route = req.extensions().get::<axum::extract::MatchedPath>().map_or("*", |p| p.as_str()).to_string();
+    let ip = client_ip(req.headers(), addr, &state.trusted_proxies);
     let user_agent = req.headers().get("user-agent")
         .and_then(|h| h.to_str().ok())
         .unwrap_or("unknown")
@@ -1189,87 +9342,725 @@ async fn log_middleware(
     let response = next.run(req).await;
     
     let status_code = response.status().as_u16();
-    let response_time = start.elapsed().as_millis();
+    let elapsed = start.elapsed();
+    let api_key_id = response.extensions().get::<ApiKeyId>().map(|k| k.0.clone());
 
     // Skip noisy internal/polling endpoints from the access log
     let skip = endpoint.starts_with("/api/v1/access-log")
+        || endpoint.starts_with("/api/v1/alarm-log")
         || endpoint.starts_with("/api/v1/stats")
+        || endpoint.starts_with("/metrics")
+        || endpoint.starts_with("/healthz")
+        || endpoint.starts_with("/readyz")
         || endpoint.starts_with("/events")
         || endpoint.starts_with("/ws/");
     if skip {
         return response;
     }
 
+    // Every logged request feeds its route's reservoir, which outlives the
+    // access log. Keyed by route rather than path and query, there's one
+    // reservoir per registered route however varied the requests are.
+    {
+        let rng = state.rngs.consumer("latency");
+        let mut latency = state.latency.lock();
+        latency.entry(route).or_default().record(elapsed.as_micros() as u64, &mut *rng.lock().unwrap());
+    }
+
+    state.request_metrics.record(&endpoint, &method, status_code, elapsed.as_millis());
+
     let mut counter = state.request_counter.lock().unwrap();
     *counter += 1;
     let id = *counter;
+    // Taken under the counter lock, so event ids rise in log order
+    let event_id = state.access_event_counter.fetch_add(1, Ordering::Relaxed) + 1;
 
     let entry = AccessLogEntry {
         id,
-        timestamp: Utc::now().to_rfc3339(),
+        event_id,
+        timestamp: state.clock.now().to_rfc3339(),
         ip,
         user_agent,
         endpoint,
         method,
         status_code,
-        response_time,
+        response_time: elapsed.as_millis(),
+        response_time_us: elapsed.as_micros() as u64,
         device_id,
+        api_key_id,
     };
 
     {
-        let mut logs = state.access_log.lock().unwrap();
-        logs.insert(0, entry.clone());
-        if logs.len() > 500 {
-            logs.truncate(500);
+        let mut logs = state.access_log.write();
+        if logs.len() == ACCESS_LOG_CAPACITY {
+            logs.pop_back();
+        }
+        logs.push_front(entry.clone());
+    }
+
+    if state.sse_tx.send(SSEEvent::Access(entry)).is_err() {
+        tracing::debug!("no SSE subscribers for access event");
+    }
+
+    response
+}
+
+// ============================================
+// Rate Limiting (per-IP token bucket)
+// ============================================
+
+/// `RATE_LIMIT_RPS` tokens per second, bursting up to `RATE_LIMIT_BURST`
+#[derive(Clone, Copy, Debug)]
+struct RateLimit {
+    rate: f64,
+    burst: f64,
+}
+
+impl RateLimit {
+    /// None when `RATE_LIMIT_RPS` is unset or 0 (no limiting)
+    fn from_env() -> Result<Option<Self>, String> {
+        let rate: f64 = env_or("RATE_LIMIT_RPS", 0.0);
+        if !rate.is_finite() || rate < 0.0 {
+            return Err("RATE_LIMIT_RPS must be a non-negative number".to_string());
+        }
+        if rate == 0.0 {
+            return Ok(None);
+        }
+        let burst: f64 = env_or("RATE_LIMIT_BURST", rate.max(1.0));
+        if !burst.is_finite() || burst < 1.0 {
+            return Err("RATE_LIMIT_BURST must be at least 1".to_string());
+        }
+        Ok(Some(RateLimit { rate, burst }))
+    }
+}
+
+struct TokenBucket {
+    tokens: f64,
+    updated: std::time::Instant,
+}
+
+/// Clients tracked at once; past this the least recently seen one is dropped
+/// (and starts over with a full bucket if it comes back)
+const RATE_LIMIT_MAX_CLIENTS: std::num::NonZeroUsize = std::num::NonZeroUsize::new(10_000).unwrap();
+
+/// Take a token from `ip`'s bucket, or say how long until one is available
+fn take_token(state: &AppState, limit: RateLimit, ip: &str) -> Option<Duration> {
+    let now = std::time::Instant::now();
+    let mut buckets = state.rate_buckets.lock();
+    let bucket = buckets.get_or_insert_mut(ip.to_string(), || TokenBucket { tokens: limit.burst, updated: now });
+    let refill = now.duration_since(bucket.updated).as_secs_f64() * limit.rate;
+    bucket.tokens = (bucket.tokens + refill).min(limit.burst);
+    bucket.updated = now;
+    if bucket.tokens >= 1.0 {
+        bucket.tokens -= 1.0;
+        None
+    } else {
+        Some(Duration::from_secs_f64((1.0 - bucket.tokens) / limit.rate))
+    }
+}
+
+/// Whether a request is a WebSocket handshake: GET with `Upgrade: websocket`,
+/// `Connection: upgrade` and a `Sec-WebSocket-Key`
+fn is_ws_handshake(req: &axum::extract::Request) -> bool {
+    let header_has = |name: axum::http::header::HeaderName, token: &str| {
+        req.headers()
+            .get_all(name)
+            .iter()
+            .filter_map(|h| h.to_str().ok())
+            .flat_map(|h| h.split(','))
+            .any(|t| t.trim().eq_ignore_ascii_case(token))
+    };
+    req.method() == axum::http::Method::GET
+        && header_has(axum::http::header::UPGRADE, "websocket")
+        && header_has(axum::http::header::CONNECTION, "upgrade")
+        && req.headers().contains_key(axum::http::header::SEC_WEBSOCKET_KEY)
+}
+
+/// 429 with Retry-After once a client's bucket is empty. Streams (SSE, and
+/// WebSocket handshakes on the WebSocket routes) and health probes are never
+/// limited.
+async fn rate_limit_middleware(
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    State(state): State<SharedState>,
+    req: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Response {
+    let Some(limit) = state.rate_limit else {
+        return next.run(req).await;
+    };
+    let path = req.uri().path();
+    let exempt = path == "/events"
+        || path.ends_with("/events")
+        || path == "/healthz"
+        || path == "/readyz"
+        || (matches!(path, "/ws/sensors" | "/graphql/ws") && is_ws_handshake(&req));
+    if exempt {
+        return next.run(req).await;
+    }
+
+    match take_token(&state, limit, &client_ip(req.headers(), addr, &state.trusted_proxies)) {
+        None => next.run(req).await,
+        Some(wait) => (
+            axum::http::StatusCode::TOO_MANY_REQUESTS,
+            [(axum::http::header::RETRY_AFTER, wait.as_secs_f64().ceil().max(1.0).to_string())],
+            Json(serde_json::json!({
+                "status": "error",
+                "error": "Rate limit exceeded",
+                "retryAfterMs": wait.as_millis() as u64
+            })),
+        ).into_response(),
+    }
+}
+
+/// Parse `API_KEYS`: comma-separated `id:secret` pairs, or bare secrets
+/// which are given the ids `key-1`, `key-2`, ... by position
+fn parse_api_keys(spec: &str) -> Result<HashMap<String, String>, String> {
+    let mut keys = HashMap::new();
+    for (i, entry) in spec.split(',').map(str::trim).filter(|e| !e.is_empty()).enumerate() {
+        let (id, secret) = match entry.split_once(':') {
+            Some((id, secret)) => (id.trim().to_string(), secret.trim()),
+            None => (format!("key-{}", i + 1), entry),
+        };
+        if id.is_empty() || secret.is_empty() {
+            return Err(format!("'{}' needs both an id and a secret", entry));
+        }
+        if keys.values().any(|existing| *existing == id) {
+            return Err(format!("duplicate key id '{}'", id));
+        }
+        if keys.insert(secret.to_string(), id).is_some() {
+            return Err("the same secret is listed twice".to_string());
         }
     }
+    Ok(keys)
+}
 
-    let _ = state.sse_tx.send(SSEEvent::Access(entry));
+/// Key id a request authenticated with, passed to the access log on the response
+#[derive(Clone, Debug)]
+struct ApiKeyId(String);
 
+/// Whether a path serves sensor data and so needs a key when API_KEYS is set.
+/// Health probes, the docs and the dashboard's static files stay open; the
+/// Modbus and OPC UA listeners have no key exchange and are only started when
+/// MODBUS_PORT / OPCUA_PORT ask for them.
+fn requires_api_key(path: &str) -> bool {
+    path.starts_with("/api/v1/")
+        || matches!(path, "/graphql" | "/graphql/ws" | "/ws/sensors" | "/events" | "/metrics")
+}
+
+/// Require a known `X-API-Key` on the data endpoints when API_KEYS is set
+async fn api_key_middleware(
+    State(state): State<SharedState>,
+    req: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Response {
+    if state.api_keys.is_empty() || !requires_api_key(req.uri().path()) {
+        return next.run(req).await;
+    }
+    let key_id = req
+        .headers()
+        .get("x-api-key")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|secret| state.api_keys.get(secret))
+        .cloned();
+    let Some(key_id) = key_id else {
+        let error = if req.headers().contains_key("x-api-key") { "Invalid API key" } else { "Missing X-API-Key header" };
+        return (
+            axum::http::StatusCode::UNAUTHORIZED,
+            Json(serde_json::json!({
+                "status": "error",
+                "error": error
+            })),
+        ).into_response();
+    };
+    let mut response = next.run(req).await;
+    response.extensions_mut().insert(ApiKeyId(key_id));
     response
 }
 
+/// CORS policy from `ALLOWED_ORIGINS`. Unset or `*` allows any origin
+/// without credentials; a comma-separated origin list allows just those and
+/// enables credentials, mirroring the requested method and headers since
+/// wildcards aren't permitted alongside credentials.
+fn cors_layer(allowed_origins: Option<&str>) -> Result<CorsLayer, String> {
+    let spec = allowed_origins.map(str::trim).unwrap_or("*");
+    if spec == "*" {
+        return Ok(CorsLayer::new().allow_origin(Any).allow_methods(Any).allow_headers(Any));
+    }
+    let origins = spec
+        .split(',')
+        .map(str::trim)
+        .filter(|o| !o.is_empty())
+        .map(|o| {
+            let valid = o.contains("://") && !o.contains(char::is_whitespace);
+            valid
+                .then(|| o.parse::<axum::http::HeaderValue>().ok())
+                .flatten()
+                .ok_or_else(|| format!("'{}' is not a valid origin (expected scheme://host[:port])", o))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if origins.is_empty() {
+        return Err("no origins listed".to_string());
+    }
+    Ok(CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods(AllowMethods::mirror_request())
+        .allow_headers(AllowHeaders::mirror_request())
+        .allow_credentials(true))
+}
+
 // ──────────────────────────────────────────────
 // Main
 // ──────────────────────────────────────────────
 
-#[tokio::main]
-async fn main() {
-    // Shared state
-    let (sse_tx, _) = broadcast::channel(100);
-    let state = Arc::new(AppState {
-        access_log: Mutex::new(Vec::with_capacity(500)),
-        request_counter: Mutex::new(0),
-        sse_tx,
-    });
+/// What `main` parses (and may reject) from the environment before the
+/// shared state is built
+struct StartupConfig {
+    /// `RNG_SEED`; startup draws (walk seeding) are made by `main` from the same seed
+    seed: Option<u64>,
+    clock: SimClock,
+    dependencies: Vec<SensorDependency>,
+    sensor_configs: &'static [sensor_config::SensorConfig],
+    sensor_states: HashMap<String, SensorState>,
+    oil_stations: &'static [OilStation],
+    api_keys: HashMap<String, String>,
+    rate_limit: Option<RateLimit>,
+    trusted_proxies: Vec<std::net::IpAddr>,
+}
+
+impl AppState {
+    fn new(config: StartupConfig) -> Self {
+        let (sse_tx, _) = broadcast::channel(env_or("SSE_CHANNEL_CAPACITY", 100usize).max(1));
+        let rngs = RngStreams::new(config.seed);
+        let gps_tracker = GpsTrackerState::new(&mut *rngs.sensor("gps-tracker").lock().unwrap());
+        let now = config.clock.now();
+        let contact = ContactState::new(&mut *rngs.sensor("contact").lock().unwrap(), now);
+        AppState {
+            rngs,
+            clock: config.clock,
+            sim_running: Mutex::new(false),
+            chaos: Mutex::new(ChaosConfig::from_env()),
+            access_log: parking_lot::RwLock::new(VecDeque::with_capacity(ACCESS_LOG_CAPACITY)),
+            request_metrics: RequestMetrics::default(),
+            request_counter: Mutex::new(0),
+            access_event_counter: AtomicU64::new(0),
+            alarm_log: Mutex::new(Vec::with_capacity(500)),
+            alarm_counter: Mutex::new(0),
+            shelved_alarms: Mutex::new(HashMap::new()),
+            injections: Mutex::new(HashMap::new()),
+            waveforms: Mutex::new(HashMap::new()),
+            oil_stations: config.oil_stations,
+            sensor_configs: config.sensor_configs,
+            sensor_faults: Mutex::new(HashMap::new()),
+            gps_tracker: Mutex::new(gps_tracker),
+            geofences: Mutex::new(Vec::new()),
+            ambient_temperature: Mutex::new(25.0),
+            solar: SolarConfig::from_env(),
+            tz_offset_hours: env_or("TZ_OFFSET", 7.0),
+            clock_sync: Mutex::new(
+                AVAILABLE_SENSORS.iter().map(|&k| (k.to_string(), ClockSyncState::new(k, now))).collect(),
+            ),
+            devices: Mutex::new(
+                SENSOR_DESCRIPTORS.iter().map(|d| (d.key.to_string(), DeviceInfo::new(d.key, d.device_id, now))).collect(),
+            ),
+            replacements: Mutex::new(Vec::new()),
+            occupancy: Mutex::new(OccupancyState::new(env_or("OCCUPANCY_CAPACITY", 120))),
+            encoder: Mutex::new(EncoderState::new(env_or("ENCODER_CPR", 4096))),
+            strain_gauge: Mutex::new(StrainGaugeState::new(
+                env_or("STRAIN_ELASTIC_MODULUS_GPA", 200.0),
+                env_or("STRAIN_OVERLOAD_MICROSTRAIN", 1000.0),
+            )),
+            control_valve: Mutex::new(ControlValveState::new()),
+            sound_level: Mutex::new(SoundLevelState::new()),
+            smoke_detector: Mutex::new(SmokeDetectorState::new()),
+            contact: Mutex::new(contact),
+            redirect_rate: env_rate("SIM_REDIRECT_RATE", 0.0),
+            redirect_max_depth: env_or("SIM_REDIRECT_MAX_DEPTH", 3),
+            // 0 or unset means unlimited
+            bandwidth_bytes_per_sec: Some(env_or("SIM_BANDWIDTH_BYTES_PER_SEC", 0u64)).filter(|&b| b > 0),
+            boot_window_secs: env_or("SIM_BOOT_WINDOW_SECS", 30.0),
+            // Off unless asked for: every device boots with the server, so any
+            // default rate would fail reads for the first boot window
+            boot_error_rate: env_rate("SIM_BOOT_ERROR_RATE", 0.0),
+            watermark_api_keys: env_or("SIM_WATERMARK", false),
+            watermark_consumers: Mutex::new(HashMap::new()),
+            subscriptions: Mutex::new(HashMap::new()),
+            webhooks: Mutex::new(HashMap::new()),
+            webhook_config: WebhookConfig::from_env(),
+            history: Mutex::new(HashMap::new()),
+            unsampled: Mutex::new(HashMap::new()),
+            latest_readings: Mutex::new(HashMap::new()),
+            history_lru: Mutex::new(VecDeque::new()),
+            retention: RetentionConfig::from_env(),
+            golden_batches: Mutex::new(HashMap::new()),
+            wiring_faults: Mutex::new(HashMap::new()),
+            reporting: Mutex::new(HashMap::new()),
+            ranges: Mutex::new(HashMap::new()),
+            sparkplug_seq: Mutex::new(0),
+            avro_schemas: Mutex::new(HashMap::new()),
+            cluster: Mutex::new(ClusterState::from_env()),
+            dependencies: config.dependencies,
+            driven_values: Mutex::new(HashMap::new()),
+            transport_delays: Mutex::new(HashMap::new()),
+            stream_sequence: Mutex::new(0),
+            duplicate_rate: env_rate("SIM_DUPLICATE_RATE", 0.0),
+            peaks: Mutex::new(HashMap::new()),
+            sensor_states: Mutex::new(config.sensor_states),
+            last_good: Mutex::new(HashMap::new()),
+            replay: Mutex::new(None),
+            prefer_stale: env_or("SIM_PREFER_STALE", true),
+            admin_token: std::env::var("ADMIN_TOKEN").ok().filter(|t| !t.is_empty()),
+            api_keys: config.api_keys,
+            max_ws_sensors: env_or("MAX_WS_SENSORS", 32),
+            max_ws_connections: env_or("MAX_WS_CONNECTIONS", 256),
+            ws_connections: Mutex::new(0),
+            rate_limit: config.rate_limit,
+            rate_buckets: parking_lot::Mutex::new(lru::LruCache::new(RATE_LIMIT_MAX_CLIENTS)),
+            trusted_proxies: config.trusted_proxies,
+            latency: parking_lot::Mutex::new(HashMap::new()),
+            sse_tx,
+            shutdown: tokio::sync::watch::Sender::new(false),
+            open_streams: Mutex::new(0),
+            drained_streams: Mutex::new(0),
+        }
+    }
+}
 
-    // CORS
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods(Any)
-        .allow_headers(Any);
+/// `/api/v1/sensors/amr/<station id>[/...]` addresses a station's own meter.
+/// Its key (`amr/<station id>`) holds a '/', so the path is re-encoded to put
+/// the whole key in the `:key` segment of the per-sensor routes. Anything
+/// that isn't a known station (`/api/v1/sensors/amr/raw`, ...) is left alone.
+async fn route_amr_stations(State(state): State<SharedState>, mut req: axum::extract::Request) -> axum::extract::Request {
+    let path = req.uri().path();
+    let Some(rest) = path.strip_prefix("/api/v1/sensors/amr/") else {
+        return req;
+    };
+    let (id, tail) = match rest.split_once('/') {
+        Some((id, tail)) => (id, format!("/{}", tail)),
+        None => (rest, String::new()),
+    };
+    if amr_station(&state, &format!("{}{}", AMR_STATION_PREFIX, id)).is_none() {
+        return req;
+    }
+    let mut rewritten = format!("/api/v1/sensors/amr%2F{}{}", id, tail);
+    if let Some(query) = req.uri().query() {
+        rewritten = format!("{}?{}", rewritten, query);
+    }
+    if let Ok(uri) = rewritten.parse() {
+        *req.uri_mut() = uri;
+    }
+    req
+}
 
-    let app = Router::new()
+/// Every HTTP route, behind API-key auth, rate limiting and the access log
+fn router(state: SharedState, cors: CorsLayer) -> Router {
+    let schema = graphql::schema(state.clone());
+    let routes = Router::new()
         .route("/events", get(sse_handler))
         .route("/ws/sensors", get(ws_handler))
+        .route("/graphql", get(graphiql).post_service(async_graphql_axum::GraphQL::new(schema.clone())))
+        .route("/graphql/ws", get(move |state, protocol, ws| graphql_ws_handler(state, schema, protocol, ws)))
         .route("/api/v1/endpoints", get(get_endpoints))
+        .route("/api/openapi.json", get(get_openapi))
+        .route("/api/v1/schema", get(get_reading_schema))
+        .route("/docs", get(get_docs))
         .route("/api/v1/sensors", get(get_all_sensors))
         .route("/api/v1/sensors/:key", get(get_sensor_data))
-        .route("/api/v1/access-log", get(get_access_log))
+        .route("/api/v1/amr/stations", get(get_amr_stations))
+        .route("/api/v1/amr/stations/:station_id", get(get_amr_station_data))
+        .route("/api/v1/sensors/:key/raw", get(get_sensor_data_raw))
+        .route("/api/v1/sensors/events", get(sensors_sse_handler))
+        .route("/api/v1/sensors/batch", post(get_sensor_batch))
+        .route("/api/v1/sensors/:key/events", get(sensor_sse_handler))
+        .route("/api/v1/sensors/:key/stream", get(sensor_ndjson_handler))
+        .route("/api/v1/sensors/:key/meta", get(get_sensor_meta))
+        .route("/api/v1/sensors/:key/history", get(get_sensor_history))
+        .route("/api/v1/sensors/:key/aggregate", get(get_sensor_aggregate))
+        .route("/api/v1/sensors/:key/aligned", get(get_aligned_readings))
+        .route("/api/v1/sensors/:key/diagnostics", get(get_diagnostics))
+        .route("/api/v1/sensors/:key/diagnostics/fault", post(inject_wiring_fault).delete(clear_wiring_fault))
+        .route("/api/v1/sensors/:key/reporting", get(get_reporting_mode).post(set_reporting_mode))
+        .route("/api/v1/sensors/:key/rerange", post(rerange_sensor).delete(reset_sensor_range))
+        .route("/api/v1/sensors/:key/dbirth", get(get_sensor_dbirth))
+        .route("/api/v1/sensors/:key/avsc", get(get_avro_schema))
+        .route("/api/v1/sensors/:key/transport-delay", get(get_transport_delay).post(set_transport_delay))
+        .route("/api/v1/sensors/:key/reset-peak", post(reset_peak))
+        .route("/api/v1/sensors/:key/inject", post(inject_value).delete(clear_injection))
+        .route("/api/v1/sensors/:key/waveform", post(set_waveform).delete(clear_waveform))
+        .route("/api/v1/sensors/:key/fault", post(set_sensor_fault).delete(clear_sensor_fault))
+        .route("/api/v1/sensors/:key/comm-fault", post(set_comm_fault).delete(clear_comm_fault))
+        .route("/api/v1/sensors/:key/golden", post(upload_golden_batch).delete(clear_golden_batch))
+        .route("/api/v1/sensors/:key/calibration-certificate", get(get_calibration_certificate))
+        .route("/api/v1/sensors/:key/reboot", post(reboot_sensor))
+        .route("/api/v1/sensors/:key/replace", post(replace_sensor))
+        .route("/api/v1/sensors/:key/replacements", get(get_replacement_history))
+        .route("/api/v1/sensors/:key/sync", get(get_sync_status).post(sync_sensor_clock))
+        .route("/api/v1/sensors/occupancy/event", post(post_occupancy_event))
+        .route("/api/v1/sensors/encoder/direction", post(set_encoder_direction))
+        .route("/api/v1/sensors/strain-gauge/load", post(apply_strain_load))
+        .route("/api/v1/sensors/control-valve/command", post(command_control_valve))
+        .route("/api/v1/sensors/smoke-detector/reset", post(reset_smoke_detector))
+        .route("/api/v1/sensors/contact/state", post(force_contact).delete(release_contact))
+        .route("/api/v1/sensors/gps-tracker/geofence", get(get_geofences).post(create_geofence))
+        .route("/api/v1/sensors/gps-tracker/geofence/:name", delete(delete_geofence))
+        .route("/api/v1/watermark/decode", post(decode_watermark_handler))
+        .route("/api/v1/subscriptions", get(list_subscriptions).post(create_subscription))
+        .route("/api/v1/subscriptions/:id", get(get_subscription).delete(delete_subscription))
+        .route("/api/v1/subscriptions/:id/events", get(subscription_sse_handler))
+        .route("/api/v1/webhooks", get(list_webhooks).post(create_webhook))
+        .route("/api/v1/webhooks/:id", delete(delete_webhook))
+        .route("/api/v1/access-log", get(get_access_log).delete(clear_access_log))
+        .route("/api/v1/access-log.csv", get(get_access_log_csv))
+        .route("/api/v1/alarm-log", get(get_alarm_log))
+        .route("/api/v1/alarms", get(get_active_alarms))
+        .route("/api/v1/alarms/shelve", post(shelve_alarms))
+        .route("/api/v1/alarms/shelve/:key", delete(unshelve_alarms))
+        .route("/api/v1/alarms/shelved", get(list_shelved_alarms))
         .route("/api/v1/stats", get(get_stats))
+        .route("/api/v1/config", get(get_config).patch(patch_config))
+        .route("/metrics", get(get_metrics))
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/api/v1/discover", get(discover_devices))
+        .route("/api/v1/cluster", get(get_cluster_status))
+        .route("/api/v1/cluster/failover", post(trigger_failover))
+        .layer(axum::middleware::from_fn_with_state(state.clone(), api_key_middleware))
+        .layer(axum::middleware::from_fn_with_state(state.clone(), rate_limit_middleware))
         .layer(axum::middleware::from_fn_with_state(state.clone(), log_middleware))
         .fallback_service(tower_http::services::ServeDir::new("dist").fallback(tower_http::services::ServeFile::new("dist/index.html")))
         .layer(cors)
-        .with_state(state);
+        .layer(tower_http::trace::TraceLayer::new_for_http().make_span_with(request_span).on_response(log_response))
+        .with_state(state.clone());
+    // Station paths are rewritten before the routes see them
+    Router::new()
+        .fallback_service(routes)
+        .layer(axum::middleware::map_request_with_state(state, route_amr_stations))
+}
+
+
+#[tokio::main]
+async fn main() {
+    // RUST_LOG picks the level (e.g. `debug`, `simmurator_server=trace`); by
+    // default info, with the chatty OPC UA stack held to warnings
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info,opcua=warn")),
+        )
+        .init();
+    // A panicking handler (e.g. on a poisoned lock) goes to the log, not just stderr
+    std::panic::set_hook(Box::new(|info| tracing::error!(panic = %info, "panic")));
+
+    // Reject a bad dependency graph up front rather than simulating nonsense
+    let dependencies = match parse_sensor_dependencies(&std::env::var("SIM_SENSOR_DEPENDS").unwrap_or_default()) {
+        Ok(deps) => deps,
+        Err(e) => {
+            tracing::error!(error = %e, "invalid SIM_SENSOR_DEPENDS");
+            std::process::exit(1);
+        }
+    };
+
+    let seed = match std::env::var("RNG_SEED") {
+        Err(_) => None,
+        Ok(seed) => match seed.trim().parse::<u64>() {
+            Ok(seed) => Some(seed),
+            Err(_) => {
+                tracing::error!(value = %seed, "invalid RNG_SEED: not an unsigned integer");
+                std::process::exit(1);
+            }
+        },
+    };
+    let mut rng = seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64);
+    let clock = match std::env::var("SIM_START_TIME") {
+        Err(_) => SimClock::system(),
+        Ok(start) => match chrono::DateTime::parse_from_rfc3339(start.trim()) {
+            Ok(start) => SimClock::starting_at(start.with_timezone(&Utc)),
+            Err(_) => {
+                tracing::error!(value = %start, "invalid SIM_START_TIME: not an RFC 3339 timestamp");
+                std::process::exit(1);
+            }
+        },
+    };
+    let mut sensor_states = match seed_sensor_states(&std::env::var("SIM_WALK_SIGMA").unwrap_or_default(), &mut rng) {
+        Ok(states) => states,
+        Err(e) => {
+            tracing::error!(error = %e, "invalid SIM_WALK_SIGMA");
+            std::process::exit(1);
+        }
+    };
+
+    // Leaked once so readings and the sensor lists can borrow the keys for 'static
+    let sensor_configs: &'static [sensor_config::SensorConfig] = match std::env::var("SENSORS_CONFIG") {
+        Err(_) => &[],
+        Ok(path) => match sensor_config::load(&path, &taken_sensor_keys()) {
+            Ok(configs) => {
+                tracing::info!(count = configs.len(), path = %path, "loaded configured sensors");
+                Box::leak(configs.into_boxed_slice())
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "invalid SENSORS_CONFIG");
+                std::process::exit(1);
+            }
+        },
+    };
+    for config in sensor_configs {
+        let walk = SensorState {
+            value: random_between(&mut rng, config.min, config.max),
+            velocity: 0.0,
+            sigma: config.sigma(),
+            min: config.min,
+            max: config.max,
+            wraps: false,
+        };
+        sensor_states.insert(config.key.clone(), walk);
+    }
+
+    let cors = match cors_layer(std::env::var("ALLOWED_ORIGINS").ok().as_deref()) {
+        Ok(cors) => cors,
+        Err(e) => {
+            tracing::error!(error = %e, "invalid ALLOWED_ORIGINS");
+            std::process::exit(1);
+        }
+    };
+
+    let api_keys = match parse_api_keys(&std::env::var("API_KEYS").unwrap_or_default()) {
+        Ok(keys) => keys,
+        Err(e) => {
+            tracing::error!(error = %e, "invalid API_KEYS");
+            std::process::exit(1);
+        }
+    };
+
+    let rate_limit = match RateLimit::from_env() {
+        Ok(limit) => limit,
+        Err(e) => {
+            tracing::error!(error = %e, "invalid rate limit");
+            std::process::exit(1);
+        }
+    };
+
+    let trusted_proxies = match parse_trusted_proxies(&std::env::var("TRUSTED_PROXIES").unwrap_or_default()) {
+        Ok(proxies) => proxies,
+        Err(e) => {
+            tracing::error!(error = %e, "invalid TRUSTED_PROXIES");
+            std::process::exit(1);
+        }
+    };
+
+    // A broken station file shouldn't take the simulator down: warn and carry on
+    let oil_stations = match std::env::var("STATIONS_FILE") {
+        Err(_) => THAI_OIL_STATIONS,
+        Ok(path) => match load_oil_stations(&path) {
+            Ok(stations) => {
+                tracing::info!(count = stations.len(), path = %path, "loaded oil stations");
+                stations
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "invalid STATIONS_FILE, using the built-in stations");
+                THAI_OIL_STATIONS
+            }
+        },
+    };
+
+    let mqtt = match MqttConfig::from_env(sensor_configs, oil_stations) {
+        Ok(mqtt) => mqtt,
+        Err(e) => {
+            tracing::error!(error = %e, "invalid MQTT configuration");
+            std::process::exit(1);
+        }
+    };
+
+    // How long streams get to close after SIGINT/SIGTERM before the process exits anyway
+    let shutdown_grace = match std::env::var("SHUTDOWN_GRACE_SECS") {
+        Err(_) => Duration::from_secs(10),
+        Ok(secs) => match secs.trim().parse::<f64>() {
+            Ok(secs) if secs.is_finite() && secs >= 0.0 => Duration::from_secs_f64(secs),
+            _ => {
+                tracing::error!(value = %secs, "invalid SHUTDOWN_GRACE_SECS: not a non-negative number of seconds");
+                std::process::exit(1);
+            }
+        },
+    };
+
+    let modbus_port = match std::env::var("MODBUS_PORT") {
+        Err(_) => None,
+        Ok(port) => match port.trim().parse::<u16>() {
+            Ok(port) => Some(port),
+            Err(_) => {
+                tracing::error!(value = %port, "invalid MODBUS_PORT: not a port number");
+                std::process::exit(1);
+            }
+        },
+    };
+
+    let opcua_port = match std::env::var("OPCUA_PORT") {
+        Err(_) => None,
+        Ok(port) => match port.trim().parse::<u16>() {
+            Ok(port) => Some(port),
+            Err(_) => {
+                tracing::error!(value = %port, "invalid OPCUA_PORT: not a port number");
+                std::process::exit(1);
+            }
+        },
+    };
+
+    let state = AppState::new(StartupConfig {
+        seed,
+        clock,
+        dependencies,
+        sensor_configs,
+        sensor_states,
+        oil_stations,
+        api_keys,
+        rate_limit,
+        trusted_proxies,
+    });
+    let state = Arc::new(state);
+
+    if let Ok(path) = std::env::var("REPLAY_FILE") {
+        if let Err(e) = load_replay(&state, &path) {
+            tracing::error!(error = %e, "invalid REPLAY_FILE");
+            std::process::exit(1);
+        }
+    }
+    tokio::spawn(run_history_sampler(state.clone()));
+    tokio::spawn(run_retention_maintenance(state.clone()));
+    tokio::spawn(run_sensor_dependencies(state.clone()));
+    if let Some(mqtt) = mqtt {
+        let (host, port) = mqtt.options.broker_address();
+        tracing::info!(sensors = mqtt.sensors.len(), broker = %format!("{}:{}", host, port), "publishing to MQTT");
+        tokio::spawn(run_mqtt_publisher(state.clone(), mqtt));
+    }
+    if !state.api_keys.is_empty() && (modbus_port.is_some() || opcua_port.is_some()) {
+        tracing::warn!("API_KEYS doesn't cover the Modbus or OPC UA listeners; they accept anonymous clients");
+    }
+    if let Some(port) = modbus_port {
+        tracing::info!(port, "Modbus TCP server listening");
+        tokio::spawn(modbus::run(state.clone(), port));
+    }
+    if let Some(port) = opcua_port {
+        tracing::info!(endpoint = %format!("opc.tcp://localhost:{}/", port), "OPC UA server listening");
+        tokio::spawn(ua_server::run(state.clone(), port));
+    }
+
+    let shutdown_state = state.clone();
+    let app = router(state, cors);
 
     let port = std::env::var("PORT").ok().and_then(|p| p.parse().ok()).unwrap_or(4040u16);
     let addr = SocketAddr::from(([0, 0, 0, 0], port));
-    println!("\n  🚀 Simmurator Rust Server running at http://localhost:{}", port);
-    println!("  📡 SSE stream at http://localhost:{}/events", port);
-    println!("  🔌 WebSocket stream at ws://localhost:{}/ws/sensors", port);
-    
+    tracing::info!(
+        http = %format!("http://localhost:{}", port),
+        sse = "/events",
+        websocket = "/ws/sensors",
+        graphql = "/graphql",
+        "Simmurator server running"
+    );
+
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
     axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(shutdown_signal(shutdown_state.clone(), shutdown_grace))
         .await
         .unwrap();
+    drain_streams(&shutdown_state).await;
+    let drained = *shutdown_state.drained_streams.lock().unwrap();
+    tracing::info!(drained, "all connections drained, shutdown complete");
 }
@@ -0,0 +1,263 @@
+//! Modbus TCP server (`MODBUS_PORT`) for SCADA tools that don't speak HTTP.
+//!
+//! A register image is refreshed from `generate_sensor_data` once a second,
+//! so faults, injections and the rest show up exactly as they do over REST.
+//! Each value is multiplied by its scale and rounded into one 16-bit
+//! register: `uint16` values saturate at 0..=65535, `int16` values are two's
+//! complement and saturate at -32768..=32767. The same image answers both
+//! Read Holding Registers (FC 03) and Read Input Registers (FC 04):
+//!
+//! | reg | sensor           | field               | scale  | type   | unit     |
+//! |-----|------------------|---------------------|--------|--------|----------|
+//! | 0   | temperature      | value               | ×10    | int16  | °C       |
+//! | 1   | humidity         | value               | ×10    | uint16 | %RH      |
+//! | 2   | humidity         | dewPoint            | ×10    | int16  | °C       |
+//! | 3   | pressure         | value               | ×10    | uint16 | hPa      |
+//! | 4   | oil-level        | value               | ×10    | uint16 | %        |
+//! | 5   | oil-pressure     | value               | ×10    | uint16 | bar      |
+//! | 6   | vibration        | velocityRms         | ×100   | uint16 | mm/s     |
+//! | 7   | energy-meter     | activePower         | ×10    | uint16 | kW       |
+//! | 8   | energy-meter     | powerFactor         | ×1000  | uint16 | -        |
+//! | 9   | amr              | flowRateM3H         | ×10    | uint16 | m³/h     |
+//! | 10  | amr              | inletPressure       | ×100   | uint16 | bar      |
+//! | 11  | flow-meter       | flowRate            | ×1     | uint16 | m³/h     |
+//! | 12  | air-quality      | pm25                | ×10    | uint16 | µg/m³    |
+//! | 13  | air-quality      | co2                 | ×1     | uint16 | ppm      |
+//! | 14  | gas-detector     | carbonMonoxide      | ×10    | uint16 | ppm      |
+//! | 15  | gas-detector     | hydrogenSulfide     | ×100   | uint16 | ppm      |
+//! | 16  | gas-detector     | lel                 | ×10    | uint16 | %LEL     |
+//! | 17  | gas-detector     | oxygen              | ×10    | uint16 | %vol     |
+//! | 18  | ph-sensor        | phValue             | ×100   | uint16 | pH       |
+//! | 19  | ph-sensor        | orp                 | ×1     | int16  | mV       |
+//! | 20  | level-sensor     | level               | ×1000  | uint16 | m        |
+//! | 21  | proximity-sensor | distance            | ×10    | uint16 | mm       |
+//! | 22  | solar-panel      | value               | ×100   | uint16 | kW       |
+//! | 23  | strain-gauge     | value               | ×1     | int16  | µε       |
+//! | 24  | control-valve    | value               | ×10    | uint16 | %        |
+//! | 25  | encoder          | value               | ×1     | uint16 | RPM      |
+//! | 26  | occupancy        | value               | ×1     | uint16 | persons  |
+//! | 27  | gps-tracker      | value               | ×10    | uint16 | km/h     |
+//! | 28  | accelerometer    | magnitude           | ×1000  | uint16 | g        |
+//! | 29  | accelerometer    | tiltAngle           | ×100   | int16  | °        |
+//! | 30  | sound-level      | value               | ×10    | uint16 | dB(A)    |
+//! | 31  | sound-level      | leq                 | ×10    | uint16 | dB(A)    |
+//! | 32  | wind             | value               | ×10    | uint16 | m/s      |
+//! | 33  | wind             | gust                | ×10    | uint16 | m/s      |
+//! | 34  | wind             | direction           | ×1     | uint16 | °        |
+//! | 35  | smoke-detector   | value               | ×100   | uint16 | %/m      |
+//! | 36  | power-quality    | efficiency          | ×100   | uint16 | %        |
+//! | 37  | power-quality    | thdCurrent          | ×10    | uint16 | %        |
+//! | 38  | power-quality    | thdVoltage          | ×100   | uint16 | %        |
+//! | 39  | power-quality    | crestFactor         | ×1000  | uint16 | -        |
+//!
+//! Boolean alarms answer Read Coils (FC 01) and Read Discrete Inputs (FC 02):
+//!
+//! | coil | sensor           | field          |
+//! |------|------------------|----------------|
+//! | 0    | gas-detector     | alarms.co      |
+//! | 1    | gas-detector     | alarms.h2s     |
+//! | 2    | gas-detector     | alarms.lel     |
+//! | 3    | gas-detector     | alarms.o2      |
+//! | 4    | amr              | leakDetected   |
+//! | 5    | strain-gauge     | overloadAlarm  |
+//! | 6    | proximity-sensor | objectDetected |
+//! | 7    | smoke-detector   | alarm          |
+//! | 8    | contact          | value          |
+//!
+//! A sensor that doesn't answer (comm fault, offline) keeps its last value.
+//! The image is read-only: write function codes get Illegal Function.
+
+use crate::{generate_sensor_data, SharedState};
+use parking_lot::RwLock;
+use serde_json::Value;
+use std::{future, net::SocketAddr, sync::Arc, time::Duration};
+use tokio_modbus::{
+    prelude::{ExceptionCode, Request, Response},
+    server::{
+        tcp::{accept_tcp_connection, Server},
+        Service,
+    },
+};
+
+#[derive(Clone, Copy)]
+enum RegisterType {
+    Uint16,
+    Int16,
+}
+
+/// (address, sensor, JSON pointer into `value`, scale, type)
+const HOLDING_REGISTERS: &[(u16, &str, &str, f64, RegisterType)] = &[
+    (0, "temperature", "/value", 10.0, RegisterType::Int16),
+    (1, "humidity", "/value", 10.0, RegisterType::Uint16),
+    (2, "humidity", "/dewPoint", 10.0, RegisterType::Int16),
+    (3, "pressure", "/value", 10.0, RegisterType::Uint16),
+    (4, "oil-level", "/value", 10.0, RegisterType::Uint16),
+    (5, "oil-pressure", "/value", 10.0, RegisterType::Uint16),
+    (6, "vibration", "/velocityRms", 100.0, RegisterType::Uint16),
+    (7, "energy-meter", "/activePower", 10.0, RegisterType::Uint16),
+    (8, "energy-meter", "/powerFactor", 1000.0, RegisterType::Uint16),
+    (9, "amr", "/flowRateM3H", 10.0, RegisterType::Uint16),
+    (10, "amr", "/inletPressure", 100.0, RegisterType::Uint16),
+    (11, "flow-meter", "/flowRate", 1.0, RegisterType::Uint16),
+    (12, "air-quality", "/pm25", 10.0, RegisterType::Uint16),
+    (13, "air-quality", "/co2", 1.0, RegisterType::Uint16),
+    (14, "gas-detector", "/carbonMonoxide", 10.0, RegisterType::Uint16),
+    (15, "gas-detector", "/hydrogenSulfide", 100.0, RegisterType::Uint16),
+    (16, "gas-detector", "/lel", 10.0, RegisterType::Uint16),
+    (17, "gas-detector", "/oxygen", 10.0, RegisterType::Uint16),
+    (18, "ph-sensor", "/phValue", 100.0, RegisterType::Uint16),
+    (19, "ph-sensor", "/orp", 1.0, RegisterType::Int16),
+    (20, "level-sensor", "/level", 1000.0, RegisterType::Uint16),
+    (21, "proximity-sensor", "/distance", 10.0, RegisterType::Uint16),
+    (22, "solar-panel", "/value", 100.0, RegisterType::Uint16),
+    (23, "strain-gauge", "/value", 1.0, RegisterType::Int16),
+    (24, "control-valve", "/value", 10.0, RegisterType::Uint16),
+    (25, "encoder", "/value", 1.0, RegisterType::Uint16),
+    (26, "occupancy", "/value", 1.0, RegisterType::Uint16),
+    (27, "gps-tracker", "/value", 10.0, RegisterType::Uint16),
+    (28, "accelerometer", "/magnitude", 1000.0, RegisterType::Uint16),
+    (29, "accelerometer", "/tiltAngle", 100.0, RegisterType::Int16),
+    (30, "sound-level", "/value", 10.0, RegisterType::Uint16),
+    (31, "sound-level", "/leq", 10.0, RegisterType::Uint16),
+    (32, "wind", "/value", 10.0, RegisterType::Uint16),
+    (33, "wind", "/gust", 10.0, RegisterType::Uint16),
+    (34, "wind", "/direction", 1.0, RegisterType::Uint16),
+    (35, "smoke-detector", "/value", 100.0, RegisterType::Uint16),
+    (36, "power-quality", "/efficiency", 100.0, RegisterType::Uint16),
+    (37, "power-quality", "/thdCurrent", 10.0, RegisterType::Uint16),
+    (38, "power-quality", "/thdVoltage", 100.0, RegisterType::Uint16),
+    (39, "power-quality", "/crestFactor", 1000.0, RegisterType::Uint16),
+];
+
+/// (address, sensor, JSON pointer into `value`)
+const COILS: &[(u16, &str, &str)] = &[
+    (0, "gas-detector", "/alarms/co"),
+    (1, "gas-detector", "/alarms/h2s"),
+    (2, "gas-detector", "/alarms/lel"),
+    (3, "gas-detector", "/alarms/o2"),
+    (4, "amr", "/leakDetected"),
+    (5, "strain-gauge", "/overloadAlarm"),
+    (6, "proximity-sensor", "/objectDetected"),
+    (7, "smoke-detector", "/alarm"),
+    (8, "contact", "/value"),
+];
+
+/// Most registers a single read may ask for (Modbus spec)
+const MAX_READ_REGISTERS: u16 = 125;
+/// Most coils a single read may ask for (Modbus spec)
+const MAX_READ_COILS: u16 = 2000;
+
+fn to_register(value: f64, scale: f64, kind: RegisterType) -> u16 {
+    let scaled = (value * scale).round();
+    match kind {
+        RegisterType::Uint16 => scaled.clamp(0.0, u16::MAX as f64) as u16,
+        RegisterType::Int16 => scaled.clamp(i16::MIN as f64, i16::MAX as f64) as i16 as u16,
+    }
+}
+
+struct RegisterImage {
+    registers: Vec<u16>,
+    coils: Vec<bool>,
+}
+
+impl RegisterImage {
+    fn new() -> Self {
+        Self { registers: vec![0; HOLDING_REGISTERS.len()], coils: vec![false; COILS.len()] }
+    }
+
+    /// Copy a sensor's fields out of one of its readings
+    fn update(&mut self, sensor: &str, data: &Value) {
+        for &(address, _, field, scale, kind) in HOLDING_REGISTERS.iter().filter(|r| r.1 == sensor) {
+            if let Some(v) = data["value"].pointer(field).and_then(Value::as_f64) {
+                self.registers[address as usize] = to_register(v, scale, kind);
+            }
+        }
+        for &(address, _, field) in COILS.iter().filter(|c| c.1 == sensor) {
+            if let Some(flag) = data["value"].pointer(field).and_then(Value::as_bool) {
+                self.coils[address as usize] = flag;
+            }
+        }
+    }
+}
+
+/// The `address..address + quantity` slice of `block`, or the exception a
+/// Modbus device answers for an out-of-range read
+fn read_block<T: Clone>(block: &[T], address: u16, quantity: u16, max: u16) -> Result<Vec<T>, ExceptionCode> {
+    if quantity == 0 || quantity > max {
+        return Err(ExceptionCode::IllegalDataValue);
+    }
+    let start = address as usize;
+    let end = start + quantity as usize;
+    block.get(start..end).map(<[T]>::to_vec).ok_or(ExceptionCode::IllegalDataAddress)
+}
+
+struct ModbusService {
+    image: Arc<RwLock<RegisterImage>>,
+}
+
+impl Service for ModbusService {
+    type Request = Request<'static>;
+    type Response = Response;
+    type Exception = ExceptionCode;
+    type Future = future::Ready<Result<Response, ExceptionCode>>;
+
+    fn call(&self, req: Self::Request) -> Self::Future {
+        let image = self.image.read();
+        future::ready(match req {
+            Request::ReadHoldingRegisters(address, quantity) => {
+                read_block(&image.registers, address, quantity, MAX_READ_REGISTERS).map(Response::ReadHoldingRegisters)
+            }
+            Request::ReadInputRegisters(address, quantity) => {
+                read_block(&image.registers, address, quantity, MAX_READ_REGISTERS).map(Response::ReadInputRegisters)
+            }
+            Request::ReadCoils(address, quantity) => {
+                read_block(&image.coils, address, quantity, MAX_READ_COILS).map(Response::ReadCoils)
+            }
+            Request::ReadDiscreteInputs(address, quantity) => {
+                read_block(&image.coils, address, quantity, MAX_READ_COILS).map(Response::ReadDiscreteInputs)
+            }
+            _ => Err(ExceptionCode::IllegalFunction),
+        })
+    }
+}
+
+/// Refresh the register image from fresh readings once a second
+async fn refresh(state: SharedState, image: Arc<RwLock<RegisterImage>>) {
+    let mut sensors: Vec<&str> = HOLDING_REGISTERS.iter().map(|r| r.1).chain(COILS.iter().map(|c| c.1)).collect();
+    sensors.sort_unstable();
+    sensors.dedup();
+
+    let mut ticker = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        ticker.tick().await;
+        for &sensor in &sensors {
+            if let Some(data) = generate_sensor_data(&state, sensor) {
+                image.write().update(sensor, &data);
+            }
+        }
+    }
+}
+
+/// Serve the register image on `port` until the process exits
+pub async fn run(state: SharedState, port: u16) {
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    let listener = match tokio::net::TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            tracing::error!(port, error = %e, "Modbus TCP server can't bind its port");
+            return;
+        }
+    };
+    let image = Arc::new(RwLock::new(RegisterImage::new()));
+    tokio::spawn(refresh(state, image.clone()));
+
+    let new_service = move |_addr| Ok(Some(ModbusService { image: image.clone() }));
+    let on_connected = move |stream, addr| {
+        let new_service = new_service.clone();
+        async move { accept_tcp_connection(stream, addr, new_service) }
+    };
+    let on_process_error = |e: std::io::Error| tracing::warn!(error = %e, "Modbus connection error");
+    if let Err(e) = Server::new(listener).serve(&on_connected, on_process_error).await {
+        tracing::error!(error = %e, "Modbus TCP server stopped");
+    }
+}
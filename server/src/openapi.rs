@@ -0,0 +1,382 @@
+//! OpenAPI 3.0 description of the core REST routes.
+//!
+//! Covers the sensor reads, discovery, access-log and stats endpoints with
+//! their real envelopes. The envelopes are hand-maintained; the reading and
+//! its nested types are generated from the structs that serialize them, the
+//! same source as `/api/v1/schema`. A reading's `value` object differs per
+//! sensor, so it's described as a free-form object; everything around it is
+//! exact.
+
+use crate::UnifiedSensorData;
+use schemars::generate::SchemaSettings;
+use serde_json::{json, Value};
+
+fn ok_envelope(properties: Value, required: &[&str]) -> Value {
+    let mut properties = properties;
+    properties["status"] = json!({ "type": "string", "enum": ["ok"] });
+    let mut required: Vec<&str> = required.to_vec();
+    required.insert(0, "status");
+    json!({ "type": "object", "required": required, "properties": properties })
+}
+
+fn json_content(schema: Value) -> Value {
+    json!({ "application/json": { "schema": schema } })
+}
+
+fn error_response(description: &str) -> Value {
+    json!({
+        "description": description,
+        "content": json_content(json!({ "$ref": "#/components/schemas/Error" }))
+    })
+}
+
+fn query_param(name: &str, description: &str, schema: Value) -> Value {
+    json!({ "name": name, "in": "query", "required": false, "description": description, "schema": schema })
+}
+
+/// `UnifiedSensorData` and every type it nests, keyed by type name
+fn reading_schemas() -> serde_json::Map<String, Value> {
+    let mut generator = SchemaSettings::openapi3().into_generator();
+    generator.subschema_for::<UnifiedSensorData>();
+    generator.take_definitions(true)
+}
+
+fn schemas() -> Value {
+    let mut schemas = json!({
+        "Error": {
+            "type": "object",
+            "required": ["status", "error"],
+            "properties": {
+                "status": { "type": "string", "enum": ["error"] },
+                "error": { "type": "string" }
+            }
+        },
+        "SensorReadingResponse": ok_envelope(json!({
+            "timestamp": { "type": "string", "format": "date-time" },
+            "data": { "$ref": "#/components/schemas/UnifiedSensorData" }
+        }), &["timestamp", "data"]),
+        "AllSensorsResponse": ok_envelope(json!({
+            "timestamp": { "type": "string", "format": "date-time" },
+            "data": {
+                "type": "object",
+                "description": "Readings keyed by sensor; a sensor whose read failed maps to an Error instead",
+                "additionalProperties": {
+                    "oneOf": [
+                        { "$ref": "#/components/schemas/UnifiedSensorData" },
+                        { "$ref": "#/components/schemas/Error" }
+                    ]
+                }
+            },
+            "unknown": {
+                "type": "array",
+                "description": "Names in `only`/`exclude` that aren't sensors; omitted when empty",
+                "items": { "type": "string" }
+            }
+        }), &["timestamp", "data"]),
+        "SensorBatchRequest": {
+            "type": "object",
+            "required": ["sensors"],
+            "properties": {
+                "sensors": { "type": "array", "minItems": 1, "items": { "type": "string" } },
+                "units": { "type": "string", "enum": ["metric", "si", "imperial", "us"] },
+                "format": { "type": "string", "enum": ["unified", "json"] }
+            }
+        },
+        "SensorBatchResponse": ok_envelope(json!({
+            "timestamp": { "type": "string", "format": "date-time" },
+            "count": { "type": "integer" },
+            "errors": { "type": "integer", "description": "Entries of `data` that are errors" },
+            "data": {
+                "type": "object",
+                "description": "Readings keyed by requested name; an unknown name or a failed read maps to an Error instead",
+                "additionalProperties": {
+                    "oneOf": [
+                        { "$ref": "#/components/schemas/UnifiedSensorData" },
+                        { "$ref": "#/components/schemas/Error" }
+                    ]
+                }
+            }
+        }), &["timestamp", "count", "errors", "data"]),
+        "SensorMeta": ok_envelope(json!({
+            "sensor": { "type": "string" },
+            "deviceId": { "type": "string" },
+            "sensorType": { "type": "string" },
+            "description": { "type": "string" },
+            "unit": { "$ref": "#/components/schemas/UcumUnit" },
+            "opcUa": { "$ref": "#/components/schemas/OpcUaNode" },
+            "equipmentHierarchy": { "$ref": "#/components/schemas/Isa95Equipment" },
+            "sparkplugTopic": { "$ref": "#/components/schemas/SparkplugTopic" },
+            "normalRange": {
+                "type": "object",
+                "nullable": true,
+                "required": ["metric", "min", "max"],
+                "properties": {
+                    "metric": { "type": "string" },
+                    "min": { "type": "number" },
+                    "max": { "type": "number" }
+                }
+            },
+            "thresholds": { "type": "object", "additionalProperties": { "type": "number" } }
+        }), &["sensor", "deviceId", "sensorType", "description", "unit", "opcUa", "equipmentHierarchy", "thresholds"]),
+        "EndpointsResponse": ok_envelope(json!({
+            "endpoints": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "required": ["name", "url", "method", "description"],
+                    "properties": {
+                        "name": { "type": "string" },
+                        "url": { "type": "string" },
+                        "method": { "type": "string" },
+                        "description": { "type": "string" }
+                    }
+                }
+            }
+        }), &["endpoints"]),
+        "AccessLogEntry": {
+            "type": "object",
+            "required": ["id", "timestamp", "ip", "userAgent", "endpoint", "method", "statusCode", "responseTime"],
+            "properties": {
+                "id": { "type": "integer", "minimum": 0 },
+                "timestamp": { "type": "string", "format": "date-time" },
+                "ip": { "type": "string" },
+                "userAgent": { "type": "string" },
+                "endpoint": { "type": "string" },
+                "method": { "type": "string" },
+                "statusCode": { "type": "integer" },
+                "responseTime": { "type": "integer", "description": "Milliseconds" },
+                "deviceId": { "type": "string", "nullable": true },
+                "apiKeyId": { "type": "string", "nullable": true, "description": "Id of the API key used, when API_KEYS is set" }
+            }
+        },
+        "AccessLogResponse": ok_envelope(json!({
+            "total": { "type": "integer", "description": "Requests logged since start (or the last clear)" },
+            "pagination": {
+                "type": "object",
+                "required": ["total", "offset", "limit", "returned"],
+                "properties": {
+                    "total": { "type": "integer", "description": "Buffered entries matching the filters" },
+                    "offset": { "type": "integer" },
+                    "limit": { "type": "integer" },
+                    "returned": { "type": "integer" }
+                }
+            },
+            "entries": { "type": "array", "items": { "$ref": "#/components/schemas/AccessLogEntry" } }
+        }), &["total", "pagination", "entries"]),
+        "AccessLogCleared": ok_envelope(json!({
+            "removed": { "type": "integer" }
+        }), &["removed"]),
+        "StatsResponse": ok_envelope(json!({
+            "totalRequests": { "type": "integer" },
+            "activeConnections": { "type": "integer" },
+            "endpointStats": {
+                "type": "object",
+                "additionalProperties": {
+                    "type": "object",
+                    "required": ["count", "totalTime", "errors", "avgResponseTime"],
+                    "properties": {
+                        "count": { "type": "integer" },
+                        "totalTime": { "type": "integer" },
+                        "errors": { "type": "integer" },
+                        "avgResponseTime": { "type": "number" }
+                    }
+                }
+            },
+            "routeLatency": {
+                "type": "object",
+                "additionalProperties": {
+                    "type": "object",
+                    "required": ["count", "avgResponseTime", "p50ResponseTime", "p95ResponseTime", "p99ResponseTime"],
+                    "properties": {
+                        "count": { "type": "integer" },
+                        "avgResponseTime": { "type": "number" },
+                        "p50ResponseTime": { "type": "number" },
+                        "p95ResponseTime": { "type": "number" },
+                        "p99ResponseTime": { "type": "number" }
+                    }
+                }
+            }
+        }), &["totalRequests", "activeConnections", "endpointStats", "routeLatency"])
+    });
+    schemas.as_object_mut().unwrap().extend(reading_schemas());
+    schemas
+}
+
+/// The OpenAPI document; `sensors` fills the `{key}` path parameter's enum
+pub fn spec(sensors: &[&str]) -> Value {
+    let sensor_key = json!({
+        "name": "key",
+        "in": "path",
+        "required": true,
+        "schema": { "type": "string", "enum": sensors }
+    });
+    let format = |formats: &[&str]| query_param(
+        "format",
+        "Response encoding (JSON unless `Accept` asks for XML)",
+        json!({ "type": "string", "enum": formats }),
+    );
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Simmurator IoT Sensor Simulator",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Simulated industrial sensors with ISA-95, OPC UA and Sparkplug B metadata"
+        },
+        "paths": {
+            "/api/v1/sensors": {
+                "get": {
+                    "summary": "Read every sensor",
+                    "parameters": [
+                        format(&["json", "xml", "influx"]),
+                        query_param("only", "Comma-separated sensors to include", json!({ "type": "string" })),
+                        query_param("exclude", "Comma-separated sensors to leave out", json!({ "type": "string" }))
+                    ],
+                    "responses": {
+                        "200": {
+                            "description": "One reading per selected sensor",
+                            "content": {
+                                "application/json": { "schema": { "$ref": "#/components/schemas/AllSensorsResponse" } },
+                                "application/xml": { "schema": { "type": "string" } },
+                                "text/plain": { "schema": { "type": "string", "description": "InfluxDB line protocol" } }
+                            }
+                        },
+                        "400": error_response("Unsupported format"),
+                        "503": error_response("Simulated failover in progress")
+                    }
+                }
+            },
+            "/api/v1/sensors/batch": {
+                "post": {
+                    "summary": "Read a chosen set of sensors",
+                    "requestBody": {
+                        "required": true,
+                        "content": json_content(json!({ "$ref": "#/components/schemas/SensorBatchRequest" }))
+                    },
+                    "responses": {
+                        "200": {
+                            "description": "One reading per requested sensor",
+                            "content": json_content(json!({ "$ref": "#/components/schemas/SensorBatchResponse" }))
+                        },
+                        "400": error_response("Empty sensor list, unsupported format or units"),
+                        "503": error_response("Simulated failover in progress")
+                    }
+                }
+            },
+            "/api/v1/sensors/{key}": {
+                "get": {
+                    "summary": "Read one sensor",
+                    "parameters": [
+                        sensor_key.clone(),
+                        format(&["json", "xml", "avro", "sparkplug", "influx"]),
+                        query_param(
+                            "units",
+                            "Unit system; imperial converts °C, bar, m, m³/h and kg/m³ fields",
+                            json!({ "type": "string", "enum": ["metric", "imperial"] })
+                        )
+                    ],
+                    "responses": {
+                        "200": {
+                            "description": "The sensor's current reading",
+                            "content": {
+                                "application/json": { "schema": { "$ref": "#/components/schemas/SensorReadingResponse" } },
+                                "application/xml": { "schema": { "type": "string" } },
+                                "application/avro": { "schema": { "type": "string", "format": "binary" } },
+                                "application/octet-stream": { "schema": { "type": "string", "format": "binary" } },
+                                "text/plain": { "schema": { "type": "string", "description": "InfluxDB line protocol" } }
+                            }
+                        },
+                        "400": error_response("Unsupported format or units"),
+                        "404": error_response("Sensor not found"),
+                        "500": error_response("Simulated sensor error when ERROR_STATUS=500"),
+                        "503": error_response("Simulated sensor error (with Retry-After) or failover in progress"),
+                        "504": error_response("Sensor not responding")
+                    }
+                }
+            },
+            "/api/v1/sensors/{key}/meta": {
+                "get": {
+                    "summary": "Static sensor configuration (no reading is taken)",
+                    "parameters": [sensor_key],
+                    "responses": {
+                        "200": {
+                            "description": "Units, hierarchy, node id and thresholds",
+                            "content": json_content(json!({ "$ref": "#/components/schemas/SensorMeta" }))
+                        },
+                        "404": error_response("Sensor not found")
+                    }
+                }
+            },
+            "/api/v1/endpoints": {
+                "get": {
+                    "summary": "List the per-sensor endpoints",
+                    "responses": {
+                        "200": {
+                            "description": "One entry per sensor",
+                            "content": json_content(json!({ "$ref": "#/components/schemas/EndpointsResponse" }))
+                        }
+                    }
+                }
+            },
+            "/api/v1/schema": {
+                "get": {
+                    "summary": "JSON Schema (draft 2020-12) of a reading",
+                    "responses": {
+                        "200": {
+                            "description": "Schema of UnifiedSensorData and its nested types",
+                            "content": json_content(json!({ "type": "object" }))
+                        }
+                    }
+                }
+            },
+            "/api/v1/access-log": {
+                "get": {
+                    "summary": "Recent requests, newest first",
+                    "parameters": [
+                        query_param("limit", "Entries to return (default 50)", json!({ "type": "integer", "minimum": 0 })),
+                        query_param("offset", "Matching entries to skip", json!({ "type": "integer", "minimum": 0 })),
+                        query_param("status", "Exact code (503) or inclusive range (500-599)", json!({ "type": "string" })),
+                        query_param("min_status", "Lowest status code to include", json!({ "type": "integer" })),
+                        query_param("endpoint", "Substring of the request path", json!({ "type": "string" })),
+                        query_param("ip", "Exact client IP", json!({ "type": "string" }))
+                    ],
+                    "responses": {
+                        "200": {
+                            "description": "A page of the access log",
+                            "content": json_content(json!({ "$ref": "#/components/schemas/AccessLogResponse" }))
+                        },
+                        "400": error_response("Malformed status filter")
+                    }
+                },
+                "delete": {
+                    "summary": "Clear the access log and reset the request counter",
+                    "parameters": [{
+                        "name": "X-Admin-Token",
+                        "in": "header",
+                        "required": true,
+                        "schema": { "type": "string" }
+                    }],
+                    "responses": {
+                        "200": {
+                            "description": "Number of entries removed",
+                            "content": json_content(json!({ "$ref": "#/components/schemas/AccessLogCleared" }))
+                        },
+                        "401": error_response("Missing or invalid admin token")
+                    }
+                }
+            },
+            "/api/v1/stats": {
+                "get": {
+                    "summary": "Per-endpoint request statistics over the retained access log",
+                    "responses": {
+                        "200": {
+                            "description": "Request counts and timings",
+                            "content": json_content(json!({ "$ref": "#/components/schemas/StatsResponse" }))
+                        }
+                    }
+                }
+            }
+        },
+        "components": { "schemas": schemas() }
+    })
+}
@@ -0,0 +1,162 @@
+//! Protobuf encoding of a unified sensor reading.
+//!
+//! Equivalent `.proto` schema for consumers:
+//!
+//! ```proto
+//! syntax = "proto3";
+//! package simmurator;
+//!
+//! message SensorReading {
+//!   string sensor = 1;
+//!   string node_id = 2;
+//!   string sensor_type = 3;
+//!   string source_timestamp = 4;
+//!   string server_timestamp = 5;
+//!   string data_quality = 6;
+//!   uint32 status_code = 7;
+//!   string unit = 8;
+//!   repeated Metric metrics = 9;
+//!   uint64 sequence = 10;
+//! }
+//!
+//! message Metric {
+//!   string name = 1;
+//!   oneof value {
+//!     double double_value = 2;
+//!     bool bool_value = 3;
+//!     string string_value = 4;
+//!   }
+//! }
+//! ```
+
+use prost::Message;
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SensorReading {
+    #[prost(string, tag = "1")]
+    pub sensor: String,
+    #[prost(string, tag = "2")]
+    pub node_id: String,
+    #[prost(string, tag = "3")]
+    pub sensor_type: String,
+    #[prost(string, tag = "4")]
+    pub source_timestamp: String,
+    #[prost(string, tag = "5")]
+    pub server_timestamp: String,
+    #[prost(string, tag = "6")]
+    pub data_quality: String,
+    #[prost(uint32, tag = "7")]
+    pub status_code: u32,
+    #[prost(string, tag = "8")]
+    pub unit: String,
+    #[prost(message, repeated, tag = "9")]
+    pub metrics: Vec<Metric>,
+    #[prost(uint64, tag = "10")]
+    pub sequence: u64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Metric {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(oneof = "metric::Value", tags = "2, 3, 4")]
+    pub value: Option<metric::Value>,
+}
+
+pub mod metric {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Value {
+        #[prost(double, tag = "2")]
+        Double(f64),
+        #[prost(bool, tag = "3")]
+        Bool(bool),
+        #[prost(string, tag = "4")]
+        String(String),
+    }
+}
+
+/// Flatten a JSON value object into metrics, using dotted names for nested
+/// objects (e.g. `alarms.co`). Arrays and nulls are skipped.
+fn flatten_metrics(value: &serde_json::Value, prefix: &str, out: &mut Vec<Metric>) {
+    let Some(obj) = value.as_object() else {
+        return;
+    };
+    for (k, v) in obj {
+        let name = if prefix.is_empty() { k.clone() } else { format!("{}.{}", prefix, k) };
+        let value = match v {
+            serde_json::Value::Number(n) => n.as_f64().map(metric::Value::Double),
+            serde_json::Value::Bool(b) => Some(metric::Value::Bool(*b)),
+            serde_json::Value::String(s) => Some(metric::Value::String(s.clone())),
+            serde_json::Value::Object(_) => {
+                flatten_metrics(v, &name, out);
+                None
+            }
+            _ => None,
+        };
+        if value.is_some() {
+            out.push(Metric { name, value });
+        }
+    }
+}
+
+/// Build a `SensorReading` from the JSON form of `UnifiedSensorData`
+pub fn sensor_reading(sensor: &str, data: &serde_json::Value, status_code: u32, sequence: u64) -> SensorReading {
+    let str_field = |v: &serde_json::Value| v.as_str().unwrap_or_default().to_string();
+    let mut metrics = Vec::new();
+    flatten_metrics(&data["value"], "", &mut metrics);
+
+    SensorReading {
+        sensor: sensor.to_string(),
+        node_id: str_field(&data["opcUa"]["nodeId"]),
+        sensor_type: str_field(&data["sensorType"]),
+        source_timestamp: str_field(&data["sourceTimestamp"]),
+        server_timestamp: str_field(&data["serverTimestamp"]),
+        data_quality: str_field(&data["dataQuality"]),
+        status_code,
+        unit: str_field(&data["unit"]["code"]),
+        metrics,
+        sequence,
+    }
+}
+
+pub fn encode_sensor_reading(sensor: &str, data: &serde_json::Value, status_code: u32, sequence: u64) -> Vec<u8> {
+    sensor_reading(sensor, data, status_code, sequence).encode_to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reading_round_trips_with_flattened_metrics() {
+        let data = serde_json::json!({
+            "opcUa": { "nodeId": "ns=2;s=TEMP-001" },
+            "sensorType": "Temperature",
+            "sourceTimestamp": "2025-01-01T00:00:00Z",
+            "dataQuality": "good",
+            "unit": { "code": "Cel" },
+            "value": {
+                "value": 21.5,
+                "alarm": false,
+                "trend": "rising",
+                "alarms": { "co": true },
+                "history": [1, 2],
+                "missing": null
+            }
+        });
+        let bytes = encode_sensor_reading("temperature", &data, 0, 42);
+        let reading = SensorReading::decode(bytes.as_slice()).unwrap();
+
+        assert_eq!(reading.sensor, "temperature");
+        assert_eq!(reading.node_id, "ns=2;s=TEMP-001");
+        assert_eq!(reading.unit, "Cel");
+        assert_eq!(reading.sequence, 42);
+        let metric = |name: &str| reading.metrics.iter().find(|m| m.name == name).and_then(|m| m.value.clone());
+        assert_eq!(metric("value"), Some(metric::Value::Double(21.5)));
+        assert_eq!(metric("alarm"), Some(metric::Value::Bool(false)));
+        assert_eq!(metric("trend"), Some(metric::Value::String("rising".to_string())));
+        assert_eq!(metric("alarms.co"), Some(metric::Value::Bool(true)));
+        // Arrays and nulls have no metric representation
+        assert_eq!(reading.metrics.len(), 4);
+    }
+}
@@ -0,0 +1,146 @@
+//! Operator-defined scalar sensors loaded from `SENSORS_CONFIG` (TOML).
+//!
+//! Each `[[sensor]]` table describes a single-value sensor that random-walks
+//! inside `[min, max]`; identity, placement and quality band are all data, so
+//! adding one needs no code change. Composite sensors (amr, energy-meter,
+//! gps-tracker, ...) stay coded in `simulate_sensor`.
+//!
+//! ```toml
+//! [[sensor]]
+//! key = "co2"
+//! device_id = "CO2-001"
+//! display_name = "CO2 Monitor"
+//! line = "HVAC"
+//! area = "Building-A"
+//! unit = "ppm"
+//! description = "Indoor carbon dioxide"
+//! min = 400.0
+//! max = 2000.0
+//! normal = { min = 400.0, max = 1000.0 }
+//! thresholds = { warning = 1000.0, alarm = 1500.0 }
+//! ```
+
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Good-quality band of the value; readings outside it degrade to uncertain/bad
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct QualityBand {
+    pub min: f64,
+    pub max: f64,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SensorConfig {
+    /// Sensor key used in URLs and subscriptions
+    pub key: String,
+    /// OPC UA node id, ISA-95 equipment and Sparkplug device id
+    pub device_id: String,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    pub line: String,
+    pub area: String,
+    /// Display unit; mapped to its UCUM code like the built-in sensors
+    pub unit: String,
+    /// `sensorType` of readings; defaults to the key
+    #[serde(default)]
+    pub sensor_type: Option<String>,
+    #[serde(default)]
+    pub description: String,
+    /// Range the value walks in
+    pub min: f64,
+    pub max: f64,
+    /// Standard deviation of each walk step; defaults to 1% of the range
+    #[serde(default)]
+    pub sigma: Option<f64>,
+    /// Decimal places the value is rounded to
+    #[serde(default = "default_decimals")]
+    pub decimals: usize,
+    /// Quality band; defaults to the whole range
+    #[serde(default)]
+    pub normal: Option<QualityBand>,
+    /// Named limits reported alongside the value (e.g. `warning`, `alarm`)
+    #[serde(default)]
+    pub thresholds: BTreeMap<String, f64>,
+}
+
+fn default_decimals() -> usize {
+    2
+}
+
+impl SensorConfig {
+    pub fn display_name(&self) -> &str {
+        self.display_name.as_deref().unwrap_or(&self.key)
+    }
+
+    pub fn sensor_type(&self) -> &str {
+        self.sensor_type.as_deref().unwrap_or(&self.key)
+    }
+
+    pub fn normal(&self) -> QualityBand {
+        self.normal.unwrap_or(QualityBand { min: self.min, max: self.max })
+    }
+
+    pub fn sigma(&self) -> f64 {
+        self.sigma.unwrap_or((self.max - self.min) * 0.01)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default)]
+    sensor: Vec<SensorConfig>,
+}
+
+fn validate(config: &SensorConfig, taken: &[&str]) -> Result<(), String> {
+    let key = &config.key;
+    let valid_key = !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid_key {
+        return Err(format!("sensor key '{}' may only use letters, digits, '-' and '_'", key));
+    }
+    if taken.contains(&key.as_str()) {
+        return Err(format!("'{}' is already used by a built-in sensor", key));
+    }
+    // `/api/v1/sensors/events` (the combined stream) and `/api/v1/sensors/batch`
+    // would shadow it
+    if key == "events" || key == "batch" {
+        return Err(format!("'{}' is reserved", key));
+    }
+    if config.device_id.trim().is_empty() {
+        return Err(format!("'{}' needs a device_id", key));
+    }
+    let finite = [config.min, config.max].iter().chain(config.thresholds.values()).all(|v| v.is_finite());
+    if !finite || config.min >= config.max {
+        return Err(format!("'{}' needs finite values with min < max", key));
+    }
+    if config.sigma.is_some_and(|s| !s.is_finite() || s < 0.0) {
+        return Err(format!("'{}' sigma must be non-negative", key));
+    }
+    if let Some(band) = config.normal {
+        if !band.min.is_finite() || !band.max.is_finite() || band.min >= band.max {
+            return Err(format!("'{}' normal band needs min < max", key));
+        }
+    }
+    if config.decimals > 6 {
+        return Err(format!("'{}' decimals must be at most 6", key));
+    }
+    Ok(())
+}
+
+/// Read and validate the file. Keys must be unique and must not be `taken`:
+/// the built-in sensors and every variable they random-walk under a key of
+/// its own (the wind vane's `wind-direction`), which share the walk table.
+pub fn load(path: &str, taken: &[&str]) -> Result<Vec<SensorConfig>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path, e))?;
+    let file: ConfigFile = toml::from_str(&text).map_err(|e| e.to_string())?;
+    for (i, config) in file.sensor.iter().enumerate() {
+        validate(config, taken)?;
+        if file.sensor[..i].iter().any(|c| c.key == config.key) {
+            return Err(format!("sensor '{}' is defined twice", config.key));
+        }
+    }
+    Ok(file.sensor)
+}
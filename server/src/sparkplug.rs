@@ -0,0 +1,210 @@
+//! Sparkplug B payloads (Eclipse Tahu `org.eclipse.tahu.protobuf.Payload`).
+//!
+//! Only the subset of the Tahu schema the simulator emits is modelled; field
+//! tags match the upstream `sparkplug_b.proto`, so any Sparkplug consumer can
+//! decode the bytes.
+
+use prost::Message;
+use serde::Serialize;
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Payload {
+    #[prost(uint64, optional, tag = "1")]
+    pub timestamp: Option<u64>,
+    #[prost(message, repeated, tag = "2")]
+    pub metrics: Vec<Metric>,
+    #[prost(uint64, optional, tag = "3")]
+    pub seq: Option<u64>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Metric {
+    #[prost(string, optional, tag = "1")]
+    pub name: Option<String>,
+    #[prost(uint64, optional, tag = "2")]
+    pub alias: Option<u64>,
+    #[prost(uint64, optional, tag = "3")]
+    pub timestamp: Option<u64>,
+    #[prost(uint32, optional, tag = "4")]
+    pub datatype: Option<u32>,
+    #[prost(bool, optional, tag = "5")]
+    pub is_historical: Option<bool>,
+    #[prost(bool, optional, tag = "6")]
+    pub is_transient: Option<bool>,
+    #[prost(bool, optional, tag = "7")]
+    pub is_null: Option<bool>,
+    #[prost(oneof = "metric::Value", tags = "11, 13, 14, 15")]
+    pub value: Option<metric::Value>,
+}
+
+pub mod metric {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Value {
+        #[prost(uint64, tag = "11")]
+        Long(u64),
+        #[prost(double, tag = "13")]
+        Double(f64),
+        #[prost(bool, tag = "14")]
+        Boolean(bool),
+        #[prost(string, tag = "15")]
+        String(String),
+    }
+}
+
+/// Sparkplug B metric datatypes used by the simulator
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[repr(u32)]
+pub enum DataType {
+    Int64 = 4,
+    UInt64 = 8,
+    Double = 10,
+    Boolean = 11,
+    String = 12,
+}
+
+/// One metric of a sensor's value object, as it appears in BIRTH/DATA messages
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MetricDef {
+    pub name: String,
+    pub alias: u64,
+    pub datatype: DataType,
+    pub is_null: bool,
+    pub value: serde_json::Value,
+}
+
+fn datatype_of(value: &serde_json::Value) -> DataType {
+    use serde_json::Value;
+    match value {
+        // Signed unless it doesn't fit, so a field's datatype doesn't flip with its sign
+        Value::Number(n) if n.is_i64() => DataType::Int64,
+        Value::Number(n) if n.is_u64() => DataType::UInt64,
+        Value::Bool(_) => DataType::Boolean,
+        // Arrays are carried as JSON text
+        Value::String(_) | Value::Array(_) => DataType::String,
+        // Nulls only show up for optional measurements (e.g. no object in range)
+        Value::Number(_) | Value::Null | Value::Object(_) => DataType::Double,
+    }
+}
+
+fn flatten(value: &serde_json::Value, prefix: &str, out: &mut Vec<MetricDef>) {
+    let Some(obj) = value.as_object() else {
+        return;
+    };
+    for (k, v) in obj {
+        // Sparkplug uses `/` to build metric folders
+        let name = if prefix.is_empty() { k.clone() } else { format!("{}/{}", prefix, k) };
+        if v.is_object() {
+            flatten(v, &name, out);
+            continue;
+        }
+        out.push(MetricDef {
+            alias: out.len() as u64,
+            datatype: datatype_of(v),
+            is_null: v.is_null(),
+            value: match v {
+                serde_json::Value::Array(_) => serde_json::json!(v.to_string()),
+                _ => v.clone(),
+            },
+            name,
+        });
+    }
+}
+
+/// The full metric set of a reading's `value` object, with stable aliases
+/// (keys are iterated in sorted order, so the same sensor always yields the
+/// same alias for the same metric)
+pub fn metric_set(data: &serde_json::Value) -> Vec<MetricDef> {
+    let mut out = Vec::new();
+    flatten(&data["value"], "", &mut out);
+    out
+}
+
+fn encode_metric(def: &MetricDef, timestamp: u64, with_name: bool) -> Metric {
+    let value = match (def.datatype, &def.value) {
+        (_, serde_json::Value::Null) => None,
+        // Sparkplug carries signed integers two's-complement in the long field
+        (DataType::Int64, v) => v.as_i64().map(|n| metric::Value::Long(n as u64)),
+        (DataType::UInt64, v) => v.as_u64().map(metric::Value::Long),
+        (DataType::Double, v) => v.as_f64().map(metric::Value::Double),
+        (DataType::Boolean, v) => v.as_bool().map(metric::Value::Boolean),
+        (DataType::String, v) => v.as_str().map(|s| metric::Value::String(s.to_string())),
+    };
+    Metric {
+        name: with_name.then(|| def.name.clone()),
+        alias: Some(def.alias),
+        timestamp: Some(timestamp),
+        datatype: Some(def.datatype as u32),
+        is_historical: None,
+        is_transient: None,
+        is_null: def.is_null.then_some(true),
+        value,
+    }
+}
+
+/// Encode a DBIRTH: every metric with its name, alias, datatype and initial value
+pub fn encode_birth(metrics: &[MetricDef], timestamp: u64, seq: u64) -> Vec<u8> {
+    Payload {
+        timestamp: Some(timestamp),
+        metrics: metrics.iter().map(|def| encode_metric(def, timestamp, true)).collect(),
+        seq: Some(seq),
+    }
+    .encode_to_vec()
+}
+
+/// Encode a reading (the JSON form of `UnifiedSensorData`) as a DDATA
+/// payload. Metrics carry their name as well as the alias, stamped with the
+/// reading's source time; the payload is stamped `now` (ms since the epoch).
+/// Quality maps onto the metric flags: a bad reading has no usable value, so
+/// every metric is sent as null, and a stale (last-usable) reading is marked
+/// historical.
+pub fn sparkplug_payload(data: &serde_json::Value, seq: u64, now: u64) -> Vec<u8> {
+    let source_ts = data["sourceTimestamp"]
+        .as_str()
+        .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+        .map_or(now, |t| t.timestamp_millis() as u64);
+    let bad = data["dataQuality"] == "bad";
+    let historical = data["stale"] == true;
+
+    let metrics = metric_set(data)
+        .iter()
+        .map(|def| {
+            let mut metric = encode_metric(def, source_ts, true);
+            if bad {
+                metric.value = None;
+                metric.is_null = Some(true);
+            }
+            metric.is_historical = historical.then_some(true);
+            metric
+        })
+        .collect();
+
+    Payload { timestamp: Some(now), metrics, seq: Some(seq) }.encode_to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn birth_enumerates_every_data_metric_with_a_datatype() {
+        let reading = |value: serde_json::Value| serde_json::json!({ "dataQuality": "good", "value": value });
+        let birth = encode_birth(
+            &metric_set(&reading(serde_json::json!({ "level": 2.5, "count": 3, "alarms": { "high": false }, "label": "ok" }))),
+            0,
+            0,
+        );
+        let data = sparkplug_payload(&reading(serde_json::json!({ "level": 2.7, "count": 4, "alarms": { "high": true }, "label": "ok" })), 1, 0);
+
+        let birth = Payload::decode(birth.as_slice()).unwrap();
+        let data = Payload::decode(data.as_slice()).unwrap();
+        assert_eq!(birth.metrics.len(), data.metrics.len());
+        for (b, d) in birth.metrics.iter().zip(&data.metrics) {
+            assert!(b.datatype.is_some(), "{:?} has no datatype", b.name);
+            assert_eq!((&b.name, b.alias, b.datatype), (&d.name, d.alias, d.datatype));
+        }
+        let names: Vec<_> = birth.metrics.iter().filter_map(|m| m.name.as_deref()).collect();
+        assert_eq!(names, ["alarms/high", "count", "label", "level"]);
+        assert_eq!(birth.metrics[0].datatype, Some(DataType::Boolean as u32));
+    }
+}
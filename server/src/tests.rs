@@ -0,0 +1,2060 @@
+use super::*;
+use axum::{body::Body, extract::connect_info::MockConnectInfo, http::Request, http::StatusCode};
+use tower::ServiceExt;
+
+/// Seeded state with nothing optional configured, as a bare `main` would build it
+fn test_state() -> SharedState {
+    state_with(|_| {})
+}
+
+/// `test_state` with some of its settings changed before it's shared
+fn state_with(configure: impl FnOnce(&mut AppState)) -> SharedState {
+    let mut rng = StdRng::seed_from_u64(7);
+    let sensor_states = seed_sensor_states("", &mut rng).unwrap();
+    let mut state = AppState::new(StartupConfig {
+        seed: Some(7),
+        clock: SimClock::system(),
+        dependencies: Vec::new(),
+        sensor_configs: &[],
+        sensor_states,
+        oil_stations: THAI_OIL_STATIONS,
+        api_keys: HashMap::new(),
+        rate_limit: None,
+        trusted_proxies: Vec::new(),
+    });
+    // Random read errors would make HTTP assertions flaky
+    *state.chaos.get_mut().unwrap() = ChaosConfig { error_rate: 0.0, slow_rate: 0.0, error_status: 503 };
+    configure(&mut state);
+    Arc::new(state)
+}
+
+/// Adds a `SENSORS_CONFIG`-style "co2" sensor (device CO2-001, 400-2000 ppm)
+fn with_co2(state: &mut AppState) {
+    let config: sensor_config::SensorConfig = toml::from_str(
+        r#"
+        key = "co2"
+        device_id = "CO2-001"
+        line = "HVAC"
+        area = "Building-A"
+        unit = "ppm"
+        min = 400.0
+        max = 2000.0
+        normal = { min = 400.0, max = 1000.0 }
+        thresholds = { warning = 1000.0, alarm = 1500.0 }
+        "#,
+    )
+    .unwrap();
+    let walk = SensorState { value: 700.0, velocity: 0.0, sigma: config.sigma(), min: config.min, max: config.max, wraps: false };
+    state.sensor_states.get_mut().unwrap().insert("co2".to_string(), walk);
+    state.sensor_configs = Box::leak(Box::new([config]));
+}
+
+/// The full router, with requests arriving from 127.0.0.1
+fn app(state: &SharedState) -> Router {
+    router(state.clone(), cors_layer(None).unwrap()).layer(MockConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))))
+}
+
+/// Send one request through the full router and decode the JSON body (a
+/// JSON string of the raw text when the body isn't JSON)
+async fn send(state: &SharedState, req: Request<Body>) -> (StatusCode, serde_json::Value) {
+    let res = app(state).oneshot(req).await.unwrap();
+    let status = res.status();
+    let bytes = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    let body = serde_json::from_slice(&bytes)
+        .unwrap_or_else(|_| serde_json::Value::String(String::from_utf8_lossy(&bytes).into_owned()));
+    (status, body)
+}
+
+/// An access-log entry for a request answered now in no time
+fn access_entry(id: usize, endpoint: &str, status_code: u16) -> AccessLogEntry {
+    AccessLogEntry {
+        id,
+        event_id: id as u64,
+        timestamp: Utc::now().to_rfc3339(),
+        ip: "127.0.0.1".to_string(),
+        user_agent: "test".to_string(),
+        endpoint: endpoint.to_string(),
+        method: "GET".to_string(),
+        status_code,
+        response_time: 0,
+        response_time_us: 0,
+        device_id: None,
+        api_key_id: None,
+    }
+}
+
+fn post_json(uri: &str, body: serde_json::Value) -> Request<Body> {
+    Request::post(uri)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+// ── Geofencing ──
+
+#[test]
+fn haversine_matches_one_degree_of_latitude() {
+    let d = haversine_meters(GeoPoint { lat: 0.0, lng: 100.0 }, GeoPoint { lat: 1.0, lng: 100.0 });
+    assert!((d - 111_195.0).abs() < 10.0, "{}", d);
+}
+
+#[test]
+fn geofence_zones_contain_points() {
+    let circle = GeofenceZone::Circle { center: GPS_DEPOT, radius_meters: 500.0 };
+    assert!(circle.contains(GPS_DEPOT));
+    assert!(!circle.contains(GeoPoint { lat: GPS_DEPOT.lat + 0.01, lng: GPS_DEPOT.lng }));
+
+    let square = GeofenceZone::Polygon {
+        points: vec![
+            GeoPoint { lat: 0.0, lng: 0.0 },
+            GeoPoint { lat: 0.0, lng: 1.0 },
+            GeoPoint { lat: 1.0, lng: 1.0 },
+            GeoPoint { lat: 1.0, lng: 0.0 },
+        ],
+    };
+    assert!(square.contains(GeoPoint { lat: 0.5, lng: 0.5 }));
+    assert!(!square.contains(GeoPoint { lat: 1.5, lng: 0.5 }));
+}
+
+#[test]
+fn geofence_validation_rejects_degenerate_zones() {
+    assert!(GeofenceZone::Circle { center: GPS_DEPOT, radius_meters: 0.0 }.validate().is_err());
+    assert!(GeofenceZone::Polygon { points: vec![GPS_DEPOT, GPS_DEPOT] }.validate().is_err());
+}
+
+#[test]
+fn geofence_transitions_raise_enter_and_exit_alarms() {
+    let state = test_state();
+    state.geofences.lock().unwrap().push(Geofence {
+        name: "depot".to_string(),
+        zone: GeofenceZone::Circle { center: GPS_DEPOT, radius_meters: 1000.0 },
+        inside: false,
+    });
+    let far = GeoPoint { lat: GPS_DEPOT.lat + 1.0, lng: GPS_DEPOT.lng };
+
+    let zones = evaluate_geofences(&state, "gps-tracker", GPS_DEPOT);
+    assert_eq!(zones[0]["inside"], true);
+    evaluate_geofences(&state, "gps-tracker", GPS_DEPOT);
+    evaluate_geofences(&state, "gps-tracker", far);
+
+    let events: Vec<_> = state.alarm_log.lock().unwrap().iter().map(|a| a.event.clone()).collect();
+    // Newest first; staying inside raises nothing
+    assert_eq!(events, ["exit", "enter"]);
+}
+
+#[tokio::test]
+async fn geofence_endpoint_rejects_invalid_zone() {
+    let state = test_state();
+    let (status, body) = send(
+        &state,
+        post_json("/api/v1/sensors/gps-tracker/geofence", serde_json::json!({
+            "name": "bad", "type": "circle", "center": { "lat": 0.0, "lng": 0.0 }, "radiusMeters": -1.0
+        })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+
+    let (status, body) = send(
+        &state,
+        post_json("/api/v1/sensors/gps-tracker/geofence", serde_json::json!({
+            "name": "depot", "type": "circle", "center": { "lat": 12.65, "lng": 101.16 }, "radiusMeters": 250.5
+        })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED, "{}", body);
+    assert_eq!(body["geofence"]["radiusMeters"], 250.5);
+}
+
+// ── Solar panel ──
+
+#[test]
+fn clear_sky_irradiance_follows_daylight() {
+    assert_eq!(clear_sky_irradiance(3.0, 6.0, 18.0), 0.0);
+    assert_eq!(clear_sky_irradiance(18.0, 6.0, 18.0), 0.0);
+    assert!((clear_sky_irradiance(12.0, 6.0, 18.0) - 1000.0).abs() < 1e-9);
+    let (morning, afternoon) = (clear_sky_irradiance(9.0, 6.0, 18.0), clear_sky_irradiance(15.0, 6.0, 18.0));
+    assert!((morning - afternoon).abs() < 1e-9);
+    assert!(morning > 0.0 && morning < 1000.0);
+}
+
+#[test]
+fn solar_output_stays_within_capacity() {
+    let state = test_state();
+    for _ in 0..20 {
+        let data = simulate_sensor(&state, "solar-panel").unwrap();
+        let ac = data["value"]["value"].as_f64().unwrap();
+        let dc = data["value"]["dcPower"].as_f64().unwrap();
+        assert!(ac >= 0.0 && ac <= dc + 1e-9, "ac {} dc {}", ac, dc);
+        assert!(dc <= state.solar.capacity_kw);
+        assert_eq!(data["value"]["daylight"], data["value"]["irradiance"].as_f64().unwrap() > 0.0);
+    }
+}
+
+#[test]
+fn solar_output_peaks_at_noon_and_stops_at_night() {
+    let output_at = |time: &str| {
+        let start = chrono::DateTime::parse_from_rfc3339(time).unwrap().with_timezone(&Utc);
+        let state = state_with(|s| {
+            s.clock = SimClock::starting_at(start);
+            s.tz_offset_hours = 0.0;
+        });
+        simulate_sensor(&state, "solar-panel").unwrap()["value"]["value"].as_f64().unwrap()
+    };
+    assert_eq!(output_at("2024-06-01T00:00:00Z"), 0.0);
+    // Even under the heaviest cloud noon gets 40 % of clear sky; half an
+    // hour from sunrise or sunset clear sky is under 10 %
+    let noon = output_at("2024-06-01T12:00:00Z");
+    for twilight in ["2024-06-01T06:30:00Z", "2024-06-01T17:30:00Z"] {
+        let output = output_at(twilight);
+        assert!(noon > 3.0 * output, "noon {} vs {} at {}", noon, output, twilight);
+    }
+}
+
+// ── Temperature & humidity ──
+
+#[test]
+fn diurnal_cycle_stays_inside_the_walk_band() {
+    // 15:00 is the temperature peak, +4 on top of a walk pinned to its max
+    let peak = chrono::DateTime::parse_from_rfc3339("2024-06-01T15:00:00Z").unwrap().with_timezone(&Utc);
+    let state = state_with(|s| {
+        s.clock = SimClock::starting_at(peak);
+        s.tz_offset_hours = 0.0;
+        let walk = s.sensor_states.get_mut().unwrap().get_mut("temperature").unwrap();
+        (walk.value, walk.velocity, walk.sigma) = (walk.max, 0.0, 0.0);
+    });
+    let max = state.sensor_states.lock().unwrap()["temperature"].max;
+    let value = walk(&state, &mut StdRng::seed_from_u64(1), "temperature");
+    assert_eq!(value, max);
+}
+
+#[test]
+fn dew_point_never_exceeds_the_ambient_temperature() {
+    for temp in [-20.0, 0.0, 18.5, 35.0] {
+        for rh in [1.0, 40.0, 99.9, 100.0, 104.0] {
+            assert!(temp_to_dewpoint(rh, temp) <= temp + 1e-9, "{} %RH at {} °C", rh, temp);
+        }
+    }
+
+    let state = test_state();
+    for _ in 0..50 {
+        let temperature = generate_sensor_data(&state, "temperature").unwrap();
+        let humidity = generate_sensor_data(&state, "humidity").unwrap();
+        let ambient = humidity["value"]["ambientTemperature"].as_f64().unwrap();
+        assert_eq!(ambient, temperature["value"]["value"].as_f64().unwrap());
+        assert!(humidity["value"]["dewPoint"].as_f64().unwrap() <= ambient);
+    }
+}
+
+// ── Data quality ──
+
+fn quality_of(value: f64, min: f64, max: f64) -> serde_json::Value {
+    serde_json::to_value(generate_data_quality(value, min, max)).unwrap()
+}
+
+#[test]
+fn quality_margins_widen_ranges_below_zero() {
+    // ORP: -500..500 mV, span 1000, so the uncertain band reaches -600
+    assert_eq!(quality_of(-550.0, -500.0, 500.0), "uncertain");
+    assert_eq!(quality_of(-599.0, -500.0, 500.0), "uncertain");
+    assert_eq!(quality_of(-601.0, -500.0, 500.0), "bad");
+    assert_eq!(quality_of(550.0, -500.0, 500.0), "uncertain");
+    // Entirely negative range
+    assert_eq!(quality_of(-25.0, -40.0, -10.0), "good");
+    assert_eq!(quality_of(-42.0, -40.0, -10.0), "uncertain");
+    assert_eq!(quality_of(-44.0, -40.0, -10.0), "bad");
+}
+
+#[test]
+fn each_quality_band_is_reachable() {
+    // 0..100: near band is 5 wide, uncertain margin 10
+    assert_eq!(quality_of(50.0, 0.0, 100.0), "good");
+    assert_eq!(quality_of(5.0, 0.0, 100.0), "good");
+    assert_eq!(quality_of(3.0, 0.0, 100.0), "goodUncertain");
+    assert_eq!(quality_of(98.0, 0.0, 100.0), "goodUncertain");
+    assert_eq!(quality_of(100.0, 0.0, 100.0), "goodUncertain");
+    assert_eq!(quality_of(105.0, 0.0, 100.0), "uncertain");
+    assert_eq!(quality_of(-10.0, 0.0, 100.0), "uncertain");
+    assert_eq!(quality_of(111.0, 0.0, 100.0), "bad");
+    assert_eq!(generate_opcua_status_code(&DataQuality::GoodUncertain).name(), "goodUncertain");
+}
+
+// ── Sensor descriptors ──
+
+#[test]
+fn descriptors_answer_unknown_thresholds_with_none() {
+    let desc = sensor_descriptor("wind").unwrap();
+    assert_eq!(desc.threshold("gale"), Some(17.2));
+    assert_eq!(desc.threshold("minThreshold"), None);
+    // Calibrated sensors carry a usable factory range
+    for desc in SENSOR_DESCRIPTORS {
+        if let Some(calibration) = desc.calibration {
+            assert!(calibration.lrv < calibration.urv, "{}", desc.key);
+        }
+    }
+}
+
+// ── Seeded randomness ──
+
+#[test]
+fn seeded_sensors_replay_regardless_of_other_reads() {
+    let start = chrono::DateTime::parse_from_rfc3339("2024-03-01T08:00:00Z").unwrap().with_timezone(&Utc);
+    let quiet = state_with(|s| s.clock = SimClock::starting_at(start));
+    let busy = state_with(|s| s.clock = SimClock::starting_at(start));
+    generate_sensor_data(&busy, "pressure").unwrap();
+    generate_sensor_data(&busy, "humidity").unwrap();
+    simulate_read_fault(&busy, "pressure");
+
+    for _ in 0..3 {
+        let expected = generate_sensor_data(&quiet, "temperature").unwrap();
+        let actual = generate_sensor_data(&busy, "temperature").unwrap();
+        assert_eq!(expected["value"], actual["value"]);
+    }
+}
+
+// ── Simulation clock ──
+
+#[test]
+fn readings_are_stamped_by_the_simulation_clock() {
+    let start = chrono::DateTime::parse_from_rfc3339("2020-01-01T00:00:00Z").unwrap().with_timezone(&Utc);
+    let state = state_with(|s| s.clock = SimClock::starting_at(start));
+    let data = generate_sensor_data(&state, "temperature").unwrap();
+    assert!(data["serverTimestamp"].as_str().unwrap().starts_with("2020-01-01T00:00:0"), "{}", data["serverTimestamp"]);
+}
+
+#[tokio::test]
+async fn injections_and_alarms_run_on_the_simulation_clock() {
+    let start = chrono::DateTime::parse_from_rfc3339("2020-01-01T00:00:00Z").unwrap().with_timezone(&Utc);
+    let state = state_with(|s| s.clock = SimClock::starting_at(start));
+    // On the wall clock a 2020 injection would have expired long ago
+    send(&state, post_json("/api/v1/sensors/temperature/inject", serde_json::json!({ "value": 80.0, "durationMs": 60_000 }))).await;
+    let data = generate_sensor_data(&state, "temperature").unwrap();
+    assert_eq!(data["injected"], true);
+
+    raise_alarm(&state, "temperature", "test", "enter", None, "test".into(), serde_json::json!({}));
+    assert!(state.alarm_log.lock().unwrap()[0].timestamp.starts_with("2020-01-01"));
+}
+
+// ── Clock synchronisation ──
+
+#[test]
+fn sync_source_and_accuracy_follow_the_sensor() {
+    assert_eq!(SyncSource::for_sensor("vibration").characteristics(), SyncSource::Ptp.characteristics());
+    assert_eq!(SyncSource::for_sensor("gps-tracker").characteristics(), SyncSource::Gnss.characteristics());
+
+    let mut clock = ClockSyncState::new("temperature", Utc::now());
+    // Fresh NTP sync: 1 ms base accuracy, drifting from there
+    assert!(clock.accuracy_us() >= 1000.0 && clock.accuracy_us() < 1001.0);
+    clock.last_sync -= Duration::from_secs(100);
+    assert!((clock.accuracy_us() - 3000.0).abs() < 1.0, "{}", clock.accuracy_us());
+    clock.resync(Utc::now());
+    assert!(clock.accuracy_us() < 1001.0);
+    assert_eq!(clock.sync_count, 1);
+}
+
+#[tokio::test]
+async fn sync_endpoint_resyncs_the_clock() {
+    let state = test_state();
+    state.clock_sync.lock().unwrap().get_mut("pressure").unwrap().last_sync -= Duration::from_secs(50);
+
+    let (status, body) = send(&state, Request::post("/api/v1/sensors/pressure/sync").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["previousAccuracy"].as_f64().unwrap() >= 2000.0);
+    assert_eq!(body["sync"]["syncCount"], 1);
+
+    let (status, _) = send(&state, Request::post("/api/v1/sensors/nope/sync").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+// ── Calibration certificates ──
+
+#[test]
+fn calibration_certificate_is_stable_and_consistent() {
+    let cert = generate_calibration_certificate("pressure").unwrap();
+    assert_eq!(cert, generate_calibration_certificate("pressure").unwrap());
+    assert_eq!(cert["deviceId"], "PRS-006");
+
+    let points = cert["referencePoints"].as_array().unwrap();
+    assert_eq!(points.len(), 5);
+    assert_eq!(points[0]["nominal"], 800.0);
+    assert_eq!(points[4]["nominal"], 1100.0);
+    let all_pass = points.iter().all(|p| p["pass"] == true);
+    assert_eq!(cert["result"], if all_pass { "PASS" } else { "FAIL" });
+
+    let date = |field: &str| chrono::NaiveDate::parse_from_str(cert[field].as_str().unwrap(), "%Y-%m-%d").unwrap();
+    assert_eq!((date("dueDate") - date("calibrationDate")).num_days(), 365);
+    assert!(generate_calibration_certificate("nope").is_none());
+}
+
+// ── Device replacement ──
+
+#[tokio::test]
+async fn replacing_a_sensor_swaps_identity_and_reseeds_its_walk() {
+    let state = test_state();
+    {
+        let mut walks = state.sensor_states.lock().unwrap();
+        let walk = walks.get_mut("temperature").unwrap();
+        walk.value = 31.999;
+        walk.velocity = 0.5;
+    }
+    let old_serial = state.devices.lock().unwrap()["temperature"].serial.clone();
+
+    let (status, body) = send(
+        &state,
+        post_json("/api/v1/sensors/temperature/replace", serde_json::json!({ "newSerial": "SN-NEW-1", "reason": "failed" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["replacement"]["oldSerial"], old_serial.as_str());
+    assert_eq!(body["replacement"]["newFirmware"], LATEST_FIRMWARE);
+    assert_eq!(state.devices.lock().unwrap()["temperature"].serial, "SN-NEW-1");
+
+    let walks = state.sensor_states.lock().unwrap();
+    let walk = &walks["temperature"];
+    assert_ne!(walk.value, 31.999);
+    assert_eq!(walk.velocity, 0.0);
+    assert!((walk.min..=walk.max).contains(&walk.value));
+    drop(walks);
+    assert_eq!(state.replacements.lock().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn replacement_requires_a_serial() {
+    let state = test_state();
+    let (status, _) = send(&state, post_json("/api/v1/sensors/temperature/replace", serde_json::json!({ "newSerial": " " }))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+// ── Occupancy ──
+
+#[test]
+fn occupancy_profile_interpolates_between_points() {
+    assert_eq!(occupancy_target_fraction(3.0), 0.0);
+    assert!((occupancy_target_fraction(8.0) - 0.3).abs() < 1e-9);
+    assert!((occupancy_target_fraction(11.5) - 0.8).abs() < 1e-9);
+    assert_eq!(occupancy_target_fraction(22.0), 0.0);
+}
+
+#[test]
+fn occupancy_count_is_bounded_by_capacity_and_zero() {
+    let mut occupancy = OccupancyState::new(10);
+    assert_eq!(occupancy.enter(15), 10);
+    assert_eq!(occupancy.exit(3), 3);
+    assert_eq!(occupancy.exit(20), 7);
+    assert_eq!((occupancy.count, occupancy.enter_count, occupancy.exit_count), (0, 10, 10));
+}
+
+#[tokio::test]
+async fn occupancy_event_reports_rejected_entries() {
+    let state = test_state();
+    let capacity = state.occupancy.lock().unwrap().capacity;
+    let (status, body) = send(
+        &state,
+        post_json("/api/v1/sensors/occupancy/event", serde_json::json!({ "direction": "in", "count": capacity + 5 })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["applied"], capacity);
+    assert_eq!(body["rejected"], 5);
+}
+
+// ── Redirect chains ──
+
+#[tokio::test]
+async fn redirects_carry_the_query_and_stop_at_max_depth() {
+    let state = state_with(|s| {
+        s.redirect_rate = 1.0;
+        s.redirect_max_depth = 2;
+    });
+    let res = app(&state).oneshot(Request::get("/api/v1/sensors/temperature?units=imperial").body(Body::empty()).unwrap()).await.unwrap();
+    assert!(res.status() == StatusCode::TEMPORARY_REDIRECT || res.status() == StatusCode::PERMANENT_REDIRECT);
+    let location = res.headers()[axum::http::header::LOCATION].to_str().unwrap().to_string();
+    assert_eq!(location, "/api/v1/sensors/temperature/raw?hop=1&units=imperial");
+
+    let res = app(&state).oneshot(Request::get("/api/v1/sensors/temperature/raw?hop=1&units=imperial").body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(res.headers()[axum::http::header::LOCATION], "/api/v1/sensors/temperature/raw?hop=2&units=imperial");
+
+    // The last hop serves the reading instead of another redirect
+    let (status, body) = send(&state, Request::get("/api/v1/sensors/temperature/raw?hop=2&units=imperial").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert!(body["data"]["value"]["value"].is_f64(), "{}", body);
+    assert_eq!(body["data"]["unit"]["code"], "[degF]");
+}
+
+// ── Watermarking ──
+
+#[test]
+fn watermark_round_trips_through_readings() {
+    let code = watermark_code("acme-analytics");
+    let mut readings = Vec::new();
+    for key in ["temperature", "humidity", "vibration", "energy-meter"] {
+        let mut data = simulate_sensor(&test_state(), key).unwrap();
+        apply_watermark(&mut data, Some(code));
+        readings.push(data);
+    }
+    let (decoded, confidence) = decode_watermark(&serde_json::json!(readings)).unwrap();
+    assert_eq!(decoded, code);
+    assert_eq!(confidence, 1.0);
+}
+
+#[test]
+fn watermark_only_touches_digits_below_reported_precision() {
+    let mut value = serde_json::json!({ "value": 21.5, "negative": -3.25, "huge": 2e9, "count": 7 });
+    embed_watermark(&mut value, 0xBEEF, &mut 0);
+    assert!((value["value"].as_f64().unwrap() - 21.5).abs() < 1e-4);
+    assert!((value["negative"].as_f64().unwrap() + 3.25).abs() < 1e-4);
+    assert_eq!(value["huge"], 2e9);
+    assert_eq!(value["count"], 7);
+    assert!(decode_watermark(&serde_json::json!({ "value": { "n": 1 } })).is_none());
+}
+
+#[tokio::test]
+async fn watermark_decode_names_the_consumer() {
+    let state = test_state();
+    let (_, body) = send(&state, Request::get("/api/v1/sensors?consumer=acme").body(Body::empty()).unwrap()).await;
+    let (status, decoded) = send(&state, post_json("/api/v1/watermark/decode", body)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(decoded["consumer"], "acme");
+}
+
+// ── Encoder ──
+
+#[test]
+fn encoder_counts_follow_direction_and_speed() {
+    let mut rng = StdRng::seed_from_u64(1);
+    let mut encoder = EncoderState::new(1000);
+    encoder.last_update -= Duration::from_secs(1);
+    let (delta, dt) = encoder.advance(&mut rng);
+    // ~1500 rpm for a second is ~25 revolutions
+    let expected = encoder.setpoint_rpm / 60.0 * dt * 1000.0;
+    assert!((delta as f64 - expected).abs() <= 1.0, "{} vs {}", delta, expected);
+    assert_eq!(encoder.counts, delta);
+
+    encoder.direction = EncoderDirection::Reverse;
+    encoder.last_update -= Duration::from_secs(1);
+    let (reverse, _) = encoder.advance(&mut rng);
+    assert!(reverse < 0);
+    assert_eq!(encoder.counts, delta + reverse);
+}
+
+#[test]
+fn encoder_reading_derives_rpm_from_the_count_delta() {
+    let state = test_state();
+    state.encoder.lock().unwrap().last_update -= Duration::from_secs(2);
+    let data = simulate_sensor(&state, "encoder").unwrap();
+    let v = &data["value"];
+    let rpm = v["deltaCounts"].as_f64().unwrap() / v["countsPerRevolution"].as_f64().unwrap()
+        / (v["deltaTimeMs"].as_f64().unwrap() / 1000.0) * 60.0;
+    assert!((v["value"].as_f64().unwrap() - rpm).abs() < 1.0);
+    let phase = v["counts"].as_i64().unwrap().rem_euclid(4);
+    let quadrature = [(false, false), (true, false), (true, true), (false, true)];
+    assert_eq!((v["channelA"].as_bool().unwrap(), v["channelB"].as_bool().unwrap()), quadrature[phase as usize]);
+}
+
+// ── Bandwidth throttling ──
+
+#[tokio::test(start_paused = true)]
+async fn pacer_spaces_frames_by_link_rate() {
+    let start = tokio::time::Instant::now();
+    let mut pacer = BandwidthPacer::new(Some(1000));
+    pacer.pace(500).await;
+    pacer.pace(500).await;
+    assert_eq!(start.elapsed(), Duration::from_secs(1));
+
+    let mut unlimited = BandwidthPacer::new(None);
+    let before = tokio::time::Instant::now();
+    unlimited.pace(1_000_000).await;
+    assert_eq!(before.elapsed(), Duration::ZERO);
+}
+
+#[tokio::test(start_paused = true)]
+async fn paced_sse_delays_each_event_by_its_size() {
+    let frames = futures_util::stream::iter([(Event::default(), 200), (Event::default(), 300)]);
+    let start = tokio::time::Instant::now();
+    let events: Vec<_> = paced_sse(frames, Some(100)).collect().await;
+    assert_eq!(events.len(), 2);
+    assert_eq!(start.elapsed(), Duration::from_secs(5));
+}
+
+// ── Strain gauge ──
+
+#[test]
+fn strain_gauge_counts_reversals_and_overload_transitions() {
+    let mut gauge = StrainGaugeState::new(200.0, 1000.0);
+    // σ = E·ε: 200 GPa × 250 µε = 50 MPa
+    assert!((gauge.stress_mpa() - 50.0).abs() < 1e-9);
+
+    assert_eq!(gauge.apply(400.0), None);
+    assert_eq!(gauge.apply(300.0), None);
+    assert_eq!(gauge.apply(1200.0), Some(true));
+    assert_eq!(gauge.apply(1300.0), None);
+    assert_eq!(gauge.apply(900.0), Some(false));
+    // up → down → up → down: three reversals
+    assert_eq!(gauge.fatigue_cycles, 3);
+    assert_eq!(gauge.peak_microstrain, 1300.0);
+}
+
+#[tokio::test]
+async fn strain_load_endpoint_raises_overload_alarm() {
+    let state = test_state();
+    let (status, body) = send(&state, post_json("/api/v1/sensors/strain-gauge/load", serde_json::json!({ "microstrain": 5000.0 }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["overloadAlarm"], true);
+    let alarms = state.alarm_log.lock().unwrap();
+    assert_eq!((alarms[0].sensor.as_str(), alarms[0].event.as_str()), ("strain-gauge", "trip"));
+}
+
+// ── Cold-boot instability ──
+
+#[test]
+fn boot_instability_decays_over_the_window() {
+    let mut device = DeviceInfo::new("temperature", "TEMP-001", Utc::now());
+    assert!(device.boot_instability(30.0) > 0.99);
+    device.booted_at -= Duration::from_secs(15);
+    assert!((device.boot_instability(30.0) - 0.25).abs() < 0.01);
+    device.booted_at -= Duration::from_secs(15);
+    assert_eq!(device.boot_instability(30.0), 0.0);
+    assert_eq!(device.boot_instability(0.0), 0.0);
+}
+
+#[tokio::test]
+async fn boot_errors_are_off_by_default_and_follow_reboots_when_enabled() {
+    assert_eq!(boot_error_rate(&test_state(), "temperature"), 0.0);
+
+    let state = state_with(|s| s.boot_error_rate = 0.5);
+    state.devices.lock().unwrap().get_mut("temperature").unwrap().booted_at -= Duration::from_secs(3600);
+    assert_eq!(boot_error_rate(&state, "temperature"), 0.0);
+
+    let (status, _) = send(&state, Request::post("/api/v1/sensors/temperature/reboot").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(boot_error_rate(&state, "temperature") > 0.49);
+}
+
+// ── REST subscriptions ──
+
+/// The first `count` SSE `data:` payloads of a streaming response
+async fn sse_events(res: Response, count: usize) -> Vec<serde_json::Value> {
+    let mut body = res.into_body().into_data_stream();
+    let (mut text, mut events) = (String::new(), Vec::new());
+    while events.len() < count {
+        text.push_str(&String::from_utf8_lossy(&body.next().await.unwrap().unwrap()));
+        while let Some(end) = text.find("\n\n") {
+            let frame: String = text.drain(..end + 2).collect();
+            if let Some(data) = frame.lines().find_map(|l| l.strip_prefix("data: ")) {
+                events.push(serde_json::from_str(data).unwrap());
+            }
+        }
+    }
+    events.truncate(count);
+    events
+}
+
+#[tokio::test]
+async fn overrun_sse_clients_are_told_how_much_they_missed() {
+    let state = test_state();
+    let res = app(&state).oneshot(Request::get("/events").body(Body::empty()).unwrap()).await.unwrap();
+    // The client hasn't read anything yet; overflow the channel behind it.
+    // Broadcast channels round the default 100 up to a power of two.
+    let capacity = 128;
+    for i in 0..capacity + 20 {
+        state.sse_tx.send(SSEEvent::Connected { message: format!("filler {}", i) }).unwrap();
+    }
+    let events = sse_events(res, 2).await;
+    assert_eq!(events[0]["type"], "connected");
+    assert_eq!(events[1]["type"], "lagged");
+    assert_eq!(events[1]["data"]["skipped"], 20);
+}
+
+#[tokio::test]
+async fn subscriptions_take_per_sensor_intervals() {
+    let state = test_state();
+    let (status, body) = send(
+        &state,
+        post_json("/api/v1/subscriptions", serde_json::json!({
+            "sensors": ["temperature", "nope"],
+            "interval": 5,
+            "intervals": { "humidity": 300 }
+        })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(body["unknown"], serde_json::json!(["nope"]));
+    let subscription = &body["subscription"];
+    assert_eq!(subscription["sensors"], serde_json::json!(["temperature", "humidity"]));
+    assert_eq!(subscription["intervals"], serde_json::json!({ "temperature": 100, "humidity": 300 }));
+}
+
+#[tokio::test(start_paused = true)]
+async fn subscription_stream_reads_each_sensor_on_its_interval() {
+    let state = test_state();
+    let (_, body) = send(
+        &state,
+        post_json("/api/v1/subscriptions", serde_json::json!({ "interval": 100, "intervals": { "temperature": 100, "humidity": 300 } })),
+    )
+    .await;
+    let url = body["subscription"]["sseUrl"].as_str().unwrap();
+
+    let res = app(&state).oneshot(Request::get(url).body(Body::empty()).unwrap()).await.unwrap();
+    let events = sse_events(res, 8).await;
+    let sensors: Vec<&str> = events.iter().map(|e| e["data"]["sensor"].as_str().unwrap()).collect();
+    // t=0 and t=300 read both; t=100, 200, 400, 500 only temperature
+    assert_eq!(sensors.iter().filter(|&&s| s == "humidity").count(), 2);
+    assert_eq!(sensors.iter().filter(|&&s| s == "temperature").count(), 6);
+}
+
+#[tokio::test]
+async fn deleted_subscription_is_gone() {
+    let state = test_state();
+    let (_, body) = send(&state, post_json("/api/v1/subscriptions", serde_json::json!({ "sensors": ["pressure"] }))).await;
+    let id = body["subscription"]["id"].as_str().unwrap().to_string();
+    let delete = Request::delete(format!("/api/v1/subscriptions/{}", id)).body(Body::empty()).unwrap();
+    assert_eq!(send(&state, delete).await.0, StatusCode::OK);
+    let events = Request::get(format!("/api/v1/subscriptions/{}/events", id)).body(Body::empty()).unwrap();
+    assert_eq!(send(&state, events).await.0, StatusCode::NOT_FOUND);
+}
+
+// ── History sampling + retention ──
+
+#[test]
+fn history_sampler_records_served_readings_without_simulating() {
+    let state = test_state();
+    generate_sensor_data(&state, "temperature").unwrap();
+    let newest = generate_sensor_data(&state, "temperature").unwrap();
+    let walk_before = state.sensor_states.lock().unwrap()["humidity"].value;
+
+    sample_history(&state);
+    sample_history(&state);
+
+    let history = state.history.lock().unwrap();
+    let samples = &history["temperature"];
+    assert_eq!(samples.len(), 1);
+    assert_eq!(samples[0].1, newest);
+    // Sensors nobody read neither gain history nor move
+    assert!(!history.contains_key("humidity"));
+    assert_eq!(state.sensor_states.lock().unwrap()["humidity"].value, walk_before);
+}
+
+#[tokio::test]
+async fn aggregates_never_simulate_and_reject_unrepresentable_windows() {
+    let state = test_state();
+    let get = |uri: &str| Request::get(uri).body(Body::empty()).unwrap();
+    let walk_before = state.sensor_states.lock().unwrap()["humidity"].value;
+    let (status, body) = send(&state, get("/api/v1/sensors/humidity/aggregate?window=1m")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["count"], 0);
+    assert_eq!(body["mean"], serde_json::Value::Null);
+    assert_eq!(state.sensor_states.lock().unwrap()["humidity"].value, walk_before);
+
+    generate_sensor_data(&state, "humidity").unwrap();
+    sample_history(&state);
+    let (status, body) = send(&state, get("/api/v1/sensors/humidity/aggregate?window=1m")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["count"], 1);
+    let (status, _) = send(&state, get("/api/v1/sensors/humidity/aggregate?field=nope")).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    for window in ["9223372036854775807s", "999999999999d", "106751991167d"] {
+        let (status, body) = send(&state, get(&format!("/api/v1/sensors/humidity/aggregate?window={}", window))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}: {}", window, body);
+    }
+}
+
+#[test]
+fn expired_history_and_access_log_entries_are_pruned() {
+    let state = state_with(|s| {
+        s.retention.history_ttl = Some(chrono::Duration::seconds(60));
+        s.retention.access_log_ttl = Some(chrono::Duration::seconds(60));
+    });
+    let now = Utc::now();
+    let at = |secs_ago: i64| (now - chrono::Duration::seconds(secs_ago)).to_rfc3339();
+    state.history.lock().unwrap().insert(
+        "temperature".to_string(),
+        VecDeque::from([(at(120), serde_json::json!(1)), (at(30), serde_json::json!(2))]),
+    );
+    {
+        let mut log = state.access_log.write();
+        log.push_back(AccessLogEntry { timestamp: at(10), ..access_entry(2, "/api/v1/sensors", 200) });
+        log.push_back(AccessLogEntry { timestamp: at(90), ..access_entry(1, "/api/v1/sensors", 200) });
+    }
+
+    prune_expired(&state, now);
+
+    let history = state.history.lock().unwrap();
+    assert_eq!(history["temperature"].iter().map(|(_, v)| v.clone()).collect::<Vec<_>>(), [serde_json::json!(2)]);
+    assert_eq!(state.access_log.read().iter().map(|e| e.id).collect::<Vec<_>>(), [2]);
+}
+
+#[test]
+fn the_hourly_pm25_average_covers_the_last_hour_of_history() {
+    let start = chrono::DateTime::parse_from_rfc3339("2024-06-01T12:00:00Z").unwrap().with_timezone(&Utc);
+    let state = state_with(|s| s.clock = SimClock::starting_at(start));
+    let at = |mins_ago: i64| (start - chrono::Duration::minutes(mins_ago)).to_rfc3339();
+    let sample = |pm25: f64| serde_json::json!({ "value": { "pm25": pm25, "pm10": 2.0 * pm25 } });
+    state.history.lock().unwrap().insert(
+        "air-quality".to_string(),
+        VecDeque::from([(at(90), sample(100.0)), (at(50), sample(10.0)), (at(20), sample(20.0)), (at(5), sample(36.0))]),
+    );
+
+    let data = simulate_sensor(&state, "air-quality").unwrap();
+    let averages = &data["value"]["averages"];
+    assert_eq!(averages["pm25"]["1h"], 22.0);
+    assert_eq!(averages["pm25"]["15m"], 36.0);
+    assert_eq!(averages["pm10"]["1h"], 44.0);
+    assert_eq!(averages["samples"]["1h"], 3);
+}
+
+#[test]
+fn history_buffers_are_capped() {
+    let state = state_with(|s| s.retention.history_capacity = 3);
+    for i in 0..5 {
+        record_history(&state, "pressure", serde_json::json!(i));
+    }
+    let history = state.history.lock().unwrap();
+    let kept: Vec<_> = history["pressure"].iter().map(|(_, v)| v.clone()).collect();
+    assert_eq!(kept, [serde_json::json!(2), serde_json::json!(3), serde_json::json!(4)]);
+}
+
+#[tokio::test]
+async fn least_recently_requested_history_is_evicted() {
+    let state = state_with(|s| s.retention.history_max_sensors = Some(2));
+    for key in ["temperature", "humidity", "pressure"] {
+        generate_sensor_data(&state, key).unwrap();
+    }
+    // Request temperature and humidity, then temperature again
+    for key in ["temperature", "humidity", "temperature"] {
+        send(&state, Request::get(format!("/api/v1/sensors/{}/history", key)).body(Body::empty()).unwrap()).await;
+    }
+    sample_history(&state);
+    assert!(state.history.lock().unwrap().contains_key("humidity"));
+    assert!(!state.history.lock().unwrap().contains_key("pressure"), "never requested, never sampled");
+
+    send(&state, Request::get("/api/v1/sensors/pressure/history").body(Body::empty()).unwrap()).await;
+    let history = state.history.lock().unwrap();
+    assert!(!history.contains_key("humidity"));
+    assert!(history.contains_key("temperature"));
+}
+
+#[tokio::test]
+async fn history_disabled_sensors_return_empty() {
+    let state = state_with(|s| s.retention.history_sensors = Some(HashSet::from(["pressure".to_string()])));
+    generate_sensor_data(&state, "temperature").unwrap();
+    sample_history(&state);
+    let (status, body) = send(&state, Request::get("/api/v1/sensors/temperature/history").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["historyEnabled"], false);
+    assert_eq!(body["count"], 0);
+}
+
+#[tokio::test]
+async fn history_is_newest_first_with_limit_and_since() {
+    let state = test_state();
+    let base = Utc::now() - chrono::Duration::seconds(30);
+    let samples: VecDeque<_> = (0..5)
+        .map(|i| ((base + chrono::Duration::seconds(i * 5)).to_rfc3339(), serde_json::json!(i)))
+        .collect();
+    state.history.lock().unwrap().insert("humidity".into(), samples);
+    let get = |query: String| Request::get(format!("/api/v1/sensors/humidity/history{}", query)).body(Body::empty()).unwrap();
+
+    let (_, body) = send(&state, get("?limit=2".into())).await;
+    let data: Vec<_> = body["samples"].as_array().unwrap().iter().map(|s| s["data"].clone()).collect();
+    assert_eq!(data, [serde_json::json!(4), serde_json::json!(3)]);
+
+    let since = (base + chrono::Duration::seconds(7)).to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    let (_, body) = send(&state, get(format!("?since={}", since))).await;
+    assert_eq!(body["count"], 3);
+
+    let (status, _) = send(&state, get("?since=yesterday".into())).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send(&state, Request::get("/api/v1/sensors/nope/history").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+// ── Golden batch ──
+
+fn golden_request(field: &str, bias: f64) -> serde_json::Value {
+    serde_json::json!({
+        "field": field,
+        "points": [{ "t": 10.0, "value": 30.0 }, { "t": 0.0, "value": 20.0 }],
+        "band": 1.0,
+        "bias": bias
+    })
+}
+
+#[test]
+fn golden_reference_interpolates_and_holds_the_ends() {
+    let req: GoldenBatchRequest = serde_json::from_value(golden_request("value", 0.0)).unwrap();
+    let batch = GoldenBatch::from_request(req).unwrap();
+    assert_eq!(batch.duration(), 10.0);
+    assert_eq!(batch.reference_at(5.0), 25.0);
+    assert_eq!(batch.reference_at(20.0), 30.0);
+
+    let bad: GoldenBatchRequest = serde_json::from_value(serde_json::json!({ "field": "value", "points": [], "band": 1.0 })).unwrap();
+    assert!(GoldenBatch::from_request(bad).is_err());
+}
+
+#[tokio::test]
+async fn golden_upload_validates_fields_without_simulating() {
+    let state = test_state();
+    let walk_before = state.sensor_states.lock().unwrap()["temperature"].value;
+    let (status, _) = send(&state, post_json("/api/v1/sensors/temperature/golden", golden_request("value", 0.0))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(state.sensor_states.lock().unwrap()["temperature"].value, walk_before);
+
+    // Secondary fields are known once a good reading has been served
+    let (status, _) = send(&state, post_json("/api/v1/sensors/temperature/golden", golden_request("dewPoint", 0.0))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let reading = generate_sensor_data(&state, "humidity").unwrap();
+    let (status, body) = send(&state, post_json("/api/v1/sensors/humidity/golden", golden_request("dewPoint", 0.0))).await;
+    assert_eq!(status, if is_good_quality(&reading) { StatusCode::OK } else { StatusCode::BAD_REQUEST }, "{}", body);
+
+    let (status, _) = send(&state, post_json("/api/v1/sensors/nope/golden", golden_request("value", 0.0))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn golden_batch_out_of_band_trips_an_alarm() {
+    let state = test_state();
+    send(&state, post_json("/api/v1/sensors/temperature/golden", golden_request("value", 5.0))).await;
+    let data = generate_sensor_data(&state, "temperature").unwrap();
+    assert_eq!(data["goldenBatch"]["inBand"], false);
+    assert_eq!(data["deviationFromGolden"], 5.0);
+    let alarms = state.alarm_log.lock().unwrap();
+    assert_eq!((alarms[0].kind.as_str(), alarms[0].event.as_str()), ("golden-batch", "trip"));
+}
+
+// ── Loop diagnostics ──
+
+#[test]
+fn loop_current_follows_namur_ne43() {
+    let healthy = generate_diagnostics("pressure", (800.0, 1100.0), None, &mut StdRng::seed_from_u64(1)).unwrap();
+    let ma = healthy["loopCurrentMa"].as_f64().unwrap();
+    assert!((4.0..=20.0).contains(&ma));
+    let pv = healthy["primaryVariable"].as_f64().unwrap();
+    assert!((pv - (800.0 + 300.0 * (ma - 4.0) / 16.0)).abs() < 0.1, "{} mA vs {}", ma, pv);
+    assert_eq!(healthy["selfTest"]["result"], "pass");
+
+    let open = generate_diagnostics("pressure", (800.0, 1100.0), Some(WiringFault::Open), &mut StdRng::seed_from_u64(1)).unwrap();
+    assert!(open["loopCurrentMa"].as_f64().unwrap() < 0.1);
+    assert!(open["primaryVariable"].is_null());
+    assert_eq!(open["selfTest"]["sensorLoop"], "open-circuit");
+
+    let short = generate_diagnostics("pressure", (800.0, 1100.0), Some(WiringFault::Short), &mut StdRng::seed_from_u64(1)).unwrap();
+    assert!(short["loopCurrentMa"].as_f64().unwrap() > 21.0);
+    assert_eq!(short["hartStatus"]["loopCurrentSaturated"], true);
+}
+
+#[tokio::test]
+async fn wiring_fault_marks_readings_bad_until_cleared() {
+    let state = test_state();
+    let (status, _) = send(&state, post_json("/api/v1/sensors/pressure/diagnostics/fault", serde_json::json!({ "wiring": "open" }))).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, body) = send(&state, Request::get("/api/v1/sensors/pressure/diagnostics").body(Body::empty()).unwrap()).await;
+    assert_eq!(body["diagnostics"]["faultType"], "open");
+
+    let data = generate_sensor_data(&state, "pressure").unwrap();
+    assert_eq!(data["dataQuality"], "bad");
+    assert_eq!(data["opcUaStatusCode"], OpcUaStatusCode::BadSensorFailure as u32);
+
+    send(&state, Request::delete("/api/v1/sensors/pressure/diagnostics/fault").body(Body::empty()).unwrap()).await;
+    let data = generate_sensor_data(&state, "pressure").unwrap();
+    assert_ne!(data["opcUaStatusCode"], OpcUaStatusCode::BadSensorFailure as u32);
+}
+
+// ── Exception reporting ──
+
+#[test]
+fn deadband_only_watches_the_primary_variable() {
+    let state = test_state();
+    state.reporting.lock().unwrap().insert(
+        "vibration".into(),
+        ReportingConfig { reporting_mode: ReportingMode::Exception, deadband: 0.5 },
+    );
+    let mut filter = ExceptionFilter::default();
+    let reading = |velocity: f64, peak: f64| serde_json::json!({ "value": { "velocityRms": velocity, "peakAcceleration": peak } });
+
+    assert!(filter.should_report(&state, "vibration", &reading(2.0, 1.0)));
+    // Secondary fields moving alone stay quiet
+    assert!(!filter.should_report(&state, "vibration", &reading(2.2, 9.0)));
+    assert!(filter.should_report(&state, "vibration", &reading(2.6, 9.0)));
+}
+
+#[test]
+fn polled_streams_report_every_tick() {
+    let state = test_state();
+    let mut filter = ExceptionFilter::default();
+    let reading = serde_json::json!({ "value": { "value": 21.0 } });
+    assert!(filter.should_report(&state, "temperature", &reading));
+    assert!(filter.should_report(&state, "temperature", &reading));
+}
+
+// ── Re-ranging ──
+
+#[test]
+fn rerange_rescales_into_the_new_span() {
+    let state = test_state();
+    let mut data = serde_json::json!({ "value": { "value": 25.0 }, "dataQuality": "good" });
+    apply_rerange(&state, &mut data, "temperature", (0.0, 50.0), (0.0, 150.0));
+    assert_eq!(data["value"]["value"], 75.0);
+    assert_eq!(data["range"]["percentOfRange"], 50.0);
+    assert_eq!(data["range"]["loopCurrentMa"], 12.0);
+    assert_eq!(data["range"]["overrange"], false);
+
+    let mut data = serde_json::json!({ "value": { "value": 60.0 }, "dataQuality": "good" });
+    apply_rerange(&state, &mut data, "temperature", (0.0, 50.0), (0.0, 150.0));
+    assert_eq!(data["value"]["value"], 154.6875, "clips at 20.5 mA");
+    assert_eq!(data["range"]["overrange"], true);
+    assert_eq!(data["dataQuality"], "uncertain");
+}
+
+#[tokio::test]
+async fn rerange_endpoint_changes_subsequent_readings() {
+    let state = test_state();
+    let (status, body) = send(&state, post_json("/api/v1/sensors/temperature/rerange", serde_json::json!({ "lrv": 100.0, "urv": 200.0 }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["previous"]["urv"], 50.0);
+    let data = generate_sensor_data(&state, "temperature").unwrap();
+    assert_eq!(data["range"]["span"], 100.0);
+    let value = data["value"]["value"].as_f64().unwrap();
+    assert!((98.75..=203.125).contains(&value), "{}", value);
+
+    let (status, _) = send(&state, post_json("/api/v1/sensors/temperature/rerange", serde_json::json!({ "lrv": 5.0, "urv": 5.0 }))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+// ── Sparkplug DBIRTH ──
+
+#[tokio::test]
+async fn dbirth_covers_every_ddata_field() {
+    let state = test_state();
+    let (status, body) = send(&state, Request::get("/api/v1/sensors/energy-meter/dbirth").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["topic"].as_str().unwrap().contains("/DBIRTH/"));
+    let birth = body["payload"]["metrics"].as_array().unwrap();
+    assert!(birth.iter().all(|m| m["datatype"].is_string()));
+
+    let data = generate_sensor_data(&state, "energy-meter").unwrap();
+    let birth_names: Vec<_> = birth.iter().map(|m| m["name"].as_str().unwrap()).collect();
+    let data_names: Vec<_> = sparkplug::metric_set(&data).into_iter().map(|m| m.name).collect();
+    assert_eq!(birth_names, data_names);
+}
+
+// ── Avro ──
+
+#[tokio::test]
+async fn avro_reading_decodes_against_the_published_schema() {
+    let state = test_state();
+    let (status, avsc) = send(&state, Request::get("/api/v1/sensors/temperature/avsc").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(avsc["type"], "record");
+    let schema = apache_avro::Schema::parse(&avsc).unwrap();
+
+    let res = app(&state)
+        .oneshot(Request::get("/api/v1/sensors/temperature?format=avro").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(res.headers()[axum::http::header::CONTENT_TYPE], "application/avro");
+    let bytes = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    let mut reader = apache_avro::Reader::new(bytes.as_ref()).unwrap();
+    assert_eq!(reader.writer_schema(), &schema, "embedded schema matches the .avsc");
+    let record = serde_json::Value::try_from(reader.next().unwrap().unwrap()).unwrap();
+    assert!(reader.next().is_none());
+
+    assert_eq!(record["sensorType"], "temperature");
+    let value = record["value"]["value"].as_f64().unwrap();
+    assert!((-10.0..=60.0).contains(&value), "{}", value);
+}
+
+// ── Recorded replay ──
+
+#[test]
+fn a_replay_file_is_loaded_or_rejected_up_front() {
+    let state = test_state();
+    assert!(load_replay(&state, "/nonexistent/replay.ndjson").unwrap_err().starts_with("can't read"));
+
+    let path = std::env::temp_dir().join(format!("simmurator-replay-{}.ndjson", std::process::id()));
+    let path = path.to_str().unwrap();
+    std::fs::write(path, "{\"sensor\": \"nope\", \"sourceTimestamp\": \"2026-01-01T00:00:00Z\", \"value\": {}}\n").unwrap();
+    assert_eq!(load_replay(&state, path).unwrap_err(), "no readings for known sensors");
+    assert!(state.replay.lock().unwrap().is_none());
+
+    let line = serde_json::json!({ "sensor": "temperature", "sourceTimestamp": "2026-01-01T00:00:00Z", "value": { "value": 21.5 } });
+    std::fs::write(path, format!("{}\n", line)).unwrap();
+    load_replay(&state, path).unwrap();
+    std::fs::remove_file(path).unwrap();
+    let data = generate_sensor_data(&state, "temperature").unwrap();
+    assert_eq!(data["value"]["value"], 21.5);
+}
+
+// ── Cluster failover ──
+
+#[tokio::test]
+async fn failover_blocks_reads_then_flips_roles() {
+    let state = state_with(|s| s.cluster.get_mut().unwrap().failover_window = Duration::from_millis(100));
+    let get = || Request::get("/api/v1/sensors/temperature").body(Body::empty()).unwrap();
+
+    let (status, body) = send(&state, post_json("/api/v1/cluster/failover", serde_json::json!({}))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["targetRole"], "standby");
+    let (status, _) = send(&state, post_json("/api/v1/cluster/failover", serde_json::json!({}))).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, _) = send(&state, get()).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    let (status, _) = send(&state, get()).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, body) = send(&state, Request::get("/api/v1/cluster").body(Body::empty()).unwrap()).await;
+    assert_eq!(body["cluster"]["role"], "standby");
+    assert_eq!(body["cluster"]["failoverCount"], 1);
+}
+
+// ── Sensor dependencies ──
+
+#[test]
+fn dependency_cycles_are_rejected() {
+    let order = parse_sensor_dependencies("level-sensor=control-valve, control-valve=pressure").unwrap();
+    let targets: Vec<_> = order.iter().map(|d| d.target.as_str()).collect();
+    assert_eq!(targets, ["control-valve", "level-sensor"]);
+
+    let err = parse_sensor_dependencies("level-sensor=control-valve,control-valve=level-sensor").unwrap_err();
+    assert!(err.starts_with("cyclic dependency"), "{}", err);
+    assert!(parse_sensor_dependencies("level-sensor=nope").is_err());
+}
+
+#[test]
+fn opening_the_valve_fills_the_tank() {
+    let state = state_with(|s| s.dependencies = parse_sensor_dependencies("level-sensor=control-valve:0.5:0.1").unwrap());
+    {
+        let mut driven = state.driven_values.lock().unwrap();
+        driven.insert("control-valve".into(), 100.0);
+        driven.insert("level-sensor".into(), 5.0);
+    }
+    let mut last = 5.0;
+    for _ in 0..3 {
+        step_sensor_dependencies(&state, 1.0);
+        let data = generate_sensor_data(&state, "level-sensor").unwrap();
+        let level = data["value"]["level"].as_f64().unwrap();
+        assert!(level > last, "{} after {}", level, last);
+        assert_eq!(data["drivenBy"]["source"], "control-valve");
+        last = level;
+    }
+    assert_eq!(last, 6.2);
+}
+
+#[test]
+fn a_driven_level_stops_at_the_top_of_the_tank() {
+    let state = state_with(|s| s.dependencies = parse_sensor_dependencies("level-sensor=control-valve:5:0").unwrap());
+    {
+        let mut driven = state.driven_values.lock().unwrap();
+        driven.insert("control-valve".into(), 100.0);
+        driven.insert("level-sensor".into(), 11.0);
+    }
+    // The factory range runs to 20 m; the tank is 12 m tall
+    step_sensor_dependencies(&state, 1.0);
+    assert_eq!(state.driven_values.lock().unwrap()["level-sensor"], LEVEL_SENSOR_TANK.height_m);
+
+    let data = generate_sensor_data(&state, "level-sensor").unwrap();
+    assert_eq!(data["value"]["level"], 12.0);
+    assert_eq!(data["value"]["percentage"], 100.0);
+    assert_eq!(data["value"]["volume"], format!("{:.2}", LEVEL_SENSOR_TANK.volume(12.0)).parse::<f64>().unwrap());
+}
+
+#[test]
+fn doubling_the_level_doubles_the_volume() {
+    let tank = LEVEL_SENSOR_TANK;
+    for level in [0.5, 3.0, 6.0] {
+        assert!((tank.volume(2.0 * level) - 2.0 * tank.volume(level)).abs() < 1e-9);
+        assert!((tank.percentage(2.0 * level) - 2.0 * tank.percentage(level)).abs() < 1e-9);
+    }
+    assert!((tank.volume(tank.height_m) - 603.19).abs() < 0.01);
+}
+
+// ── Network discovery ──
+
+#[tokio::test(start_paused = true)]
+async fn discovery_covers_configured_sensors() {
+    let state = state_with(with_co2);
+    let (status, body) = send(&state, Request::get("/api/v1/discover?timeout=30000").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    let expected = sensor_keys(&state).count() + UNCONFIGURED_DEVICES.len();
+    assert_eq!(body["found"].as_u64().unwrap() + body["notResponding"].as_u64().unwrap(), expected as u64);
+    let co2 = body["devices"].as_array().unwrap().iter().find(|d| d["sensor"] == "co2").expect("co2 answers the scan");
+    assert_eq!(co2["deviceId"], "CO2-001");
+    assert_eq!(co2["configured"], true);
+
+    // Probes answer by device id, so a short scan loses the same slow devices every time
+    let (status, short) = send(&state, Request::get("/api/v1/discover?timeout=50").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(short["found"].as_u64().unwrap() < body["found"].as_u64().unwrap(), "{} vs {}", short["found"], body["found"]);
+    assert!(short["notResponding"].as_u64().unwrap() > body["notResponding"].as_u64().unwrap());
+}
+
+// ── Configured sensors ──
+
+#[test]
+fn configured_sensors_cannot_take_a_walked_variable() {
+    let path = std::env::temp_dir().join(format!("simmurator-sensors-{}.toml", std::process::id()));
+    let config = |key: &str| format!("[[sensor]]\nkey = \"{}\"\ndevice_id = \"X-001\"\nline = \"L\"\narea = \"A\"\nunit = \"ppm\"\nmin = 0.0\nmax = 1.0\n", key);
+    let load = |key: &str| {
+        std::fs::write(&path, config(key)).unwrap();
+        sensor_config::load(path.to_str().unwrap(), &taken_sensor_keys())
+    };
+
+    assert!(load("co2").is_ok());
+    for key in ["temperature", "wind-direction", "events", "batch"] {
+        assert!(load(key).is_err(), "{} was accepted", key);
+    }
+    std::fs::remove_file(&path).unwrap();
+}
+
+// ── Transport delay ──
+
+#[tokio::test]
+async fn upstream_change_appears_after_the_transport_delay() {
+    let state = state_with(|s| s.dependencies = parse_sensor_dependencies("level-sensor=control-valve").unwrap());
+    let (status, _) = send(&state, post_json("/api/v1/sensors/level-sensor/transport-delay", serde_json::json!({ "transportDelayMs": 200 }))).await;
+    assert_eq!(status, StatusCode::OK);
+    let level = |state: &SharedState| generate_sensor_data(state, "level-sensor").unwrap()["value"]["level"].as_f64().unwrap();
+
+    state.driven_values.lock().unwrap().insert("level-sensor".into(), 5.0);
+    assert_eq!(level(&state), 5.0);
+    state.driven_values.lock().unwrap().insert("level-sensor".into(), 8.0);
+    assert_eq!(level(&state), 5.0, "still inside the dead-time");
+
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    assert_eq!(level(&state), 8.0);
+}
+
+// ── Aligned readings ──
+
+#[tokio::test]
+async fn aligned_readings_sit_on_the_boundary_grid() {
+    let state = test_state();
+    let (status, body) = send(&state, Request::get("/api/v1/sensors/temperature/aligned?boundary=60000&count=10").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    let readings = body["readings"].as_array().unwrap();
+    assert_eq!(readings.len(), 10);
+    for reading in readings {
+        let ms = reading["epochMs"].as_i64().unwrap();
+        assert_eq!(ms % 60_000, 0);
+        let ts = chrono::DateTime::parse_from_rfc3339(reading["data"]["sourceTimestamp"].as_str().unwrap()).unwrap();
+        assert_eq!(ts.timestamp_millis(), ms);
+    }
+    // Backfilled along one trajectory rather than ten unrelated draws
+    let values: Vec<_> = readings.iter().map(|r| r["data"]["value"]["value"].as_f64().unwrap()).collect();
+    assert!(values.windows(2).all(|w| (w[1] - w[0]).abs() < 2.0), "{:?}", values);
+}
+
+#[test]
+fn backfill_steps_a_copy_of_the_walk() {
+    let state = test_state();
+    let before = state.sensor_states.lock().unwrap()["pressure"].value;
+    let offsets = backfill_offsets(&state, "pressure", 20);
+    assert_eq!(offsets.len(), 20);
+    assert!(offsets.iter().any(|&o| o != 0.0));
+    assert_eq!(state.sensor_states.lock().unwrap()["pressure"].value, before);
+    assert_eq!(backfill_offsets(&state, "gps-tracker", 3), [0.0; 3]);
+}
+
+// ── Duplicate delivery ──
+
+#[tokio::test(start_paused = true)]
+async fn duplicated_frames_repeat_their_sequence_and_payload() {
+    let state = state_with(|s| s.duplicate_rate = 1.0);
+    let res = app(&state)
+        .oneshot(Request::get("/api/v1/sensors/temperature/events?interval=100").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let events = sse_events(res, 6).await;
+    for pair in events.chunks(2) {
+        assert_eq!(pair[0], pair[1], "a duplicate is identical, sequence included");
+    }
+    let sequences: Vec<_> = events.iter().step_by(2).map(|e| e["data"]["sequence"].as_u64().unwrap()).collect();
+    assert!(sequences.windows(2).all(|w| w[1] > w[0]), "{:?}", sequences);
+}
+
+#[test]
+fn duplicates_are_off_by_default() {
+    let state = test_state();
+    assert_eq!(with_duplicates(&state, 1), [1]);
+}
+
+// ── Communication faults ──
+
+#[tokio::test]
+async fn unreachable_sensor_without_cache_reports_not_responding() {
+    let state = state_with(|s| s.prefer_stale = true);
+    let (status, _) = send(&state, post_json("/api/v1/sensors/humidity/comm-fault", serde_json::json!({}))).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let data = generate_sensor_data(&state, "humidity").expect("streams still get a reading");
+    assert_eq!(data["notResponding"], true);
+    assert_eq!(data["dataQuality"], "bad");
+    assert_eq!(data["opcUaStatusCode"], OpcUaStatusCode::BadCommunicationError as u32);
+    assert_eq!(data["sparkplugTopic"]["deviceId"], "HUM-002");
+
+    let (status, body) = send(&state, Request::get("/api/v1/sensors/humidity").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+    assert_eq!(body["error"], "Sensor not responding");
+}
+
+#[tokio::test]
+async fn unreachable_sensor_serves_its_last_good_reading() {
+    let state = state_with(|s| s.prefer_stale = true);
+    let live = generate_sensor_data(&state, "pressure").unwrap();
+    assert_eq!(live["dataQuality"], "good");
+    send(&state, post_json("/api/v1/sensors/pressure/comm-fault", serde_json::json!({}))).await;
+
+    let stale = generate_sensor_data(&state, "pressure").unwrap();
+    assert_eq!(stale["stale"], true);
+    assert_eq!(stale["value"], live["value"]);
+    assert_eq!(stale["opcUaStatusCode"], OpcUaStatusCode::UncertainLastUsableValue as u32);
+}
+
+#[tokio::test]
+async fn offline_faults_and_comm_faults_are_one_fault() {
+    let state = test_state();
+    let (status, _) = send(&state, post_json("/api/v1/sensors/humidity/fault", serde_json::json!({ "mode": "offline" }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(generate_sensor_data(&state, "humidity").unwrap()["notResponding"], true);
+    let (_, body) = send(&state, Request::delete("/api/v1/sensors/humidity/comm-fault").body(Body::empty()).unwrap()).await;
+    assert_eq!(body["cleared"], true);
+    assert!(generate_sensor_data(&state, "humidity").unwrap().get("notResponding").is_none());
+
+    // A stuck transmitter still answers, so clearing a comm fault leaves it stuck
+    send(&state, post_json("/api/v1/sensors/humidity/fault", serde_json::json!({ "mode": "stuck" }))).await;
+    let (_, body) = send(&state, Request::delete("/api/v1/sensors/humidity/comm-fault").body(Body::empty()).unwrap()).await;
+    assert_eq!(body["cleared"], false);
+    assert!(state.sensor_faults.lock().unwrap().contains_key("humidity"));
+}
+
+// ── Sensor faults ──
+
+#[tokio::test]
+async fn drift_grows_with_elapsed_time() {
+    let state = state_with(with_co2);
+    let (status, body) = send(&state, post_json("/api/v1/sensors/pressure/fault", serde_json::json!({ "mode": "drift", "rate": 0.25 }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["fault"]["rate"], 0.25);
+
+    // Backdate the fault: ten seconds of drift, however many reads happened
+    state.sensor_faults.lock().unwrap().get_mut("pressure").unwrap().started -= chrono::Duration::seconds(10);
+    for _ in 0..3 {
+        let data = generate_sensor_data(&state, "pressure").unwrap();
+        let bias = data["fault"]["bias"].as_f64().unwrap();
+        assert!((2.5..2.6).contains(&bias), "{}", bias);
+    }
+
+    // Configured sensors default the rate from their range: 0.1% of 1600 ppm
+    let (status, body) = send(&state, post_json("/api/v1/sensors/co2/fault", serde_json::json!({ "mode": "drift" }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["fault"]["rate"], 1.6);
+
+    let (status, _) = send(&state, post_json("/api/v1/sensors/pressure/fault", serde_json::json!({ "mode": "wobble" }))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, _) = send(&state, post_json("/api/v1/sensors/nope/fault", serde_json::json!({ "mode": "stuck" }))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+// ── Peak hold ──
+
+#[tokio::test]
+async fn peak_hold_latches_until_reset() {
+    let state = test_state();
+    let velocity = |data: &serde_json::Value| data["value"]["velocityRms"].as_f64().unwrap();
+    let (mut max, mut min) = (f64::MIN, f64::MAX);
+    for _ in 0..10 {
+        let data = generate_sensor_data(&state, "vibration").unwrap();
+        max = max.max(velocity(&data));
+        min = min.min(velocity(&data));
+        assert_eq!(data["peakMax"].as_f64().unwrap(), max);
+        assert_eq!(data["peakMin"].as_f64().unwrap(), min);
+    }
+
+    let (status, body) = send(&state, post_json("/api/v1/sensors/vibration/reset-peak", serde_json::json!({}))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["previous"]["peakMax"].as_f64().unwrap(), max);
+    let data = generate_sensor_data(&state, "vibration").unwrap();
+    assert_eq!(data["peakMax"].as_f64().unwrap(), velocity(&data));
+    assert_eq!(data["peakMin"].as_f64().unwrap(), velocity(&data));
+}
+
+// ── XML ──
+
+#[tokio::test]
+async fn xml_is_served_on_request() {
+    let state = test_state();
+    let (status, body) = send(&state, Request::get("/api/v1/sensors/temperature?format=xml").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    let xml = body.as_str().unwrap();
+    assert!(xml.starts_with("<?xml"));
+    assert!(xml.contains(r#"<reading sensor="temperature">"#));
+    assert!(xml.contains("<code>Cel</code>"));
+
+    let req = Request::get("/api/v1/sensors").header("accept", "application/xml").body(Body::empty()).unwrap();
+    let (status, body) = send(&state, req).await;
+    assert_eq!(status, StatusCode::OK);
+    let xml = body.as_str().unwrap();
+    assert!(xml.contains("<sensors timestamp="));
+    assert!(xml.contains(r#"<reading sensor="humidity">"#));
+}
+
+// ── Alarm shelving ──
+
+#[tokio::test]
+async fn shelved_alarms_stay_out_of_the_log_but_not_the_reading() {
+    let state = test_state();
+    let mut events = state.sse_tx.subscribe();
+    let (status, _) = send(&state, post_json("/api/v1/alarms/shelve", serde_json::json!({ "sensor": "strain-gauge", "durationMs": 60000 }))).await;
+    assert_eq!(status, StatusCode::OK);
+
+    send(&state, post_json("/api/v1/sensors/strain-gauge/load", serde_json::json!({ "microstrain": 5000.0 }))).await;
+    assert!(state.alarm_log.lock().unwrap().is_empty());
+    while let Ok(event) = events.try_recv() {
+        assert!(!matches!(event, SSEEvent::Alarm(_)), "no alarm event while shelved");
+    }
+
+    let data = generate_sensor_data(&state, "strain-gauge").unwrap();
+    assert_eq!(data["value"]["overloadAlarm"], true);
+    assert_eq!(data["shelved"], true);
+    let (_, body) = send(&state, Request::get("/api/v1/alarms/shelved").body(Body::empty()).unwrap()).await;
+    assert_eq!(body["shelved"][0]["suppressed"], 1);
+
+    // Once unshelved, the next transition is logged again
+    send(&state, Request::delete("/api/v1/alarms/shelve/strain-gauge").body(Body::empty()).unwrap()).await;
+    send(&state, post_json("/api/v1/sensors/strain-gauge/load", serde_json::json!({ "microstrain": 0.0 }))).await;
+    assert_eq!(state.alarm_log.lock().unwrap()[0].event, "clear");
+}
+
+// ── Active alarms ──
+
+#[tokio::test]
+async fn the_alarm_panel_judges_the_latest_reading_without_taking_one() {
+    let state = state_with(|s| {
+        let walk = s.sensor_states.get_mut().unwrap().get_mut("temperature").unwrap();
+        (walk.value, walk.sigma) = (22.0, 0.0);
+    });
+    let alarms = |query: &'static str| {
+        let state = state.clone();
+        async move { send(&state, Request::get(format!("/api/v1/alarms{}", query)).body(Body::empty()).unwrap()).await.1 }
+    };
+    let body = alarms("").await;
+    assert_eq!(body["count"], 0);
+    assert!(state.latest_readings.lock().unwrap().is_empty(), "polling read a sensor");
+
+    // A broken loop makes the reading bad while the value is still in band
+    state.wiring_faults.lock().unwrap().insert("temperature".into(), WiringFault::Open);
+    generate_sensor_data(&state, "temperature").unwrap();
+    let body = alarms("?min_severity=bad").await;
+    let alarm = &body["alarms"][0];
+    assert_eq!(alarm["sensor"], "temperature");
+    assert_eq!(alarm["severity"], "bad");
+    assert!(!alarm["message"].as_str().unwrap().contains("outside"), "{}", alarm["message"]);
+
+    state.wiring_faults.lock().unwrap().clear();
+    send(&state, post_json("/api/v1/sensors/temperature/inject", serde_json::json!({ "value": 80.0, "durationMs": 60_000 }))).await;
+    generate_sensor_data(&state, "temperature").unwrap();
+    let body = alarms("").await;
+    assert!(body["alarms"][0]["message"].as_str().unwrap().contains("outside normal 18..27"), "{}", body);
+}
+
+// ── Injection & waveforms ──
+
+#[tokio::test]
+async fn injection_forces_the_primary_variable_and_rejects_digital_sensors() {
+    let state = test_state();
+    let inject = serde_json::json!({ "value": 80.0, "durationMs": 60_000 });
+    let (status, body) = send(&state, post_json("/api/v1/sensors/temperature/inject", inject.clone())).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["field"], "value");
+    let data = generate_sensor_data(&state, "temperature").unwrap();
+    assert_eq!(data["value"]["value"], 80.0);
+    assert_eq!(data["injected"], true);
+    assert_eq!(data["dataQuality"], "bad");
+
+    let (status, _) = send(&state, post_json("/api/v1/sensors/contact/inject", inject)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(state.injections.lock().unwrap().get("contact").is_none());
+}
+
+#[tokio::test]
+async fn waveforms_reject_digital_sensors() {
+    let state = test_state();
+    let waveform = serde_json::json!({ "shape": "sine", "periodMs": 1000, "amplitude": 2.0, "offset": 0.0 });
+    let (status, body) = send(&state, post_json("/api/v1/sensors/temperature/waveform", waveform.clone())).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    let (status, _) = send(&state, post_json("/api/v1/sensors/contact/waveform", waveform)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(!state.waveforms.lock().unwrap().contains_key("contact"));
+}
+
+// ── Smoke detector ──
+
+#[tokio::test]
+async fn the_smoke_alarm_latches_bad_until_reset() {
+    let start = chrono::DateTime::parse_from_rfc3339("2020-01-01T00:00:00Z").unwrap().with_timezone(&Utc);
+    let state = state_with(|s| s.clock = SimClock::starting_at(start));
+    state.smoke_detector.lock().unwrap().obscuration = 10.0;
+    let data = generate_sensor_data(&state, "smoke-detector").unwrap();
+    assert_eq!(data["value"]["alarm"], true);
+    assert_eq!(data["value"]["alarmCause"], "smoke");
+    assert!(data["value"]["latchedAt"].as_str().unwrap().starts_with("2020-01-01"), "{}", data["value"]["latchedAt"]);
+
+    // The smoke clears, but the alarm holds the point bad until the panel resets it
+    {
+        let mut detector = state.smoke_detector.lock().unwrap();
+        (detector.obscuration, detector.heat_rise_rate, detector.incident_remaining) = (0.2, 0.0, 0);
+    }
+    let data = generate_sensor_data(&state, "smoke-detector").unwrap();
+    assert_eq!(data["value"]["alarm"], true);
+    assert_eq!(data["dataQuality"], "bad");
+    assert_eq!(data["opcUaStatusCode"], OpcUaStatusCode::BadOutOfRange as u32);
+
+    let (status, body) = send(&state, Request::post("/api/v1/sensors/smoke-detector/reset").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["wasLatched"], true);
+    let data = generate_sensor_data(&state, "smoke-detector").unwrap();
+    assert_eq!(data["value"]["alarm"], false);
+    assert!(data["value"]["latchedAt"].is_null());
+    assert_eq!(state.alarm_log.lock().unwrap()[0].event, "clear");
+}
+
+// ── Contact ──
+
+#[tokio::test]
+async fn the_contact_stays_out_of_numeric_pipelines() {
+    let state = test_state();
+    let get = |uri: &str| Request::get(uri).body(Body::empty()).unwrap();
+    send(&state, get("/api/v1/sensors/contact")).await;
+
+    let (status, _) = send(&state, get("/api/v1/sensors/contact/aggregate")).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, body) = send(&state, get("/api/v1/sensors/contact/aggregate?field=transitionCount")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    let deadband = serde_json::json!({ "reportingMode": "exception", "deadband": 0.5 });
+    let (status, _) = send(&state, post_json("/api/v1/sensors/contact/reporting", deadband)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let on_change = serde_json::json!({ "reportingMode": "exception" });
+    let (status, _) = send(&state, post_json("/api/v1/sensors/contact/reporting", on_change)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = send(&state, post_json("/api/v1/sensors/contact/rerange", serde_json::json!({ "lrv": 0.0, "urv": 2.0 }))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, _) = send(&state, get("/api/v1/sensors/contact/diagnostics")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn the_contact_is_calibrated_open_and_closed() {
+    let certificate = generate_calibration_certificate("contact").unwrap();
+    let nominal: Vec<_> = certificate["referencePoints"].as_array().unwrap().iter().map(|p| p["nominal"].clone()).collect();
+    assert_eq!(nominal, [0.0, 1.0]);
+}
+
+#[tokio::test]
+async fn contact_changes_are_stamped_by_the_simulation_clock() {
+    let start = chrono::DateTime::parse_from_rfc3339("2020-01-01T00:00:00Z").unwrap().with_timezone(&Utc);
+    let state = state_with(|s| s.clock = SimClock::starting_at(start));
+    let (status, _) = send(&state, post_json("/api/v1/sensors/contact/state", serde_json::json!({ "closed": false }))).await;
+    assert_eq!(status, StatusCode::OK);
+    let data = generate_sensor_data(&state, "contact").unwrap();
+    assert_eq!(data["value"]["value"], false);
+    assert!(data["value"]["lastChange"].as_str().unwrap().starts_with("2020-01-01"), "{}", data["value"]["lastChange"]);
+}
+
+// ── Power quality ──
+
+#[tokio::test]
+async fn power_quality_follows_the_meter_as_served() {
+    let state = test_state();
+    let derived = generate_sensor_data(&state, "power-quality").unwrap();
+    let meter = state.latest_readings.lock().unwrap()["energy-meter"].clone();
+    assert_eq!(derived["value"]["source"]["activePower"], meter["value"]["activePower"]);
+    assert_eq!(derived["value"]["source"]["deviceId"], sensor_descriptor("energy-meter").unwrap().device_id);
+
+    state.wiring_faults.lock().unwrap().insert("energy-meter".into(), WiringFault::Open);
+    generate_sensor_data(&state, "energy-meter");
+    let derived = generate_sensor_data(&state, "power-quality").unwrap();
+    assert_eq!(derived["dataQuality"], "bad");
+    assert_eq!(derived["opcUaStatusCode"], OpcUaStatusCode::BadSensorFailure as u32);
+
+    let state = test_state();
+    send(&state, post_json("/api/v1/sensors/energy-meter/fault", serde_json::json!({ "mode": "offline" }))).await;
+    assert_eq!(generate_sensor_data(&state, "power-quality").unwrap()["notResponding"], true);
+}
+
+#[test]
+fn clean_power_is_good_all_the_way_down() {
+    let desc = sensor_descriptor("power-quality").unwrap();
+    let grade = |thd| serde_json::json!(desc.ceiling_quality(thd));
+    assert_eq!(grade(0.1), "good");
+    assert_eq!(grade(7.9), "goodUncertain");
+    assert_eq!(grade(8.5), "uncertain");
+}
+
+// ── Webhooks ──
+
+/// A webhook on every sensor going bad, with its queue's receiving end
+fn bad_webhook(id: &str, capacity: usize) -> (Webhook, tokio::sync::mpsc::Receiver<WebhookDelivery>) {
+    let (queue, deliveries) = tokio::sync::mpsc::channel(capacity);
+    let webhook = Webhook {
+        id: id.to_string(),
+        url: "http://127.0.0.1:9/".to_string(),
+        sensors: Vec::new(),
+        on_quality: vec!["bad".to_string()],
+        created_at: Utc::now().to_rfc3339(),
+        delivered: 0,
+        failed: 0,
+        dropped: 0,
+        queue,
+        last_quality: HashMap::new(),
+    };
+    (webhook, deliveries)
+}
+
+#[test]
+fn webhooks_fire_on_transitions_into_their_quality_with_their_own_queues() {
+    let state = test_state();
+    let (fast, mut fast_rx) = bad_webhook("fast", 8);
+    let (slow, _slow_rx) = bad_webhook("slow", 1);
+    state.webhooks.lock().unwrap().extend([("fast".to_string(), fast), ("slow".to_string(), slow)]);
+
+    for quality in ["bad", "bad", "good", "bad"] {
+        notify_webhooks(&state, "temperature", &serde_json::json!({ "dataQuality": quality }));
+    }
+    let mut delivered = 0;
+    while let Ok(delivery) = fast_rx.try_recv() {
+        assert_eq!(delivery.sensor, "temperature");
+        delivered += 1;
+    }
+    assert_eq!(delivered, 2, "staying bad isn't news");
+    // The slow receiver's full queue costs it a reading, not the other webhook
+    let webhooks = state.webhooks.lock().unwrap();
+    assert_eq!(webhooks["slow"].dropped, 1);
+    assert_eq!(webhooks["fast"].dropped, 0);
+}
+
+#[tokio::test]
+async fn webhooks_see_offline_readings_too() {
+    let state = test_state();
+    let (webhook, mut deliveries) = bad_webhook("ops", 8);
+    state.webhooks.lock().unwrap().insert("ops".to_string(), webhook);
+    send(&state, post_json("/api/v1/sensors/pressure/comm-fault", serde_json::json!({}))).await;
+
+    generate_sensor_data(&state, "pressure").unwrap();
+    let delivery = deliveries.try_recv().unwrap();
+    assert_eq!(delivery.body["dataQuality"], "bad");
+}
+
+// ── Access log ──
+
+#[tokio::test]
+async fn clearing_the_access_log_needs_the_admin_token() {
+    let state = state_with(|s| s.admin_token = Some("secret".into()));
+    for _ in 0..2 {
+        send(&state, Request::get("/api/v1/sensors/temperature").body(Body::empty()).unwrap()).await;
+    }
+    let anonymous = Request::delete("/api/v1/access-log").body(Body::empty()).unwrap();
+    assert_eq!(send(&state, anonymous).await.0, StatusCode::UNAUTHORIZED);
+    let wrong = Request::delete("/api/v1/access-log").header("x-admin-token", "guess").body(Body::empty()).unwrap();
+    assert_eq!(send(&state, wrong).await.0, StatusCode::UNAUTHORIZED);
+
+    // The access-log endpoint doesn't log itself, so only the two reads go
+    let clear = Request::delete("/api/v1/access-log").header("x-admin-token", "secret").body(Body::empty()).unwrap();
+    let (status, body) = send(&state, clear).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["removed"], 2);
+    assert!(state.access_log.read().is_empty());
+    // Ids start over; SSE event ids carry on, so a client that saw event 2
+    // before the clear is replayed the new entry
+    send(&state, Request::get("/api/v1/sensors/temperature").body(Body::empty()).unwrap()).await;
+    {
+        let log = state.access_log.read();
+        assert_eq!(log.len(), 1);
+        assert_eq!((log[0].id, log[0].event_id), (1, 3));
+    }
+    let everything = AccessLogFilter::from_params(&HashMap::new()).unwrap();
+    let replay = access_log_since(&state, 2, &everything);
+    assert_eq!(replay.iter().map(|e| e.event_id).collect::<Vec<_>>(), [3]);
+}
+
+#[test]
+fn csv_export_defuses_spreadsheet_formulas() {
+    let mut entry = access_entry(1, "/api/v1/sensors/temperature", 200);
+    entry.user_agent = "=HYPERLINK(\"http://evil\",\"x\")".to_string();
+    let mut plus = access_entry(2, "+1", 404);
+    plus.user_agent = "@SUM(A1)".to_string();
+    let csv = access_log_csv(&[entry, plus, access_entry(3, "-2", 404)]);
+    let rows: Vec<&str> = csv.lines().skip(1).collect();
+    assert!(rows[0].contains(r#","'=HYPERLINK(""http://evil"",""x"")","#), "{}", rows[0]);
+    assert!(rows[1].contains(",'@SUM(A1),'+1,"), "{}", rows[1]);
+    assert!(rows[2].contains(",test,'-2,"), "{}", rows[2]);
+}
+
+/// Writers hammer the log while readers page through it, all through one router
+async fn load_access_log(state: &SharedState, writers: usize, requests_each: usize) {
+    let router = app(state);
+    let mut tasks = tokio::task::JoinSet::new();
+    for i in 0..writers + writers / 2 {
+        let router = router.clone();
+        let uris: &[&str] = if i < writers { &["/api/v1/endpoints"] } else { &["/api/v1/access-log?limit=100", "/api/v1/stats"] };
+        tasks.spawn(async move {
+            for _ in 0..requests_each {
+                for uri in uris {
+                    let res = router.clone().oneshot(Request::get(*uri).body(Body::empty()).unwrap()).await.unwrap();
+                    axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+                }
+            }
+        });
+    }
+    while let Some(done) = tasks.join_next().await {
+        done.unwrap();
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn access_log_stays_bounded_and_newest_first_under_load() {
+    let state = test_state();
+    load_access_log(&state, 6, 90).await;
+    let log = state.access_log.read();
+    assert_eq!(log.len(), ACCESS_LOG_CAPACITY);
+    assert!(log.iter().zip(log.iter().skip(1)).all(|(newer, older)| newer.id > older.id));
+}
+
+/// Throughput of the access log under concurrent writers and readers:
+/// `cargo test --release access_log_throughput -- --ignored --nocapture`
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+#[ignore = "benchmark"]
+async fn access_log_throughput() {
+    let state = test_state();
+    let (writers, requests_each) = (16, 500);
+    let started = std::time::Instant::now();
+    load_access_log(&state, writers, requests_each).await;
+    let elapsed = started.elapsed();
+    let total = (writers + writers / 2 * 2) * requests_each;
+    println!("{} requests in {:?}: {:.0} req/s", total, elapsed, total as f64 / elapsed.as_secs_f64());
+}
+
+#[test]
+fn endpoint_averages_keep_sub_millisecond_precision() {
+    let timed = |id, endpoint: &str, status, us: u64| AccessLogEntry {
+        response_time: (us / 1000) as u128,
+        response_time_us: us,
+        ..access_entry(id, endpoint, status)
+    };
+    let log = [
+        timed(1, "/api/v1/sensors/temperature", 200, 1_200),
+        timed(2, "/api/v1/sensors/temperature", 500, 1_300),
+        timed(3, "/api/v1/sensors/temperature", 200, 1_900),
+        timed(4, "/healthz", 200, 400),
+    ];
+    let totals = endpoint_totals(&log);
+
+    let temperature = &totals["/api/v1/sensors/temperature"];
+    assert_eq!((temperature.count, temperature.errors, temperature.total_ms), (3, 1, 3));
+    // Whole-millisecond timings would average 1.0
+    assert_eq!(format!("{:.1}", temperature.avg_ms()), "1.5");
+    assert_eq!(format!("{:.1}", totals["/healthz"].avg_ms()), "0.4");
+}
+
+#[tokio::test]
+async fn route_latency_shares_one_reservoir_per_route() {
+    let state = test_state();
+    for uri in ["/api/v1/sensors/temperature", "/api/v1/sensors/humidity?units=si", "/api/v1/sensors/pressure"] {
+        send(&state, Request::get(uri).body(Body::empty()).unwrap()).await;
+    }
+    assert_eq!(state.latency.lock().len(), 1);
+
+    let (_, body) = send(&state, Request::get("/api/v1/stats").body(Body::empty()).unwrap()).await;
+    assert_eq!(body["endpointStats"]["/api/v1/sensors/temperature"]["count"], 1);
+    let sensors = &body["routeLatency"]["/api/v1/sensors/:key"];
+    assert_eq!(sensors["count"], 3);
+    assert!(sensors["avgResponseTime"].is_number());
+    assert!(sensors["p99ResponseTime"].as_f64().unwrap() >= sensors["p50ResponseTime"].as_f64().unwrap());
+
+    let mut reservoir = LatencyReservoir::default();
+    assert_eq!(reservoir.mean_ms(), None);
+    for us in [1_200, 1_300, 1_900] {
+        reservoir.record(us, &mut StdRng::seed_from_u64(7));
+    }
+    assert_eq!(reservoir.mean_ms(), Some(1.47));
+}
+
+// ── Prometheus metrics ──
+
+#[tokio::test]
+async fn metrics_counters_survive_clearing_the_access_log() {
+    let state = state_with(|s| s.admin_token = Some("secret".into()));
+    for _ in 0..3 {
+        send(&state, Request::get("/api/v1/sensors/temperature").body(Body::empty()).unwrap()).await;
+    }
+    let clear = Request::delete("/api/v1/access-log").header("x-admin-token", "secret").body(Body::empty()).unwrap();
+    assert_eq!(send(&state, clear).await.0, StatusCode::OK);
+    assert!(state.access_log.read().iter().all(|e| e.endpoint != "/api/v1/sensors/temperature"));
+    send(&state, Request::get("/api/v1/sensors/temperature?units=imperial").body(Body::empty()).unwrap()).await;
+
+    let (status, body) = send(&state, Request::get("/metrics").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    let text = body.as_str().unwrap();
+    assert!(text.contains(r#"simmurator_requests_total{endpoint="/api/v1/sensors/temperature",method="GET",status="200"} 4"#), "{}", text);
+    let count = text.lines().find_map(|l| l.strip_prefix("simmurator_response_time_ms_count ")).unwrap();
+    let inf = text.lines().find_map(|l| l.strip_prefix(r#"simmurator_response_time_ms_bucket{le="+Inf"} "#)).unwrap();
+    assert_eq!(count, inf);
+    assert_eq!(count, "4");
+}
+
+// ── OPC UA status codes ──
+
+#[test]
+fn status_codes_serialize_as_their_numeric_value() {
+    assert_eq!(serde_json::to_value(OpcUaStatusCode::BadSensorFailure).unwrap(), 2147745792u32);
+    assert_eq!(serde_json::to_value(OpcUaStatusCode::Good).unwrap(), 0);
+    assert_eq!(OpcUaStatusCode::BadSensorFailure.name(), "badSensorFailure");
+
+    let state = test_state();
+    state.wiring_faults.lock().unwrap().insert("humidity".into(), WiringFault::Open);
+    let data = generate_sensor_data(&state, "humidity").unwrap();
+    assert_eq!(data["opcUaStatusCode"], 2147745792u32);
+    assert_eq!(data["opcUaStatusName"], "badSensorFailure");
+}
+
+#[tokio::test]
+async fn a_fresh_reading_matches_the_published_schema() {
+    let state = test_state();
+    let (status, schema) = send(&state, Request::get("/api/v1/schema").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    let codes: Vec<u32> = OpcUaStatusCode::ALL.iter().map(|&code| code as u32).collect();
+    assert_eq!(schema["$defs"]["OpcUaStatusCode"]["enum"], serde_json::json!(codes));
+
+    let validator = jsonschema::options().should_validate_formats(true).build(&schema).unwrap();
+    let reading = generate_sensor_data(&state, "temperature").unwrap();
+    let errors: Vec<String> = validator.iter_errors(&reading).map(|e| e.to_string()).collect();
+    assert!(errors.is_empty(), "{:?}", errors);
+
+    // The OpenAPI document carries the same reading schema
+    let spec = openapi::spec(&["temperature"]);
+    let reading_schema = &spec["components"]["schemas"]["UnifiedSensorData"];
+    assert_eq!(reading_schema["required"], schema["required"]);
+    assert_eq!(spec["components"]["schemas"]["OpcUaStatusCode"]["enum"], serde_json::json!(codes));
+}
+
+// ── Graceful shutdown ──
+
+#[test]
+fn streams_closed_after_shutdown_count_as_drained() {
+    let state = test_state();
+    let before = OpenStream::new(&state);
+    let during = OpenStream::new(&state);
+    let stuck = OpenStream::new(&state);
+    drop(before);
+    state.shutdown.send_replace(true);
+    drop(during);
+    assert_eq!(*state.drained_streams.lock().unwrap(), 1);
+    assert_eq!(*state.open_streams.lock().unwrap(), 1);
+    drop(stuck);
+}
+
+// ── Chaos configuration ──
+
+#[tokio::test]
+async fn error_rate_can_be_dialled_at_runtime() {
+    let state = test_state();
+    let patch = |body: serde_json::Value| {
+        Request::patch("/api/v1/config").header("content-type", "application/json").body(Body::from(body.to_string())).unwrap()
+    };
+    let get = || Request::get("/api/v1/sensors/temperature").body(Body::empty()).unwrap();
+
+    let (status, body) = send(&state, patch(serde_json::json!({ "errorRate": 1.0, "errorStatus": 502 }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["config"]["slowRate"], 0.0, "omitted fields keep their value");
+    assert_eq!(send(&state, get()).await.0, StatusCode::BAD_GATEWAY);
+
+    let (status, _) = send(&state, patch(serde_json::json!({ "errorRate": 1.5 }))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send(&state, patch(serde_json::json!({ "errorStatus": 404 }))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    send(&state, patch(serde_json::json!({ "errorRate": 0.0 }))).await;
+    assert_eq!(send(&state, get()).await.0, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn failed_reads_error_single_entries_of_the_batch() {
+    let state = state_with(|s| s.chaos.get_mut().unwrap().error_rate = 1.0);
+    let (status, body) = send(&state, Request::get("/api/v1/sensors?only=temperature,humidity").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    for key in ["temperature", "humidity"] {
+        assert_eq!(body["data"][key]["status"], "error", "{}", body);
+    }
+
+    let state = test_state();
+    let (_, body) = send(&state, Request::get("/api/v1/sensors?only=temperature,humidity").body(Body::empty()).unwrap()).await;
+    assert_eq!(body["data"]["temperature"]["sensorType"], "temperature");
+}
+
+#[tokio::test(start_paused = true)]
+async fn combined_read_takes_as_long_as_its_slowest_sensor() {
+    // Every read is slow (200-800 ms); one after another they'd take seconds
+    let state = state_with(|s| s.chaos.get_mut().unwrap().slow_rate = 1.0);
+    let started = tokio::time::Instant::now();
+    let (status, body) = send(&state, Request::get("/api/v1/sensors").body(Body::empty()).unwrap()).await;
+    let elapsed = started.elapsed();
+    assert_eq!(status, StatusCode::OK);
+    let sensors = body["data"].as_object().unwrap();
+    assert_eq!(sensors.len(), AVAILABLE_SENSORS.len());
+    assert!(sensors.values().all(|data| data.get("sensorType").is_some()), "{}", body);
+    assert!(elapsed >= Duration::from_millis(200) && elapsed < Duration::from_millis(800), "{:?}", elapsed);
+}
+
+// ── AMR stations ──
+
+#[tokio::test]
+async fn station_meters_are_served_under_the_amr_sensor_path() {
+    let state = test_state();
+    let station = "map-ta-phut-refinery-station";
+    let (status, body) = send(&state, Request::get(format!("/api/v1/sensors/amr/{}", station)).body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["value"]["meterSerial"], "AMR-PIPE-2024-04");
+
+    // Per-sensor routes take the station too
+    let (status, body) = send(&state, post_json(&format!("/api/v1/sensors/amr/{}/fault", station), serde_json::json!({ "mode": "stuck" }))).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert!(state.sensor_faults.lock().unwrap().contains_key(&format!("amr/{}", station)));
+
+    // Plain amr keeps its own sub-routes
+    let (status, body) = send(&state, Request::get("/api/v1/sensors/amr/raw").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["sensorType"], "amr_oil_pipeline");
+    let (status, _) = send(&state, Request::get("/api/v1/sensors/amr/nowhere").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn configured_and_station_sensors_reach_the_per_sensor_endpoints() {
+    let state = state_with(with_co2);
+    let station = "amr/map-ta-phut-refinery-station";
+    assert_eq!(primary_variable_field(&state, "co2"), Some("value"));
+    assert_eq!(primary_variable_field(&state, station), Some("flowRate"));
+
+    let get = |uri: String| Request::get(uri).body(Body::empty()).unwrap();
+    for key in ["co2", station] {
+        for path in ["meta", "history", "aggregate", "reporting", "transport-delay", "replacements", "sync"] {
+            let (status, body) = send(&state, get(format!("/api/v1/sensors/{}/{}", key, path))).await;
+            assert_eq!(status, StatusCode::OK, "{} {}: {}", key, path, body);
+        }
+        let posts = [
+            ("inject", serde_json::json!({ "value": 900.0, "durationMs": 60_000 })),
+            ("waveform", serde_json::json!({ "shape": "sine", "periodMs": 1000, "amplitude": 1.0 })),
+            ("rerange", serde_json::json!({ "lrv": 0.0, "urv": 4000.0 })),
+            ("reset-peak", serde_json::json!({})),
+            ("reboot", serde_json::json!({})),
+            ("sync", serde_json::json!({})),
+        ];
+        for (path, body) in posts {
+            let (status, body) = send(&state, post_json(&format!("/api/v1/sensors/{}/{}", key, path), body)).await;
+            assert_eq!(status, StatusCode::OK, "{} {}: {}", key, path, body);
+        }
+        let (status, _) = send(&state, post_json("/api/v1/alarms/shelve", serde_json::json!({ "sensor": key, "durationMs": 60_000 }))).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let (_, body) = send(&state, get("/api/v1/sensors/co2/meta".to_string())).await;
+    assert_eq!(body["deviceId"], "CO2-001");
+    assert_eq!(body["normalRange"]["max"], 1000.0);
+    let (_, body) = send(&state, get(format!("/api/v1/sensors/{}/meta", station))).await;
+    assert_eq!(body["deviceId"], "AMR-PIPE-2024-04");
+    let (_, body) = send(&state, get("/api/v1/alarms/shelved".to_string())).await;
+    assert_eq!(body["count"], 2);
+}
+
+// ── CORS ──
+
+#[tokio::test]
+async fn cors_answers_only_listed_origins() {
+    let state = test_state();
+    let cors = cors_layer(Some("https://dashboard.example, http://localhost:5173")).unwrap();
+    let app = router(state, cors).layer(MockConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))));
+    let preflight = |origin: &str| {
+        Request::builder()
+            .method("OPTIONS")
+            .uri("/api/v1/sensors/temperature")
+            .header("origin", origin)
+            .header("access-control-request-method", "GET")
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let allowed = app.clone().oneshot(preflight("https://dashboard.example")).await.unwrap();
+    assert_eq!(allowed.headers()["access-control-allow-origin"], "https://dashboard.example");
+    assert_eq!(allowed.headers()["access-control-allow-credentials"], "true");
+
+    let denied = app.oneshot(preflight("https://evil.example")).await.unwrap();
+    assert!(denied.headers().get("access-control-allow-origin").is_none());
+
+    assert!(cors_layer(Some("dashboard.example")).is_err());
+    assert!(cors_layer(Some(" , ")).is_err());
+}
+
+// ── API keys ──
+
+#[tokio::test]
+async fn api_keys_guard_every_data_endpoint() {
+    let state = state_with(|s| s.api_keys = parse_api_keys("dash:s3cret").unwrap());
+    let get = |uri: &str| Request::get(uri).body(Body::empty()).unwrap();
+
+    for uri in ["/api/v1/sensors/temperature", "/graphql", "/graphql/ws", "/ws/sensors", "/events", "/metrics"] {
+        let (status, body) = send(&state, get(uri)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "{}", uri);
+        assert_eq!(body["error"], "Missing X-API-Key header", "{}", uri);
+    }
+    let (status, _) = send(&state, Request::get("/metrics").header("x-api-key", "wrong").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, _) = send(&state, Request::get("/metrics").header("x-api-key", "s3cret").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&state, get("/healthz")).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+// ── Rate limiting ──
+
+#[test]
+fn forwarded_for_is_only_believed_from_trusted_proxies() {
+    let proxy = SocketAddr::from(([10, 0, 0, 2], 40000));
+    let mut headers = axum::http::HeaderMap::new();
+    headers.insert("x-forwarded-for", "6.6.6.6, 203.0.113.9, 10.0.0.3".parse().unwrap());
+
+    assert_eq!(client_ip(&headers, proxy, &[]), "10.0.0.2");
+    // The spoofable leftmost hop is skipped in favour of the last untrusted one
+    let trusted = parse_trusted_proxies("10.0.0.2, 10.0.0.3").unwrap();
+    assert_eq!(client_ip(&headers, proxy, &trusted), "203.0.113.9");
+    assert_eq!(client_ip(&headers, SocketAddr::from(([198, 51, 100, 1], 1)), &trusted), "198.51.100.1");
+    assert!(parse_trusted_proxies("10.0.0.0/8").is_err());
+}
+
+#[tokio::test]
+async fn rate_limit_exempts_only_real_websocket_handshakes() {
+    let state = state_with(|s| s.rate_limit = Some(RateLimit { rate: 0.001, burst: 1.0 }));
+    let get = |uri: &str| Request::get(uri).body(Body::empty()).unwrap();
+    let (status, _) = send(&state, get("/api/v1/sensors/temperature")).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = send(&state, get("/api/v1/sensors/temperature")).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    // An Upgrade header alone doesn't get a data request past the limit
+    let sneaky = Request::get("/api/v1/sensors/temperature").header("upgrade", "websocket").body(Body::empty()).unwrap();
+    let (status, _) = send(&state, sneaky).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    let handshake = Request::get("/ws/sensors")
+        .header("upgrade", "websocket")
+        .header("connection", "keep-alive, Upgrade")
+        .header("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ==")
+        .header("sec-websocket-version", "13")
+        .body(Body::empty())
+        .unwrap();
+    let (status, _) = send(&state, handshake).await;
+    assert_ne!(status, StatusCode::TOO_MANY_REQUESTS);
+    let (status, _) = send(&state, get("/healthz")).await;
+    assert_eq!(status, StatusCode::OK);
+}
@@ -0,0 +1,241 @@
+//! OPC UA server (`OPCUA_PORT`) serving the nodes the readings describe.
+//!
+//! Every sensor is a `Double` variable under `Objects/Sensors` (`Boolean` for
+//! digital inputs such as the door contact), with the node id its readings
+//! already carry in `opcUa.nodeId` (`ns=2;s=TEMP-001`, ...). Until the first
+//! reading arrives a variable holds 0 (or false) with `UncertainInitialValue`. Once a second the variables take the primary
+//! variable and status code of a fresh `generate_sensor_data` reading, so
+//! clients can browse, read and subscribe (monitored items) and see faults as
+//! UA status codes. A sensor that doesn't answer keeps its last value with
+//! `BadCommunicationError`.
+//!
+//! Like the MQTT publisher, each refresh is a real read of every sensor: it
+//! advances the random walks and feeds alarms, webhooks and history just as
+//! an HTTP read would. The server polls whether or not a client is connected.
+//!
+//! The single endpoint is `opc.tcp://<host>:<port>/` with security policy
+//! None and anonymous access. `API_KEYS` doesn't apply here, so only enable
+//! `OPCUA_PORT` on a network that may see every reading.
+
+use crate::{generate_sensor_data, primary_variable_field, sensor_config, sensor_device_id, sensor_keys, SharedState, DIGITAL_SENSORS};
+use opcua::server::prelude::*;
+use opcua::sync::RwLock;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+/// Namespace of the sensor nodes; registered first, so it gets index 2
+const SENSOR_NAMESPACE: &str = "urn:simmurator:sensors";
+
+fn device_id(state: &SharedState, key: &str) -> Option<&'static str> {
+    sensor_device_id(state, key).or_else(|| sensor_config(state, key).map(|c| c.device_id.as_str()))
+}
+
+/// Value a variable holds before its first reading
+fn initial_value(key: &str) -> Variant {
+    if DIGITAL_SENSORS.contains(&key) {
+        Variant::Boolean(false)
+    } else {
+        Variant::Double(0.0)
+    }
+}
+
+/// Source timestamp of a reading, falling back to now
+fn source_timestamp(data: &serde_json::Value) -> DateTime {
+    data["sourceTimestamp"]
+        .as_str()
+        .and_then(|ts| chrono::DateTime::parse_from_rfc3339(ts).ok())
+        .map(|ts| DateTime::from(ts.with_timezone(&chrono::Utc)))
+        .unwrap_or_else(DateTime::now)
+}
+
+/// Add a variable per sensor under `Objects/Sensors`; returns (sensor, node id)
+fn build_address_space(state: &SharedState, address_space: &mut AddressSpace) -> Result<Vec<(&'static str, NodeId)>, String> {
+    let ns = address_space
+        .register_namespace(SENSOR_NAMESPACE)
+        .map_err(|_| format!("can't register namespace {}", SENSOR_NAMESPACE))?;
+    let folder = NodeId::new(ns, "Sensors");
+    address_space.add_folder_with_id(&folder, "Sensors", "Sensors", &NodeId::objects_folder_id());
+
+    let mut nodes = Vec::new();
+    for key in sensor_keys(state) {
+        let Some(device_id) = device_id(state, key) else {
+            continue;
+        };
+        let node_id = NodeId::new(ns, device_id);
+        let data_type = if DIGITAL_SENSORS.contains(&key) { DataTypeId::Boolean } else { DataTypeId::Double };
+        let inserted = VariableBuilder::new(&node_id, device_id, key)
+            .description(format!("Primary variable of the {} sensor", key))
+            .data_type(data_type)
+            .value(initial_value(key))
+            .organized_by(&folder)
+            .insert(address_space);
+        if !inserted {
+            return Err(format!("duplicate node id {}", node_id));
+        }
+        // Nothing has been read yet, so the initial value isn't a measurement
+        if let Some(variable) = address_space.find_variable_mut_by_ref(&node_id) {
+            let now = DateTime::now();
+            let _ = variable.set_value_direct(initial_value(key), StatusCode::UncertainInitialValue, &now, &now);
+        }
+        nodes.push((key, node_id));
+    }
+    Ok(nodes)
+}
+
+/// Write one round of readings (in `nodes` order) into the variables. `last`
+/// holds each sensor's last value, kept when a sensor doesn't answer.
+fn apply_readings(
+    state: &SharedState,
+    address_space: &mut AddressSpace,
+    nodes: &[(&'static str, NodeId)],
+    readings: Vec<Option<serde_json::Value>>,
+    last: &mut HashMap<&'static str, Variant>,
+) {
+    let now = DateTime::now();
+    for ((key, node_id), reading) in nodes.iter().zip(readings) {
+        let Some(variable) = address_space.find_variable_mut_by_ref(node_id) else {
+            continue;
+        };
+        let (value, status, source_ts) = match &reading {
+            Some(data) => {
+                let status = StatusCode::from_u32(crate::opcua_status_code_value(&data["opcUaStatusCode"]))
+                    .unwrap_or(StatusCode::BadInternalError);
+                let primary = &data["value"][primary_variable_field(state, key).unwrap_or("value")];
+                let value = if DIGITAL_SENSORS.contains(key) {
+                    primary.as_bool().map(Variant::Boolean)
+                } else {
+                    primary.as_f64().map(Variant::Double)
+                };
+                (value, status, source_timestamp(data))
+            }
+            None => (None, StatusCode::BadCommunicationError, now),
+        };
+        let value = match value {
+            Some(value) => {
+                last.insert(key, value.clone());
+                value
+            }
+            None => last.get(key).cloned().unwrap_or_else(|| initial_value(key)),
+        };
+        let _ = variable.set_value_direct(value, status, &now, &source_ts);
+    }
+}
+
+/// Copy fresh readings into the variables once a second. The readings are
+/// simulated on the blocking pool so a slow round never stalls the runtime.
+async fn refresh(state: SharedState, address_space: Arc<RwLock<AddressSpace>>, nodes: Vec<(&'static str, NodeId)>) {
+    let mut last = HashMap::new();
+    let mut ticker = tokio::time::interval(Duration::from_secs(1));
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let keys: Arc<[&'static str]> = nodes.iter().map(|(key, _)| *key).collect();
+    loop {
+        ticker.tick().await;
+        let (reader, keys) = (state.clone(), keys.clone());
+        let readings = match tokio::task::spawn_blocking(move || {
+            keys.iter().map(|key| generate_sensor_data(&reader, key)).collect::<Vec<_>>()
+        })
+        .await
+        {
+            Ok(readings) => readings,
+            Err(e) => {
+                tracing::error!(error = %e, "OPC UA refresh failed");
+                continue;
+            }
+        };
+        apply_readings(&state, &mut address_space.write(), &nodes, readings, &mut last);
+    }
+}
+
+/// Serve the sensor nodes on `port` until the process exits
+pub async fn run(state: SharedState, port: u16) {
+    let pki_dir = std::env::temp_dir().join("simmurator-opcua-pki");
+    let Some(server) = ServerBuilder::new_anonymous("Simmurator")
+        .application_uri("urn:simmurator")
+        .product_uri("urn:simmurator")
+        .host_and_port("0.0.0.0", port)
+        .pki_dir(pki_dir)
+        // Only the None endpoint is served, but the stack still wants an instance certificate
+        .create_sample_keypair(true)
+        .discovery_server_url(None)
+        .server()
+    else {
+        tracing::error!("OPC UA server configuration is invalid");
+        return;
+    };
+
+    let address_space = server.address_space();
+    let nodes = match build_address_space(&state, &mut address_space.write()) {
+        Ok(nodes) => nodes,
+        Err(e) => {
+            tracing::error!(error = %e, "can't build the OPC UA address space");
+            return;
+        }
+    };
+    tokio::spawn(refresh(state, address_space, nodes));
+    Server::new_server_task(Arc::new(RwLock::new(server))).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AppState, StartupConfig};
+
+    fn state() -> SharedState {
+        let mut rng = <rand::rngs::StdRng as rand::SeedableRng>::seed_from_u64(7);
+        let state = AppState::new(StartupConfig {
+            seed: Some(7),
+            clock: crate::SimClock::system(),
+            dependencies: Vec::new(),
+            sensor_configs: &[],
+            sensor_states: crate::seed_sensor_states("", &mut rng).unwrap(),
+            oil_stations: crate::THAI_OIL_STATIONS,
+            api_keys: HashMap::new(),
+            rate_limit: None,
+            trusted_proxies: Vec::new(),
+        });
+        Arc::new(state)
+    }
+
+    fn current(address_space: &AddressSpace, node_id: &NodeId) -> DataValue {
+        let variable = address_space.find_variable_by_ref(node_id).unwrap();
+        variable.value(TimestampsToReturn::Both, NumericRange::None, &QualifiedName::null(), 0.0)
+    }
+
+    #[test]
+    fn variables_start_uncertain_and_keep_their_last_value() {
+        let state = state();
+        let mut address_space = AddressSpace::new();
+        let nodes = build_address_space(&state, &mut address_space).unwrap();
+        let (key, node_id) = nodes.iter().find(|(key, _)| *key == "temperature").unwrap().clone();
+        assert_eq!(current(&address_space, &node_id).status, Some(StatusCode::UncertainInitialValue));
+
+        let reading = generate_sensor_data(&state, key).unwrap();
+        let expected = reading["value"]["value"].as_f64().unwrap();
+        let mut last = HashMap::new();
+        let readings = nodes.iter().map(|(k, _)| (*k == key).then(|| reading.clone())).collect();
+        apply_readings(&state, &mut address_space, &nodes, readings, &mut last);
+        let value = current(&address_space, &node_id);
+        assert_eq!(value.value, Some(Variant::Double(expected)));
+        assert!(value.status.is_some_and(|s| !s.is_bad()));
+
+        let silent = nodes.iter().map(|_| None).collect();
+        apply_readings(&state, &mut address_space, &nodes, silent, &mut last);
+        let value = current(&address_space, &node_id);
+        assert_eq!(value.value, Some(Variant::Double(expected)));
+        assert_eq!(value.status, Some(StatusCode::BadCommunicationError));
+    }
+
+    #[test]
+    fn digital_inputs_are_boolean_variables() {
+        let state = state();
+        let mut address_space = AddressSpace::new();
+        let nodes = build_address_space(&state, &mut address_space).unwrap();
+        let (key, node_id) = nodes.iter().find(|(key, _)| *key == "contact").unwrap().clone();
+        assert_eq!(current(&address_space, &node_id).value, Some(Variant::Boolean(false)));
+
+        let reading = generate_sensor_data(&state, key).unwrap();
+        let closed = reading["value"]["value"].as_bool().unwrap();
+        let readings = nodes.iter().map(|(k, _)| (*k == key).then(|| reading.clone())).collect();
+        apply_readings(&state, &mut address_space, &nodes, readings, &mut HashMap::new());
+        assert_eq!(current(&address_space, &node_id).value, Some(Variant::Boolean(closed)));
+    }
+}
@@ -0,0 +1,174 @@
+//! Unit conversion for sensor readings (`?units=imperial`).
+//!
+//! Readings are produced in metric units. Converting a reading rewrites the
+//! numeric fields listed in `FIELD_UNITS` and, when the reading's own unit
+//! has a conversion, its `unit` object along with every field measured in
+//! it (primary variable, thresholds and limits, peak hold, range).
+//! Conversions are keyed by UCUM code:
+//!
+//! | metric            | imperial              | factor              |
+//! |-------------------|-----------------------|---------------------|
+//! | `Cel` (°C)        | `[degF]` (°F)         | × 1.8 + 32          |
+//! | `bar`             | `[psi]` (psi)         | × 14.503774         |
+//! | `m`               | `[ft_i]` (ft)         | × 3.280840          |
+//! | `m3/h` (m³/h)     | `[gal_us]/min` (gpm)  | × 4.402868          |
+//! | `kg/m3` (kg/m³)   | `[lb_av]/[cft_i]`     | × 0.062428          |
+//!
+//! Fields in any other unit (and dimensionless sensors such as pH, %, ppm or
+//! counts) are left as they are.
+
+use serde_json::Value;
+
+/// Unit system requested by the consumer
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum UnitSystem {
+    #[default]
+    Metric,
+    Imperial,
+}
+
+impl UnitSystem {
+    /// Parse the `units` query parameter (absent means metric)
+    pub fn from_param(param: Option<&str>) -> Result<Self, String> {
+        match param {
+            None | Some("metric") | Some("si") => Ok(Self::Metric),
+            Some("imperial") | Some("us") => Ok(Self::Imperial),
+            Some(other) => Err(format!("Unsupported units '{}' (expected metric or imperial)", other)),
+        }
+    }
+}
+
+/// Linear conversion from a metric UCUM unit: `imperial = metric × scale + offset`
+struct Conversion {
+    metric: &'static str,
+    imperial: &'static str,
+    display: &'static str,
+    scale: f64,
+    offset: f64,
+}
+
+const CONVERSIONS: &[Conversion] = &[
+    Conversion { metric: "Cel", imperial: "[degF]", display: "°F", scale: 1.8, offset: 32.0 },
+    Conversion { metric: "bar", imperial: "[psi]", display: "psi", scale: 14.503774, offset: 0.0 },
+    Conversion { metric: "m", imperial: "[ft_i]", display: "ft", scale: 3.280840, offset: 0.0 },
+    Conversion { metric: "m3/h", imperial: "[gal_us]/min", display: "gpm", scale: 4.402868, offset: 0.0 },
+    Conversion { metric: "kg/m3", imperial: "[lb_av]/[cft_i]", display: "lb/ft³", scale: 0.062428, offset: 0.0 },
+];
+
+/// (sensorType, fields of the `value` object besides the primary variable
+/// measured in the reading's own unit)
+const READING_UNIT_FIELDS: &[(&str, &[&str])] = &[
+    ("temperature", &["minThreshold", "maxThreshold", "criticalLow", "criticalHigh"]),
+    ("oil_pressure", &["maxWorkingPressure"]),
+    ("level_sensor", &["tankHeight"]),
+];
+
+/// (sensorType, UCUM code, fields of the `value` object always measured in it)
+const FIELD_UNITS: &[(&str, &str, &[&str])] = &[
+    ("humidity", "Cel", &["ambientTemperature", "dewPoint"]),
+    ("amr_oil_pipeline", "m3/h", &["flowRateM3H"]),
+    ("amr_oil_pipeline", "bar", &["inletPressure", "outletPressure", "differentialPressure"]),
+    ("amr_oil_pipeline", "Cel", &["temperature"]),
+    ("amr_oil_pipeline", "kg/m3", &["density"]),
+    ("flow_meter", "bar", &["pressure"]),
+    ("flow_meter", "Cel", &["temperature"]),
+    ("flow_meter", "kg/m3", &["density"]),
+    ("ph_sensor", "Cel", &["temperature"]),
+    ("solar_panel", "Cel", &["ambientTemperature", "panelTemperature"]),
+];
+
+fn conversion(code: &str) -> Option<&'static Conversion> {
+    CONVERSIONS.iter().find(|c| c.metric == code)
+}
+
+/// Convert `fields` of `object` in place. Differences (a span) only scale;
+/// absolute values also take the offset.
+fn convert_fields<'a>(object: &mut Value, fields: impl IntoIterator<Item = &'a str>, conv: &Conversion, difference: bool) {
+    let offset = if difference { 0.0 } else { conv.offset };
+    for field in fields {
+        if let Some(v) = object[field].as_f64() {
+            let converted = v * conv.scale + offset;
+            object[field] = serde_json::json!(format!("{:.2}", converted).parse::<f64>().unwrap());
+        }
+    }
+}
+
+/// Rewrite a reading (the JSON form of `UnifiedSensorData`) into `units`.
+/// Metric is a no-op. Fields with a fixed unit are found by the reading's
+/// `sensorType`; everything measured in the reading's own `unit` converts
+/// with it: `primary` (the primary variable and any configured limits),
+/// the sensor's thresholds, `value.limits`, the peak hold and a re-ranged
+/// `range`.
+pub fn convert(data: &mut Value, primary: &[&str], units: UnitSystem) {
+    if units == UnitSystem::Metric {
+        return;
+    }
+    let sensor_type = data["sensorType"].as_str().unwrap_or_default().to_string();
+    for &(_, code, fields) in FIELD_UNITS.iter().filter(|&&(s, _, _)| s == sensor_type) {
+        if let Some(conv) = conversion(code) {
+            convert_fields(&mut data["value"], fields.iter().copied(), conv, false);
+        }
+    }
+    let Some(conv) = data["unit"]["code"].as_str().and_then(conversion) else {
+        return;
+    };
+    let thresholds = READING_UNIT_FIELDS.iter().filter(|&&(s, _)| s == sensor_type).flat_map(|&(_, fields)| fields.iter().copied());
+    convert_fields(&mut data["value"], primary.iter().copied().chain(thresholds), conv, false);
+    if let Some(limits) = data["value"]["limits"].as_object() {
+        let names: Vec<String> = limits.keys().cloned().collect();
+        convert_fields(&mut data["value"]["limits"], names.iter().map(String::as_str), conv, false);
+    }
+    convert_fields(data, ["peakMax", "peakMin"], conv, false);
+    if data["range"].is_object() {
+        convert_fields(&mut data["range"], ["lrv", "urv"], conv, false);
+        convert_fields(&mut data["range"], ["span"], conv, true);
+    }
+    data["unit"] = serde_json::json!({ "code": conv.imperial, "display": conv.display });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn everything_in_the_reading_unit_converts_with_it() {
+        let mut data = json!({
+            "sensorType": "temperature",
+            "unit": { "code": "Cel", "display": "°C" },
+            "value": { "value": 20.0, "maxThreshold": 27.0, "limits": { "trip": 100.0 } },
+            "peakMax": 30.0,
+            "peakMin": 10.0,
+            "range": { "lrv": 0.0, "urv": 50.0, "span": 50.0, "percentOfRange": 40.0 }
+        });
+        convert(&mut data, &["value"], UnitSystem::Imperial);
+        assert_eq!(data["unit"]["code"], "[degF]");
+        assert_eq!(data["value"]["value"], 68.0);
+        assert_eq!(data["value"]["maxThreshold"], 80.6);
+        assert_eq!(data["value"]["limits"]["trip"], 212.0);
+        assert_eq!((data["peakMax"].as_f64(), data["peakMin"].as_f64()), (Some(86.0), Some(50.0)));
+        assert_eq!(data["range"], json!({ "lrv": 32.0, "urv": 122.0, "span": 90.0, "percentOfRange": 40.0 }));
+    }
+
+    #[test]
+    fn configured_sensors_and_stations_convert_by_unit_and_type() {
+        // A configured sensor: its key means nothing here, the unit does
+        let mut data = json!({
+            "sensorType": "boiler-temp",
+            "unit": { "code": "Cel", "display": "°C" },
+            "value": { "value": 100.0, "alarm": 0.0 }
+        });
+        convert(&mut data, &["value", "alarm"], UnitSystem::Imperial);
+        assert_eq!(data["value"], json!({ "value": 212.0, "alarm": 32.0 }));
+
+        // A pipeline station's meter: fixed-unit fields by sensor type
+        let mut data = json!({
+            "sensorType": "amr_oil_pipeline",
+            "unit": { "code": "L/min", "display": "L/min" },
+            "value": { "flowRate": 100.0, "inletPressure": 10.0 }
+        });
+        convert(&mut data, &["flowRate"], UnitSystem::Imperial);
+        assert_eq!(data["value"], json!({ "flowRate": 100.0, "inletPressure": 145.04 }));
+        assert!(data.get("range").is_none());
+    }
+}
@@ -0,0 +1,125 @@
+//! XML rendering of sensor readings for legacy SCADA consumers.
+//!
+//! The element tree mirrors the JSON field for field: objects become nested
+//! elements, scalars become text, array entries are repeated `<item>`
+//! children and nulls are empty elements marked `nil="true"`. Keys that
+//! aren't valid XML names (e.g. the `1m` rolling average) get a `_` prefix.
+
+use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::Writer;
+use serde_json::Value;
+use std::io;
+
+fn element_name(s: &str) -> String {
+    let mut name: String = s
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.' { c } else { '_' })
+        .collect();
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        name.insert(0, '_');
+    }
+    name
+}
+
+fn write_value(writer: &mut Writer<Vec<u8>>, name: &str, value: &Value, attrs: &[(&str, &str)]) -> io::Result<()> {
+    let mut start = BytesStart::new(name);
+    for &attr in attrs {
+        start.push_attribute(attr);
+    }
+    match value {
+        Value::Null => {
+            start.push_attribute(("nil", "true"));
+            writer.write_event(Event::Empty(start))
+        }
+        Value::Object(obj) => {
+            writer.write_event(Event::Start(start))?;
+            for (k, v) in obj {
+                write_value(writer, &element_name(k), v, &[])?;
+            }
+            writer.write_event(Event::End(BytesEnd::new(name)))
+        }
+        Value::Array(items) => {
+            writer.write_event(Event::Start(start))?;
+            for item in items {
+                write_value(writer, "item", item, &[])?;
+            }
+            writer.write_event(Event::End(BytesEnd::new(name)))
+        }
+        Value::String(s) => write_text(writer, start, name, s),
+        other => write_text(writer, start, name, &other.to_string()),
+    }
+}
+
+fn write_text(writer: &mut Writer<Vec<u8>>, start: BytesStart, name: &str, text: &str) -> io::Result<()> {
+    writer.write_event(Event::Start(start))?;
+    writer.write_event(Event::Text(BytesText::new(text)))?;
+    writer.write_event(Event::End(BytesEnd::new(name)))
+}
+
+fn document(build: impl FnOnce(&mut Writer<Vec<u8>>) -> io::Result<()>) -> io::Result<String> {
+    let mut writer = Writer::new_with_indent(Vec::new(), b' ', 2);
+    writer.write_event(Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), None)))?;
+    build(&mut writer)?;
+    String::from_utf8(writer.into_inner()).map_err(io::Error::other)
+}
+
+/// One reading as a `<reading sensor="...">` document
+pub fn reading(sensor: &str, data: &Value) -> io::Result<String> {
+    document(|w| write_value(w, "reading", data, &[("sensor", sensor)]))
+}
+
+/// Several readings wrapped in a `<sensors>` root, one `<reading>` each
+pub fn readings<'a>(timestamp: &str, readings: impl IntoIterator<Item = (&'a str, &'a Value)>) -> io::Result<String> {
+    document(|w| {
+        let mut start = BytesStart::new("sensors");
+        start.push_attribute(("timestamp", timestamp));
+        w.write_event(Event::Start(start))?;
+        for (sensor, data) in readings {
+            write_value(w, "reading", data, &[("sensor", sensor)])?;
+        }
+        w.write_event(Event::End(BytesEnd::new("sensors")))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quick_xml::events::Event;
+    use quick_xml::Reader;
+
+    /// (element path, text) of every text node
+    fn texts(xml: &str) -> Vec<(String, String)> {
+        let mut reader = Reader::from_str(xml);
+        let (mut path, mut out) = (Vec::new(), Vec::new());
+        loop {
+            match reader.read_event().unwrap() {
+                Event::Start(e) => path.push(e.name().as_ref().to_string()),
+                Event::End(_) => {
+                    path.pop();
+                }
+                Event::Text(t) => out.push((path.join("/"), t.xml10_content().into_owned())),
+                Event::Eof => return out,
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn reading_mirrors_the_json_fields() {
+        let data = serde_json::json!({
+            "value": { "value": 21.5, "1m": 20.0, "missing": null },
+            "unit": { "code": "Cel", "symbol": "°C" },
+            "tags": ["a", "b"]
+        });
+        let xml = reading("temperature", &data).unwrap();
+        assert!(xml.contains(r#"<reading sensor="temperature">"#));
+        assert!(xml.contains(r#"<missing nil="true"/>"#));
+        let texts = texts(&xml);
+        let text = |path: &str| texts.iter().find(|(p, _)| p == path).map(|(_, t)| t.as_str());
+        assert_eq!(text("reading/value/value"), Some("21.5"));
+        assert_eq!(text("reading/value/_1m"), Some("20.0"));
+        assert_eq!(text("reading/unit/code"), Some("Cel"));
+        assert_eq!(text("reading/unit/symbol"), Some("°C"));
+        assert_eq!(texts.iter().filter(|(p, _)| p == "reading/tags/item").count(), 2);
+    }
+}
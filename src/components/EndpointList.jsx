@@ -27,7 +27,8 @@ const unifiedSchema = {
   serverTimestamp: '2025-02-20T08:30:00.000Z',
   value: {},
   dataQuality: 'good',
-  opcUaStatusCode: 'good',
+  opcUaStatusCode: 0,
+  opcUaStatusName: 'good',
   unit: { code: 'Cel', display: '°C' },
   sensorType: 'sensor_type',
   description: 'Sensor description',
@@ -259,7 +260,7 @@ function DataQualityBadge({ quality }) {
     good: 'status-good',
     uncertain: 'status-warn',
     bad: 'status-bad',
-    GoodUncertain: 'status-warn',
+    goodUncertain: 'status-warn',
   };
   return <span className={`status-badge ${map[quality] || 'status-idle'}`}>{quality}</span>;
 }
@@ -382,7 +383,9 @@ function SensorCard({ name, schema, live, isSubscribed, onToggle }) {
           <h4>Data Quality</h4>
           <div className="quality-row">
             <DataQualityBadge quality={data.dataQuality} />
-            <span className="status-code">({data.opcUaStatusCode})</span>
+            <span className="status-code" title={`StatusCode ${data.opcUaStatusCode}`}>
+              ({data.opcUaStatusName ?? data.opcUaStatusCode})
+            </span>
           </div>
         </div>
       </div>